@@ -0,0 +1,20 @@
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    // The SP1 prover embeds the guest program, see `src/prover/sp1.rs`. The program runs the
+    // checks of this crate, so it is rebuilt along with them, and its dependencies on the Zcash
+    // crates need the same `zcash_unstable` cfg.
+    #[cfg(feature = "sp1")]
+    {
+        println!("cargo::rerun-if-changed=src");
+        sp1_build::build_program_with_args(
+            "program",
+            sp1_build::BuildArgs {
+                rustflags: vec![
+                    "--cfg".to_string(),
+                    "zcash_unstable=\"zfuture\"".to_string(),
+                ],
+                ..Default::default()
+            },
+        );
+    }
+}
@@ -3,12 +3,22 @@ pragma solidity ^0.8.20;
 
 import {Script, console2} from "forge-std-1.12.0/Script.sol";
 
+import {MockStateTransitionVerifier} from "src/MockStateTransitionVerifier.sol";
+import {SP1StateTransitionVerifier} from "src/SP1StateTransitionVerifier.sol";
 import {ZcashBridge} from "src/ZcashBridge.sol";
 import {WZec} from "src/WZec.sol";
 
 contract DeployBridge is Script {
     function run() external {
         uint256 deployerKey = vm.envUint("PRIVATE_KEY");
+        // The verifier can only be set once, so installing the mock rules out a real verifier
+        // or a committee for good. Only meant for local testing of the proof path.
+        bool useMockVerifier = vm.envOr("USE_MOCK_VERIFIER", false);
+        // SP1 verifier gateway and key of the guest program, as logged by a relayer built with
+        // the `sp1` feature.
+        address sp1Gateway = vm.envOr("SP1_VERIFIER_GATEWAY", address(0));
+        bytes32 sp1ProgramVKey = vm.envOr("SP1_PROGRAM_VKEY", bytes32(0));
+        require(!(useMockVerifier && sp1Gateway != address(0)), "choose one verifier");
 
         vm.startBroadcast(deployerKey);
 
@@ -19,6 +29,17 @@ contract DeployBridge is Script {
         console2.log("WZec:", address(token));
         console2.log("ZcashBridge:", address(bridge));
 
+        if (useMockVerifier) {
+            // Deployed last to keep the token and bridge addresses the relayer is configured with.
+            MockStateTransitionVerifier verifier = new MockStateTransitionVerifier();
+            bridge.setVerifier(address(verifier));
+            console2.log("MockStateTransitionVerifier:", address(verifier));
+        } else if (sp1Gateway != address(0)) {
+            SP1StateTransitionVerifier verifier = new SP1StateTransitionVerifier(sp1Gateway, sp1ProgramVKey);
+            bridge.setVerifier(address(verifier));
+            console2.log("SP1StateTransitionVerifier:", address(verifier));
+        }
+
         vm.stopBroadcast();
     }
 }
@@ -0,0 +1,11 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// @title IStateTransitionVerifier
+/// @notice Verifies proofs that a state update is a valid transition of the bridge state.
+interface IStateTransitionVerifier {
+    /// @notice Reverts unless `proof` is valid for `publicInputs`.
+    /// @param publicInputs `abi.encode(oldStateRoot, newStateRoot, updateCommitment)`.
+    /// @param proof Backend-specific proof bytes.
+    function verifyProof(bytes calldata publicInputs, bytes calldata proof) external view;
+}
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {IStateTransitionVerifier} from "src/IStateTransitionVerifier.sol";
+
+/// @title MockStateTransitionVerifier
+/// @notice Accepts the proofs of the relayer's `MockProver`, for local deployments only.
+/// @dev Anyone can compute a mock proof, so this verifier provides no security.
+contract MockStateTransitionVerifier is IStateTransitionVerifier {
+    bytes internal constant MOCK_PROOF_DOMAIN = "zcash-eth-bridge/mock-state-transition";
+
+    error InvalidProof();
+
+    function verifyProof(bytes calldata publicInputs, bytes calldata proof) external pure {
+        if (proof.length != 32 || bytes32(proof) != keccak256(abi.encodePacked(MOCK_PROOF_DOMAIN, publicInputs))) {
+            revert InvalidProof();
+        }
+    }
+}
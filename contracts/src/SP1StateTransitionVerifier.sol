@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {IStateTransitionVerifier} from "src/IStateTransitionVerifier.sol";
+
+/// @dev Interface of the SP1 verifier gateway deployed by Succinct.
+interface ISP1Verifier {
+    function verifyProof(bytes32 programVKey, bytes calldata publicValues, bytes calldata proofBytes)
+        external
+        view;
+}
+
+/// @title SP1StateTransitionVerifier
+/// @notice Verifies proofs of the relayer's `Sp1Prover` through an SP1 verifier gateway.
+contract SP1StateTransitionVerifier is IStateTransitionVerifier {
+    /// @notice SP1 verifier gateway.
+    ISP1Verifier public immutable sp1Verifier;
+
+    /// @notice Verification key of the state transition guest program.
+    bytes32 public immutable programVKey;
+
+    constructor(address sp1VerifierAddress, bytes32 programVKey_) {
+        sp1Verifier = ISP1Verifier(sp1VerifierAddress);
+        programVKey = programVKey_;
+    }
+
+    function verifyProof(bytes calldata publicInputs, bytes calldata proof) external view {
+        sp1Verifier.verifyProof(programVKey, publicInputs, proof);
+    }
+}
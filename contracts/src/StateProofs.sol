@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {ZcashBridge} from "src/ZcashBridge.sol";
+
+/// @title StateProofs
+/// @notice Computes the bridge state root committed to by the STF UTXO, and verifies that a transfer was
+/// processed given a proof against it. Mirrors `src/state.rs` and `src/merkle.rs` of the relayer.
+/// @dev The verification functions trust `proof.stateRoot`; use `ZcashBridge.verifyDepositProof` and
+/// `ZcashBridge.verifyWithdrawalProof` to check it against the roots stored by the bridge.
+library StateProofs {
+    /// @dev Index of the deposit log leaf in the state tree.
+    uint256 internal constant DEPOSITS_LEAF = 2;
+    /// @dev Index of the withdrawal log leaf in the state tree.
+    uint256 internal constant WITHDRAWALS_LEAF = 3;
+    /// @dev Number of leaves in the state tree.
+    uint256 internal constant STATE_LEAVES = 5;
+    /// @dev Root of the state before the first update, which a newly created STF commits to.
+    bytes32 internal constant EMPTY_STATE_ROOT = 0x5a91a052b93682c3b1c2000a5013d2a60f217bab58caa6abdb01a65cc3b8ecdf;
+
+    /// @dev Inclusion proof of a leaf; the bits of `index` select the side of each sibling.
+    struct MerkleProof {
+        uint64 index;
+        bytes32[] siblings;
+    }
+
+    /// @dev Transfers of one direction processed so far, as the right frontier of their Merkle tree.
+    struct TransferLog {
+        uint64 count;
+        uint64 total;
+        /// Root of the last complete subtree of each height, set if the bit of `count` for the height is.
+        bytes32[64] branch;
+    }
+
+    /// @dev Inclusion proof of a transfer in the transfer log committed to by `stateRoot`.
+    struct TransferProof {
+        MerkleProof transfer;
+        uint64 logCount;
+        uint64 logTotal;
+        MerkleProof log;
+        bytes32 stateRoot;
+    }
+
+    function hashLeaf(bytes memory data) internal pure returns (bytes32) {
+        return keccak256(abi.encodePacked(bytes1(0x00), data));
+    }
+
+    function hashNode(bytes32 left, bytes32 right) internal pure returns (bytes32) {
+        return keccak256(abi.encodePacked(bytes1(0x01), left, right));
+    }
+
+    /// @notice Number of levels above the leaves in a tree with `leafCount` leaves.
+    function depth(uint64 leafCount) internal pure returns (uint256 levels) {
+        while ((uint256(1) << levels) < leafCount) {
+            levels++;
+        }
+    }
+
+    function computeRoot(bytes32 leaf, MerkleProof memory proof) internal pure returns (bytes32 node) {
+        node = leaf;
+        uint64 index = proof.index;
+        for (uint256 i = 0; i < proof.siblings.length; i++) {
+            node = index & 1 == 0 ? hashNode(node, proof.siblings[i]) : hashNode(proof.siblings[i], node);
+            index >>= 1;
+        }
+    }
+
+    function verify(bytes32 root, bytes32 leaf, uint64 leafCount, MerkleProof memory proof)
+        internal
+        pure
+        returns (bool)
+    {
+        return proof.index < leafCount && proof.siblings.length == depth(leafCount)
+            && computeRoot(leaf, proof) == root;
+    }
+
+    /// @notice Appends the transfer with leaf hash `leaf` and amount `amount` to `log`.
+    function push(TransferLog storage log, bytes32 leaf, uint64 amount) internal {
+        log.total += amount;
+        uint64 size = ++log.count;
+        bytes32 node = leaf;
+        for (uint256 height = 0;; height++) {
+            if (size & 1 == 1) {
+                log.branch[height] = node;
+                return;
+            }
+            node = hashNode(log.branch[height], node);
+            size >>= 1;
+        }
+    }
+
+    /// @notice Root of the tree over the leaves of `log`, padded with zero nodes like `merkle::root`.
+    function root(TransferLog storage log) internal view returns (bytes32 node) {
+        uint64 count = log.count;
+        if (count == 0) {
+            return bytes32(0);
+        }
+        uint256 levels = depth(count);
+        // Whether `node` holds the rightmost node of the current level, i.e. the leaves are not a
+        // complete tree of this height.
+        bool hasNode;
+        for (uint256 height = 0; height < levels; height++) {
+            if ((count >> height) & 1 == 1) {
+                node = hashNode(log.branch[height], hasNode ? node : bytes32(0));
+                hasNode = true;
+            } else if (hasNode) {
+                node = hashNode(node, bytes32(0));
+            }
+        }
+        return hasNode ? node : log.branch[levels];
+    }
+
+    function transferLogLeaf(TransferLog storage log) internal view returns (bytes32) {
+        return hashLeaf(abi.encodePacked(log.count, log.total, root(log)));
+    }
+
+    function checkpointLeaf(uint64 blockNumber, bytes32 blockHash) internal pure returns (bytes32) {
+        return hashLeaf(abi.encodePacked(blockNumber, blockHash));
+    }
+
+    /// @notice Root of the state tree over `leaves`, see `src/state.rs` for the layout.
+    function stateRoot(bytes32[STATE_LEAVES] memory leaves) internal pure returns (bytes32) {
+        bytes32 checkpoints = hashNode(leaves[0], leaves[1]);
+        bytes32 logs = hashNode(leaves[2], leaves[3]);
+        bytes32 lastUpdate = hashNode(hashNode(leaves[4], bytes32(0)), bytes32(0));
+        return hashNode(hashNode(checkpoints, logs), lastUpdate);
+    }
+
+    function depositLeaf(address to, uint64 amount) internal pure returns (bytes32) {
+        return hashLeaf(abi.encodePacked(to, amount));
+    }
+
+    function withdrawalLeaf(ZcashBridge.ReceiverType receiverType, bytes memory receiver, uint64 amount)
+        internal
+        pure
+        returns (bytes32)
+    {
+        return hashLeaf(abi.encodePacked(uint8(receiverType), receiver, amount));
+    }
+
+    function verifyDeposit(address to, uint64 amount, TransferProof memory proof) internal pure returns (bool) {
+        return _verifyTransfer(depositLeaf(to, amount), DEPOSITS_LEAF, proof);
+    }
+
+    function verifyWithdrawal(
+        ZcashBridge.ReceiverType receiverType,
+        bytes memory receiver,
+        uint64 amount,
+        TransferProof memory proof
+    ) internal pure returns (bool) {
+        return _verifyTransfer(withdrawalLeaf(receiverType, receiver, amount), WITHDRAWALS_LEAF, proof);
+    }
+
+    function _verifyTransfer(bytes32 leaf, uint256 logIndex, TransferProof memory proof) private pure returns (bool) {
+        bytes32 logRoot = computeRoot(leaf, proof.transfer);
+        if (!verify(logRoot, leaf, proof.logCount, proof.transfer)) {
+            return false;
+        }
+        bytes32 logLeaf = hashLeaf(abi.encodePacked(proof.logCount, proof.logTotal, logRoot));
+        return proof.log.index == logIndex && verify(proof.stateRoot, logLeaf, uint64(STATE_LEAVES), proof.log);
+    }
+}
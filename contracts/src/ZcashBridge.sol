@@ -1,21 +1,52 @@
 // SPDX-License-Identifier: MIT
 pragma solidity ^0.8.20;
 
+import {ECDSA} from "@openzeppelin-contracts-5.0.2/utils/cryptography/ECDSA.sol";
+import {MessageHashUtils} from "@openzeppelin-contracts-5.0.2/utils/cryptography/MessageHashUtils.sol";
+
+import {IStateTransitionVerifier} from "src/IStateTransitionVerifier.sol";
+import {StateProofs} from "src/StateProofs.sol";
 import {WZec} from "src/WZec.sol";
 
 /// @title ZcashBridge
 /// @notice Handles minting and burning of WZec based on cross-chain state updates between Ethereum and Zcash.
+/// @dev Once the owner sets a relayer committee, updates are only accepted with enough committee signatures.
+/// Once the owner sets a state transition verifier, updates are only accepted with a valid proof.
 contract ZcashBridge {
+    using StateProofs for StateProofs.TransferLog;
+
     /// @dev Zcash-to-Ethereum transfer that mints WZec to the specified address.
     struct ProcessedZecToEthTransfer {
         uint256 amount;
         address to;
     }
 
-    /// @dev Ethereum-to-Zcash transfer that burns locked WZec corresponding to a pubkey hash.
+    /// @dev Kind of Zcash receiver a withdrawal pays to.
+    enum ReceiverType {
+        P2PKH,
+        Sapling,
+        Orchard
+    }
+
+    /// @dev Ethereum-to-Zcash transfer that burns locked WZec corresponding to a Zcash receiver.
     struct ProcessedEthToZecTransfer {
         uint256 amount;
-        bytes20 pubkeyHash;
+        ReceiverType receiverType;
+        bytes receiver;
+    }
+
+    /// @dev Deposit below the minimum paid back on Zcash instead of being minted.
+    struct ProcessedRefund {
+        uint256 amount;
+        bytes32 depositTxid;
+        ReceiverType receiverType;
+        bytes receiver;
+    }
+
+    /// @dev Zcash deposit output held in the relayer quarantine instead of being processed.
+    struct QuarantinedDeposit {
+        bytes32 txid;
+        uint32 index;
     }
 
     /// @dev Complete state update submitted by bridge operators.
@@ -30,6 +61,13 @@ contract ZcashBridge {
         uint64 newZecBlockNumber;
         ProcessedZecToEthTransfer[] zecToEthTransfers;
         ProcessedEthToZecTransfer[] ethToZecTransfers;
+        ProcessedRefund[] refunds;
+        /// @dev Quarantined withdrawals whose tokens are returned to the requester instead of being burned.
+        ProcessedEthToZecTransfer[] withdrawalRefunds;
+        /// @dev Transfers held in the quarantine after the update, in the order they were quarantined.
+        QuarantinedDeposit[] quarantinedDeposits;
+        ProcessedEthToZecTransfer[] quarantinedWithdrawals;
+        bytes32 commitment;
     }
 
     /// @dev Bridge state checkpoints for both chains.
@@ -44,17 +82,42 @@ contract ZcashBridge {
     struct WithdrawalRequest {
         address requester;
         uint256 amount;
-        bytes20 pubkeyHash;
+        ReceiverType receiverType;
+        bytes receiver;
         bool processed;
     }
 
+    /// @dev Length of raw Sapling and Orchard receivers (diversifier and pk_d).
+    uint256 internal constant SHIELDED_RECEIVER_LENGTH = 43;
+
+    /// @notice Version of the canonical state update encoding, see `encodeStateUpdate`.
+    uint8 public constant STATE_UPDATE_ENCODING_VERSION = 3;
+
+    error Unauthorized();
+    error VerifierAlreadySet();
+    error VerifierNotSet();
+    error ProofRequired();
+    error CommitteeAlreadySet();
+    error CommitteeNotSet();
+    error InvalidCommittee();
+    error SignaturesRequired();
+    error InsufficientSignatures(uint256 required);
+    error InvalidSignature(address signer);
+    error SignersNotSorted();
     error InvalidPreviousState();
+    error InvalidPreviousStateRoot(bytes32 expected);
+    error InvalidNewStateRoot(bytes32 expected);
+    error AmountTooLarge();
     error InvalidBlockNumber();
+    error InvalidCommitment(bytes32 expected);
     error ZeroAmount();
     error EmptyPubkeyHash();
+    error InvalidReceiver();
     error InvalidRecipient();
     error WithdrawalNotFound(bytes32 key);
     error WithdrawalAlreadyProcessed(uint256 requestId);
+    /// @notice Raised by `simulateTransfers` once every transfer has been processed.
+    error TransfersSimulated();
 
     event StateUpdated(
         bytes32 previousEthRoot,
@@ -64,36 +127,168 @@ contract ZcashBridge {
         bytes32 previousZecRoot,
         bytes32 newZecRoot,
         uint64 previousZecBlockNumber,
-        uint64 newZecBlockNumber
+        uint64 newZecBlockNumber,
+        bytes32 commitment
+    );
+    event WithdrawalRequested(
+        uint256 indexed requestId, address indexed requester, uint256 amount, ReceiverType receiverType, bytes receiver
     );
-    event WithdrawalRequested(uint256 indexed requestId, address indexed requester, uint256 amount, bytes20 pubkeyHash);
-    event WithdrawalProcessed(uint256 indexed requestId, uint256 amount, bytes20 pubkeyHash);
+    event WithdrawalProcessed(uint256 indexed requestId, uint256 amount, ReceiverType receiverType, bytes receiver);
     event ZecTransferProcessed(address indexed recipient, uint256 amount);
+    event DepositRefunded(bytes32 indexed depositTxid, uint256 amount, ReceiverType receiverType, bytes receiver);
+    event WithdrawalRefunded(
+        uint256 indexed requestId, address indexed requester, uint256 amount, ReceiverType receiverType, bytes receiver
+    );
+    event DepositQuarantined(bytes32 indexed txid, uint32 index);
+    event WithdrawalQuarantined(uint256 amount, ReceiverType receiverType, bytes receiver);
+    event VerifierUpdated(address indexed newVerifier);
+    event CommitteeUpdated(address[] members, uint256 threshold);
 
     WZec public immutable token;
 
+    /// @notice Deployer, allowed to set the verifier once.
+    address public immutable owner;
+
+    /// @notice Verifier of state transition proofs, unset while updates are trusted.
+    IStateTransitionVerifier public verifier;
+
     BridgeState public latestState;
+    bytes32 public latestCommitment;
     bool public stateInitialized;
     uint256 public nextWithdrawalId = 1;
     uint256 public totalLocked;
     uint256 public totalMinted;
     uint256 public totalBurned;
 
+    /// @notice Bridge state root after the last update, as committed to by the STF UTXO.
+    bytes32 public latestStateRoot;
+
+    /// @notice Whether the bridge state had the root after some update, so that transfer proofs
+    /// against it can be trusted.
+    mapping(bytes32 => bool) public isStateRoot;
+
+    /// @dev Processed deposits and withdrawals, committed to by the state root.
+    StateProofs.TransferLog private depositLog;
+    StateProofs.TransferLog private withdrawalLog;
+
+    /// @notice Relayers allowed to sign state updates.
+    mapping(address => bool) public isCommitteeMember;
+
+    /// @notice Number of committee signatures required per update, zero while no committee is set.
+    uint256 public committeeThreshold;
+
     mapping(uint256 => WithdrawalRequest) private withdrawalRequests;
     mapping(bytes32 => uint256[]) private pendingWithdrawalIds;
     mapping(bytes32 => uint256) private pendingWithdrawalIndex;
 
+    /// @param tokenAddress WZec token minted and burned by the bridge.
+    /// @dev The state root starts at `StateProofs.EMPTY_STATE_ROOT` like a newly created STF, since it
+    /// is computed from the transfers processed by this contract.
     constructor(address tokenAddress) {
         if (tokenAddress == address(0)) revert InvalidRecipient();
         token = WZec(tokenAddress);
+        owner = msg.sender;
+        latestStateRoot = StateProofs.EMPTY_STATE_ROOT;
+    }
+
+    /// @notice Require `threshold` signatures of `members` for all further state updates.
+    /// @param members Committee members.
+    /// @param threshold Number of signatures required per update.
+    function setCommittee(address[] calldata members, uint256 threshold) external {
+        if (msg.sender != owner) revert Unauthorized();
+        if (committeeThreshold != 0) revert CommitteeAlreadySet();
+        if (threshold == 0 || threshold > members.length) revert InvalidCommittee();
+        for (uint256 i; i < members.length; ++i) {
+            if (members[i] == address(0) || isCommitteeMember[members[i]]) revert InvalidCommittee();
+            isCommitteeMember[members[i]] = true;
+        }
+        committeeThreshold = threshold;
+        emit CommitteeUpdated(members, threshold);
+    }
+
+    /// @notice Digest committee members sign for a state update, as an EIP-191 personal message.
+    /// @param commitment Commitment of the state update.
+    /// @return Digest bound to this bridge and chain.
+    function computeSignedDigest(bytes32 commitment) public view returns (bytes32) {
+        return keccak256(abi.encode(block.chainid, address(this), commitment));
     }
 
-    /// @notice Compute the key that groups withdrawal requests by amount and lock script.
+    /// @notice Require proofs for all further state updates.
+    /// @param verifierAddress State transition verifier.
+    function setVerifier(address verifierAddress) external {
+        if (msg.sender != owner) revert Unauthorized();
+        if (address(verifier) != address(0)) revert VerifierAlreadySet();
+        if (verifierAddress == address(0)) revert InvalidRecipient();
+        verifier = IStateTransitionVerifier(verifierAddress);
+        emit VerifierUpdated(verifierAddress);
+    }
+
+    /// @notice Compute the key that groups withdrawal requests by amount and receiver.
     /// @param amount Requested withdrawal amount.
-    /// @param pubkeyHash Recipient pubkey hash on Zcash.
+    /// @param receiverType Kind of the Zcash receiver.
+    /// @param receiver Raw receiver bytes (pubkey hash for P2PKH, raw address for shielded pools).
     /// @return Withdrawal grouping key.
-    function computeWithdrawalKey(uint256 amount, bytes20 pubkeyHash) public pure returns (bytes32) {
-        return keccak256(abi.encode(amount, pubkeyHash));
+    function computeWithdrawalKey(uint256 amount, ReceiverType receiverType, bytes memory receiver)
+        public
+        pure
+        returns (bytes32)
+    {
+        return keccak256(abi.encode(amount, receiverType, receiver));
+    }
+
+    /// @notice Canonical binary encoding of a state update, shared with the relayer.
+    /// @dev Integers are big-endian, amounts take 32 bytes and receivers are stored raw.
+    /// @param update State update to encode.
+    /// @return encoded Encoded state update.
+    function encodeStateUpdate(StateUpdate calldata update) public pure returns (bytes memory encoded) {
+        encoded = abi.encodePacked(
+            STATE_UPDATE_ENCODING_VERSION,
+            update.previousEthBlockNumber,
+            update.newEthBlockNumber,
+            update.previousEthRoot,
+            update.newEthRoot
+        );
+        encoded = abi.encodePacked(
+            encoded,
+            update.previousZecBlockNumber,
+            update.newZecBlockNumber,
+            update.previousZecRoot,
+            update.newZecRoot
+        );
+
+        uint256 mintCount = update.zecToEthTransfers.length;
+        encoded = abi.encodePacked(encoded, uint32(mintCount));
+        for (uint256 i; i < mintCount; ++i) {
+            ProcessedZecToEthTransfer calldata transferData = update.zecToEthTransfers[i];
+            encoded = abi.encodePacked(encoded, transferData.to, transferData.amount);
+        }
+
+        encoded = _encodeWithdrawals(encoded, update.ethToZecTransfers);
+
+        uint256 refundCount = update.refunds.length;
+        encoded = abi.encodePacked(encoded, uint32(refundCount));
+        for (uint256 i; i < refundCount; ++i) {
+            ProcessedRefund calldata refund = update.refunds[i];
+            encoded = abi.encodePacked(
+                encoded, refund.depositTxid, uint8(refund.receiverType), refund.receiver, refund.amount
+            );
+        }
+        encoded = _encodeWithdrawals(encoded, update.withdrawalRefunds);
+
+        uint256 quarantinedCount = update.quarantinedDeposits.length;
+        encoded = abi.encodePacked(encoded, uint32(quarantinedCount));
+        for (uint256 i; i < quarantinedCount; ++i) {
+            QuarantinedDeposit calldata deposit = update.quarantinedDeposits[i];
+            encoded = abi.encodePacked(encoded, deposit.txid, deposit.index);
+        }
+        encoded = _encodeWithdrawals(encoded, update.quarantinedWithdrawals);
+    }
+
+    /// @notice Commitment the relayer binds both chains' transactions of a state update to.
+    /// @param update State update to commit to; its `commitment` field is ignored.
+    /// @return Keccak-256 hash of the canonical encoding.
+    function computeCommitment(StateUpdate calldata update) public pure returns (bytes32) {
+        return keccak256(encodeStateUpdate(update));
     }
 
     /// @notice Retrieve details about a withdrawal request.
@@ -110,41 +305,152 @@ contract ZcashBridge {
     }
 
     /// @notice Number of pending withdrawals for a given key.
-    /// @param key Withdrawal grouping key (amount + pubkey hash).
+    /// @param key Withdrawal grouping key (amount + receiver).
     /// @return count Pending withdrawal count for the key.
     function pendingWithdrawalCount(bytes32 key) external view returns (uint256) {
         uint256[] storage queue = pendingWithdrawalIds[key];
         return queue.length - pendingWithdrawalIndex[key];
     }
 
-    /// @notice Request withdrawal to the Zcash chain by locking WZec tokens.
+    /// @notice Request withdrawal to a transparent Zcash address by locking WZec tokens.
     /// @param amount Amount of WZec to withdraw.
     /// @param pubkeyHash Recipient pubkey hash on Zcash.
     /// @return requestId Identifier of the newly created request.
     function requestWithdrawal(uint256 amount, bytes20 pubkeyHash) external returns (uint256 requestId) {
-        if (amount == 0) revert ZeroAmount();
-        if (pubkeyHash.length == 0) revert EmptyPubkeyHash();
+        if (pubkeyHash == bytes20(0)) revert EmptyPubkeyHash();
+        requestId = _requestWithdrawal(amount, ReceiverType.P2PKH, abi.encodePacked(pubkeyHash));
+    }
 
-        token.transferFrom(msg.sender, address(this), amount);
-        totalLocked += amount;
+    /// @notice Request withdrawal to a shielded Zcash address by locking WZec tokens.
+    /// @param amount Amount of WZec to withdraw.
+    /// @param receiverType Shielded pool to withdraw to.
+    /// @param receiver Raw 43-byte Sapling or Orchard receiver.
+    /// @return requestId Identifier of the newly created request.
+    function requestShieldedWithdrawal(uint256 amount, ReceiverType receiverType, bytes calldata receiver)
+        external
+        returns (uint256 requestId)
+    {
+        if (receiverType == ReceiverType.P2PKH) revert InvalidReceiver();
+        if (receiver.length != SHIELDED_RECEIVER_LENGTH) revert InvalidReceiver();
+        requestId = _requestWithdrawal(amount, receiverType, receiver);
+    }
 
-        requestId = nextWithdrawalId++;
-        withdrawalRequests[requestId] =
-            WithdrawalRequest({requester: msg.sender, amount: amount, pubkeyHash: pubkeyHash, processed: false});
+    /// @notice Submit a state update along with processed cross-chain transfers.
+    /// @dev Only accepted until a committee or a verifier is set.
+    /// @param update Full state update payload.
+    function submitStateUpdate(StateUpdate calldata update) external {
+        if (address(verifier) != address(0)) revert ProofRequired();
+        if (committeeThreshold != 0) revert SignaturesRequired();
+        _validateStateTransition(update);
+        _updateStateRoot(update);
 
-        bytes32 key = computeWithdrawalKey(amount, pubkeyHash);
-        pendingWithdrawalIds[key].push(requestId);
+        _processZecToEthTransfers(update.zecToEthTransfers);
+        _processEthToZecTransfers(update.ethToZecTransfers);
+        _processRefunds(update.refunds);
+        _processWithdrawalRefunds(update.withdrawalRefunds);
+        _emitQuarantine(update);
+    }
 
-        emit WithdrawalRequested(requestId, msg.sender, amount, pubkeyHash);
+    /// @notice Submit a state update signed by the relayer committee.
+    /// @dev Only accepted until a verifier is set.
+    /// @param update Full state update payload.
+    /// @param signatures Signatures of distinct members on `computeSignedDigest(update.commitment)`,
+    /// ordered by ascending signer address.
+    function submitStateUpdateWithSignatures(StateUpdate calldata update, bytes[] calldata signatures) external {
+        if (address(verifier) != address(0)) revert ProofRequired();
+        if (committeeThreshold == 0) revert CommitteeNotSet();
+        _validateStateTransition(update);
+        _checkSignatures(update.commitment, signatures);
+        _updateStateRoot(update);
+
+        _processZecToEthTransfers(update.zecToEthTransfers);
+        _processEthToZecTransfers(update.ethToZecTransfers);
+        _processRefunds(update.refunds);
+        _processWithdrawalRefunds(update.withdrawalRefunds);
+        _emitQuarantine(update);
     }
 
-    /// @notice Submit a state update along with processed cross-chain transfers.
+    /// @notice Submit a state update with a proof that it transitions the bridge state root.
     /// @param update Full state update payload.
-    function submitStateUpdate(StateUpdate calldata update) external {
+    /// @param oldStateRoot Bridge state root the update applies to.
+    /// @param newStateRoot Bridge state root after the update, which has to match the one computed by
+    /// the bridge.
+    /// @param proof Proof for `abi.encode(oldStateRoot, newStateRoot, update.commitment)`.
+    function submitStateUpdateWithProof(
+        StateUpdate calldata update,
+        bytes32 oldStateRoot,
+        bytes32 newStateRoot,
+        bytes calldata proof
+    ) external {
+        if (address(verifier) == address(0)) revert VerifierNotSet();
+        if (oldStateRoot != latestStateRoot) revert InvalidPreviousStateRoot(latestStateRoot);
         _validateStateTransition(update);
+        verifier.verifyProof(abi.encode(oldStateRoot, newStateRoot, update.commitment), proof);
+        bytes32 stateRoot = _updateStateRoot(update);
+        if (stateRoot != newStateRoot) revert InvalidNewStateRoot(stateRoot);
 
         _processZecToEthTransfers(update.zecToEthTransfers);
         _processEthToZecTransfers(update.ethToZecTransfers);
+        _processRefunds(update.refunds);
+        _processWithdrawalRefunds(update.withdrawalRefunds);
+        _emitQuarantine(update);
+    }
+
+    /// @notice Processes transfers as a state update would, without the state checks, and reverts.
+    /// @dev Meant for `eth_call`, to find the transfers making an update revert. Always reverts,
+    /// with `TransfersSimulated` if every transfer is processed.
+    function simulateTransfers(
+        ProcessedZecToEthTransfer[] calldata zecToEthTransfers,
+        ProcessedEthToZecTransfer[] calldata ethToZecTransfers,
+        ProcessedRefund[] calldata refunds,
+        ProcessedEthToZecTransfer[] calldata withdrawalRefunds
+    ) external {
+        _processZecToEthTransfers(zecToEthTransfers);
+        _processEthToZecTransfers(ethToZecTransfers);
+        _processRefunds(refunds);
+        _processWithdrawalRefunds(withdrawalRefunds);
+        revert TransfersSimulated();
+    }
+
+    /// @notice Check that a deposit was processed, given a proof against a state root of the bridge.
+    /// @param to Recipient of the deposit.
+    /// @param amount Credited amount in zatoshis.
+    /// @param proof Proof as served by the relayer API.
+    /// @return Whether the proof is valid against a state root stored by the bridge.
+    function verifyDepositProof(address to, uint64 amount, StateProofs.TransferProof calldata proof)
+        external
+        view
+        returns (bool)
+    {
+        return isStateRoot[proof.stateRoot] && StateProofs.verifyDeposit(to, amount, proof);
+    }
+
+    /// @notice Check that a withdrawal was processed, given a proof against a state root of the bridge.
+    /// @param receiverType Kind of the Zcash receiver.
+    /// @param receiver Raw receiver bytes.
+    /// @param amount Withdrawn amount in zatoshis.
+    /// @param proof Proof as served by the relayer API.
+    /// @return Whether the proof is valid against a state root stored by the bridge.
+    function verifyWithdrawalProof(
+        ReceiverType receiverType,
+        bytes calldata receiver,
+        uint64 amount,
+        StateProofs.TransferProof calldata proof
+    ) external view returns (bool) {
+        return isStateRoot[proof.stateRoot] && StateProofs.verifyWithdrawal(receiverType, receiver, amount, proof);
+    }
+
+    function _checkSignatures(bytes32 commitment, bytes[] calldata signatures) internal view {
+        if (signatures.length < committeeThreshold) revert InsufficientSignatures(committeeThreshold);
+        bytes32 digest = MessageHashUtils.toEthSignedMessageHash(computeSignedDigest(commitment));
+        address previous;
+        for (uint256 i; i < signatures.length; ++i) {
+            address signer = ECDSA.recover(digest, signatures[i]);
+            if (!isCommitteeMember[signer]) revert InvalidSignature(signer);
+            // Ascending order rules out counting a member twice.
+            if (signer <= previous) revert SignersNotSorted();
+            previous = signer;
+        }
     }
 
     function _validateStateTransition(StateUpdate calldata update) internal {
@@ -164,6 +470,9 @@ contract ZcashBridge {
         if (update.newEthBlockNumber <= update.previousEthBlockNumber) revert InvalidBlockNumber();
         if (update.newZecBlockNumber <= update.previousZecBlockNumber) revert InvalidBlockNumber();
 
+        bytes32 commitment = computeCommitment(update);
+        if (update.commitment != commitment) revert InvalidCommitment(commitment);
+
         emit StateUpdated(
             update.previousEthRoot,
             update.newEthRoot,
@@ -172,7 +481,8 @@ contract ZcashBridge {
             update.previousZecRoot,
             update.newZecRoot,
             update.previousZecBlockNumber,
-            update.newZecBlockNumber
+            update.newZecBlockNumber,
+            commitment
         );
 
         latestState = BridgeState({
@@ -181,6 +491,41 @@ contract ZcashBridge {
             zecRoot: update.newZecRoot,
             zecBlockNumber: update.newZecBlockNumber
         });
+        latestCommitment = commitment;
+    }
+
+    /// @dev Appends the transfers of `update` to the transfer logs and stores the resulting state root.
+    function _updateStateRoot(StateUpdate calldata update) internal returns (bytes32 stateRoot) {
+        for (uint256 i; i < update.zecToEthTransfers.length; ++i) {
+            ProcessedZecToEthTransfer calldata transferData = update.zecToEthTransfers[i];
+            uint64 amount = _zatoshis(transferData.amount);
+            depositLog.push(StateProofs.depositLeaf(transferData.to, amount), amount);
+        }
+        for (uint256 i; i < update.ethToZecTransfers.length; ++i) {
+            ProcessedEthToZecTransfer calldata transferData = update.ethToZecTransfers[i];
+            uint64 amount = _zatoshis(transferData.amount);
+            withdrawalLog.push(
+                StateProofs.withdrawalLeaf(transferData.receiverType, transferData.receiver, amount), amount
+            );
+        }
+
+        stateRoot = StateProofs.stateRoot(
+            [
+                StateProofs.checkpointLeaf(update.newEthBlockNumber, update.newEthRoot),
+                StateProofs.checkpointLeaf(update.newZecBlockNumber, update.newZecRoot),
+                depositLog.transferLogLeaf(),
+                withdrawalLog.transferLogLeaf(),
+                StateProofs.hashLeaf(abi.encodePacked(update.commitment))
+            ]
+        );
+        latestStateRoot = stateRoot;
+        isStateRoot[stateRoot] = true;
+    }
+
+    /// @dev Amounts are zatoshis, which the state tree stores as `uint64`.
+    function _zatoshis(uint256 amount) internal pure returns (uint64) {
+        if (amount > type(uint64).max) revert AmountTooLarge();
+        return uint64(amount);
     }
 
     function _processZecToEthTransfers(ProcessedZecToEthTransfer[] calldata transfers) internal {
@@ -197,13 +542,38 @@ contract ZcashBridge {
         }
     }
 
+    function _requestWithdrawal(uint256 amount, ReceiverType receiverType, bytes memory receiver)
+        internal
+        returns (uint256 requestId)
+    {
+        if (amount == 0) revert ZeroAmount();
+
+        token.transferFrom(msg.sender, address(this), amount);
+        totalLocked += amount;
+
+        requestId = nextWithdrawalId++;
+        withdrawalRequests[requestId] = WithdrawalRequest({
+            requester: msg.sender,
+            amount: amount,
+            receiverType: receiverType,
+            receiver: receiver,
+            processed: false
+        });
+
+        bytes32 key = computeWithdrawalKey(amount, receiverType, receiver);
+        pendingWithdrawalIds[key].push(requestId);
+
+        emit WithdrawalRequested(requestId, msg.sender, amount, receiverType, receiver);
+    }
+
     function _processEthToZecTransfers(ProcessedEthToZecTransfer[] calldata transfers) internal {
         uint256 length = transfers.length;
         for (uint256 i; i < length; ++i) {
             ProcessedEthToZecTransfer calldata transferData = transfers[i];
             if (transferData.amount == 0) revert ZeroAmount();
-            if (transferData.pubkeyHash == bytes20(0)) revert EmptyPubkeyHash();
-            uint256 requestId = _popNextWithdrawal(transferData.amount, transferData.pubkeyHash);
+            if (transferData.receiver.length == 0) revert InvalidReceiver();
+            uint256 requestId =
+                _popNextWithdrawal(transferData.amount, transferData.receiverType, transferData.receiver);
             WithdrawalRequest storage request = withdrawalRequests[requestId];
             if (request.processed) revert WithdrawalAlreadyProcessed(requestId);
             request.processed = true;
@@ -212,12 +582,72 @@ contract ZcashBridge {
             totalBurned += request.amount;
             token.burn(request.amount);
 
-            emit WithdrawalProcessed(requestId, request.amount, request.pubkeyHash);
+            emit WithdrawalProcessed(requestId, request.amount, request.receiverType, request.receiver);
+        }
+    }
+
+    /// @dev Refunds are paid on Zcash from the refunded deposits, so nothing is minted or burned.
+    function _processRefunds(ProcessedRefund[] calldata refunds) internal {
+        uint256 length = refunds.length;
+        for (uint256 i; i < length; ++i) {
+            ProcessedRefund calldata refund = refunds[i];
+            if (refund.amount == 0) revert ZeroAmount();
+            if (refund.receiver.length == 0) revert InvalidReceiver();
+            emit DepositRefunded(refund.depositTxid, refund.amount, refund.receiverType, refund.receiver);
+        }
+    }
+
+    /// @dev Returns the tokens locked by each withdrawal to its requester, which the relayer never pays on Zcash.
+    function _processWithdrawalRefunds(ProcessedEthToZecTransfer[] calldata refunds) internal {
+        uint256 length = refunds.length;
+        for (uint256 i; i < length; ++i) {
+            ProcessedEthToZecTransfer calldata refund = refunds[i];
+            uint256 requestId = _popNextWithdrawal(refund.amount, refund.receiverType, refund.receiver);
+            WithdrawalRequest storage request = withdrawalRequests[requestId];
+            if (request.processed) revert WithdrawalAlreadyProcessed(requestId);
+            request.processed = true;
+
+            totalLocked -= request.amount;
+            token.transfer(request.requester, request.amount);
+
+            emit WithdrawalRefunded(
+                requestId, request.requester, request.amount, request.receiverType, request.receiver
+            );
+        }
+    }
+
+    /// @dev Publishes the quarantine of `update`, so that verifiers can tell the transfers left out on purpose.
+    function _emitQuarantine(StateUpdate calldata update) internal {
+        for (uint256 i; i < update.quarantinedDeposits.length; ++i) {
+            QuarantinedDeposit calldata deposit = update.quarantinedDeposits[i];
+            emit DepositQuarantined(deposit.txid, deposit.index);
+        }
+        for (uint256 i; i < update.quarantinedWithdrawals.length; ++i) {
+            ProcessedEthToZecTransfer calldata transferData = update.quarantinedWithdrawals[i];
+            emit WithdrawalQuarantined(transferData.amount, transferData.receiverType, transferData.receiver);
+        }
+    }
+
+    function _encodeWithdrawals(bytes memory encoded, ProcessedEthToZecTransfer[] calldata transfers)
+        internal
+        pure
+        returns (bytes memory)
+    {
+        encoded = abi.encodePacked(encoded, uint32(transfers.length));
+        for (uint256 i; i < transfers.length; ++i) {
+            ProcessedEthToZecTransfer calldata transferData = transfers[i];
+            encoded = abi.encodePacked(
+                encoded, uint8(transferData.receiverType), transferData.receiver, transferData.amount
+            );
         }
+        return encoded;
     }
 
-    function _popNextWithdrawal(uint256 amount, bytes20 pubkeyHash) internal returns (uint256 requestId) {
-        bytes32 key = computeWithdrawalKey(amount, pubkeyHash);
+    function _popNextWithdrawal(uint256 amount, ReceiverType receiverType, bytes calldata receiver)
+        internal
+        returns (uint256 requestId)
+    {
+        bytes32 key = computeWithdrawalKey(amount, receiverType, receiver);
         uint256 cursor = pendingWithdrawalIndex[key];
         uint256[] storage queue = pendingWithdrawalIds[key];
         if (cursor >= queue.length) revert WithdrawalNotFound(key);
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {Test} from "forge-std-1.12.0/Test.sol";
+
+import {StateProofs} from "src/StateProofs.sol";
+
+contract StateProofsTest is Test {
+    using StateProofs for StateProofs.TransferLog;
+
+    StateProofs.TransferLog internal log;
+
+    /// @dev Root over `leaves` computed level by level, like `merkle::root`.
+    function _root(bytes32[] memory leaves) internal pure returns (bytes32) {
+        if (leaves.length == 0) {
+            return bytes32(0);
+        }
+        while (leaves.length > 1) {
+            bytes32[] memory next = new bytes32[]((leaves.length + 1) / 2);
+            for (uint256 i = 0; i < next.length; i++) {
+                bytes32 right = 2 * i + 1 < leaves.length ? leaves[2 * i + 1] : bytes32(0);
+                next[i] = StateProofs.hashNode(leaves[2 * i], right);
+            }
+            leaves = next;
+        }
+        return leaves[0];
+    }
+
+    function _stateProof(bytes32 logLeaf, bytes32[5] memory leaves, uint64 index)
+        internal
+        pure
+        returns (StateProofs.MerkleProof memory proof, bytes32 root)
+    {
+        leaves[index] = logLeaf;
+        bytes32 n01 = StateProofs.hashNode(leaves[0], leaves[1]);
+        bytes32 n23 = StateProofs.hashNode(leaves[2], leaves[3]);
+        bytes32 n4 = StateProofs.hashNode(leaves[4], bytes32(0));
+        bytes32 n0123 = StateProofs.hashNode(n01, n23);
+        bytes32 n4z = StateProofs.hashNode(n4, bytes32(0));
+        root = StateProofs.hashNode(n0123, n4z);
+
+        proof.index = index;
+        proof.siblings = new bytes32[](3);
+        proof.siblings[0] = index == 2 ? leaves[3] : leaves[2];
+        proof.siblings[1] = n01;
+        proof.siblings[2] = n4z;
+    }
+
+    function test_EmptyStateRoot() public pure {
+        bytes32 checkpoint = StateProofs.hashLeaf(abi.encodePacked(uint64(0), bytes32(0)));
+        bytes32 log = StateProofs.hashLeaf(abi.encodePacked(uint64(0), uint64(0), bytes32(0)));
+        bytes32 lastUpdate = StateProofs.hashLeaf(abi.encodePacked(bytes32(0)));
+        bytes32[5] memory leaves = [checkpoint, checkpoint, log, log, lastUpdate];
+        (, bytes32 root) = _stateProof(log, leaves, 2);
+        assertEq(root, StateProofs.EMPTY_STATE_ROOT);
+    }
+
+    function test_TransferLogRoot() public {
+        bytes32[] memory leaves = new bytes32[](9);
+        assertEq(log.root(), bytes32(0));
+        for (uint256 i = 0; i < leaves.length; i++) {
+            leaves[i] = StateProofs.depositLeaf(address(uint160(i + 1)), uint64(i + 1));
+            log.push(leaves[i], uint64(i + 1));
+
+            bytes32[] memory pushed = new bytes32[](i + 1);
+            for (uint256 j = 0; j <= i; j++) {
+                pushed[j] = leaves[j];
+            }
+            assertEq(log.root(), _root(pushed), "Root mismatch");
+            assertEq(log.count, i + 1);
+            assertEq(log.total, (i + 1) * (i + 2) / 2);
+        }
+    }
+
+    function test_VerifyDeposit() public pure {
+        address to = address(0xBEEF);
+        bytes32 first = StateProofs.depositLeaf(to, 90_000);
+        bytes32 second = StateProofs.depositLeaf(address(0xCAFE), 10_000);
+        bytes32 logRoot = StateProofs.hashNode(first, second);
+        bytes32 logLeaf = StateProofs.hashLeaf(abi.encodePacked(uint64(2), uint64(100_000), logRoot));
+
+        bytes32[5] memory leaves;
+        for (uint256 i = 0; i < 5; i++) {
+            leaves[i] = StateProofs.hashLeaf(abi.encodePacked(i));
+        }
+        (StateProofs.MerkleProof memory logProof, bytes32 stateRoot) = _stateProof(logLeaf, leaves, 2);
+
+        StateProofs.TransferProof memory proof;
+        proof.transfer.index = 0;
+        proof.transfer.siblings = new bytes32[](1);
+        proof.transfer.siblings[0] = second;
+        proof.logCount = 2;
+        proof.logTotal = 100_000;
+        proof.log = logProof;
+        proof.stateRoot = stateRoot;
+
+        assertTrue(StateProofs.verifyDeposit(to, 90_000, proof), "Valid proof rejected");
+        assertFalse(StateProofs.verifyDeposit(to, 90_001, proof), "Wrong amount accepted");
+
+        // The same leaf must not verify as a withdrawal log entry.
+        proof.log.index = 3;
+        assertFalse(StateProofs.verifyDeposit(to, 90_000, proof), "Wrong log accepted");
+    }
+}
@@ -3,6 +3,9 @@ pragma solidity ^0.8.20;
 
 import {Test} from "forge-std-1.12.0/Test.sol";
 
+import {MockStateTransitionVerifier} from "src/MockStateTransitionVerifier.sol";
+import {ISP1Verifier, SP1StateTransitionVerifier} from "src/SP1StateTransitionVerifier.sol";
+import {StateProofs} from "src/StateProofs.sol";
 import {ZcashBridge} from "src/ZcashBridge.sol";
 import {WZec} from "src/WZec.sol";
 
@@ -11,6 +14,7 @@ contract ZcashBridgeTest is Test {
     ZcashBridge internal bridge;
 
     address internal constant user = address(0xBEEF);
+    bytes32 internal constant genesisRoot = StateProofs.EMPTY_STATE_ROOT;
 
     bytes32 internal currentEthRoot;
     uint64 internal currentEthBlock;
@@ -65,7 +69,11 @@ contract ZcashBridgeTest is Test {
         vm.stopPrank();
 
         ZcashBridge.ProcessedEthToZecTransfer[] memory burns = new ZcashBridge.ProcessedEthToZecTransfer[](1);
-        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({amount: amount, pubkeyHash: pubkeyHash});
+        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: amount,
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(pubkeyHash)
+        });
 
         _applyStateUpdate(_emptyMints(), burns);
 
@@ -77,6 +85,67 @@ contract ZcashBridgeTest is Test {
         assertEq(bridge.totalBurned(), amount, "Burn stats incorrect");
     }
 
+    function test_SubmitStateUpdate_ProcessesShieldedWithdrawal() public {
+        uint256 amount = 3e8;
+        _applyStateUpdate(_singleMint(user, amount), _emptyBurns());
+
+        bytes memory receiver = _shieldedReceiver(user);
+        vm.startPrank(user);
+        token.approve(address(bridge), amount);
+        uint256 requestId = bridge.requestShieldedWithdrawal(amount, ZcashBridge.ReceiverType.Orchard, receiver);
+        vm.stopPrank();
+
+        ZcashBridge.ProcessedEthToZecTransfer[] memory burns = new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: amount,
+            receiverType: ZcashBridge.ReceiverType.Orchard,
+            receiver: receiver
+        });
+
+        _applyStateUpdate(_emptyMints(), burns);
+
+        ZcashBridge.WithdrawalRequest memory request = bridge.getWithdrawalRequest(requestId);
+        assertEq(request.processed, true, "Withdrawal was not processed");
+        assertEq(request.receiver, receiver, "Receiver mismatch");
+        assertEq(bridge.totalBurned(), amount, "Burn stats incorrect");
+    }
+
+    function test_RevertWhen_ShieldedReceiverMalformed() public {
+        uint256 amount = 1e8;
+        _applyStateUpdate(_singleMint(user, amount), _emptyBurns());
+
+        vm.startPrank(user);
+        token.approve(address(bridge), amount);
+        vm.expectRevert(ZcashBridge.InvalidReceiver.selector);
+        bridge.requestShieldedWithdrawal(amount, ZcashBridge.ReceiverType.Sapling, hex"1234");
+        vm.expectRevert(ZcashBridge.InvalidReceiver.selector);
+        bridge.requestShieldedWithdrawal(amount, ZcashBridge.ReceiverType.P2PKH, _shieldedReceiver(user));
+        vm.stopPrank();
+    }
+
+    function test_RevertWhen_WithdrawalPoolMismatch() public {
+        uint256 amount = 2e8;
+        _applyStateUpdate(_singleMint(user, amount), _emptyBurns());
+
+        bytes memory receiver = _shieldedReceiver(user);
+        vm.startPrank(user);
+        token.approve(address(bridge), amount);
+        bridge.requestShieldedWithdrawal(amount, ZcashBridge.ReceiverType.Sapling, receiver);
+        vm.stopPrank();
+
+        // The same raw receiver in another pool is a different withdrawal.
+        ZcashBridge.ProcessedEthToZecTransfer[] memory burns = new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: amount,
+            receiverType: ZcashBridge.ReceiverType.Orchard,
+            receiver: receiver
+        });
+        bytes32 key = bridge.computeWithdrawalKey(amount, ZcashBridge.ReceiverType.Orchard, receiver);
+
+        vm.expectRevert(abi.encodeWithSelector(ZcashBridge.WithdrawalNotFound.selector, key));
+        _applyStateUpdate(_emptyMints(), burns);
+    }
+
     function test_RevertWhen_StateMismatch() public {
         _applyStateUpdate(_singleMint(user, 1e8), _emptyBurns());
 
@@ -90,16 +159,402 @@ contract ZcashBridgeTest is Test {
             newZecRoot: bytes32(uint256(444)),
             newZecBlockNumber: currentZecBlock + 1,
             zecToEthTransfers: _emptyMints(),
-            ethToZecTransfers: _emptyBurns()
+            ethToZecTransfers: _emptyBurns(),
+            refunds: _emptyRefunds(),
+            withdrawalRefunds: _emptyBurns(),
+            quarantinedDeposits: _emptyQuarantinedDeposits(),
+            quarantinedWithdrawals: _emptyBurns(),
+            commitment: bytes32(0)
         });
+        badUpdate.commitment = bridge.computeCommitment(badUpdate);
 
         vm.expectRevert(ZcashBridge.InvalidPreviousState.selector);
         bridge.submitStateUpdate(badUpdate);
     }
 
-    function testFuzz_RequestWithdrawal(uint96 fuzzAmount, bytes20 pubkeyHash) public {
+    function test_RevertWhen_CommitmentMismatch() public {
+        _applyStateUpdate(_singleMint(user, 1e8), _emptyBurns());
+
+        ZcashBridge.StateUpdate memory update = ZcashBridge.StateUpdate({
+            previousEthRoot: currentEthRoot,
+            previousEthBlockNumber: currentEthBlock,
+            newEthRoot: bytes32(uint256(333)),
+            newEthBlockNumber: currentEthBlock + 1,
+            previousZecRoot: currentZecRoot,
+            previousZecBlockNumber: currentZecBlock,
+            newZecRoot: bytes32(uint256(444)),
+            newZecBlockNumber: currentZecBlock + 1,
+            zecToEthTransfers: _emptyMints(),
+            ethToZecTransfers: _emptyBurns(),
+            refunds: _emptyRefunds(),
+            withdrawalRefunds: _emptyBurns(),
+            quarantinedDeposits: _emptyQuarantinedDeposits(),
+            quarantinedWithdrawals: _emptyBurns(),
+            commitment: bytes32(0)
+        });
+        bytes32 commitment = bridge.computeCommitment(update);
+
+        // Commitment to a different set of transfers.
+        update.zecToEthTransfers = _singleMint(user, 1e8);
+
+        update.commitment = commitment;
+        vm.expectRevert(
+            abi.encodeWithSelector(ZcashBridge.InvalidCommitment.selector, bridge.computeCommitment(update))
+        );
+        bridge.submitStateUpdate(update);
+    }
+
+    function test_SubmitStateUpdateWithProof_TracksStateRoot() public {
+        bridge.setVerifier(address(new MockStateTransitionVerifier()));
+
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bytes32 oldRoot = genesisRoot;
+        bytes32 newRoot = _firstStateRoot(update);
+        bridge.submitStateUpdateWithProof(update, oldRoot, newRoot, _mockProof(oldRoot, newRoot, update.commitment));
+
+        assertEq(bridge.latestStateRoot(), newRoot, "State root not updated");
+        assertTrue(bridge.isStateRoot(newRoot), "State root not stored");
+        assertEq(token.balanceOf(user), 1e8, "Mint did not credit recipient");
+
+        // Unproven updates are no longer accepted.
+        vm.expectRevert(ZcashBridge.ProofRequired.selector);
+        bridge.submitStateUpdate(update);
+    }
+
+    function test_RevertWhen_FirstProofSkipsGenesisRoot() public {
+        bridge.setVerifier(address(new MockStateTransitionVerifier()));
+
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bytes32 oldRoot = bytes32(uint256(12));
+        bytes32 newRoot = bytes32(uint256(13));
+        bytes memory proof = _mockProof(oldRoot, newRoot, update.commitment);
+
+        vm.expectRevert(abi.encodeWithSelector(ZcashBridge.InvalidPreviousStateRoot.selector, genesisRoot));
+        bridge.submitStateUpdateWithProof(update, oldRoot, newRoot, proof);
+    }
+
+    function test_RevertWhen_ProvenStateRootDiffers() public {
+        bridge.setVerifier(address(new MockStateTransitionVerifier()));
+
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bytes32 oldRoot = genesisRoot;
+        // A valid proof of a root that the processed transfers do not lead to.
+        bytes32 newRoot = bytes32(uint256(11));
+        bytes memory proof = _mockProof(oldRoot, newRoot, update.commitment);
+
+        vm.expectRevert(abi.encodeWithSelector(ZcashBridge.InvalidNewStateRoot.selector, _firstStateRoot(update)));
+        bridge.submitStateUpdateWithProof(update, oldRoot, newRoot, proof);
+    }
+
+    function test_SubmitStateUpdate_StoresStateRoot() public {
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bridge.submitStateUpdate(update);
+        bytes32 stateRoot = _firstStateRoot(update);
+        assertEq(bridge.latestStateRoot(), stateRoot, "State root not updated");
+
+        // The only deposit is the root of the deposit log, which is leaf 2 of the state tree.
+        StateProofs.TransferProof memory proof;
+        proof.logCount = 1;
+        proof.logTotal = 1e8;
+        proof.log.index = 2;
+        proof.log.siblings = new bytes32[](3);
+        proof.log.siblings[0] = _emptyLogLeaf();
+        proof.log.siblings[1] = StateProofs.hashNode(
+            StateProofs.checkpointLeaf(update.newEthBlockNumber, update.newEthRoot),
+            StateProofs.checkpointLeaf(update.newZecBlockNumber, update.newZecRoot)
+        );
+        proof.log.siblings[2] = StateProofs.hashNode(
+            StateProofs.hashNode(StateProofs.hashLeaf(abi.encodePacked(update.commitment)), bytes32(0)), bytes32(0)
+        );
+        proof.stateRoot = stateRoot;
+        assertTrue(bridge.verifyDepositProof(user, 1e8, proof), "Valid proof rejected");
+        assertFalse(bridge.verifyDepositProof(user, 1e8 + 1, proof), "Wrong amount accepted");
+
+        // A self-consistent proof against a root the bridge never had.
+        proof.log.siblings[2] = bytes32(0);
+        bytes32 depositLogLeaf = StateProofs.hashLeaf(
+            abi.encodePacked(uint64(1), uint64(1e8), StateProofs.depositLeaf(user, 1e8))
+        );
+        proof.stateRoot = StateProofs.hashNode(
+            StateProofs.hashNode(proof.log.siblings[1], StateProofs.hashNode(depositLogLeaf, _emptyLogLeaf())),
+            bytes32(0)
+        );
+        assertTrue(StateProofs.verifyDeposit(user, 1e8, proof), "Forged proof must be self-consistent");
+        assertFalse(bridge.verifyDepositProof(user, 1e8, proof), "Proof against unknown root accepted");
+    }
+
+    function test_RevertWhen_ProofInvalid() public {
+        bridge.setVerifier(address(new MockStateTransitionVerifier()));
+
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bytes32 oldRoot = genesisRoot;
+        // A proof of a different transition.
+        bytes memory proof = _mockProof(oldRoot, bytes32(uint256(12)), update.commitment);
+
+        vm.expectRevert(MockStateTransitionVerifier.InvalidProof.selector);
+        bridge.submitStateUpdateWithProof(update, oldRoot, bytes32(uint256(11)), proof);
+    }
+
+    function test_SP1Verifier_ChecksProofsOfTheProgram() public {
+        address gateway = address(0x5B1);
+        bytes32 programVKey = bytes32(uint256(0x1234));
+        bytes memory publicInputs = abi.encode(bytes32(uint256(1)), bytes32(uint256(2)), bytes32(uint256(3)));
+        bytes memory proof = hex"abcd";
+        bytes memory verifyCall = abi.encodeCall(ISP1Verifier.verifyProof, (programVKey, publicInputs, proof));
+        SP1StateTransitionVerifier verifier = new SP1StateTransitionVerifier(gateway, programVKey);
+
+        vm.mockCall(gateway, verifyCall, "");
+        vm.expectCall(gateway, verifyCall);
+        verifier.verifyProof(publicInputs, proof);
+
+        vm.mockCallRevert(gateway, verifyCall, "invalid proof");
+        vm.expectRevert("invalid proof");
+        verifier.verifyProof(publicInputs, proof);
+    }
+
+    function test_RevertWhen_SetVerifierTwiceOrByOthers() public {
+        MockStateTransitionVerifier verifier = new MockStateTransitionVerifier();
+
+        vm.prank(user);
+        vm.expectRevert(ZcashBridge.Unauthorized.selector);
+        bridge.setVerifier(address(verifier));
+
+        bridge.setVerifier(address(verifier));
+        vm.expectRevert(ZcashBridge.VerifierAlreadySet.selector);
+        bridge.setVerifier(address(verifier));
+    }
+
+    function test_SubmitStateUpdateWithSignatures_RequiresThreshold() public {
+        (address[] memory members, uint256[] memory keys) = _committee();
+        bridge.setCommittee(members, 2);
+
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bytes32 digest = _ethSignedDigest(update.commitment);
+
+        bytes[] memory signatures = new bytes[](1);
+        signatures[0] = _sign(keys[0], digest);
+        vm.expectRevert(abi.encodeWithSelector(ZcashBridge.InsufficientSignatures.selector, 2));
+        bridge.submitStateUpdateWithSignatures(update, signatures);
+
+        signatures = new bytes[](2);
+        signatures[0] = _sign(keys[1], digest);
+        signatures[1] = _sign(keys[0], digest);
+        vm.expectRevert(ZcashBridge.SignersNotSorted.selector);
+        bridge.submitStateUpdateWithSignatures(update, signatures);
+
+        vm.expectRevert(ZcashBridge.SignaturesRequired.selector);
+        bridge.submitStateUpdate(update);
+
+        signatures[0] = _sign(keys[0], digest);
+        signatures[1] = _sign(keys[1], digest);
+        bridge.submitStateUpdateWithSignatures(update, signatures);
+        assertEq(token.balanceOf(user), 1e8, "Mint did not credit recipient");
+    }
+
+    function test_RevertWhen_SignerNotInCommittee() public {
+        (address[] memory members,) = _committee();
+        bridge.setCommittee(members, 1);
+
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_singleMint(user, 1e8));
+        bytes[] memory signatures = new bytes[](1);
+        signatures[0] = _sign(0xBAD, _ethSignedDigest(update.commitment));
+
+        vm.expectRevert(abi.encodeWithSelector(ZcashBridge.InvalidSignature.selector, vm.addr(0xBAD)));
+        bridge.submitStateUpdateWithSignatures(update, signatures);
+    }
+
+    function test_EncodeStateUpdate_Layout() public view {
+        // Same update, encoding and commitment as the test vector in `src/types.rs`.
+        ZcashBridge.ProcessedEthToZecTransfer[] memory burns = new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: 50_000,
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(bytes20(hex"1111111111111111111111111111111111111111"))
+        });
+        ZcashBridge.ProcessedRefund[] memory refunds = new ZcashBridge.ProcessedRefund[](1);
+        refunds[0] = ZcashBridge.ProcessedRefund({
+            amount: 5_000,
+            depositTxid: bytes32(uint256(0x55)),
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(bytes20(hex"4444444444444444444444444444444444444444"))
+        });
+        ZcashBridge.ProcessedEthToZecTransfer[] memory withdrawalRefunds = new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        withdrawalRefunds[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: 8_000,
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(bytes20(hex"8888888888888888888888888888888888888888"))
+        });
+        ZcashBridge.QuarantinedDeposit[] memory quarantinedDeposits = new ZcashBridge.QuarantinedDeposit[](1);
+        quarantinedDeposits[0] = ZcashBridge.QuarantinedDeposit({txid: bytes32(uint256(0x66)), index: 1});
+        ZcashBridge.ProcessedEthToZecTransfer[] memory quarantinedWithdrawals =
+            new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        quarantinedWithdrawals[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: 7_000,
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(bytes20(hex"7777777777777777777777777777777777777777"))
+        });
+        ZcashBridge.StateUpdate memory update = ZcashBridge.StateUpdate({
+            previousEthRoot: bytes32(uint256(1)),
+            previousEthBlockNumber: 2,
+            newEthRoot: bytes32(uint256(3)),
+            newEthBlockNumber: 4,
+            previousZecRoot: bytes32(uint256(5)),
+            previousZecBlockNumber: 6,
+            newZecRoot: bytes32(uint256(7)),
+            newZecBlockNumber: 8,
+            zecToEthTransfers: _singleMint(address(bytes20(hex"3333333333333333333333333333333333333333")), 90_000),
+            ethToZecTransfers: burns,
+            refunds: refunds,
+            withdrawalRefunds: withdrawalRefunds,
+            quarantinedDeposits: quarantinedDeposits,
+            quarantinedWithdrawals: quarantinedWithdrawals,
+            commitment: bytes32(0)
+        });
+
+        bytes memory expected =
+            hex"0300000000000000020000000000000004000000000000000000000000000000"
+            hex"0000000000000000000000000000000001000000000000000000000000000000"
+            hex"0000000000000000000000000000000003000000000000000600000000000000"
+            hex"0800000000000000000000000000000000000000000000000000000000000000"
+            hex"0500000000000000000000000000000000000000000000000000000000000000"
+            hex"0700000001333333333333333333333333333333333333333300000000000000"
+            hex"00000000000000000000000000000000000000000000015f9000000001001111"
+            hex"1111111111111111111111111111111111110000000000000000000000000000"
+            hex"00000000000000000000000000000000c3500000000100000000000000000000"
+            hex"0000000000000000000000000000000000000000005500444444444444444444"
+            hex"4444444444444444444444000000000000000000000000000000000000000000"
+            hex"0000000000000000001388000000010088888888888888888888888888888888"
+            hex"8888888800000000000000000000000000000000000000000000000000000000"
+            hex"00001f4000000001000000000000000000000000000000000000000000000000"
+            hex"0000000000000066000000010000000100777777777777777777777777777777"
+            hex"7777777777000000000000000000000000000000000000000000000000000000"
+            hex"0000001b58";
+        assertEq(bridge.encodeStateUpdate(update), expected, "Encoding mismatch");
+        assertEq(
+            bridge.computeCommitment(update),
+            bytes32(hex"e404d6112bf2a836b3c8bd9574136bc2ce84e9ea075b190e3968136c382e0f59"),
+            "Commitment mismatch"
+        );
+        assertEq(uint8(expected[0]), bridge.STATE_UPDATE_ENCODING_VERSION(), "Version mismatch");
+    }
+
+    function test_SubmitStateUpdate_EmitsRefundsWithoutMinting() public {
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_emptyMints());
+        update.refunds = new ZcashBridge.ProcessedRefund[](1);
+        update.refunds[0] = ZcashBridge.ProcessedRefund({
+            amount: 5_000,
+            depositTxid: bytes32(uint256(0xD0)),
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(bytes20(uint160(0xCAFE)))
+        });
+        update.commitment = bridge.computeCommitment(update);
+
+        vm.expectEmit(address(bridge));
+        emit ZcashBridge.DepositRefunded(
+            bytes32(uint256(0xD0)), 5_000, ZcashBridge.ReceiverType.P2PKH, abi.encodePacked(bytes20(uint160(0xCAFE)))
+        );
+        bridge.submitStateUpdate(update);
+        assertEq(token.totalSupply(), 0, "Refunds must not mint");
+
+        // The refunds are bound by the commitment.
+        assertTrue(bridge.computeCommitment(update) != bridge.computeCommitment(_firstUpdate(_emptyMints())));
+    }
+
+    function test_SubmitStateUpdate_EmitsQuarantine() public {
+        ZcashBridge.StateUpdate memory update = _firstUpdate(_emptyMints());
+        update.quarantinedDeposits = new ZcashBridge.QuarantinedDeposit[](1);
+        update.quarantinedDeposits[0] = ZcashBridge.QuarantinedDeposit({txid: bytes32(uint256(0xD1)), index: 2});
+        update.quarantinedWithdrawals = new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        update.quarantinedWithdrawals[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: 5_000,
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(bytes20(uint160(0xCAFE)))
+        });
+        update.commitment = bridge.computeCommitment(update);
+
+        vm.expectEmit(address(bridge));
+        emit ZcashBridge.DepositQuarantined(bytes32(uint256(0xD1)), 2);
+        vm.expectEmit(address(bridge));
+        emit ZcashBridge.WithdrawalQuarantined(
+            5_000, ZcashBridge.ReceiverType.P2PKH, abi.encodePacked(bytes20(uint160(0xCAFE)))
+        );
+        bridge.submitStateUpdate(update);
+
+        // The quarantine is bound by the commitment.
+        assertTrue(bridge.computeCommitment(update) != bridge.computeCommitment(_firstUpdate(_emptyMints())));
+    }
+
+    function test_SubmitStateUpdate_RefundsWithdrawal() public {
+        uint256 amount = 2e8;
+        _applyStateUpdate(_singleMint(user, amount), _emptyBurns());
+
+        bytes20 pubkeyHash = bytes20(keccak256(abi.encodePacked(user)));
+        vm.startPrank(user);
+        token.approve(address(bridge), amount);
+        uint256 requestId = bridge.requestWithdrawal(amount, pubkeyHash);
+        vm.stopPrank();
+
+        ZcashBridge.ProcessedEthToZecTransfer[] memory withdrawalRefunds = new ZcashBridge.ProcessedEthToZecTransfer[](1);
+        withdrawalRefunds[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: amount,
+            receiverType: ZcashBridge.ReceiverType.P2PKH,
+            receiver: abi.encodePacked(pubkeyHash)
+        });
+        ZcashBridge.StateUpdate memory update = ZcashBridge.StateUpdate({
+            previousEthRoot: currentEthRoot,
+            previousEthBlockNumber: currentEthBlock,
+            newEthRoot: bytes32(uint256(333)),
+            newEthBlockNumber: currentEthBlock + 1,
+            previousZecRoot: currentZecRoot,
+            previousZecBlockNumber: currentZecBlock,
+            newZecRoot: bytes32(uint256(444)),
+            newZecBlockNumber: currentZecBlock + 1,
+            zecToEthTransfers: _emptyMints(),
+            ethToZecTransfers: _emptyBurns(),
+            refunds: _emptyRefunds(),
+            withdrawalRefunds: withdrawalRefunds,
+            quarantinedDeposits: _emptyQuarantinedDeposits(),
+            quarantinedWithdrawals: _emptyBurns(),
+            commitment: bytes32(0)
+        });
+        update.commitment = bridge.computeCommitment(update);
+
+        vm.expectEmit(address(bridge));
+        emit ZcashBridge.WithdrawalRefunded(
+            requestId, user, amount, ZcashBridge.ReceiverType.P2PKH, abi.encodePacked(pubkeyHash)
+        );
+        bridge.submitStateUpdate(update);
+        currentEthRoot = update.newEthRoot;
+        currentEthBlock = update.newEthBlockNumber;
+        currentZecRoot = update.newZecRoot;
+        currentZecBlock = update.newZecBlockNumber;
+
+        assertTrue(bridge.getWithdrawalRequest(requestId).processed, "Withdrawal was not settled");
+        assertEq(token.balanceOf(user), amount, "Tokens should be returned to the requester");
+        assertEq(token.totalSupply(), amount, "Refunds must not burn");
+        assertEq(bridge.totalLocked(), 0, "Locked total should decrease");
+        assertEq(bridge.totalBurned(), 0, "Refunds must not count as burned");
+
+        // The request is settled and cannot be paid on Zcash any more.
+        bytes32 key = bridge.computeWithdrawalKey(amount, ZcashBridge.ReceiverType.P2PKH, abi.encodePacked(pubkeyHash));
+        vm.expectRevert(abi.encodeWithSelector(ZcashBridge.WithdrawalNotFound.selector, key));
+        _applyStateUpdate(_emptyMints(), withdrawalRefunds);
+    }
+
+    function test_SimulateTransfers_RevertsWithoutChangingState() public {
+        vm.expectRevert(ZcashBridge.TransfersSimulated.selector);
+        bridge.simulateTransfers(_singleMint(user, 1e8), _emptyBurns(), _emptyRefunds(), _emptyBurns());
+
+        vm.expectRevert(ZcashBridge.InvalidRecipient.selector);
+        bridge.simulateTransfers(_singleMint(address(0), 1e8), _emptyBurns(), _emptyRefunds(), _emptyBurns());
+
+        assertEq(token.totalSupply(), 0, "Simulation must not mint");
+    }
+
+    function testFuzz_RequestWithdrawal(uint64 fuzzAmount, bytes20 pubkeyHash) public {
         vm.assume(pubkeyHash != bytes20(0));
-        uint256 amount = bound(uint256(fuzzAmount), 1, type(uint96).max);
+        // Amounts are zatoshis, which the state tree stores as `uint64`.
+        uint256 amount = bound(uint256(fuzzAmount), 1, type(uint64).max);
 
         _applyStateUpdate(_singleMint(user, amount), _emptyBurns());
 
@@ -112,6 +567,10 @@ contract ZcashBridgeTest is Test {
         assertEq(bridge.totalLocked(), amount, "Locked total mismatch");
     }
 
+    function _shieldedReceiver(address owner) internal pure returns (bytes memory) {
+        return abi.encodePacked(bytes11(keccak256(abi.encodePacked(owner))), keccak256(abi.encodePacked(owner, "pk_d")));
+    }
+
     function _singleMint(address recipient, uint256 amount)
         internal
         pure
@@ -129,6 +588,90 @@ contract ZcashBridgeTest is Test {
         transfers = new ZcashBridge.ProcessedEthToZecTransfer[](0);
     }
 
+    function _emptyRefunds() internal pure returns (ZcashBridge.ProcessedRefund[] memory refunds) {
+        refunds = new ZcashBridge.ProcessedRefund[](0);
+    }
+
+    function _emptyQuarantinedDeposits() internal pure returns (ZcashBridge.QuarantinedDeposit[] memory deposits) {
+        deposits = new ZcashBridge.QuarantinedDeposit[](0);
+    }
+
+    function _firstUpdate(ZcashBridge.ProcessedZecToEthTransfer[] memory mintTransfers)
+        internal
+        view
+        returns (ZcashBridge.StateUpdate memory update)
+    {
+        update = ZcashBridge.StateUpdate({
+            previousEthRoot: bytes32(0),
+            previousEthBlockNumber: 0,
+            newEthRoot: bytes32(uint256(1)),
+            newEthBlockNumber: 1,
+            previousZecRoot: bytes32(0),
+            previousZecBlockNumber: 0,
+            newZecRoot: bytes32(uint256(2)),
+            newZecBlockNumber: 1,
+            zecToEthTransfers: mintTransfers,
+            ethToZecTransfers: _emptyBurns(),
+            refunds: _emptyRefunds(),
+            withdrawalRefunds: _emptyBurns(),
+            quarantinedDeposits: _emptyQuarantinedDeposits(),
+            quarantinedWithdrawals: _emptyBurns(),
+            commitment: bytes32(0)
+        });
+        update.commitment = bridge.computeCommitment(update);
+    }
+
+    function _emptyLogLeaf() internal pure returns (bytes32) {
+        return StateProofs.hashLeaf(abi.encodePacked(uint64(0), uint64(0), bytes32(0)));
+    }
+
+    /// @dev State root after `update` with a single deposit, processed as the first update.
+    function _firstStateRoot(ZcashBridge.StateUpdate memory update) internal pure returns (bytes32) {
+        ZcashBridge.ProcessedZecToEthTransfer memory deposit = update.zecToEthTransfers[0];
+        uint64 amount = uint64(deposit.amount);
+        return StateProofs.stateRoot(
+            [
+                StateProofs.checkpointLeaf(update.newEthBlockNumber, update.newEthRoot),
+                StateProofs.checkpointLeaf(update.newZecBlockNumber, update.newZecRoot),
+                StateProofs.hashLeaf(abi.encodePacked(uint64(1), amount, StateProofs.depositLeaf(deposit.to, amount))),
+                _emptyLogLeaf(),
+                StateProofs.hashLeaf(abi.encodePacked(update.commitment))
+            ]
+        );
+    }
+
+    /// @dev Three committee members, ordered by ascending address.
+    function _committee() internal pure returns (address[] memory members, uint256[] memory keys) {
+        members = new address[](3);
+        keys = new uint256[](3);
+        for (uint256 i; i < 3; ++i) {
+            keys[i] = 0xC0 + i;
+            members[i] = vm.addr(keys[i]);
+        }
+        for (uint256 i; i < 3; ++i) {
+            for (uint256 j = i + 1; j < 3; ++j) {
+                if (members[j] < members[i]) {
+                    (members[i], members[j]) = (members[j], members[i]);
+                    (keys[i], keys[j]) = (keys[j], keys[i]);
+                }
+            }
+        }
+    }
+
+    function _ethSignedDigest(bytes32 commitment) internal view returns (bytes32) {
+        return keccak256(abi.encodePacked("\x19Ethereum Signed Message:\n32", bridge.computeSignedDigest(commitment)));
+    }
+
+    function _sign(uint256 key, bytes32 digest) internal pure returns (bytes memory) {
+        (uint8 v, bytes32 r, bytes32 s) = vm.sign(key, digest);
+        return abi.encodePacked(r, s, v);
+    }
+
+    function _mockProof(bytes32 oldRoot, bytes32 newRoot, bytes32 commitment) internal pure returns (bytes memory) {
+        bytes memory publicInputs = abi.encode(oldRoot, newRoot, commitment);
+        return abi.encodePacked(keccak256(abi.encodePacked("zcash-eth-bridge/mock-state-transition", publicInputs)));
+    }
+
     function _applyStateUpdate(
         ZcashBridge.ProcessedZecToEthTransfer[] memory mintTransfers,
         ZcashBridge.ProcessedEthToZecTransfer[] memory burnTransfers
@@ -145,8 +688,14 @@ contract ZcashBridgeTest is Test {
             newZecRoot: keccak256(abi.encode(currentZecRoot, block.number, mintTransfers.length, burnTransfers.length)),
             newZecBlockNumber: stateInitialized ? currentZecBlock + 1 : 1,
             zecToEthTransfers: mintTransfers,
-            ethToZecTransfers: burnTransfers
+            ethToZecTransfers: burnTransfers,
+            refunds: _emptyRefunds(),
+            withdrawalRefunds: _emptyBurns(),
+            quarantinedDeposits: _emptyQuarantinedDeposits(),
+            quarantinedWithdrawals: _emptyBurns(),
+            commitment: bytes32(0)
         });
+        update.commitment = bridge.computeCommitment(update);
 
         bridge.submitStateUpdate(update);
 
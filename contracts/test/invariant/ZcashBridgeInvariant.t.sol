@@ -77,7 +77,11 @@ contract ZcashBridgeHandler is Test {
         if (request.amount == 0 || request.processed) return;
 
         ZcashBridge.ProcessedEthToZecTransfer[] memory burns = new ZcashBridge.ProcessedEthToZecTransfer[](1);
-        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({amount: request.amount, pubkeyHash: request.pubkeyHash});
+        burns[0] = ZcashBridge.ProcessedEthToZecTransfer({
+            amount: request.amount,
+            receiverType: request.receiverType,
+            receiver: request.receiver
+        });
 
         _submitStateUpdate(_emptyMints(), burns);
     }
@@ -97,8 +101,14 @@ contract ZcashBridgeHandler is Test {
                 newZecRoot: keccak256(abi.encode(currentZecRoot, nonce, block.timestamp)),
                 newZecBlockNumber: stateInitialized ? currentZecBlock + 1 : 1,
                 zecToEthTransfers: mints,
-                ethToZecTransfers: burns
+                ethToZecTransfers: burns,
+                refunds: new ZcashBridge.ProcessedRefund[](0),
+                withdrawalRefunds: new ZcashBridge.ProcessedEthToZecTransfer[](0),
+                quarantinedDeposits: new ZcashBridge.QuarantinedDeposit[](0),
+                quarantinedWithdrawals: new ZcashBridge.ProcessedEthToZecTransfer[](0),
+                commitment: bytes32(0)
             });
+        update.commitment = bridge.computeCommitment(update);
 
         bridge.submitStateUpdate(update);
 
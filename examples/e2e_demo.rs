@@ -161,10 +161,10 @@ async fn main() -> anyhow::Result<()> {
         .await?;
     tracing::info!("Existing address UTXOs: {start_utxos:?}");
 
+    // The bridge fee is deducted from the deposit, so only the minted amount is withdrawn.
+    let minted = u64::try_from(balance_after_bridging - initial_balance)?;
     tracing::info!("Submitting Ethereum->Zcash withdrawal");
-    let withdraw_block = demo
-        .withdraw_zec(zcash_pk.pubkey_hash(), DEPOSIT_AMOUNT)
-        .await?;
+    let withdraw_block = demo.withdraw_zec(zcash_pk.pubkey_hash(), minted).await?;
     tracing::info!(
         "Withdrawal requested on Ethereum in block {withdraw_block}, waiting for it to be processed on Zcash"
     );
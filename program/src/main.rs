@@ -0,0 +1,13 @@
+//! SP1 guest program proving state transitions of the bridge, see `src/prover/sp1.rs`.
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use zcash_eth_bridge::prover::{TransitionWitness, check_transition};
+
+pub fn main() {
+    let witness =
+        TransitionWitness::decode(&sp1_zkvm::io::read_vec()).expect("malformed transition witness");
+    let public_inputs = check_transition(&witness).expect("invalid state transition");
+    sp1_zkvm::io::commit_slice(&public_inputs.encode());
+}
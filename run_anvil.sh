@@ -1,3 +1,6 @@
 #!/usr/bin/env bash
 
+# The state is dumped from a fresh deployment of the current contracts, so the chain never runs
+# stale bytecode. Deployment addresses only depend on the deployer nonce and stay the same.
+./deploy_anvil.sh || exit 1
 anvil --load-state anvil-state.json
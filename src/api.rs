@@ -0,0 +1,540 @@
+//! HTTP API exposing the relayer state, pending transfers, node health and metrics.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use alloy::primitives::U256;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use crate::{
+    deposits::ObservedDeposit,
+    eth::watcher::EthWatcher,
+    metrics::METRICS,
+    shutdown::CancellationToken,
+    state::{BridgeState, ChainCheckpoint, TransferProof},
+    status::{TransferStatus, WithdrawalInfo, deposit_status, withdrawal_status},
+    types::{EthToZecTransfer, ZecToEthTransfer},
+    zcash::watcher::ZcashWatcher,
+    zebra_client::helpers::txid_from_rpc_string,
+};
+use zcash_primitives::transaction::components::{TzeOut, tze};
+use zcash_protocol::TxId;
+
+/// Number of blocks the relayer may lag behind the tip of a chain and still be considered ready.
+///
+/// The lag is measured from the last scanned block rather than the last checkpoint, which stays
+/// behind for as long as the batching policy holds transfers back.
+const MAX_READY_LAG: u64 = 10;
+
+/// State of the relayer as of the last submitted state update.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RelayerState {
+    /// Current STF UTXO, as `txid:index`.
+    pub stf_outpoint: Option<String>,
+    /// Value locked in the STF UTXO, in zatoshis.
+    pub deposited: u64,
+    /// Root of the bridge state committed to by the STF UTXO.
+    #[serde(with = "hex::serde")]
+    pub state_root: [u8; 32],
+    pub zcash: ChainCheckpoint,
+    pub eth: ChainCheckpoint,
+    /// Last Zcash block fetched by the relayer, at or after the `zcash` checkpoint.
+    pub scanned_zcash_height: u64,
+    /// Last Ethereum block fetched by the relayer, at or after the `eth` checkpoint.
+    pub scanned_eth_height: u64,
+    /// Number of state updates submitted since the relayer start.
+    pub updates_submitted: u64,
+    /// Bridge state committed to by the STF UTXO, used to build inclusion proofs.
+    #[serde(skip)]
+    pub bridge_state: BridgeState,
+    /// Indices of the processed deposits in the deposit log, by Zcash transaction.
+    #[serde(skip)]
+    pub deposit_indices: HashMap<TxId, Vec<u64>>,
+    /// Transfers fetched since the last submitted update, including queued deposits.
+    #[serde(skip)]
+    pub pending: PendingTransfers,
+}
+
+impl RelayerState {
+    /// Records a submitted update, after which the STF output `stf` commits to `state`.
+    ///
+    /// `credited` are the deposits credited by the update, in order, and `queued` those left
+    /// pending.
+    pub fn record_update(
+        &mut self,
+        stf: &(tze::OutPoint, TzeOut),
+        state: &BridgeState,
+        credited: &[ObservedDeposit],
+        queued: &[ObservedDeposit],
+    ) {
+        let first_deposit = self.bridge_state.deposits.count();
+        for (index, deposit) in (first_deposit..).zip(credited) {
+            let txid = *deposit.outpoint.txid();
+            self.deposit_indices.entry(txid).or_default().push(index);
+        }
+        self.stf_outpoint = Some(format!("{}:{}", stf.0.txid(), stf.0.n()));
+        self.deposited = stf.1.value.into_u64();
+        self.state_root = state.root();
+        self.zcash = state.zcash;
+        self.eth = state.eth;
+        self.updates_submitted += 1;
+        self.bridge_state = state.clone();
+        self.pending = PendingTransfers {
+            deposits: queued.iter().map(PendingDeposit::from).collect(),
+            withdrawals: Vec::new(),
+        };
+    }
+}
+
+pub type SharedRelayerState = Arc<RwLock<RelayerState>>;
+
+#[derive(Clone)]
+struct ApiState {
+    relayer: SharedRelayerState,
+    zcash_watcher: Arc<ZcashWatcher>,
+    eth_watcher: Arc<EthWatcher>,
+}
+
+/// Error returned by the API handlers, rendered as a JSON object.
+struct ApiError(StatusCode, anyhow::Error);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.1.to_string() });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+pub fn router(
+    relayer: SharedRelayerState,
+    zcash_watcher: Arc<ZcashWatcher>,
+    eth_watcher: Arc<EthWatcher>,
+) -> Router {
+    Router::new()
+        .route("/state", get(get_state))
+        .route("/pending", get(get_pending))
+        .route("/transfers/zcash/:txid", get(get_deposit))
+        .route("/transfers/eth/:request_id", get(get_withdrawal))
+        .route("/proofs/deposits/:index", get(get_deposit_proof))
+        .route("/proofs/withdrawals/:index", get(get_withdrawal_proof))
+        .route("/proofs/zcash/:txid", get(get_deposit_proofs_by_txid))
+        .route("/health", get(get_health))
+        .route("/ready", get(get_ready))
+        .route("/metrics", get(get_metrics))
+        .with_state(ApiState {
+            relayer,
+            zcash_watcher,
+            eth_watcher,
+        })
+}
+
+/// Serves `router` until `shutdown` is cancelled, letting in-flight requests finish.
+pub async fn serve(
+    addr: SocketAddr,
+    router: Router,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("API listening on {addr}");
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+async fn get_state(State(state): State<ApiState>) -> Json<RelayerState> {
+    Json(state.relayer.read().await.clone())
+}
+
+/// Transfers fetched by the relayer and not submitted yet.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PendingTransfers {
+    pub deposits: Vec<PendingDeposit>,
+    pub withdrawals: Vec<EthToZecTransfer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingDeposit {
+    pub txid: String,
+    #[serde(flatten)]
+    pub transfer: ZecToEthTransfer,
+}
+
+impl From<&ObservedDeposit> for PendingDeposit {
+    fn from(deposit: &ObservedDeposit) -> Self {
+        Self {
+            txid: deposit.outpoint.txid().to_string(),
+            transfer: deposit.transfer.clone(),
+        }
+    }
+}
+
+/// Returns the transfers to be included in the next state update, as last fetched by the
+/// relayer.
+async fn get_pending(State(state): State<ApiState>) -> Json<PendingTransfers> {
+    Json(state.relayer.read().await.pending.clone())
+}
+
+async fn get_deposit(
+    State(state): State<ApiState>,
+    Path(txid): Path<String>,
+) -> ApiResult<TransferStatus> {
+    let txid = txid_from_rpc_string(&txid).map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    let status = deposit_status(
+        state.zcash_watcher.client(),
+        &state.eth_watcher.bridge_contract,
+        &txid,
+    )
+    .await?;
+    Ok(Json(status))
+}
+
+async fn get_withdrawal(
+    State(state): State<ApiState>,
+    Path(request_id): Path<U256>,
+) -> ApiResult<WithdrawalInfo> {
+    match withdrawal_status(&state.eth_watcher.bridge_contract, request_id).await? {
+        Some(info) => Ok(Json(info)),
+        None => Err(ApiError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("withdrawal request {request_id} not found"),
+        )),
+    }
+}
+
+fn proof_not_found(kind: &str, index: u64) -> ApiError {
+    ApiError(
+        StatusCode::NOT_FOUND,
+        anyhow::anyhow!("no processed {kind} with index {index}"),
+    )
+}
+
+async fn get_deposit_proof(
+    State(state): State<ApiState>,
+    Path(index): Path<u64>,
+) -> ApiResult<TransferProof> {
+    let relayer = state.relayer.read().await;
+    match relayer.bridge_state.deposit_proof(index) {
+        Some(proof) => Ok(Json(proof)),
+        None => Err(proof_not_found("deposit", index)),
+    }
+}
+
+async fn get_withdrawal_proof(
+    State(state): State<ApiState>,
+    Path(index): Path<u64>,
+) -> ApiResult<TransferProof> {
+    let relayer = state.relayer.read().await;
+    match relayer.bridge_state.withdrawal_proof(index) {
+        Some(proof) => Ok(Json(proof)),
+        None => Err(proof_not_found("withdrawal", index)),
+    }
+}
+
+/// Proofs of the deposits made by a Zcash transaction, in output order.
+async fn get_deposit_proofs_by_txid(
+    State(state): State<ApiState>,
+    Path(txid): Path<String>,
+) -> ApiResult<Vec<TransferProof>> {
+    let txid = txid_from_rpc_string(&txid).map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    let relayer = state.relayer.read().await;
+    let Some(indices) = relayer.deposit_indices.get(&txid) else {
+        return Err(ApiError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("transaction {txid} has no processed deposits"),
+        ));
+    };
+    let proofs = indices
+        .iter()
+        .map(|&index| relayer.bridge_state.deposit_proof(index))
+        .collect::<Option<_>>()
+        .ok_or_else(|| anyhow::anyhow!("deposit log is missing deposits of {txid}"))?;
+    Ok(Json(proofs))
+}
+
+#[derive(Debug, Serialize)]
+struct NodeHealth {
+    reachable: bool,
+    tip: Option<u64>,
+    /// Last block covered by a submitted state update.
+    processed: u64,
+    /// Last block fetched by the relayer.
+    scanned: u64,
+}
+
+impl NodeHealth {
+    fn lag(&self) -> Option<u64> {
+        self.tip.map(|tip| tip.saturating_sub(self.scanned))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    zcash: NodeHealth,
+    eth: NodeHealth,
+}
+
+async fn health(state: &ApiState) -> Health {
+    let relayer = state.relayer.read().await.clone();
+    let zcash_tip = state.zcash_watcher.get_block_count().await.ok();
+    let eth_tip = state.eth_watcher.get_block_number().await.ok();
+    Health {
+        zcash: NodeHealth {
+            reachable: zcash_tip.is_some(),
+            tip: zcash_tip.map(u64::from),
+            processed: relayer.zcash.height,
+            scanned: relayer.scanned_zcash_height,
+        },
+        eth: NodeHealth {
+            reachable: eth_tip.is_some(),
+            tip: eth_tip,
+            processed: relayer.eth.height,
+            scanned: relayer.scanned_eth_height,
+        },
+    }
+}
+
+/// Succeeds if both nodes are reachable.
+async fn get_health(State(state): State<ApiState>) -> (StatusCode, Json<Health>) {
+    let health = health(&state).await;
+    let code = if health.zcash.reachable && health.eth.reachable {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(health))
+}
+
+/// Succeeds if both nodes are reachable and the relayer keeps fetching the blocks of both chains.
+async fn get_ready(State(state): State<ApiState>) -> (StatusCode, Json<Health>) {
+    let health = health(&state).await;
+    let in_sync = |node: &NodeHealth| node.lag().is_some_and(|lag| lag <= MAX_READY_LAG);
+    let code = if in_sync(&health.zcash) && in_sync(&health.eth) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(health))
+}
+
+async fn get_metrics() -> String {
+    METRICS.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{primitives::Address, sol_types::SolValue as _};
+    use axum::{
+        body::{Body, to_bytes},
+        http::Request,
+        routing::post,
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::{
+        eth::contract::ZcashBridge::WithdrawalRequest,
+        test_utils::{deposit, update},
+    };
+
+    const ZCASH_TIP: u64 = 120;
+    const ETH_TIP: u64 = 50;
+    const TXID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    /// Answers the node RPCs used by the API: both tips, and an empty withdrawal request for
+    /// every `eth_call`.
+    async fn stub_rpc(Json(request): Json<Value>) -> Json<Value> {
+        let result = match request["method"].as_str() {
+            Some("getblockcount") => json!(ZCASH_TIP),
+            Some("eth_blockNumber") => json!(format!("{ETH_TIP:#x}")),
+            Some("eth_call") => {
+                let empty = WithdrawalRequest {
+                    requester: Address::ZERO,
+                    amount: U256::ZERO,
+                    receiverType: 0,
+                    receiver: Default::default(),
+                    processed: false,
+                };
+                json!(format!("0x{}", hex::encode(empty.abi_encode())))
+            }
+            method => panic!("unexpected RPC {method:?}"),
+        };
+        Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+    }
+
+    /// Router over `relayer`, with both nodes served by [`stub_rpc`], or unreachable.
+    async fn router_with(relayer: RelayerState, reachable: bool) -> Router {
+        let address = if reachable {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                axum::serve(listener, Router::new().route("/", post(stub_rpc)))
+                    .await
+                    .unwrap()
+            });
+            address
+        } else {
+            // Nothing listens on the discard port.
+            SocketAddr::from(([127, 0, 0, 1], 9))
+        };
+        let bridge = Address::repeat_byte(0x01).to_string();
+        router(
+            Arc::new(RwLock::new(relayer)),
+            Arc::new(ZcashWatcher::new(&address.to_string())),
+            Arc::new(EthWatcher::new(
+                &format!("http://{address}"),
+                &bridge,
+                &bridge,
+            )),
+        )
+    }
+
+    async fn get(router: &Router, uri: &str) -> (StatusCode, Value) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn relayer_with_deposits() -> anyhow::Result<RelayerState> {
+        let mut bridge_state = BridgeState::default();
+        bridge_state.apply(
+            &update(10, 12)
+                .deposit(90_000, [0x33; 20])
+                .deposit(10_000, [0x44; 20])
+                .build(),
+        )?;
+        let txid = txid_from_rpc_string(TXID)?;
+        Ok(RelayerState {
+            state_root: bridge_state.root(),
+            bridge_state,
+            deposit_indices: HashMap::from([(txid, vec![0, 1])]),
+            scanned_zcash_height: ZCASH_TIP - 2,
+            scanned_eth_height: ETH_TIP,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn state_and_proofs_are_served_from_the_relayer_state() -> anyhow::Result<()> {
+        let relayer = relayer_with_deposits()?;
+        let root = hex::encode(relayer.state_root);
+        let router = router_with(relayer, true).await;
+
+        let (status, state) = get(&router, "/state").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state["state_root"], root);
+        assert_eq!(get(&router, "/pending").await.0, StatusCode::OK);
+
+        let (status, proof) = get(&router, "/proofs/deposits/1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(proof["state_root"], root);
+        let (status, proofs) = get(&router, &format!("/proofs/zcash/{TXID}")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(proofs.as_array().map(Vec::len), Some(2));
+
+        for missing in [
+            "/proofs/deposits/2".to_string(),
+            "/proofs/withdrawals/0".to_string(),
+            format!("/proofs/zcash/{}", "22".repeat(32)),
+        ] {
+            let (status, body) = get(&router, &missing).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{missing}");
+            assert!(body["error"].is_string(), "{missing}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn submitted_updates_index_the_credited_deposits() -> anyhow::Result<()> {
+        let mut relayer = relayer_with_deposits()?;
+        let credited = deposit(5, [0x55; 20], 70_000, 13, false);
+        let queued = deposit(6, [0x66; 20], 100, 13, false);
+        let mut state = relayer.bridge_state.clone();
+        state.apply(&update(12, 14).deposit(70_000, [0x55; 20]).build())?;
+        let stf = deposit(7, [0; 20], 170_000, 14, false);
+
+        relayer.record_update(
+            &(stf.outpoint, stf.output),
+            &state,
+            std::slice::from_ref(&credited),
+            &[queued],
+        );
+        assert_eq!(relayer.deposit_indices[credited.outpoint.txid()], [2]);
+        assert_eq!(relayer.state_root, state.root());
+        assert_eq!(relayer.zcash.height, 14);
+        assert_eq!(relayer.deposited, 170_000);
+        assert_eq!(relayer.updates_submitted, 1);
+        assert_eq!(relayer.pending.deposits.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn malformed_and_unknown_transfers_are_rejected() {
+        let router = router_with(RelayerState::default(), true).await;
+        for malformed in [
+            "/transfers/zcash/zz",
+            "/transfers/zcash/1111",
+            "/proofs/zcash/zz",
+            "/proofs/deposits/first",
+            "/transfers/eth/request",
+        ] {
+            assert_eq!(
+                get(&router, malformed).await.0,
+                StatusCode::BAD_REQUEST,
+                "{malformed}"
+            );
+        }
+        let (status, body) = get(&router, "/transfers/eth/7").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn ready_requires_reachable_nodes_and_a_bounded_lag() -> anyhow::Result<()> {
+        let router = router_with(relayer_with_deposits()?, true).await;
+        let (status, health) = get(&router, "/ready").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health["zcash"]["tip"], ZCASH_TIP);
+        assert_eq!(get(&router, "/health").await.0, StatusCode::OK);
+
+        let lagging = RelayerState {
+            scanned_zcash_height: ZCASH_TIP - MAX_READY_LAG - 1,
+            ..relayer_with_deposits()?
+        };
+        let router = router_with(lagging, true).await;
+        assert_eq!(
+            get(&router, "/ready").await.0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        // A lagging relayer is still healthy.
+        assert_eq!(get(&router, "/health").await.0, StatusCode::OK);
+
+        let router = router_with(relayer_with_deposits()?, false).await;
+        let (status, health) = get(&router, "/health").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health["eth"]["reachable"], false);
+        assert_eq!(
+            get(&router, "/ready").await.0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! Solvency checks of the value locked on Zcash against the WZEC issued on Ethereum.
+
+use serde::Serialize;
+use zcash_primitives::transaction::components::tze;
+use zcash_protocol::consensus::BranchId;
+use zebra_node_services::rpc_client::RpcRequestClient;
+
+use crate::{
+    eth::watcher::EthWatcher, metrics::METRICS, zcash::sender::LOCK_IN_VALUE,
+    zebra_client::client::RpcClient as _,
+};
+
+/// Balances of both sides of the bridge as of one processed Zcash height.
+#[derive(Debug, Clone, Serialize)]
+pub struct SolvencyReport {
+    /// Zcash height covered by the latest state update on Ethereum.
+    pub zcash_height: u64,
+    /// Ethereum block covered by the latest state update on Ethereum.
+    pub eth_block: u64,
+    /// Value of the STF UTXO, in zatoshis.
+    pub stf_value: u64,
+    pub wzec_total_supply: u64,
+    pub total_minted: u64,
+    pub total_burned: u64,
+    pub total_locked: u64,
+}
+
+/// Broken bridge invariant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SolvencyViolation {
+    /// The STF UTXO does not hold exactly the minted and not yet burned value plus lock-in dust.
+    StfValueMismatch { stf_value: u64, expected: u64 },
+    /// The WZEC supply does not match the mint and burn totals of the bridge.
+    SupplyMismatch { total_supply: u64, expected: u64 },
+    /// The bridge holds more locked WZEC than exists.
+    LockedExceedsSupply {
+        total_locked: u64,
+        total_supply: u64,
+    },
+}
+
+impl SolvencyReport {
+    /// Returns every invariant the reported balances break.
+    pub fn violations(&self) -> Vec<SolvencyViolation> {
+        let mut violations = Vec::new();
+        let outstanding = self.total_minted as i128 - self.total_burned as i128;
+
+        let expected_stf_value = outstanding + LOCK_IN_VALUE.into_u64() as i128;
+        if self.stf_value as i128 != expected_stf_value {
+            violations.push(SolvencyViolation::StfValueMismatch {
+                stf_value: self.stf_value,
+                expected: expected_stf_value.max(0) as u64,
+            });
+        }
+        if self.wzec_total_supply as i128 != outstanding {
+            violations.push(SolvencyViolation::SupplyMismatch {
+                total_supply: self.wzec_total_supply,
+                expected: outstanding.max(0) as u64,
+            });
+        }
+        if self.total_locked > self.wzec_total_supply {
+            violations.push(SolvencyViolation::LockedExceedsSupply {
+                total_locked: self.total_locked,
+                total_supply: self.wzec_total_supply,
+            });
+        }
+        violations
+    }
+
+    /// Difference between the STF value and the value it is expected to hold.
+    pub fn divergence(&self) -> i64 {
+        let expected =
+            self.total_minted as i64 - self.total_burned as i64 + LOCK_IN_VALUE.into_u64() as i64;
+        self.stf_value as i64 - expected
+    }
+}
+
+/// Reads both sides of the bridge once the STF UTXO at `stf_outpoint` is processed up to
+/// `zcash_height` on Ethereum.
+///
+/// Returns `None` if the latest Ethereum state update covers a different Zcash height, since
+/// the balances of both chains are then not comparable.
+pub async fn audit_solvency(
+    client: &RpcRequestClient,
+    eth_watcher: &EthWatcher,
+    stf_outpoint: &tze::OutPoint,
+    zcash_height: u64,
+) -> anyhow::Result<Option<SolvencyReport>> {
+    let bridge = &eth_watcher.bridge_contract;
+    let state = bridge.latestState().call().await?;
+    if state.zecBlockNumber != zcash_height {
+        return Ok(None);
+    }
+
+    let tx = client
+        .get_transaction(stf_outpoint.txid(), BranchId::ZFuture)
+        .await?;
+    // The STF output is the only TZE output of a state update.
+    let stf_value = tx
+        .tze_bundle()
+        .and_then(|bundle| bundle.vout.first())
+        .ok_or_else(|| anyhow::anyhow!("{} has no STF output", stf_outpoint.txid()))?
+        .value
+        .into_u64();
+
+    let to_u64 = |value: alloy::primitives::U256| u64::try_from(value).expect("Amount exceeds u64");
+    Ok(Some(SolvencyReport {
+        zcash_height,
+        eth_block: state.ethBlockNumber,
+        stf_value,
+        wzec_total_supply: to_u64(eth_watcher.wzec_contract.totalSupply().call().await?),
+        total_minted: to_u64(bridge.totalMinted().call().await?),
+        total_burned: to_u64(bridge.totalBurned().call().await?),
+        total_locked: to_u64(bridge.totalLocked().call().await?),
+    }))
+}
+
+/// Raises an alert for every invariant broken in `report`.
+///
+/// Returns whether the bridge is solvent.
+pub fn check_report(report: &SolvencyReport) -> bool {
+    METRICS.solvency_divergence.set(report.divergence());
+    let violations = report.violations();
+    for violation in &violations {
+        tracing::error!(
+            target: "solvency",
+            zcash_height = report.zcash_height,
+            eth_block = report.eth_block,
+            violation = %serde_json::to_string(violation).unwrap(),
+            "Solvency invariant violated"
+        );
+    }
+    violations.is_empty()
+}
@@ -0,0 +1,205 @@
+//! Policy deciding when pending transfers are worth a state update.
+//!
+//! Every update costs fees on both chains, so the relayer lets blocks and transfers accumulate
+//! until one of the rules of the [`BatchingPolicy`] asks for a submission.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use zcash_primitives::transaction::components::tze;
+
+use crate::{deposits::ObservedDeposit, types::StateUpdate};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchingPolicy {
+    /// Submit once at least this many transfers are pending.
+    pub min_transfers: usize,
+    /// Submit once the pending transfers move at least this many zatoshis.
+    pub min_value: u64,
+    /// Submit once transfers are pending and the last update is this old.
+    pub max_delay: Duration,
+    /// Submit once a deposit has been pending this long, as its funds are locked meanwhile.
+    pub max_deposit_age: Duration,
+    /// Submit once the last update is this old, even without transfers.
+    pub heartbeat: Duration,
+}
+
+/// Rule of the [`BatchingPolicy`] that triggered a submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlushReason {
+    TransferCount,
+    Value,
+    MaxDelay,
+    DepositAge,
+    Heartbeat,
+}
+
+/// Transfers accumulated since the last update.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pending {
+    pub transfers: usize,
+    /// Zatoshis moved by the transfers in both directions and by refunds.
+    pub value: u64,
+    pub since_last_update: Duration,
+    /// Time since the oldest pending deposit was observed.
+    pub oldest_deposit_age: Option<Duration>,
+}
+
+impl Pending {
+    /// Fails if the transfers move more than `u64::MAX` zatoshis, which the bridge contract
+    /// rejects as well.
+    pub fn new(
+        update: &StateUpdate,
+        since_last_update: Duration,
+        oldest_deposit_age: Option<Duration>,
+    ) -> anyhow::Result<Self> {
+        let deposits = update.zec_to_eth_transfers.iter().map(|t| t.amount);
+        let withdrawals = update.eth_to_zec_transfers.iter().map(|t| t.amount);
+        let refunds = update.refunds.iter().map(|r| r.amount);
+        let returns = update.withdrawal_refunds.iter().map(|t| t.amount);
+        let value = deposits
+            .chain(withdrawals)
+            .chain(refunds)
+            .chain(returns)
+            .try_fold(0u64, u64::checked_add)
+            .ok_or_else(|| anyhow::anyhow!("pending transfers overflow u64"))?;
+        Ok(Self {
+            transfers: update.zec_to_eth_transfers.len()
+                + update.eth_to_zec_transfers.len()
+                + update.refunds.len()
+                + update.withdrawal_refunds.len(),
+            value,
+            since_last_update,
+            oldest_deposit_age,
+        })
+    }
+}
+
+/// When the queued deposits were first observed, for the deposit age rule.
+///
+/// Deposits can stay queued across updates, below the minimum or in quarantine, so their age
+/// does not restart with every submission.
+#[derive(Debug, Clone, Default)]
+pub struct DepositClock {
+    observed: Vec<(tze::OutPoint, Instant)>,
+}
+
+impl DepositClock {
+    /// Records `deposits` as observed at `now`, keeping the time of those already known.
+    pub fn observe<'a>(
+        &mut self,
+        deposits: impl IntoIterator<Item = &'a ObservedDeposit>,
+        now: Instant,
+    ) {
+        for deposit in deposits {
+            if !self.observed.iter().any(|(o, _)| *o == deposit.outpoint) {
+                self.observed.push((deposit.outpoint.clone(), now));
+            }
+        }
+    }
+
+    /// Forgets the deposits missing from `queued`, which were submitted or quarantined.
+    pub fn retain(&mut self, queued: &[ObservedDeposit]) {
+        self.observed
+            .retain(|(outpoint, _)| queued.iter().any(|d| d.outpoint == *outpoint));
+    }
+
+    /// Age at `now` of the oldest deposit still queued.
+    pub fn oldest_age(&self, now: Instant) -> Option<Duration> {
+        self.observed
+            .iter()
+            .map(|(_, observed)| now.saturating_duration_since(*observed))
+            .max()
+    }
+}
+
+impl BatchingPolicy {
+    /// Submits an update whenever both chains have new blocks, like a relayer without batching.
+    pub const EVERY_BLOCK: Self = Self {
+        min_transfers: 0,
+        min_value: 0,
+        max_delay: Duration::ZERO,
+        max_deposit_age: Duration::ZERO,
+        heartbeat: Duration::ZERO,
+    };
+
+    /// Returns why `pending` should be submitted now, or `None` to keep accumulating.
+    pub fn decide(&self, pending: &Pending) -> Option<FlushReason> {
+        if pending.since_last_update >= self.heartbeat {
+            return Some(FlushReason::Heartbeat);
+        }
+        if pending.transfers == 0 {
+            return None;
+        }
+        if pending.transfers >= self.min_transfers {
+            Some(FlushReason::TransferCount)
+        } else if pending.value >= self.min_value {
+            Some(FlushReason::Value)
+        } else if pending
+            .oldest_deposit_age
+            .is_some_and(|age| age >= self.max_deposit_age)
+        {
+            Some(FlushReason::DepositAge)
+        } else if pending.since_last_update >= self.max_delay {
+            Some(FlushReason::MaxDelay)
+        } else {
+            None
+        }
+    }
+
+    /// Returns how long until a time-based rule submits `pending`, if no new blocks arrive.
+    ///
+    /// Mirrors [`Self::decide`], so that the relayer sleeps until then instead of polling.
+    pub fn time_to_flush(&self, pending: &Pending) -> Duration {
+        let heartbeat = self.heartbeat.saturating_sub(pending.since_last_update);
+        if pending.transfers == 0 {
+            return heartbeat;
+        }
+        let max_delay = self.max_delay.saturating_sub(pending.since_last_update);
+        let deposit_age = pending.oldest_deposit_age.map_or(Duration::MAX, |age| {
+            self.max_deposit_age.saturating_sub(age)
+        });
+        heartbeat.min(max_delay).min(deposit_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{deposit, update};
+
+    #[test]
+    fn pending_value_overflow_is_an_error() {
+        let update = update(1, 2)
+            .deposit(u64::MAX, [1; 20])
+            .deposit(1, [2; 20])
+            .build();
+        assert!(Pending::new(&update, Duration::ZERO, None).is_err());
+    }
+
+    #[test]
+    fn queued_deposits_keep_their_age_across_updates() {
+        let start = Instant::now();
+        let first = deposit(1, [1; 20], 100, 10, false);
+        let second = deposit(2, [2; 20], 100, 11, false);
+        let mut clock = DepositClock::default();
+        assert_eq!(clock.oldest_age(start), None);
+
+        clock.observe([&first], start);
+        let later = start + Duration::from_secs(20);
+        clock.observe([&first, &second], later);
+        assert_eq!(clock.oldest_age(later), Some(Duration::from_secs(20)));
+
+        // The first deposit stays queued after an update, so its age keeps growing.
+        clock.retain(std::slice::from_ref(&first));
+        let next = later + Duration::from_secs(10);
+        assert_eq!(clock.oldest_age(next), Some(Duration::from_secs(30)));
+
+        clock.observe([&second], next);
+        clock.retain(std::slice::from_ref(&second));
+        assert_eq!(clock.oldest_age(next), Some(Duration::ZERO));
+        clock.retain(&[]);
+        assert_eq!(clock.oldest_age(next), None);
+    }
+}
@@ -0,0 +1,484 @@
+//! User-facing CLI for bridging funds between Zcash and Ethereum.
+
+use std::time::Duration;
+
+use alloy::{
+    primitives::{Address, Bytes, U256},
+    sol_types::SolEvent as _,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use tracing_subscriber::EnvFilter;
+use zcash_eth_bridge::{
+    eth::{contract::ZcashBridge, sender::EthSender, watcher::EthWatcher},
+    status::{TransferStatus, deposit_status, withdrawal_status},
+    types::ZcashRecipient,
+    zcash::{deposit::DepositRequest, sender::TzeSender},
+    zebra_client::{client::RpcClient as _, helpers::txid_from_rpc_string},
+};
+use zcash_protocol::{consensus::NetworkType, value::Zatoshis};
+use zebra_node_services::rpc_client::RpcRequestClient;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "zec-bridge-cli",
+    about = "Bridge ZEC between Zcash and Ethereum"
+)]
+struct Cli {
+    #[command(flatten)]
+    connection: Connection,
+    /// Print machine-readable JSON instead of human-readable text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Args)]
+struct Connection {
+    #[arg(long, global = true, default_value = "127.0.0.1:18232")]
+    zcash_rpc: String,
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8545")]
+    eth_rpc: String,
+    #[arg(
+        long,
+        global = true,
+        default_value = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"
+    )]
+    bridge_address: String,
+    #[arg(
+        long,
+        global = true,
+        default_value = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+    )]
+    wzec_address: String,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Deposit ZEC from the regtest operator wallet to an Ethereum address.
+    Deposit {
+        /// Ethereum recipient of the minted WZEC.
+        #[arg(long)]
+        to: Address,
+        /// Amount in zatoshis.
+        #[arg(long)]
+        amount: u64,
+        #[arg(long, default_value_t = 50_000)]
+        fee: u64,
+        /// Wait until the deposit is processed by the bridge.
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Print a deposit request to be funded by any wallet supporting the bridge extension.
+    DepositRequest {
+        /// Ethereum recipient of the minted WZEC.
+        #[arg(long)]
+        to: Address,
+        /// Amount in zatoshis.
+        #[arg(long)]
+        amount: u64,
+        /// Fee the funding wallet is expected to pay, in zatoshis.
+        #[arg(long, default_value_t = 50_000)]
+        fee: u64,
+        #[arg(long, value_enum, default_value_t = RequestFormat::Uri)]
+        format: RequestFormat,
+    },
+    /// Burn WZEC and withdraw ZEC to a transparent, Sapling or unified Zcash address.
+    Withdraw {
+        /// Zcash recipient address.
+        #[arg(long)]
+        to: String,
+        /// Amount in zatoshis.
+        #[arg(long)]
+        amount: u64,
+        /// Private key of the Ethereum account holding WZEC.
+        #[arg(long, env = "ETH_PRIVATE_KEY")]
+        private_key: String,
+        /// Wait until the withdrawal is processed by the bridge.
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Show the status of a deposit (Zcash txid) or withdrawal (Ethereum request ID).
+    Status { id: String },
+    /// Show the WZEC balance of an Ethereum address and, optionally, ZEC of a Zcash address.
+    Balance {
+        #[arg(long)]
+        eth_address: Option<Address>,
+        #[arg(long)]
+        zcash_address: Option<String>,
+    },
+    /// Wait until the bridge processes the given block on either chain.
+    Wait {
+        #[arg(
+            long,
+            conflicts_with = "eth_block",
+            required_unless_present = "eth_block"
+        )]
+        zcash_height: Option<u64>,
+        #[arg(long)]
+        eth_block: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum RequestFormat {
+    /// ZIP-321 payment request URI.
+    Uri,
+    /// Partially built transaction as JSON, to which the wallet adds inputs and change.
+    Pczt,
+}
+
+#[derive(Debug, Serialize)]
+struct DepositOutput {
+    txid: String,
+    height: u64,
+    amount: u64,
+    to: Address,
+    #[serde(flatten)]
+    status: TransferStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct WithdrawOutput {
+    request_id: U256,
+    eth_tx_hash: String,
+    block: u64,
+    amount: u64,
+    #[serde(flatten)]
+    status: TransferStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct BalanceOutput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wzec: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zec: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct BridgeStateOutput {
+    eth_block: u64,
+    zcash_height: u64,
+}
+
+/// Formats an amount in zatoshis as ZEC.
+fn format_zec(zatoshis: u64) -> String {
+    format!(
+        "{}.{:08} ZEC",
+        zatoshis / 100_000_000,
+        zatoshis % 100_000_000
+    )
+}
+
+fn print_output(json: bool, output: &impl Serialize, human: impl FnOnce() -> String) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(output).unwrap());
+    } else {
+        println!("{}", human());
+    }
+}
+
+struct Context {
+    connection: Connection,
+    zcash_client: RpcRequestClient,
+    eth_watcher: EthWatcher,
+}
+
+impl Context {
+    fn new(connection: Connection) -> Self {
+        let zcash_client = RpcRequestClient::new(connection.zcash_rpc.parse().unwrap());
+        let eth_watcher = EthWatcher::new(
+            &connection.eth_rpc,
+            &connection.bridge_address,
+            &connection.wzec_address,
+        );
+        Self {
+            connection,
+            zcash_client,
+            eth_watcher,
+        }
+    }
+
+    async fn wait_for_bridge(
+        &self,
+        zcash_height: Option<u64>,
+        eth_block: Option<u64>,
+    ) -> anyhow::Result<BridgeStateOutput> {
+        loop {
+            let state = self
+                .eth_watcher
+                .bridge_contract
+                .latestState()
+                .call()
+                .await?;
+            let reached = zcash_height.is_none_or(|h| state.zecBlockNumber >= h)
+                && eth_block.is_none_or(|b| state.ethBlockNumber >= b);
+            if reached {
+                return Ok(BridgeStateOutput {
+                    eth_block: state.ethBlockNumber,
+                    zcash_height: state.zecBlockNumber,
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn deposit(
+        &self,
+        to: Address,
+        amount: u64,
+        fee: u64,
+        wait: bool,
+    ) -> anyhow::Result<DepositOutput> {
+        let mut sender = TzeSender::new(&self.connection.zcash_rpc).await?;
+        let (outpoint, _) = sender
+            .send_tze_deposit(to.into_array(), Zatoshis::from_u64(amount)?, fee)
+            .await?;
+        let height = sender.wait_for_tx(outpoint.txid()).await?;
+        if wait {
+            self.wait_for_bridge(Some(height), None).await?;
+        }
+        let status = deposit_status(
+            &self.zcash_client,
+            &self.eth_watcher.bridge_contract,
+            outpoint.txid(),
+        )
+        .await?;
+        Ok(DepositOutput {
+            txid: outpoint.txid().to_string(),
+            height,
+            amount,
+            to,
+            status,
+        })
+    }
+
+    async fn withdraw(
+        &self,
+        to: &str,
+        amount: u64,
+        private_key: &str,
+        wait: bool,
+    ) -> anyhow::Result<WithdrawOutput> {
+        let recipient = ZcashRecipient::parse(to, NetworkType::Regtest)?;
+        let eth_sender = EthSender::new(
+            &self.connection.eth_rpc,
+            private_key,
+            &self.connection.bridge_address,
+            &self.connection.wzec_address,
+        );
+
+        let approve_tx = eth_sender
+            .wzec_contract
+            .approve(*eth_sender.bridge_contract.address(), U256::from(amount));
+        approve_tx.send().await?.get_receipt().await?;
+
+        let receipt = match &recipient {
+            ZcashRecipient::Transparent(pubkey_hash) => {
+                eth_sender
+                    .bridge_contract
+                    .requestWithdrawal(U256::from(amount), (*pubkey_hash).into())
+                    .send()
+                    .await?
+                    .get_receipt()
+                    .await?
+            }
+            shielded => {
+                eth_sender
+                    .bridge_contract
+                    .requestShieldedWithdrawal(
+                        U256::from(amount),
+                        shielded.receiver_type(),
+                        Bytes::copy_from_slice(shielded.receiver_bytes()),
+                    )
+                    .send()
+                    .await?
+                    .get_receipt()
+                    .await?
+            }
+        };
+        anyhow::ensure!(receipt.status(), "withdrawal request reverted");
+
+        let request_id = receipt
+            .inner
+            .logs()
+            .iter()
+            .find_map(|log| ZcashBridge::WithdrawalRequested::decode_log(&log.inner).ok())
+            .map(|event| event.requestId)
+            .ok_or_else(|| anyhow::anyhow!("no WithdrawalRequested event in receipt"))?;
+        let block = receipt.block_number.unwrap();
+
+        if wait {
+            self.wait_for_bridge(None, Some(block)).await?;
+        }
+        let status = withdrawal_status(&self.eth_watcher.bridge_contract, request_id)
+            .await?
+            .map(|info| info.status)
+            .unwrap_or(TransferStatus::NotFound);
+        Ok(WithdrawOutput {
+            request_id,
+            eth_tx_hash: receipt.transaction_hash.to_string(),
+            block,
+            amount,
+            status,
+        })
+    }
+
+    async fn balance(
+        &self,
+        eth_address: Option<Address>,
+        zcash_address: Option<String>,
+    ) -> anyhow::Result<BalanceOutput> {
+        let mut output = BalanceOutput {
+            wzec: None,
+            zec: None,
+        };
+        if let Some(eth_address) = eth_address {
+            let balance = self
+                .eth_watcher
+                .wzec_contract
+                .balanceOf(eth_address)
+                .call()
+                .await?;
+            output.wzec = Some(u64::try_from(balance).expect("Balance exceeds u64"));
+        }
+        if let Some(zcash_address) = zcash_address {
+            let utxos = self.zcash_client.get_address_utxos(zcash_address).await?;
+            output.zec = Some(utxos.iter().map(|u| u.satoshis().to_owned()).sum());
+        }
+        Ok(output)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive("zcash_eth_bridge=warn".parse().unwrap())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let json = cli.json;
+    let context = Context::new(cli.connection);
+
+    match cli.command {
+        Command::Deposit {
+            to,
+            amount,
+            fee,
+            wait,
+        } => {
+            let output = context.deposit(to, amount, fee, wait).await?;
+            print_output(json, &output, || {
+                format!(
+                    "Deposited {} to {} in Zcash tx {} (height {}): {}",
+                    format_zec(output.amount),
+                    output.to,
+                    output.txid,
+                    output.height,
+                    output.status
+                )
+            });
+        }
+        Command::DepositRequest {
+            to,
+            amount,
+            fee,
+            format,
+        } => {
+            let request = DepositRequest::new(to.into_array(), Zatoshis::from_u64(amount)?);
+            match format {
+                RequestFormat::Uri => {
+                    let uri = request.to_uri()?;
+                    print_output(json, &uri, || uri.clone());
+                }
+                RequestFormat::Pczt => {
+                    let partial = request.partial(Zatoshis::from_u64(fee)?)?;
+                    println!("{}", serde_json::to_string_pretty(&partial)?);
+                }
+            }
+        }
+        Command::Withdraw {
+            to,
+            amount,
+            private_key,
+            wait,
+        } => {
+            let output = context.withdraw(&to, amount, &private_key, wait).await?;
+            print_output(json, &output, || {
+                format!(
+                    "Requested withdrawal #{} of {} to {} in Ethereum tx {} (block {}): {}",
+                    output.request_id,
+                    format_zec(output.amount),
+                    to,
+                    output.eth_tx_hash,
+                    output.block,
+                    output.status
+                )
+            });
+        }
+        Command::Status { id } => {
+            let id = id.trim_start_matches("0x");
+            if id.len() == 64 {
+                let txid = txid_from_rpc_string(id)?;
+                let status = deposit_status(
+                    &context.zcash_client,
+                    &context.eth_watcher.bridge_contract,
+                    &txid,
+                )
+                .await?;
+                print_output(json, &status, || format!("Deposit {id}: {status}"));
+            } else {
+                let request_id: U256 = id.parse()?;
+                let info =
+                    withdrawal_status(&context.eth_watcher.bridge_contract, request_id).await?;
+                print_output(json, &info, || match &info {
+                    Some(info) => format!(
+                        "Withdrawal #{} of {} by {}: {}",
+                        info.request_id,
+                        format_zec(info.amount),
+                        info.requester,
+                        info.status
+                    ),
+                    None => format!("Withdrawal #{request_id}: not found"),
+                });
+            }
+        }
+        Command::Balance {
+            eth_address,
+            zcash_address,
+        } => {
+            let output = context.balance(eth_address, zcash_address).await?;
+            print_output(json, &output, || {
+                let mut lines = Vec::new();
+                if let Some(wzec) = output.wzec {
+                    lines.push(format!("WZEC: {}", format_zec(wzec)));
+                }
+                if let Some(zec) = output.zec {
+                    lines.push(format!("ZEC: {}", format_zec(zec)));
+                }
+                lines.join("\n")
+            });
+        }
+        Command::Wait {
+            zcash_height,
+            eth_block,
+        } => {
+            let output = context.wait_for_bridge(zcash_height, eth_block).await?;
+            print_output(json, &output, || {
+                format!(
+                    "Bridge reached Zcash height {} and Ethereum block {}",
+                    output.zcash_height, output.eth_block
+                )
+            });
+        }
+    }
+
+    Ok(())
+}
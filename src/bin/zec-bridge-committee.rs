@@ -0,0 +1,109 @@
+//! Committee member co-signing the state updates it independently re-derives.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use alloy::{
+    primitives::Address,
+    providers::{Provider as _, ProviderBuilder},
+    signers::local::PrivateKeySigner,
+};
+use clap::Parser;
+use tracing_subscriber::EnvFilter;
+use zcash_eth_bridge::{
+    committee::{CommitteeMember, member_service},
+    deposits::DepositPolicy,
+    eth::watcher::EthWatcher,
+    watchtower::Watchtower,
+    zcash::watcher::ZcashWatcher,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "zec-bridge-committee",
+    about = "Co-sign state updates of the Zcash <-> Ethereum bridge after re-deriving them"
+)]
+struct Cli {
+    #[arg(long, default_value = "127.0.0.1:18232")]
+    zcash_rpc: String,
+    #[arg(long, default_value = "http://127.0.0.1:8545")]
+    eth_rpc: String,
+    #[arg(long, default_value = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512")]
+    bridge_address: String,
+    #[arg(long, default_value = "0x5FbDB2315678afecb367f032d93F642f64180aa3")]
+    wzec_address: String,
+    /// Key this member signs state updates with.
+    #[arg(long, env = "COMMITTEE_SIGNER_PK")]
+    signer_pk: String,
+    #[arg(long, default_value = "127.0.0.1:3100")]
+    listen: SocketAddr,
+    /// Ethereum block the bridge was deployed in, from which submitted updates are replayed
+    /// to re-derive the transfers they carried.
+    #[arg(long, default_value_t = 0)]
+    bridge_deployed_at: u64,
+    /// Smallest deposit total credited to a recipient, in zatoshis; must match the relayer.
+    #[arg(long, default_value_t = 10_000)]
+    min_deposit: u64,
+    /// Bridge fee on deposits in basis points; must match the relayer.
+    #[arg(long, default_value_t = 10)]
+    deposit_fee_bps: u64,
+    /// Ethereum address the deposit fees are minted to; must match the relayer.
+    #[arg(long, default_value = "0xa0Ee7A142d267C1f36714E4a8F75612F20a79720")]
+    fee_recipient: Address,
+    /// Age in Zcash blocks after which deposits below the minimum are refunded.
+    #[arg(long, default_value_t = 100)]
+    refund_after_blocks: u64,
+    /// Ethereum address whose deposits are refunded instead of credited; must match the relayer.
+    #[arg(long = "deny-recipient")]
+    deny_recipients: Vec<Address>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive("info".parse().unwrap())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let cli = Cli::parse();
+    let signer: PrivateKeySigner = cli.signer_pk.parse()?;
+    let chain_id = ProviderBuilder::new()
+        .connect_http(cli.eth_rpc.parse()?)
+        .get_chain_id()
+        .await?;
+    let bridge: Address = cli.bridge_address.parse()?;
+    let policy = DepositPolicy {
+        min_amount: cli.min_deposit,
+        fee_bps: cli.deposit_fee_bps,
+        fee_recipient: cli.fee_recipient.into_array(),
+        refund_after: cli.refund_after_blocks,
+        deny_list: cli
+            .deny_recipients
+            .iter()
+            .map(|address| address.into_array())
+            .collect(),
+    };
+    policy.validate()?;
+    let member = CommitteeMember::new(
+        signer,
+        Watchtower::new(
+            ZcashWatcher::new(&cli.zcash_rpc),
+            EthWatcher::new(&cli.eth_rpc, &cli.bridge_address, &cli.wzec_address),
+        )
+        .with_deposit_policy(policy),
+        chain_id,
+        bridge,
+    )
+    .with_history_from(cli.bridge_deployed_at);
+    tracing::info!(
+        "Committee member {} signing for bridge {bridge} on chain {chain_id}",
+        member.address()
+    );
+
+    let listener = tokio::net::TcpListener::bind(cli.listen).await?;
+    tracing::info!("Listening on {}", cli.listen);
+    axum::serve(listener, member_service(Arc::new(member))).await?;
+    Ok(())
+}
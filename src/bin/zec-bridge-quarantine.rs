@@ -0,0 +1,109 @@
+//! Operator command resolving the transfers quarantined by the relayer.
+
+use clap::{Parser, Subcommand};
+use zcash_eth_bridge::{
+    quarantine::{QuarantineStore, QuarantinedTransfer, Resolution},
+    types::ZcashRecipient,
+};
+use zcash_protocol::{TxId, consensus::NetworkType};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "zec-bridge-quarantine",
+    about = "List, release or refund transfers that made state updates revert"
+)]
+struct Cli {
+    /// Quarantine file of the relayer.
+    #[arg(long, default_value = "quarantine.json")]
+    store: String,
+    /// Print machine-readable JSON instead of human-readable text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the quarantined transfers.
+    List,
+    /// Include a transfer in the next state update again.
+    Release { id: u64 },
+    /// Pay a quarantined deposit back on Zcash, or return the tokens of a quarantined withdrawal
+    /// to its requester on Ethereum.
+    Refund {
+        id: u64,
+        /// Zcash address to refund a deposit to, instead of the refund address of the deposit.
+        #[arg(long)]
+        to: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let store = QuarantineStore::new(&cli.store);
+    match cli.command {
+        Command::List => {
+            let entries = store.entries().await?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+                return Ok(());
+            }
+            for entry in entries {
+                let transfer = match &entry.transfer {
+                    QuarantinedTransfer::Deposit(deposit) => format!(
+                        "deposit {}:{} of {} zatoshis to 0x{}",
+                        TxId::from_bytes(deposit.txid),
+                        deposit.index,
+                        deposit.amount,
+                        hex::encode(deposit.eth_address)
+                    ),
+                    QuarantinedTransfer::Withdrawal(withdrawal) => format!(
+                        "withdrawal of {} zatoshis to {:?}",
+                        withdrawal.amount, withdrawal.recipient
+                    ),
+                };
+                let resolution = entry
+                    .resolution
+                    .map_or(String::new(), |resolution| format!(" [{resolution:?}]"));
+                println!("#{} {transfer}: {}{resolution}", entry.id, entry.error);
+            }
+        }
+        Command::Release { id } => {
+            store.resolve(id, Resolution::Release).await?;
+            println!("Transfer #{id} is released into the next state update");
+        }
+        Command::Refund { id, to } => {
+            let entry = store
+                .entries()
+                .await?
+                .into_iter()
+                .find(|entry| entry.id == id)
+                .ok_or_else(|| anyhow::anyhow!("no quarantined transfer #{id}"))?;
+            match entry.transfer {
+                QuarantinedTransfer::Deposit(deposit) => {
+                    let to = match to {
+                        Some(address) => ZcashRecipient::parse(&address, NetworkType::Regtest)?,
+                        None => deposit.refund_to.ok_or_else(|| {
+                            anyhow::anyhow!("deposit #{id} has no refund address, pass --to")
+                        })?,
+                    };
+                    store.resolve(id, Resolution::Refund { to }).await?;
+                    println!("Deposit #{id} is refunded in the next state update");
+                }
+                QuarantinedTransfer::Withdrawal(_) => {
+                    anyhow::ensure!(
+                        to.is_none(),
+                        "withdrawals are returned to their requester, --to does not apply"
+                    );
+                    store.resolve(id, Resolution::Return).await?;
+                    println!(
+                        "Withdrawal #{id} is returned to its requester in the next state update"
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Read-only watchtower re-deriving every state update submitted by the bridge operator.
+
+use std::{collections::VecDeque, time::Duration};
+
+use alloy::{primitives::Address, providers::Provider as _};
+use clap::Parser;
+use tracing_subscriber::EnvFilter;
+use zcash_eth_bridge::{
+    deposits::DepositPolicy,
+    eth::watcher::EthWatcher,
+    types::StateUpdate,
+    watchtower::{
+        Carried, ExpectedUpdate, StfSpend, Watchtower, check_commitment, check_stf_proof,
+        check_stf_signatures, check_stf_spend, check_update, report,
+    },
+    zcash::watcher::ZcashWatcher,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "zec-bridge-watchtower",
+    about = "Independently verify the state updates of the Zcash <-> Ethereum bridge"
+)]
+struct Cli {
+    #[arg(long, default_value = "127.0.0.1:18232")]
+    zcash_rpc: String,
+    #[arg(long, default_value = "http://127.0.0.1:8545")]
+    eth_rpc: String,
+    #[arg(long, default_value = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512")]
+    bridge_address: String,
+    #[arg(long, default_value = "0x5FbDB2315678afecb367f032d93F642f64180aa3")]
+    wzec_address: String,
+    /// First Zcash height to scan for STF spends.
+    #[arg(long, default_value_t = 1)]
+    from_zcash_height: u32,
+    /// First Ethereum block to scan for state updates.
+    #[arg(long, default_value_t = 0)]
+    from_eth_block: u64,
+    /// Ethereum block the bridge was deployed in; the updates between it and
+    /// `--from-eth-block` are replayed to re-derive the transfers they carried.
+    #[arg(long, default_value_t = 0)]
+    bridge_deployed_at: u64,
+    /// Seconds between polls of both nodes.
+    #[arg(long, default_value_t = 5)]
+    poll_interval: u64,
+    /// Smallest deposit total credited to a recipient, in zatoshis; must match the relayer.
+    #[arg(long, default_value_t = 10_000)]
+    min_deposit: u64,
+    /// Bridge fee on deposits in basis points; must match the relayer.
+    #[arg(long, default_value_t = 10)]
+    deposit_fee_bps: u64,
+    /// Ethereum address the deposit fees are minted to; must match the relayer.
+    #[arg(long, default_value = "0xa0Ee7A142d267C1f36714E4a8F75612F20a79720")]
+    fee_recipient: Address,
+    /// Age in Zcash blocks after which deposits below the minimum are refunded.
+    #[arg(long, default_value_t = 100)]
+    refund_after_blocks: u64,
+    /// Ethereum address whose deposits are refunded instead of credited; must match the relayer.
+    #[arg(long = "deny-recipient")]
+    deny_recipients: Vec<Address>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive("info".parse().unwrap())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let cli = Cli::parse();
+    let policy = DepositPolicy {
+        min_amount: cli.min_deposit,
+        fee_bps: cli.deposit_fee_bps,
+        fee_recipient: cli.fee_recipient.into_array(),
+        refund_after: cli.refund_after_blocks,
+        deny_list: cli
+            .deny_recipients
+            .iter()
+            .map(|address| address.into_array())
+            .collect(),
+    };
+    policy.validate()?;
+    let watchtower = Watchtower::new(
+        ZcashWatcher::new(&cli.zcash_rpc),
+        EthWatcher::new(&cli.eth_rpc, &cli.bridge_address, &cli.wzec_address),
+    )
+    .with_deposit_policy(policy);
+
+    let chain_id = watchtower
+        .eth_watcher()
+        .bridge_contract
+        .provider()
+        .get_chain_id()
+        .await?;
+    let bridge = *watchtower.eth_watcher().bridge_contract.address();
+    // Committee whose signatures STF spends must carry, once one is set on the bridge contract.
+    let mut committee = None;
+
+    let mut next_zcash_height = cli.from_zcash_height;
+    let mut next_eth_block = cli.from_eth_block;
+    let mut previous: Option<StateUpdate> = None;
+    // Deposits queued and transfers held in quarantine after the previous update.
+    let mut carried = if cli.from_eth_block > cli.bridge_deployed_at {
+        watchtower
+            .replay_carried(cli.bridge_deployed_at, cli.from_eth_block - 1, u64::MAX)
+            .await?
+    } else {
+        Carried::default()
+    };
+    // Updates and STF spends are paired in submission order, since each update is processed by
+    // exactly one spend. Either side may be observed first.
+    let mut unpaired_updates: VecDeque<ExpectedUpdate> = VecDeque::new();
+    let mut unpaired_spends: VecDeque<StfSpend> = VecDeque::new();
+
+    loop {
+        let eth_tip = watchtower.eth_watcher().get_block_number().await?;
+        if eth_tip >= next_eth_block {
+            for submitted in watchtower
+                .submitted_updates(next_eth_block, eth_tip)
+                .await?
+            {
+                let expected = watchtower
+                    .expected_update(&submitted.update, std::mem::take(&mut carried))
+                    .await?;
+                let mut mismatches =
+                    check_update(previous.as_ref(), &submitted.update, &expected.update);
+                mismatches.extend(check_commitment(&submitted, &expected.update));
+                report(&format!("ETH tx {}", submitted.eth_tx), &mismatches);
+                if mismatches.is_empty() {
+                    tracing::info!(
+                        "Verified state update in ETH block {}: ZEC {}-{}, ETH {}-{}",
+                        submitted.eth_block,
+                        submitted.update.old_zcash_block + 1,
+                        submitted.update.new_zcash_block,
+                        submitted.update.old_eth_block + 1,
+                        submitted.update.new_eth_block
+                    );
+                }
+                previous = Some(submitted.update);
+                carried = expected.carried.clone();
+                unpaired_updates.push_back(expected);
+            }
+            next_eth_block = eth_tip + 1;
+        }
+
+        let zcash_tip = watchtower.zcash_watcher().get_block_count().await?;
+        if zcash_tip >= next_zcash_height {
+            unpaired_spends.extend(watchtower.stf_spends(next_zcash_height, zcash_tip).await?);
+            next_zcash_height = zcash_tip + 1;
+        }
+
+        if committee.is_none() && !unpaired_spends.is_empty() {
+            committee = watchtower.committee().await?;
+        }
+        while !unpaired_updates.is_empty() && !unpaired_spends.is_empty() {
+            let expected = unpaired_updates.pop_front().unwrap();
+            let spend = unpaired_spends.pop_front().unwrap();
+            let mut mismatches = check_stf_spend(&spend, &expected);
+            mismatches.extend(check_stf_proof(&spend, &expected));
+            mismatches.extend(watchtower.verify_stf_proof(&spend).await?);
+            if let Some(committee) = &committee {
+                mismatches.extend(check_stf_signatures(
+                    &spend, &expected, committee, chain_id, bridge,
+                ));
+            }
+            report(&format!("ZEC tx {}", spend.txid), &mismatches);
+            if mismatches.is_empty() {
+                tracing::info!(
+                    "Verified STF spend {} at ZEC height {}",
+                    spend.txid,
+                    spend.height
+                );
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(cli.poll_interval)).await;
+    }
+}
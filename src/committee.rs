@@ -0,0 +1,468 @@
+//! Committee of independent relayers co-signing state updates.
+//!
+//! Each member re-derives the update it is asked to sign from its own nodes, with the same
+//! checks as the watchtower, and signs the digest of its commitment only if both match. The
+//! submitting relayer collects signatures from the members and submits them once `threshold`
+//! distinct members agree, see `ZcashBridge.submitStateUpdateWithSignatures`.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alloy::{
+    primitives::{Address, B256, Signature, keccak256},
+    signers::{SignerSync as _, local::PrivateKeySigner},
+    sol_types::SolValue as _,
+};
+use axum::{
+    Json, Router,
+    extract::State,
+    routing::{get, post},
+};
+use futures::{StreamExt as _, stream::FuturesUnordered};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    types::StateUpdate,
+    watchtower::{Carried, Watchtower, check_update},
+};
+
+/// Digest signed by committee members, bound to the bridge and the chain it is deployed on.
+///
+/// Equal to `ZcashBridge.computeSignedDigest`; members sign it as an EIP-191 personal message.
+pub fn signing_digest(chain_id: u64, bridge: Address, update: &StateUpdate) -> B256 {
+    keccak256((chain_id, bridge, B256::new(update.commitment())).abi_encode())
+}
+
+/// Members allowed to sign state updates and the number of signatures required.
+#[derive(Debug, Clone)]
+pub struct Committee {
+    pub members: Vec<Address>,
+    pub threshold: usize,
+}
+
+impl Committee {
+    /// Checks `signatures` on `update` and returns `threshold` of them in the order expected by
+    /// the contract, i.e. by ascending signer address.
+    ///
+    /// Signatures of non-members, invalid signatures and duplicates are skipped.
+    pub fn aggregate(
+        &self,
+        chain_id: u64,
+        bridge: Address,
+        update: &StateUpdate,
+        signatures: &[Signature],
+    ) -> anyhow::Result<Vec<Signature>> {
+        let digest = signing_digest(chain_id, bridge, update);
+        let mut seen = HashSet::new();
+        let mut signed: Vec<_> = signatures
+            .iter()
+            .filter_map(|signature| {
+                let signer = signature.recover_address_from_msg(digest).ok()?;
+                (self.members.contains(&signer) && seen.insert(signer))
+                    .then_some((signer, *signature))
+            })
+            .collect();
+        anyhow::ensure!(
+            signed.len() >= self.threshold,
+            "got {} of {} required committee signatures",
+            signed.len(),
+            self.threshold
+        );
+        signed.sort_by_key(|(signer, _)| *signer);
+        Ok(signed
+            .into_iter()
+            .take(self.threshold)
+            .map(|(_, signature)| signature)
+            .collect())
+    }
+}
+
+/// Committee member signing only the updates it re-derives itself.
+pub struct CommitteeMember {
+    signer: PrivateKeySigner,
+    watchtower: Watchtower,
+    chain_id: u64,
+    bridge: Address,
+    /// Ethereum block from which submitted updates are replayed, see [`Self::with_history_from`].
+    history_from: u64,
+    /// Transfers carried after the updates signed so far, by the Zcash height they end at.
+    carried: Mutex<BTreeMap<u64, Carried>>,
+}
+
+impl CommitteeMember {
+    pub fn new(
+        signer: PrivateKeySigner,
+        watchtower: Watchtower,
+        chain_id: u64,
+        bridge: Address,
+    ) -> Self {
+        Self {
+            signer,
+            watchtower,
+            chain_id,
+            bridge,
+            history_from: 0,
+            carried: Mutex::default(),
+        }
+    }
+
+    /// Sets the Ethereum block, e.g. the deployment of the bridge, from which the updates
+    /// submitted before this member started are replayed.
+    pub fn with_history_from(mut self, eth_block: u64) -> Self {
+        self.history_from = eth_block;
+        self
+    }
+
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    /// Signs `claimed` if it equals the update re-derived for the same block ranges.
+    ///
+    /// Deposits queued by the deposit policy and transfers held in quarantine are known from the
+    /// previous update signed by this member or, after a restart, re-derived by replaying the
+    /// updates submitted to Ethereum.
+    pub async fn sign(&self, claimed: &StateUpdate) -> anyhow::Result<Signature> {
+        let known = self
+            .carried
+            .lock()
+            .unwrap()
+            .get(&claimed.old_zcash_block)
+            .cloned();
+        let carried = match known {
+            Some(carried) => carried,
+            None => {
+                let eth_tip = self.watchtower.eth_watcher().get_block_number().await?;
+                self.watchtower
+                    .replay_carried(self.history_from, eth_tip, claimed.old_zcash_block)
+                    .await?
+            }
+        };
+        let expected = self.watchtower.expected_update(claimed, carried).await?;
+        let mismatches = check_update(None, claimed, &expected.update);
+        anyhow::ensure!(
+            mismatches.is_empty(),
+            "update diverges from the chains: {mismatches:?}"
+        );
+        let mut carried = self.carried.lock().unwrap();
+        // Keeps the transfers carried before this update, in case it is signed again.
+        carried.retain(|&height, _| height >= claimed.old_zcash_block);
+        carried.insert(claimed.new_zcash_block, expected.carried);
+        drop(carried);
+
+        let digest = signing_digest(self.chain_id, self.bridge, claimed);
+        Ok(self.signer.sign_message_sync(digest.as_slice())?)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AddressResponse {
+    address: Address,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignRequest {
+    /// Canonical encoding of the update, hex encoded.
+    update: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignResponse {
+    /// 65-byte `r || s || v` signature, hex encoded.
+    signature: String,
+}
+
+/// Returns an HTTP service exposing `member` with the protocol used by [`collect_signatures`].
+pub fn member_service(member: Arc<CommitteeMember>) -> Router {
+    Router::new()
+        .route("/address", get(address_handler))
+        .route("/sign", post(sign_handler))
+        .with_state(member)
+}
+
+async fn address_handler(State(member): State<Arc<CommitteeMember>>) -> Json<AddressResponse> {
+    Json(AddressResponse {
+        address: member.address(),
+    })
+}
+
+async fn sign_handler(
+    State(member): State<Arc<CommitteeMember>>,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignResponse>, (axum::http::StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (axum::http::StatusCode::BAD_REQUEST, e.to_string());
+
+    let update = hex::decode(&request.update)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| StateUpdate::decode(&bytes))
+        .map_err(bad_request)?;
+    let signature = member.sign(&update).await.map_err(bad_request)?;
+    Ok(Json(SignResponse {
+        signature: hex::encode(signature.as_bytes()),
+    }))
+}
+
+/// Requests signatures on `update` from the committee members at `urls` concurrently.
+///
+/// Each member has `timeout` to answer. Members that are unreachable, too slow or refuse to sign
+/// are skipped, as are signatures of non-members, so the result has to be checked with
+/// [`Committee::aggregate`]. Collection stops once `committee.threshold` distinct members
+/// signed, without waiting for the rest.
+pub async fn collect_signatures(
+    urls: &[String],
+    update: &StateUpdate,
+    committee: &Committee,
+    chain_id: u64,
+    bridge: Address,
+    timeout: Duration,
+) -> Vec<Signature> {
+    let client = reqwest::Client::new();
+    let request = SignRequest {
+        update: hex::encode(update.encode()),
+    };
+    let digest = signing_digest(chain_id, bridge, update);
+    let mut responses: FuturesUnordered<_> = urls
+        .iter()
+        .map(|url| {
+            let response = request_signature(&client, url, &request);
+            async move {
+                let response = tokio::time::timeout(timeout, response)
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("no answer within {timeout:?}")));
+                (url, response)
+            }
+        })
+        .collect();
+
+    let mut signers = HashSet::new();
+    let mut signatures = Vec::new();
+    while signers.len() < committee.threshold {
+        let Some((url, response)) = responses.next().await else {
+            break;
+        };
+        let signature = response.and_then(|signature| {
+            let signer = signature.recover_address_from_msg(digest)?;
+            anyhow::ensure!(
+                committee.members.contains(&signer),
+                "{signer} is not a committee member"
+            );
+            Ok((signer, signature))
+        });
+        match signature {
+            Ok((signer, signature)) => {
+                if signers.insert(signer) {
+                    signatures.push(signature);
+                }
+            }
+            Err(err) => tracing::warn!("Committee member {url} did not sign: {err:#}"),
+        }
+    }
+    signatures
+}
+
+async fn request_signature(
+    client: &reqwest::Client,
+    url: &str,
+    request: &SignRequest,
+) -> anyhow::Result<Signature> {
+    let response: SignResponse = client
+        .post(format!("{}/sign", url.trim_end_matches('/')))
+        .json(request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(Signature::try_from(
+        hex::decode(response.signature)?.as_slice(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use zcash_protocol::TxId;
+
+    use super::*;
+    use crate::{
+        test_utils::update,
+        watchtower::{ExpectedUpdate, Mismatch, StfSpend, check_stf_signatures},
+    };
+
+    const CHAIN_ID: u64 = 31337;
+    const BRIDGE: Address = Address::repeat_byte(0xb1);
+
+    fn state_update() -> StateUpdate {
+        update(20, 22)
+            .zcash_blocks(100, 102)
+            .deposit(90_000, [0x33; 20])
+            .build()
+    }
+
+    fn sign(signer: &PrivateKeySigner, update: &StateUpdate) -> Signature {
+        signer
+            .sign_message_sync(signing_digest(CHAIN_ID, BRIDGE, update).as_slice())
+            .unwrap()
+    }
+
+    #[test]
+    fn aggregate_sorts_and_filters_signatures() -> anyhow::Result<()> {
+        let members: Vec<_> = (0..3).map(|_| PrivateKeySigner::random()).collect();
+        let outsider = PrivateKeySigner::random();
+        let committee = Committee {
+            members: members.iter().map(|member| member.address()).collect(),
+            threshold: 2,
+        };
+        let update = state_update();
+
+        let signatures = vec![
+            sign(&outsider, &update),
+            sign(&members[0], &update),
+            sign(&members[0], &update),
+            sign(&members[2], &update),
+        ];
+        let aggregated = committee.aggregate(CHAIN_ID, BRIDGE, &update, &signatures)?;
+        let digest = signing_digest(CHAIN_ID, BRIDGE, &update);
+        let signers = aggregated
+            .iter()
+            .map(|signature| signature.recover_address_from_msg(digest))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut expected = vec![members[0].address(), members[2].address()];
+        expected.sort();
+        assert_eq!(signers, expected);
+
+        // Duplicates and outsiders do not count towards the threshold.
+        assert!(
+            committee
+                .aggregate(CHAIN_ID, BRIDGE, &update, &signatures[..3])
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn signatures_are_bound_to_the_bridge() {
+        let member = PrivateKeySigner::random();
+        let committee = Committee {
+            members: vec![member.address()],
+            threshold: 1,
+        };
+        let update = state_update();
+        let signature = sign(&member, &update);
+
+        assert!(
+            committee
+                .aggregate(CHAIN_ID, BRIDGE, &update, &[signature])
+                .is_ok()
+        );
+        assert!(
+            committee
+                .aggregate(CHAIN_ID + 1, BRIDGE, &update, &[signature])
+                .is_err()
+        );
+        assert!(
+            committee
+                .aggregate(CHAIN_ID, Address::ZERO, &update, &[signature])
+                .is_err()
+        );
+    }
+
+    /// Serves a committee member answering every request with `response`.
+    async fn member(response: Option<Signature>) -> anyhow::Result<String> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let router = Router::new().route(
+            "/sign",
+            post(move || async move {
+                match response {
+                    Some(signature) => Json(SignResponse {
+                        signature: hex::encode(signature.as_bytes()),
+                    }),
+                    None => std::future::pending().await,
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn signatures_are_collected_concurrently_up_to_the_threshold() -> anyhow::Result<()> {
+        let members: Vec<_> = (0..3).map(|_| PrivateKeySigner::random()).collect();
+        let committee = Committee {
+            members: members.iter().map(|member| member.address()).collect(),
+            threshold: 2,
+        };
+        let update = state_update();
+        let outsider = sign(&PrivateKeySigner::random(), &update);
+        // The last member never answers.
+        let urls = vec![
+            member(Some(outsider)).await?,
+            member(Some(sign(&members[0], &update))).await?,
+            member(Some(sign(&members[1], &update))).await?,
+            member(None).await?,
+        ];
+
+        // Reaching the threshold does not wait for the silent member.
+        let timeout = Duration::from_secs(60);
+        let signatures = tokio::time::timeout(
+            Duration::from_secs(10),
+            collect_signatures(&urls, &update, &committee, CHAIN_ID, BRIDGE, timeout),
+        )
+        .await?;
+        assert_eq!(signatures.len(), 2);
+        assert!(
+            committee
+                .aggregate(CHAIN_ID, BRIDGE, &update, &signatures)
+                .is_ok()
+        );
+
+        // Below the threshold, the silent member is given up on after the timeout.
+        let committee = Committee {
+            threshold: 3,
+            ..committee
+        };
+        let timeout = Duration::from_millis(200);
+        let signatures =
+            collect_signatures(&urls, &update, &committee, CHAIN_ID, BRIDGE, timeout).await;
+        assert_eq!(signatures.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn stf_spends_must_carry_committee_signatures() {
+        let members: Vec<_> = (0..2).map(|_| PrivateKeySigner::random()).collect();
+        let committee = Committee {
+            members: members.iter().map(|member| member.address()).collect(),
+            threshold: 2,
+        };
+        let expected = ExpectedUpdate {
+            update: state_update(),
+            deposit_outpoints: Vec::new(),
+            carried: Carried::default(),
+        };
+        let mut spend = StfSpend {
+            txid: TxId::from_bytes([0x77; 32]),
+            height: 102,
+            deposit_inputs: Vec::new(),
+            transparent_withdrawals: Vec::new(),
+            shielded_outflow: 0,
+            prev_value: 0,
+            new_value: 90_000,
+            signatures: members
+                .iter()
+                .map(|member| sign(member, &expected.update))
+                .collect(),
+            proof: None,
+        };
+        assert!(check_stf_signatures(&spend, &expected, &committee, CHAIN_ID, BRIDGE).is_empty());
+
+        spend.signatures.pop();
+        assert!(matches!(
+            check_stf_signatures(&spend, &expected, &committee, CHAIN_ID, BRIDGE)[..],
+            [Mismatch::StfSignatures { .. }]
+        ));
+    }
+}
@@ -0,0 +1,369 @@
+//! Rules deciding how deposits observed on Zcash are credited on Ethereum.
+//!
+//! Credited deposits pay a bridge fee in basis points, minted to the fee recipient in the same
+//! update. Deposits to a recipient whose pending total is below the minimum stay unclaimed in
+//! their TZE outputs until further deposits reach the minimum, and are refunded on Zcash once
+//! they are [`DepositPolicy::refund_after`] blocks old.
+//!
+//! Deposits to recipients the bridge contract would reject or that could never use the minted
+//! WZEC, i.e. the zero address, precompiles and the deny-list, are refunded right away instead,
+//! so that a single bad deposit cannot make every update revert.
+
+use std::collections::HashMap;
+
+use zcash_primitives::transaction::components::{TzeOut, tze};
+
+use crate::types::{DepositRefund, ZcashRecipient, ZecToEthTransfer};
+
+/// Denominator of [`DepositPolicy::fee_bps`].
+pub const BPS: u64 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepositPolicy {
+    /// Smallest total in zatoshis credited to a recipient, summed over its pending deposits.
+    pub min_amount: u64,
+    /// Bridge fee in basis points of every credited deposit, below [`BPS`].
+    pub fee_bps: u64,
+    /// Ethereum address the fees are minted to.
+    pub fee_recipient: [u8; 20],
+    /// Age in Zcash blocks after which a deposit below the minimum is refunded.
+    pub refund_after: u64,
+    /// Ethereum addresses never credited.
+    pub deny_list: Vec<[u8; 20]>,
+}
+
+/// Why a deposit recipient cannot be credited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidRecipient {
+    #[error("zero address")]
+    Zero,
+    #[error("precompile address")]
+    Precompile,
+    #[error("denied address")]
+    Denied,
+}
+
+/// Deposit output on Zcash not claimed by the STF yet.
+#[derive(Debug, Clone)]
+pub struct ObservedDeposit {
+    pub outpoint: tze::OutPoint,
+    pub output: TzeOut,
+    /// Recipient and full amount of the deposit.
+    pub transfer: ZecToEthTransfer,
+    /// Height of the block including the deposit.
+    pub height: u64,
+    /// Where a refund is paid, if the deposit has a known owner on Zcash.
+    pub refund_to: Option<ZcashRecipient>,
+}
+
+/// Outcome of applying a [`DepositPolicy`] to the pending deposits.
+#[derive(Debug, Clone, Default)]
+pub struct Settlement {
+    /// Deposits credited on Ethereum, in the order of `transfers`.
+    pub credited: Vec<ObservedDeposit>,
+    /// Deposits paid back on Zcash, in the order of `refunds`.
+    pub refunded: Vec<ObservedDeposit>,
+    /// Deposits left for a later update.
+    pub queued: Vec<ObservedDeposit>,
+    /// Transfers minted on Ethereum: the credited deposits net of the fee, then the fees.
+    pub transfers: Vec<ZecToEthTransfer>,
+    pub refunds: Vec<DepositRefund>,
+}
+
+impl Settlement {
+    /// Deposit outputs the STF spend claims: the credited ones, then the refunded ones.
+    pub fn claimed(&self) -> Vec<(tze::OutPoint, TzeOut)> {
+        self.credited
+            .iter()
+            .chain(&self.refunded)
+            .map(|deposit| (deposit.outpoint.clone(), deposit.output.clone()))
+            .collect()
+    }
+
+    /// Refunds `deposit` to `to` regardless of the policy, e.g. on request of an operator.
+    pub fn refund(&mut self, deposit: ObservedDeposit, to: ZcashRecipient) {
+        self.refunds.push(DepositRefund {
+            amount: deposit.transfer.amount,
+            recipient: to,
+            deposit_txid: *deposit.outpoint.txid().as_ref(),
+        });
+        self.refunded.push(deposit);
+    }
+}
+
+impl DepositPolicy {
+    /// Credits every deposit to a valid recipient in full, like a bridge without deposit rules.
+    pub const NONE: Self = Self {
+        min_amount: 0,
+        fee_bps: 0,
+        fee_recipient: [0; 20],
+        refund_after: u64::MAX,
+        deny_list: Vec::new(),
+    };
+
+    /// Checks that the policy can be applied: the fee is below 100% and, if fees are taken, can
+    /// be minted to the fee recipient.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.fee_bps < BPS, "deposit fee must be below 100%");
+        if self.fee_bps > 0 {
+            self.check_recipient(&self.fee_recipient)
+                .map_err(|e| anyhow::anyhow!("invalid deposit fee recipient: {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that deposits to `address` can be credited.
+    ///
+    /// Addresses up to `0xff` are treated as precompiles, covering the current ones and room for
+    /// future forks.
+    pub fn check_recipient(&self, address: &[u8; 20]) -> Result<(), InvalidRecipient> {
+        if *address == [0; 20] {
+            Err(InvalidRecipient::Zero)
+        } else if address[..19] == [0; 19] {
+            Err(InvalidRecipient::Precompile)
+        } else if self.deny_list.contains(address) {
+            Err(InvalidRecipient::Denied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fee taken from a credited deposit of `amount` zatoshis.
+    pub fn fee(&self, amount: u64) -> u64 {
+        (u128::from(amount) * u128::from(self.fee_bps) / u128::from(BPS)) as u64
+    }
+
+    /// Decides which of `deposits` are credited, refunded or queued in an update ending at
+    /// Zcash height `zcash_height`.
+    ///
+    /// Deposits to invalid recipients without a refund address stay queued, locked in their
+    /// outputs.
+    ///
+    /// The outcome only depends on the arguments, so verifiers holding the same queue re-derive
+    /// the same settlement.
+    pub fn settle(&self, deposits: Vec<ObservedDeposit>, zcash_height: u64) -> Settlement {
+        let mut totals: HashMap<[u8; 20], u64> = HashMap::new();
+        for deposit in &deposits {
+            if self.check_recipient(&deposit.transfer.eth_address).is_err() {
+                continue;
+            }
+            let total = totals.entry(deposit.transfer.eth_address).or_default();
+            *total = total.saturating_add(deposit.transfer.amount);
+        }
+
+        let mut settlement = Settlement::default();
+        for deposit in deposits {
+            let Some(&total) = totals.get(&deposit.transfer.eth_address) else {
+                if deposit.refund_to.is_some() {
+                    settlement.refunded.push(deposit);
+                } else {
+                    settlement.queued.push(deposit);
+                }
+                continue;
+            };
+            if total >= self.min_amount {
+                settlement.credited.push(deposit);
+            } else if deposit.refund_to.is_some()
+                && zcash_height.saturating_sub(deposit.height) >= self.refund_after
+            {
+                settlement.refunded.push(deposit);
+            } else {
+                settlement.queued.push(deposit);
+            }
+        }
+
+        let mut fees = 0;
+        for deposit in &settlement.credited {
+            let fee = self.fee(deposit.transfer.amount);
+            fees += fee;
+            settlement.transfers.push(ZecToEthTransfer {
+                amount: deposit.transfer.amount - fee,
+                eth_address: deposit.transfer.eth_address,
+            });
+        }
+        if fees > 0 {
+            settlement.transfers.push(ZecToEthTransfer {
+                amount: fees,
+                eth_address: self.fee_recipient,
+            });
+        }
+        settlement.refunds = settlement
+            .refunded
+            .iter()
+            .map(|deposit| DepositRefund {
+                amount: deposit.transfer.amount,
+                recipient: deposit.refund_to.clone().unwrap(),
+                deposit_txid: *deposit.outpoint.txid().as_ref(),
+            })
+            .collect();
+        settlement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{deposit, ids};
+
+    const POLICY: DepositPolicy = DepositPolicy {
+        min_amount: 100_000,
+        fee_bps: 30,
+        fee_recipient: [0xFE; 20],
+        refund_after: 10,
+        deny_list: Vec::new(),
+    };
+
+    const ALICE: [u8; 20] = [0xA1; 20];
+    const BOB: [u8; 20] = [0xB0; 20];
+
+    #[test]
+    fn fees_are_minted_to_fee_recipient() {
+        let settlement = POLICY.settle(
+            vec![
+                deposit(1, ALICE, 1_000_000, 5, true),
+                deposit(2, BOB, 200_000, 5, true),
+            ],
+            5,
+        );
+        assert_eq!(ids(&settlement.credited), [1, 2]);
+        assert_eq!(
+            settlement.transfers,
+            [
+                ZecToEthTransfer {
+                    amount: 997_000,
+                    eth_address: ALICE,
+                },
+                ZecToEthTransfer {
+                    amount: 199_400,
+                    eth_address: BOB,
+                },
+                ZecToEthTransfer {
+                    amount: 3_600,
+                    eth_address: [0xFE; 20],
+                },
+            ]
+        );
+        // The credited value equals the claimed deposits, keeping the bridge solvent.
+        let minted: u64 = settlement.transfers.iter().map(|t| t.amount).sum();
+        assert_eq!(minted, 1_200_000);
+        assert_eq!(settlement.claimed().len(), 2);
+    }
+
+    #[test]
+    fn small_deposits_are_queued_until_minimum() {
+        let first = POLICY.settle(vec![deposit(1, ALICE, 60_000, 5, true)], 6);
+        assert!(first.credited.is_empty() && first.refunded.is_empty());
+        assert!(first.transfers.is_empty());
+        assert_eq!(ids(&first.queued), [1]);
+
+        // A second deposit to the same recipient reaches the minimum, crediting both.
+        let mut pending = first.queued;
+        pending.push(deposit(2, ALICE, 50_000, 7, true));
+        pending.push(deposit(3, BOB, 50_000, 7, true));
+        let second = POLICY.settle(pending, 8);
+        assert_eq!(ids(&second.credited), [1, 2]);
+        assert_eq!(ids(&second.queued), [3]);
+        assert_eq!(second.transfers.len(), 3);
+    }
+
+    #[test]
+    fn old_small_deposits_are_refunded() {
+        let settlement = POLICY.settle(
+            vec![
+                deposit(1, ALICE, 60_000, 5, true),
+                deposit(2, BOB, 50_000, 5, false),
+                deposit(3, ALICE, 10_000, 12, true),
+            ],
+            15,
+        );
+        assert_eq!(ids(&settlement.refunded), [1]);
+        // Deposits without a known owner and young deposits stay queued.
+        assert_eq!(ids(&settlement.queued), [2, 3]);
+        assert_eq!(
+            settlement.refunds,
+            [DepositRefund {
+                amount: 60_000,
+                recipient: ZcashRecipient::Transparent([1; 20]),
+                deposit_txid: [1; 32],
+            }]
+        );
+        assert!(settlement.transfers.is_empty());
+        assert_eq!(settlement.claimed().len(), 1);
+    }
+
+    #[test]
+    fn no_policy_credits_deposits_in_full() {
+        let settlement = DepositPolicy::NONE.settle(vec![deposit(1, ALICE, 1, 5, false)], 5);
+        assert_eq!(
+            settlement.transfers,
+            [ZecToEthTransfer {
+                amount: 1,
+                eth_address: ALICE,
+            }]
+        );
+    }
+
+    #[test]
+    fn invalid_recipients_are_refunded_right_away() {
+        let policy = DepositPolicy {
+            deny_list: vec![BOB],
+            ..POLICY
+        };
+        let mut precompile = [0; 20];
+        precompile[19] = 0x0a;
+        let settlement = policy.settle(
+            vec![
+                deposit(1, [0; 20], 1_000_000, 5, true),
+                deposit(2, precompile, 1_000_000, 5, true),
+                deposit(3, BOB, 1_000_000, 5, true),
+                deposit(4, BOB, 1_000_000, 5, false),
+                deposit(5, ALICE, 1_000_000, 5, true),
+            ],
+            5,
+        );
+        assert_eq!(ids(&settlement.credited), [5]);
+        assert_eq!(ids(&settlement.refunded), [1, 2, 3]);
+        // Without a refund address the deposit stays locked, but does not block the update.
+        assert_eq!(ids(&settlement.queued), [4]);
+        assert_eq!(settlement.refunds.len(), 3);
+        assert!(
+            settlement
+                .transfers
+                .iter()
+                .all(|transfer| policy.check_recipient(&transfer.eth_address).is_ok())
+        );
+    }
+
+    #[test]
+    fn recipients_are_checked() {
+        let mut precompile = [0; 20];
+        precompile[19] = 0x01;
+        assert_eq!(
+            POLICY.check_recipient(&[0; 20]),
+            Err(InvalidRecipient::Zero)
+        );
+        assert_eq!(
+            DepositPolicy::NONE.check_recipient(&precompile),
+            Err(InvalidRecipient::Precompile)
+        );
+        assert_eq!(POLICY.check_recipient(&ALICE), Ok(()));
+    }
+
+    #[test]
+    fn policies_are_validated() {
+        assert!(POLICY.validate().is_ok());
+        // Without fees, the fee recipient is never minted to.
+        assert!(DepositPolicy::NONE.validate().is_ok());
+
+        let full_fee = DepositPolicy {
+            fee_bps: 10_000,
+            ..POLICY
+        };
+        assert!(full_fee.validate().is_err());
+        let denied_fee_recipient = DepositPolicy {
+            deny_list: vec![POLICY.fee_recipient],
+            ..POLICY
+        };
+        assert!(denied_fee_recipient.validate().is_err());
+    }
+}
@@ -11,3 +11,9 @@ sol!(
     WZec,
     "./contracts/out/WZec.sol/WZec.json"
 );
+
+sol!(
+    #[sol(rpc)]
+    IStateTransitionVerifier,
+    "./contracts/out/IStateTransitionVerifier.sol/IStateTransitionVerifier.json"
+);
@@ -1,3 +1,8 @@
 pub mod contract;
+#[cfg(feature = "node")]
 pub mod sender;
+#[cfg(feature = "node")]
+pub mod subscription;
+pub mod verifier;
+#[cfg(feature = "node")]
 pub mod watcher;
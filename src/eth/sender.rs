@@ -1,19 +1,26 @@
 use alloy::{
-    primitives::{Address, B256, U256},
+    primitives::{Address, B256, Bytes, Signature, U256},
     providers::{DynProvider, ProviderBuilder},
     signers::local::PrivateKeySigner,
 };
+use anyhow::Context as _;
 
 use crate::eth::contract::{
+    IStateTransitionVerifier,
     WZec::{self, WZecInstance},
-    ZcashBridge::{self, ZcashBridgeInstance},
+    ZcashBridge::{self, ZcashBridgeErrors, ZcashBridgeInstance},
 };
-use crate::types::StateUpdate;
+use crate::metrics::{METRICS, observe_rpc};
+use crate::prover::StateTransitionProof;
+use crate::shutdown::{CancellationToken, cancellable};
+use crate::types::{EthToZecTransfer, StateUpdate};
 
 pub struct EthSender {
-    _provider: DynProvider,
+    provider: DynProvider,
     pub bridge_contract: ZcashBridgeInstance<DynProvider>,
     pub wzec_contract: WZecInstance<DynProvider>,
+    /// Interrupts waiting for receipts.
+    cancel: CancellationToken,
 }
 
 impl EthSender {
@@ -27,49 +34,152 @@ impl EthSender {
         let bridge_contract = ZcashBridge::new(bridge_address.parse().unwrap(), provider.clone());
         let wzec_contract = WZec::new(wzec_address.parse().unwrap(), provider.clone());
         Self {
-            _provider: provider,
+            provider,
             bridge_contract,
             wzec_contract,
+            cancel: CancellationToken::new(),
         }
     }
 
-    pub async fn update_bridge(&self, state_update: StateUpdate) -> anyhow::Result<()> {
-        let state_update = super::contract::ZcashBridge::StateUpdate {
-            previousEthRoot: B256::new(state_update.old_eth_hash),
-            previousEthBlockNumber: state_update.old_eth_block,
-            newEthRoot: B256::new(state_update.new_eth_hash),
-            newEthBlockNumber: state_update.new_eth_block,
-            previousZecRoot: B256::new(state_update.old_zcash_hash),
-            previousZecBlockNumber: state_update.old_zcash_block,
-            newZecRoot: B256::new(state_update.new_zcash_hash),
-            newZecBlockNumber: state_update.new_zcash_block,
-            zecToEthTransfers: state_update
-                .zec_to_eth_transfers
-                .iter()
-                .map(
-                    |transfer| super::contract::ZcashBridge::ProcessedZecToEthTransfer {
-                        to: Address::from_slice(&transfer.eth_address),
-                        amount: U256::from(transfer.amount),
-                    },
-                )
-                .collect(),
-            ethToZecTransfers: state_update
-                .eth_to_zec_transfers
-                .iter()
-                .map(
-                    |transfer| super::contract::ZcashBridge::ProcessedEthToZecTransfer {
-                        pubkeyHash: transfer.pubkey_hash.into(),
-                        amount: U256::from(transfer.amount),
-                    },
-                )
-                .collect(),
+    /// Makes waiting for receipts fail once `token` is cancelled.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    /// Simulates processing the transfers of `state_update` on the current bridge state.
+    ///
+    /// Returns the decoded revert reason if the transfers make a submission revert.
+    pub async fn simulate_transfers(
+        &self,
+        state_update: &StateUpdate,
+    ) -> anyhow::Result<Result<(), String>> {
+        let update = contract_state_update(state_update);
+        let call = self.bridge_contract.simulateTransfers(
+            update.zecToEthTransfers,
+            update.ethToZecTransfers,
+            update.refunds,
+            update.withdrawalRefunds,
+        );
+        let Err(err) = observe_rpc("eth", "eth_call", async { call.call().await }).await else {
+            anyhow::bail!("transfer simulation returned without reverting");
         };
+        match err.as_decoded_interface_error::<ZcashBridgeErrors>() {
+            Some(ZcashBridgeErrors::TransfersSimulated(_)) => Ok(Ok(())),
+            Some(error) => Ok(Err(format!("{error:?}"))),
+            None if err.as_revert_data().is_some() => Ok(Err(err.to_string())),
+            None => Err(err.into()),
+        }
+    }
+
+    pub async fn update_bridge(
+        &self,
+        state_update: StateUpdate,
+        proof: &StateTransitionProof,
+        signatures: &[Signature],
+    ) -> anyhow::Result<()> {
+        proof.ensure_proves(&state_update)?;
+        let state_update = contract_state_update(&state_update);
+        let commitment = state_update.commitment;
 
-        let tx = self.bridge_contract.submitStateUpdate(state_update);
-        let pending_tx = tx.send().await?;
-        let receipt = pending_tx.get_receipt().await?;
+        // Once the bridge has a verifier, only proof-carrying updates are accepted, and once it
+        // has a committee, only signed ones.
+        let verifier = self.bridge_contract.verifier().call().await?;
+        let pending_tx = if verifier.is_zero() {
+            if self.bridge_contract.committeeThreshold().call().await? > U256::ZERO {
+                let signatures = signatures
+                    .iter()
+                    .map(|signature| Bytes::copy_from_slice(&signature.as_bytes()))
+                    .collect();
+                let tx = self
+                    .bridge_contract
+                    .submitStateUpdateWithSignatures(state_update, signatures);
+                observe_rpc("eth", "eth_sendTransaction", tx.send()).await?
+            } else {
+                let tx = self.bridge_contract.submitStateUpdate(state_update);
+                observe_rpc("eth", "eth_sendTransaction", tx.send()).await?
+            }
+        } else {
+            let public_inputs = Bytes::from(proof.public_inputs.encode());
+            let proof_bytes = Bytes::from(proof.proof.clone());
+            IStateTransitionVerifier::new(verifier, self.provider.clone())
+                .verifyProof(public_inputs, proof_bytes.clone())
+                .call()
+                .await
+                .context("state transition proof rejected by the verifier contract")?;
+            let tx = self.bridge_contract.submitStateUpdateWithProof(
+                state_update,
+                B256::new(proof.public_inputs.old_state_root),
+                B256::new(proof.public_inputs.new_state_root),
+                proof_bytes,
+            );
+            observe_rpc("eth", "eth_sendTransaction", tx.send()).await?
+        };
+        let receipt = cancellable(&self.cancel, async { Ok(pending_tx.get_receipt().await?) })
+            .await
+            .with_context(|| format!("waiting for state update {}", hex::encode(commitment)))?;
         tracing::debug!("[ETH] Submitted state update, receipt: {receipt:?}");
+        METRICS
+            .eth_fees_paid
+            .inc_by(receipt.gas_used as f64 * receipt.effective_gas_price as f64);
 
         Ok(())
     }
 }
+
+/// Converts `update` to its representation in the bridge contract.
+fn contract_state_update(update: &StateUpdate) -> ZcashBridge::StateUpdate {
+    let commitment = B256::new(update.commitment());
+    ZcashBridge::StateUpdate {
+        previousEthRoot: B256::new(update.old_eth_hash),
+        previousEthBlockNumber: update.old_eth_block,
+        newEthRoot: B256::new(update.new_eth_hash),
+        newEthBlockNumber: update.new_eth_block,
+        previousZecRoot: B256::new(update.old_zcash_hash),
+        previousZecBlockNumber: update.old_zcash_block,
+        newZecRoot: B256::new(update.new_zcash_hash),
+        newZecBlockNumber: update.new_zcash_block,
+        zecToEthTransfers: update
+            .zec_to_eth_transfers
+            .iter()
+            .map(|transfer| ZcashBridge::ProcessedZecToEthTransfer {
+                to: Address::from_slice(&transfer.eth_address),
+                amount: U256::from(transfer.amount),
+            })
+            .collect(),
+        ethToZecTransfers: contract_withdrawals(&update.eth_to_zec_transfers),
+        refunds: update
+            .refunds
+            .iter()
+            .map(|refund| ZcashBridge::ProcessedRefund {
+                amount: U256::from(refund.amount),
+                depositTxid: B256::new(refund.deposit_txid),
+                receiverType: refund.recipient.receiver_type(),
+                receiver: Bytes::copy_from_slice(refund.recipient.receiver_bytes()),
+            })
+            .collect(),
+        withdrawalRefunds: contract_withdrawals(&update.withdrawal_refunds),
+        quarantinedDeposits: update
+            .quarantined_deposits
+            .iter()
+            .map(|deposit| ZcashBridge::QuarantinedDeposit {
+                txid: B256::new(deposit.txid),
+                index: deposit.index,
+            })
+            .collect(),
+        quarantinedWithdrawals: contract_withdrawals(&update.quarantined_withdrawals),
+        commitment,
+    }
+}
+
+fn contract_withdrawals(
+    withdrawals: &[EthToZecTransfer],
+) -> Vec<ZcashBridge::ProcessedEthToZecTransfer> {
+    withdrawals
+        .iter()
+        .map(|transfer| ZcashBridge::ProcessedEthToZecTransfer {
+            receiverType: transfer.recipient.receiver_type(),
+            receiver: Bytes::copy_from_slice(transfer.recipient.receiver_bytes()),
+            amount: U256::from(transfer.amount),
+        })
+        .collect()
+}
@@ -0,0 +1,80 @@
+//! New Ethereum blocks through `eth_subscribe("newHeads")`.
+
+use std::{pin::Pin, time::Duration};
+
+use alloy::{
+    providers::{DynProvider, Provider as _, ProviderBuilder, WsConnect},
+    rpc::types::Header,
+};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt as _};
+
+use crate::{metrics::observe_rpc, subscription::BlockSubscription};
+
+/// Waits for Ethereum blocks announced over a WebSocket subscription, or by polling the tip of
+/// an HTTP endpoint every `poll_interval`.
+pub struct EthBlocks {
+    provider: DynProvider,
+    heads: Option<Pin<Box<dyn Stream<Item = Header> + Send>>>,
+    poll_interval: Duration,
+}
+
+impl EthBlocks {
+    /// Subscribes to new heads if `rpc_url` is a WebSocket URL, and polls it otherwise.
+    pub async fn connect(rpc_url: &str, poll_interval: Duration) -> anyhow::Result<Self> {
+        if !rpc_url.starts_with("ws") {
+            let provider = DynProvider::new(ProviderBuilder::new().connect_http(rpc_url.parse()?));
+            return Ok(Self {
+                provider,
+                heads: None,
+                poll_interval,
+            });
+        }
+
+        let provider = DynProvider::new(
+            ProviderBuilder::new()
+                .connect_ws(WsConnect::new(rpc_url))
+                .await?,
+        );
+        let heads = provider.subscribe_blocks().await?.into_stream();
+        Ok(Self {
+            provider,
+            heads: Some(Box::pin(heads)),
+            poll_interval,
+        })
+    }
+}
+
+#[async_trait]
+impl BlockSubscription for EthBlocks {
+    async fn wait_for_block(&mut self, height: u64) -> anyhow::Result<u64> {
+        // Blocks may have been mined before the subscription was created.
+        let tip = observe_rpc("eth", "eth_blockNumber", self.provider.get_block_number()).await?;
+        if tip > height {
+            return Ok(tip);
+        }
+        loop {
+            match &mut self.heads {
+                Some(heads) => {
+                    let Some(head) = heads.next().await else {
+                        tracing::warn!("Ethereum head subscription closed, polling the tip");
+                        self.heads = None;
+                        continue;
+                    };
+                    if head.number > height {
+                        return Ok(head.number);
+                    }
+                }
+                None => {
+                    tokio::time::sleep(self.poll_interval).await;
+                    let tip =
+                        observe_rpc("eth", "eth_blockNumber", self.provider.get_block_number())
+                            .await?;
+                    if tip > height {
+                        return Ok(tip);
+                    }
+                }
+            }
+        }
+    }
+}
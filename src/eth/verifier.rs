@@ -0,0 +1,86 @@
+//! Verification of Ethereum blocks and withdrawal events against their headers.
+//!
+//! Headers are checked to hash to the claimed block hash and to link via `parent_hash`. Withdrawal
+//! events are taken from the block receipts only once the receipts trie rebuilt from them matches
+//! the `receipts_root` of the header, instead of trusting `eth_getLogs`.
+
+use alloy::{
+    consensus::{ReceiptEnvelope, TxReceipt as _, proofs::calculate_receipt_root},
+    primitives::{Address, B256, Log},
+    rpc::types::{Block, TransactionReceipt},
+    sol_types::SolEvent as _,
+};
+use anyhow::Context as _;
+
+use crate::{
+    eth::contract::ZcashBridge::WithdrawalRequested,
+    types::{EthToZecTransfer, ZcashRecipient},
+};
+
+/// Checks that the header of `block` hashes to the block hash reported by the node.
+pub fn verify_header(block: &Block) -> anyhow::Result<()> {
+    let hash = block.header.inner.hash_slow();
+    anyhow::ensure!(
+        hash == block.header.hash,
+        "header of block {} hashes to {hash}, node reported {}",
+        block.header.number,
+        block.header.hash
+    );
+    Ok(())
+}
+
+/// Checks the headers of consecutive `blocks` and that the first one is the child of
+/// `parent_hash`.
+pub fn verify_chain(parent_hash: B256, blocks: &[Block]) -> anyhow::Result<()> {
+    let mut parent_hash = parent_hash;
+    for block in blocks {
+        verify_header(block)?;
+        anyhow::ensure!(
+            block.header.parent_hash == parent_hash,
+            "block {} does not link to parent {parent_hash}",
+            block.header.number
+        );
+        parent_hash = block.header.hash;
+    }
+    Ok(())
+}
+
+/// Checks that `receipts` are exactly the receipts committed to by the header of `block`.
+pub fn verify_receipts(
+    block: &Block,
+    receipts: Vec<TransactionReceipt>,
+) -> anyhow::Result<Vec<ReceiptEnvelope<Log>>> {
+    let receipts: Vec<_> = receipts
+        .into_iter()
+        .map(|receipt| receipt.into_primitives_receipt().inner)
+        .collect();
+    let root = calculate_receipt_root(&receipts);
+    anyhow::ensure!(
+        root == block.header.receipts_root,
+        "receipts of block {} have root {root}, header commits to {}",
+        block.header.number,
+        block.header.receipts_root
+    );
+    Ok(receipts)
+}
+
+/// Extracts the withdrawals requested from `bridge` in verified receipts, in log order.
+pub fn withdrawals_from_receipts(
+    bridge: Address,
+    receipts: &[ReceiptEnvelope<Log>],
+) -> anyhow::Result<Vec<EthToZecTransfer>> {
+    let mut transfers = Vec::new();
+    for log in receipts.iter().flat_map(|receipt| receipt.logs()) {
+        if log.address != bridge
+            || log.topics().first() != Some(&WithdrawalRequested::SIGNATURE_HASH)
+        {
+            continue;
+        }
+        let event = WithdrawalRequested::decode_log(log)?;
+        transfers.push(EthToZecTransfer {
+            recipient: ZcashRecipient::from_receiver(event.receiverType, &event.receiver)?,
+            amount: u64::try_from(event.amount).context("amount exceeds u64")?,
+        });
+    }
+    Ok(transfers)
+}
@@ -1,4 +1,6 @@
 use alloy::{
+    consensus::ReceiptEnvelope,
+    primitives::Log,
     providers::{DynProvider, Provider, ProviderBuilder},
     rpc::types::Filter,
     sol_types::SolEvent,
@@ -10,7 +12,9 @@ use crate::{
         WZec::{self, WZecInstance},
         ZcashBridge::{self, ZcashBridgeInstance},
     },
-    types::EthToZecTransfer,
+    eth::verifier,
+    metrics::observe_rpc,
+    types::{EthToZecTransfer, ZcashRecipient},
 };
 
 pub struct EthWatcher {
@@ -33,7 +37,8 @@ impl EthWatcher {
     }
 
     pub async fn get_block_number(&self) -> Result<u64> {
-        let block_number = self.provider.get_block_number().await?;
+        let block_number =
+            observe_rpc("eth", "eth_blockNumber", self.provider.get_block_number()).await?;
         Ok(block_number)
     }
 
@@ -51,12 +56,12 @@ impl EthWatcher {
             .from_block(first_block)
             .to_block(last_block)
             .event_signature(super::contract::ZcashBridge::WithdrawalRequested::SIGNATURE_HASH);
-        let logs = self.provider.get_logs(&filter).await?;
+        let logs = observe_rpc("eth", "eth_getLogs", self.provider.get_logs(&filter)).await?;
 
         for log in logs {
             let event = super::contract::ZcashBridge::WithdrawalRequested::decode_log(&log.into())?;
             let transfer = EthToZecTransfer {
-                pubkey_hash: event.pubkeyHash.0,
+                recipient: ZcashRecipient::from_receiver(event.receiverType, &event.receiver)?,
                 amount: u64::try_from(event.amount).expect("Amount exceeds u64"),
             };
             transfers.push(transfer);
@@ -65,12 +70,68 @@ impl EthWatcher {
         Ok(transfers)
     }
 
+    /// Like [`Self::extract_eth_to_zec_transfers`], but reads the withdrawals from the block
+    /// receipts after checking them against the `receipts_root` of each header.
+    pub async fn extract_verified_eth_to_zec_transfers(
+        &self,
+        blocks: &[alloy::rpc::types::Block],
+    ) -> Result<Vec<EthToZecTransfer>> {
+        let mut transfers = Vec::new();
+        for block in blocks {
+            let receipts = self.get_verified_receipts(block).await?;
+            transfers.extend(verifier::withdrawals_from_receipts(
+                *self.bridge_contract.address(),
+                &receipts,
+            )?);
+        }
+        Ok(transfers)
+    }
+
+    /// Returns the receipts of `block` after checking them against its `receipts_root`.
+    pub async fn get_verified_receipts(
+        &self,
+        block: &alloy::rpc::types::Block,
+    ) -> Result<Vec<ReceiptEnvelope<Log>>> {
+        let receipts = self.get_block_receipts(block.number()).await?;
+        verifier::verify_receipts(block, receipts)
+    }
+
+    /// Returns all logs of the bridge contract in blocks `from_block..=to_block`, in order.
+    pub async fn get_bridge_logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<alloy::rpc::types::Log>> {
+        let filter = Filter::new()
+            .address(*self.bridge_contract.address())
+            .from_block(from_block)
+            .to_block(to_block);
+        let logs = observe_rpc("eth", "eth_getLogs", self.provider.get_logs(&filter)).await?;
+        Ok(logs)
+    }
+
+    pub async fn get_block_receipts(
+        &self,
+        block_number: u64,
+    ) -> Result<Vec<alloy::rpc::types::TransactionReceipt>> {
+        let receipts = observe_rpc(
+            "eth",
+            "eth_getBlockReceipts",
+            self.provider.get_block_receipts(block_number.into()),
+        )
+        .await?
+        .expect("Block not found");
+        Ok(receipts)
+    }
+
     pub async fn get_block(&self, block_number: u64) -> Result<alloy::rpc::types::Block> {
-        let block = self
-            .provider
-            .get_block(block_number.into())
-            .await?
-            .expect("Block not found");
+        let block = observe_rpc(
+            "eth",
+            "eth_getBlockByNumber",
+            self.provider.get_block(block_number.into()),
+        )
+        .await?
+        .expect("Block not found");
         Ok(block)
     }
 }
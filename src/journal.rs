@@ -0,0 +1,216 @@
+//! Journal of the relayer, persisting what it needs to complete its work after a restart.
+//!
+//! A state update is sent to Zcash first and to Ethereum second. If the relayer stops in
+//! between, the STF has processed an update that the bridge contract has not, and every later
+//! update would be rejected by one of the chains. The update is therefore recorded before its
+//! STF spend is sent, and rolled forward on Ethereum when the relayer starts again, see
+//! [`crate::leader::ChainFollower`]. The journal also records where the STF was deployed, so that
+//! a restarted relayer follows it instead of deploying a new one.
+
+use std::path::PathBuf;
+
+use alloy::primitives::Signature;
+use serde::{Deserialize, Serialize};
+use zcash_protocol::TxId;
+
+use crate::{
+    prover::{PublicInputs, StateTransitionProof},
+    store::JsonFile,
+    types::StateUpdate,
+};
+
+/// Contents of a [`Journal`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalRecord {
+    /// Blocks before the deployment of the STF, from which the bridge is followed on restart.
+    pub follow_from: Option<FollowFrom>,
+    /// Update whose STF spend may have been sent without the update reaching Ethereum.
+    pub in_flight: Option<InFlightUpdate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FollowFrom {
+    pub zcash_height: u32,
+    pub eth_block: u64,
+}
+
+/// State update with what is needed to submit it to the bridge contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InFlightUpdate {
+    pub update: StateUpdate,
+    /// Public inputs of the proof, see [`PublicInputs::encode`].
+    #[serde(with = "hex::serde")]
+    public_inputs: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    proof: Vec<u8>,
+    /// 65-byte `r || s || v` committee signatures, hex encoded.
+    signatures: Vec<String>,
+    /// Transaction of the STF spend, once it is signed; it may not have reached the node.
+    stf_spend: Option<String>,
+}
+
+impl InFlightUpdate {
+    pub fn new(
+        update: StateUpdate,
+        proof: &StateTransitionProof,
+        signatures: &[Signature],
+    ) -> Self {
+        Self {
+            update,
+            public_inputs: proof.public_inputs.encode(),
+            proof: proof.proof.clone(),
+            signatures: signatures
+                .iter()
+                .map(|signature| hex::encode(signature.as_bytes()))
+                .collect(),
+            stf_spend: None,
+        }
+    }
+
+    pub fn stf_spend(&self) -> anyhow::Result<Option<TxId>> {
+        self.stf_spend
+            .as_ref()
+            .map(|txid| {
+                let bytes = hex::decode(txid)?;
+                Ok(TxId::from_bytes(bytes.as_slice().try_into()?))
+            })
+            .transpose()
+    }
+
+    pub fn proof(&self) -> anyhow::Result<StateTransitionProof> {
+        Ok(StateTransitionProof {
+            public_inputs: PublicInputs::decode(&self.public_inputs)?,
+            proof: self.proof.clone(),
+        })
+    }
+
+    pub fn signatures(&self) -> anyhow::Result<Vec<Signature>> {
+        self.signatures
+            .iter()
+            .map(|signature| Ok(Signature::try_from(hex::decode(signature)?.as_slice())?))
+            .collect()
+    }
+}
+
+/// Journal stored in a local file, which standbys sharing it with the leader read to roll its
+/// update forward. Every change is written atomically.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    file: JsonFile,
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            file: JsonFile::new(path.into()),
+        }
+    }
+
+    async fn update(
+        &self,
+        update: impl FnOnce(&mut JournalRecord) + Send + 'static,
+    ) -> anyhow::Result<()> {
+        self.file
+            .update(move |record: &mut JournalRecord| {
+                update(record);
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn read(&self) -> anyhow::Result<JournalRecord> {
+        self.file.read().await
+    }
+
+    /// Records a newly deployed STF, forgetting any update of a previous one.
+    pub async fn deployed(&self, follow_from: FollowFrom) -> anyhow::Result<()> {
+        self.update(move |record| {
+            *record = JournalRecord {
+                follow_from: Some(follow_from),
+                in_flight: None,
+            }
+        })
+        .await
+    }
+
+    /// Records `update` before its STF spend is sent.
+    pub async fn begin(&self, update: InFlightUpdate) -> anyhow::Result<()> {
+        self.update(move |record| record.in_flight = Some(update))
+            .await
+    }
+
+    /// Records the STF spend of the in-flight update before it is sent.
+    pub async fn sent(&self, stf_spend: TxId) -> anyhow::Result<()> {
+        self.update(move |record| {
+            if let Some(update) = &mut record.in_flight {
+                update.stf_spend = Some(hex::encode(stf_spend.as_ref()));
+            }
+        })
+        .await
+    }
+
+    /// Forgets the in-flight update once both chains have processed it, or neither will.
+    pub async fn complete(&self) -> anyhow::Result<()> {
+        self.update(|record| record.in_flight = None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::signers::{SignerSync as _, local::PrivateKeySigner};
+
+    use super::*;
+    use crate::test_utils::update;
+
+    #[tokio::test]
+    async fn journal_keeps_the_in_flight_update_until_completed() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("journal-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let journal = Journal::new(&path);
+        assert_eq!(journal.read().await?, JournalRecord::default());
+
+        let follow_from = FollowFrom {
+            zcash_height: 10,
+            eth_block: 20,
+        };
+        journal.deployed(follow_from).await?;
+
+        let update = update(20, 22).zcash_blocks(10, 12).build();
+        let proof = StateTransitionProof {
+            public_inputs: PublicInputs {
+                old_state_root: [1; 32],
+                new_state_root: [2; 32],
+                update_commitment: update.commitment(),
+            },
+            proof: vec![0xAB; 4],
+        };
+        let signature = PrivateKeySigner::random().sign_message_sync(b"update")?;
+        journal
+            .begin(InFlightUpdate::new(update.clone(), &proof, &[signature]))
+            .await?;
+        let stf_spend = TxId::from_bytes([7; 32]);
+        journal.sent(stf_spend).await?;
+
+        // The update survives a restart of the relayer.
+        let record = Journal::new(&path).read().await?;
+        assert_eq!(record.follow_from, Some(follow_from));
+        let in_flight = record.in_flight.expect("in-flight update");
+        assert_eq!(in_flight.update, update);
+        assert_eq!(in_flight.proof()?, proof);
+        assert_eq!(in_flight.signatures()?, [signature]);
+        assert_eq!(in_flight.stf_spend()?, Some(stf_spend));
+
+        journal.complete().await?;
+        let record = journal.read().await?;
+        assert_eq!(record.follow_from, Some(follow_from));
+        assert!(record.in_flight.is_none());
+
+        // A new STF starts over.
+        journal
+            .begin(InFlightUpdate::new(update, &proof, &[]))
+            .await?;
+        journal.deployed(follow_from).await?;
+        assert!(journal.read().await?.in_flight.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,411 @@
+//! Active/standby operation of several relayer instances.
+//!
+//! Instances compete for a lease through a [`LeaseBackend`], and only the instance holding it
+//! submits state updates. Standbys follow both chains with a [`ChainFollower`], so that they can
+//! continue from the last submitted update as soon as the lease of the leader expires.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE;
+use zcash_primitives::transaction::{
+    Transaction,
+    components::{TzeOut, tze},
+};
+use zcash_protocol::{TxId, consensus::BranchId, value::Zatoshis};
+use zebra_chain::serialization::ZcashSerialize as _;
+
+use crate::{
+    state::{BridgeState, ChainCheckpoint},
+    watchtower::{Carried, MODE_STF, Watchtower},
+    zcash::deposit::MODE_DEPOSIT,
+};
+
+/// Shared lease electing the relayer allowed to submit state updates.
+#[async_trait]
+pub trait LeaseBackend: Send + Sync {
+    /// Acquires the lease for `holder` if it is free or expired, or extends it if `holder`
+    /// already has it, so that it expires `ttl` from now.
+    ///
+    /// Returns whether `holder` holds the lease.
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> anyhow::Result<bool>;
+
+    /// Releases the lease if it is held by `holder`.
+    async fn release(&self, holder: &str) -> anyhow::Result<()>;
+}
+
+/// Lease record stored by [`FileLease`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    pub holder: String,
+    /// Expiry as milliseconds since the Unix epoch.
+    pub expires_at_ms: u64,
+}
+
+/// Lease stored in a local file, for instances running on the same host and in tests.
+///
+/// Every access holds an exclusive lock on the file, so concurrent instances cannot both
+/// observe a free lease.
+#[derive(Debug, Clone)]
+pub struct FileLease {
+    path: PathBuf,
+}
+
+impl FileLease {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Runs `update` on the current lease under the file lock and stores the lease it returns.
+    async fn update<T: Send + 'static>(
+        &self,
+        update: impl FnOnce(Option<Lease>) -> (Option<Lease>, T) + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            file.lock()?;
+            let (lease, result) = update(read_lease(&mut file)?);
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            if let Some(lease) = lease {
+                serde_json::to_writer(&file, &lease)?;
+            }
+            file.flush()?;
+            Ok(result)
+        })
+        .await?
+    }
+}
+
+fn read_lease(file: &mut File) -> anyhow::Result<Option<Lease>> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    if contents.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&contents)?))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before the Unix epoch")
+        .as_millis() as u64
+}
+
+#[async_trait]
+impl LeaseBackend for FileLease {
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let holder = holder.to_string();
+        self.update(move |lease| {
+            let now = now_ms();
+            match lease {
+                Some(lease) if lease.holder != holder && lease.expires_at_ms > now => {
+                    (Some(lease), false)
+                }
+                _ => (
+                    Some(Lease {
+                        holder,
+                        expires_at_ms: now + ttl.as_millis() as u64,
+                    }),
+                    true,
+                ),
+            }
+        })
+        .await
+    }
+
+    async fn release(&self, holder: &str) -> anyhow::Result<()> {
+        let holder = holder.to_string();
+        self.update(move |lease| (lease.filter(|lease| lease.holder != holder), ()))
+            .await
+    }
+}
+
+/// Lease held, or awaited, by this relayer instance.
+pub struct Leadership {
+    backend: Box<dyn LeaseBackend>,
+    holder: String,
+    ttl: Duration,
+    leader: bool,
+}
+
+impl Leadership {
+    pub fn new(backend: Box<dyn LeaseBackend>, holder: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            backend,
+            holder: holder.into(),
+            ttl,
+            leader: false,
+        }
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+
+    /// Acquires or renews the lease and returns whether this instance leads for the next `ttl`.
+    pub async fn poll(&mut self) -> anyhow::Result<bool> {
+        let leader = self.backend.try_acquire(&self.holder, self.ttl).await?;
+        match (self.leader, leader) {
+            (false, true) => tracing::info!("{} acquired the relayer lease", self.holder),
+            (true, false) => tracing::warn!("{} lost the relayer lease", self.holder),
+            _ => {}
+        }
+        self.leader = leader;
+        Ok(leader)
+    }
+
+    pub async fn release(&mut self) -> anyhow::Result<()> {
+        self.backend.release(&self.holder).await?;
+        self.leader = false;
+        Ok(())
+    }
+}
+
+/// Point from which a new leader continues the bridge.
+#[derive(Debug, Clone)]
+pub struct Takeover {
+    pub stf: (tze::OutPoint, TzeOut),
+    /// Bridge state committed to by the STF output.
+    pub state: BridgeState,
+    /// Value held by the STF output.
+    pub deposited: Zatoshis,
+    /// Deposits queued and transfers held in quarantine after the last update.
+    pub carried: Carried,
+    /// Indices of the credited deposits in the deposit log, by Zcash transaction.
+    pub deposit_indices: HashMap<TxId, Vec<u64>>,
+    /// Last blocks covered by the bridge state.
+    pub zcash: ChainCheckpoint,
+    pub eth: ChainCheckpoint,
+}
+
+/// STF output found on Zcash along with the number of updates it has processed.
+#[derive(Debug, Clone)]
+struct FollowedStf {
+    outpoint: tze::OutPoint,
+    output: TzeOut,
+    /// STF spends since the STF was created.
+    spends: u64,
+    /// Checkpoints at which the STF was created, used until the first update.
+    zcash: ChainCheckpoint,
+    eth: ChainCheckpoint,
+}
+
+/// Tracks the state updates submitted by the leader, without submitting anything.
+pub struct ChainFollower {
+    watchtower: Watchtower,
+    next_zcash_height: u32,
+    next_eth_block: u64,
+    stf: Option<FollowedStf>,
+    /// State after the updates submitted to Ethereum since the STF was created.
+    state: BridgeState,
+    /// Transfers carried after these updates, re-derived with the policy of the watchtower.
+    carried: Carried,
+    /// Transactions of the deposit outputs claimed by each STF spend, in input order.
+    spent_deposits: Vec<Vec<TxId>>,
+    /// Index of the first deposit in the deposit log and number of refunds of each update.
+    applied_deposits: Vec<(u64, usize)>,
+}
+
+impl ChainFollower {
+    pub fn new(watchtower: Watchtower, from_zcash_height: u32, from_eth_block: u64) -> Self {
+        Self {
+            watchtower,
+            next_zcash_height: from_zcash_height,
+            next_eth_block: from_eth_block,
+            stf: None,
+            state: BridgeState::default(),
+            carried: Carried::default(),
+            spent_deposits: Vec::new(),
+            applied_deposits: Vec::new(),
+        }
+    }
+
+    /// Applies the updates submitted to Ethereum and tracks the STF output on Zcash up to the
+    /// current tips.
+    pub async fn poll(&mut self) -> anyhow::Result<()> {
+        let zcash_tip = self.watchtower.zcash_watcher().get_block_count().await?;
+        let eth_tip = self.watchtower.eth_watcher().get_block_number().await?;
+
+        for height in self.next_zcash_height..=zcash_tip {
+            let block = self.watchtower.zcash_watcher().get_block(height).await?;
+            for tx in &block.transactions {
+                let tx = Transaction::read(&tx.zcash_serialize_to_vec()?[..], BranchId::ZFuture)?;
+                self.track_stf(
+                    &tx,
+                    ChainCheckpoint {
+                        height: height as u64,
+                        hash: block.hash().0,
+                    },
+                    eth_tip,
+                )
+                .await?;
+            }
+        }
+        self.next_zcash_height = self.next_zcash_height.max(zcash_tip + 1);
+
+        if eth_tip >= self.next_eth_block {
+            for submitted in self
+                .watchtower
+                .submitted_updates(self.next_eth_block, eth_tip)
+                .await?
+            {
+                let first_deposit = self.state.deposits.count();
+                self.state.apply(&submitted.update)?;
+                self.applied_deposits
+                    .push((first_deposit, submitted.update.refunds.len()));
+                self.carried = self
+                    .watchtower
+                    .carried_after(std::mem::take(&mut self.carried), &submitted.update)
+                    .await?;
+            }
+            self.next_eth_block = eth_tip + 1;
+        }
+        Ok(())
+    }
+
+    async fn track_stf(
+        &mut self,
+        tx: &Transaction,
+        zcash: ChainCheckpoint,
+        eth_tip: u64,
+    ) -> anyhow::Result<()> {
+        let Some(bundle) = tx.tze_bundle() else {
+            return Ok(());
+        };
+        let is_stf = |extension_id, mode| extension_id == EXTENSION_ETH_BRIDGE && mode == MODE_STF;
+        let Some(index) = bundle
+            .vout
+            .iter()
+            .position(|output| is_stf(output.precondition.extension_id, output.precondition.mode))
+        else {
+            return Ok(());
+        };
+        // TZE outpoints come after transparent outputs.
+        let transparent_outputs = tx
+            .transparent_bundle()
+            .map_or(0, |bundle| bundle.vout.len());
+        let outpoint = tze::OutPoint::new(tx.txid(), (transparent_outputs + index) as u32);
+        let output = bundle.vout[index].clone();
+
+        let spent_stf = bundle
+            .vin
+            .iter()
+            .find(|input| is_stf(input.witness.extension_id, input.witness.mode));
+        match spent_stf {
+            Some(input) => {
+                // Spends of other STFs than the followed one are ignored.
+                if let Some(stf) = self
+                    .stf
+                    .as_mut()
+                    .filter(|stf| stf.outpoint == input.prevout)
+                {
+                    stf.outpoint = outpoint;
+                    stf.output = output;
+                    stf.spends += 1;
+                    self.spent_deposits.push(
+                        bundle
+                            .vin
+                            .iter()
+                            .filter(|input| input.witness.mode == MODE_DEPOSIT)
+                            .map(|input| *input.prevout.txid())
+                            .collect(),
+                    );
+                }
+            }
+            None => {
+                // A new STF was created, the bridge state starts over.
+                tracing::info!("Following STF created in {}", tx.txid());
+                let block = self.watchtower.eth_watcher().get_block(eth_tip).await?;
+                self.stf = Some(FollowedStf {
+                    outpoint,
+                    output,
+                    spends: 0,
+                    zcash,
+                    eth: ChainCheckpoint {
+                        height: eth_tip,
+                        hash: block.hash().0,
+                    },
+                });
+                self.state = BridgeState::default();
+                self.carried = Carried::default();
+                self.spent_deposits.clear();
+                self.applied_deposits.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the STF has processed one update more than the bridge contract, i.e. the
+    /// last update was sent to Zcash but not to Ethereum, see [`crate::journal`].
+    pub fn awaits_ethereum(&self) -> bool {
+        self.stf
+            .as_ref()
+            .is_some_and(|stf| stf.spends == self.state.updates + 1)
+    }
+
+    /// Returns whether an STF was found; a new leader deploys one otherwise.
+    pub fn has_stf(&self) -> bool {
+        self.stf.is_some()
+    }
+
+    /// Returns the point to continue from, or `None` while no STF exists or one chain has
+    /// processed an update the other has not.
+    pub fn takeover(&self) -> Option<Takeover> {
+        let stf = self.stf.as_ref()?;
+        if stf.spends != self.state.updates {
+            return None;
+        }
+        let (zcash, eth) = if self.state.updates > 0 {
+            (self.state.zcash, self.state.eth)
+        } else {
+            (stf.zcash, stf.eth)
+        };
+        Some(Takeover {
+            stf: (stf.outpoint.clone(), stf.output.clone()),
+            state: self.state.clone(),
+            deposited: stf.output.value,
+            carried: self.carried.clone(),
+            deposit_indices: self.deposit_indices(),
+            zcash,
+            eth,
+        })
+    }
+
+    /// Matches the deposits credited by each update with the deposit outputs claimed by the
+    /// STF spend processing it, which claims the credited deposits before the refunded ones.
+    fn deposit_indices(&self) -> HashMap<TxId, Vec<u64>> {
+        let mut indices: HashMap<TxId, Vec<u64>> = HashMap::new();
+        for (txids, &(first_deposit, refunds)) in
+            self.spent_deposits.iter().zip(&self.applied_deposits)
+        {
+            let credited = &txids[..txids.len().saturating_sub(refunds)];
+            for (index, txid) in (first_deposit..).zip(credited) {
+                indices.entry(*txid).or_default().push(index);
+            }
+        }
+        indices
+    }
+}
@@ -1,6 +1,42 @@
 #![allow(unexpected_cfgs)]
 
+#[cfg(feature = "node")]
+pub mod api;
+#[cfg(feature = "node")]
+pub mod audit;
+#[cfg(feature = "node")]
+pub mod batching;
+#[cfg(feature = "node")]
+pub mod committee;
+#[cfg(feature = "node")]
+pub mod deposits;
 pub mod eth;
+#[cfg(feature = "node")]
+pub mod journal;
+#[cfg(feature = "node")]
+pub mod leader;
+pub mod merkle;
+#[cfg(feature = "node")]
+pub mod metrics;
+pub mod prover;
+#[cfg(feature = "node")]
+pub mod quarantine;
+#[cfg(feature = "node")]
+pub mod relayer;
+#[cfg(feature = "node")]
+pub mod shutdown;
+pub mod state;
+#[cfg(feature = "node")]
+pub mod status;
+#[cfg(feature = "node")]
+mod store;
+#[cfg(feature = "node")]
+pub mod subscription;
+#[cfg(test)]
+mod test_utils;
 pub mod types;
+#[cfg(feature = "node")]
+pub mod watchtower;
 pub mod zcash;
+#[cfg(feature = "node")]
 pub mod zebra_client;
@@ -1,20 +1,98 @@
-use std::time::Duration;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use alloy::{primitives::B256, providers::Provider as _};
 
 use tracing_subscriber::EnvFilter;
+use zcash_eth_bridge::api::{
+    self, PendingDeposit, PendingTransfers, RelayerState, SharedRelayerState,
+};
+use zcash_eth_bridge::audit::{audit_solvency, check_report};
+use zcash_eth_bridge::batching::{BatchingPolicy, DepositClock, Pending};
+use zcash_eth_bridge::committee::{Committee, collect_signatures};
+use zcash_eth_bridge::deposits::DepositPolicy;
 use zcash_eth_bridge::eth::sender::EthSender;
-use zcash_eth_bridge::types::StateUpdate;
+use zcash_eth_bridge::eth::subscription::EthBlocks;
+use zcash_eth_bridge::eth::verifier;
+use zcash_eth_bridge::journal::{FollowFrom, InFlightUpdate, Journal};
+use zcash_eth_bridge::leader::{ChainFollower, FileLease, Leadership};
+use zcash_eth_bridge::metrics::METRICS;
+use zcash_eth_bridge::prover::{self, StateTransitionProver, TransitionWitness};
+use zcash_eth_bridge::quarantine::{
+    self, QuarantineStore, QuarantinedTransfer, Resolution, TransferIndex,
+};
+use zcash_eth_bridge::relayer::{self, SignedUpdate, Start, quarantine_transfer};
+use zcash_eth_bridge::shutdown::{self, Cancelled};
+use zcash_eth_bridge::state::ChainCheckpoint;
+use zcash_eth_bridge::subscription::BlockSubscription as _;
+use zcash_eth_bridge::types::{DepositOutpoint, StateUpdate};
+use zcash_eth_bridge::watchtower::Watchtower;
 
 use zcash_eth_bridge::eth::watcher::EthWatcher;
+use zcash_eth_bridge::zcash::deposit::is_payable;
+use zcash_eth_bridge::zcash::light_client::{BlockHeader, ConsensusParams, HeaderChain};
 use zcash_eth_bridge::zcash::sender::TzeSender;
+use zcash_eth_bridge::zcash::subscription::ZebraBlocks;
 use zcash_eth_bridge::zcash::watcher::ZcashWatcher;
 
 #[derive(Debug)]
 struct Config {
     zcash_rpc: String,
     eth_rpc: String,
+    /// Endpoint of the Ethereum node for head subscriptions; HTTP endpoints are polled.
+    eth_ws_rpc: String,
+    /// Interval of polling the chain tips when a node does not notify of new blocks.
+    poll_interval: Duration,
+    /// When accumulated transfers are submitted in a state update.
+    batching: BatchingPolicy,
+    /// Minimum, fee and refunds of deposits, shared with the watchtower and committee.
+    deposits: DepositPolicy,
     eth_bridge_address: String,
     wzec_token_address: String,
     eth_operator_pk: String,
+    api_address: String,
+    /// Whether to check the bridge solvency after every state update.
+    audit_solvency: bool,
+    /// Consensus rules to validate Zcash block headers with instead of trusting the node.
+    zcash_headers: Option<ConsensusParams>,
+    /// Whether to check Ethereum headers and read withdrawals from verified receipts.
+    verify_eth_receipts: bool,
+    /// Whether to prove updates with the SP1 guest program instead of the insecure mock prover.
+    sp1_prover: bool,
+    /// Committee co-signing state updates, if the bridge contract requires signatures.
+    committee: Option<CommitteeConfig>,
+    /// Lease shared with standby instances; without it this instance always submits.
+    lease: Option<LeaseConfig>,
+    /// Time granted to an in-flight state update to complete after a shutdown signal.
+    shutdown_timeout: Duration,
+    /// File of the transfers left out of updates because they make submissions revert, shared
+    /// with `zec-bridge-quarantine`.
+    quarantine_path: String,
+    /// File recording the deployed STF and the update in flight between the two chains, from
+    /// which a restarted relayer continues.
+    journal_path: String,
+}
+
+#[derive(Debug)]
+struct CommitteeConfig {
+    members: Vec<String>,
+    threshold: usize,
+    /// URLs of the `zec-bridge-committee` instances to collect signatures from.
+    urls: Vec<String>,
+    /// Time each member has to answer a signature request.
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+struct LeaseConfig {
+    /// Lease file shared by the instances on this host.
+    path: String,
+    ttl: Duration,
+    /// Blocks from which standbys follow the chains, before the deployment of the STF.
+    follow_from_zcash_height: u32,
+    follow_from_eth_block: u64,
 }
 
 impl Config {
@@ -22,6 +100,26 @@ impl Config {
         Self {
             zcash_rpc: "127.0.0.1:18232".to_string(),
             eth_rpc: "http://127.0.0.1:8545".to_string(),
+            eth_ws_rpc: "ws://127.0.0.1:8545".to_string(),
+            poll_interval: Duration::from_secs(5),
+            batching: BatchingPolicy {
+                min_transfers: 10,
+                min_value: 10 * 100_000_000,
+                max_delay: Duration::from_secs(60),
+                max_deposit_age: Duration::from_secs(30),
+                heartbeat: Duration::from_secs(600),
+            },
+            deposits: DepositPolicy {
+                min_amount: 10_000,
+                fee_bps: 10,
+                // The last account generated by anvil.
+                fee_recipient: alloy::primitives::address!(
+                    "a0Ee7A142d267C1f36714E4a8F75612F20a79720"
+                )
+                .into_array(),
+                refund_after: 100,
+                deny_list: Vec::new(),
+            },
             // This value is obtained by running `deploy_anvil.sh` on a fresh anvil instance.
             eth_bridge_address: "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512".to_string(),
             // This value is obtained by running `deploy_anvil.sh` on a fresh anvil instance.
@@ -30,6 +128,38 @@ impl Config {
             // This private key corresponds to the first account generated by anvil.
             eth_operator_pk: "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
                 .to_string(),
+            api_address: "127.0.0.1:3000".to_string(),
+            audit_solvency: true,
+            // The internal miner of Zebra on Regtest does not solve Equihash, so its headers
+            // would be rejected.
+            zcash_headers: None,
+            verify_eth_receipts: true,
+            sp1_prover: false,
+            committee: None,
+            lease: None,
+            shutdown_timeout: Duration::from_secs(60),
+            quarantine_path: "quarantine.json".to_string(),
+            journal_path: "journal.json".to_string(),
+        }
+    }
+
+    fn prover(&self) -> anyhow::Result<Box<dyn StateTransitionProver>> {
+        match self.sp1_prover {
+            #[cfg(feature = "sp1")]
+            true => {
+                let prover = prover::Sp1Prover::new();
+                tracing::info!(
+                    "Using the SP1 prover, program key 0x{}",
+                    hex::encode(prover.program_vkey())
+                );
+                Ok(Box::new(prover))
+            }
+            #[cfg(not(feature = "sp1"))]
+            true => anyhow::bail!("the SP1 prover requires the `sp1` feature"),
+            false => {
+                tracing::warn!("Using the insecure mock prover");
+                Ok(Box::new(prover::MockProver))
+            }
         }
     }
 }
@@ -45,65 +175,399 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let config = Config::hardcoded();
-    let zcash_watcher = ZcashWatcher::new(&config.zcash_rpc);
-    let eth_watcher = EthWatcher::new(
+    config.deposits.validate()?;
+    let shutdown = shutdown::on_signal();
+    let deadline = shutdown::deadline(&shutdown, config.shutdown_timeout);
+    let prover = config.prover()?;
+    let zcash_watcher = Arc::new(ZcashWatcher::new(&config.zcash_rpc));
+    let eth_watcher = Arc::new(EthWatcher::new(
         &config.eth_rpc,
         &config.eth_bridge_address,
         &config.wzec_token_address,
-    );
+    ));
 
-    let eth_sender = EthSender::new(
+    let mut eth_sender = EthSender::new(
         &config.eth_rpc,
         &config.eth_operator_pk,
         &config.eth_bridge_address,
         &config.wzec_token_address,
     );
+    eth_sender.set_cancellation(deadline.clone());
+    let quarantine_store = QuarantineStore::new(&config.quarantine_path);
+
+    let committee = match &config.committee {
+        Some(committee) => Some((
+            Committee {
+                members: committee
+                    .members
+                    .iter()
+                    .map(|member| member.parse())
+                    .collect::<Result<_, _>>()?,
+                threshold: committee.threshold,
+            },
+            committee.urls.clone(),
+            eth_sender.bridge_contract.provider().get_chain_id().await?,
+            committee.timeout,
+        )),
+        None => None,
+    };
 
     let mut zcash_sender = TzeSender::new(&config.zcash_rpc).await?;
-    let (mut stf_tze_outpoint, mut stf_tze_output) = zcash_sender.deploy().await?; // TODO: should take txid/n as input I guess?
+    zcash_sender.set_cancellation(deadline.clone());
+    let journal = Journal::new(&config.journal_path);
+    zcash_sender.set_journal(journal.clone());
+    let record = journal.read().await?;
+    let stf_spend = record.in_flight.as_ref().map(InFlightUpdate::stf_spend);
+    if let Some(stf_spend) = stf_spend.transpose()?.flatten() {
+        // The followers only see mined spends; one still in the mempool would conflict with
+        // the next update. A spend the node does not know was never broadcast.
+        if zcash_sender.is_sent(&stf_spend).await? {
+            tracing::info!("Waiting for STF spend {stf_spend} of the last update");
+            zcash_sender.wait_for_tx(&stf_spend).await?;
+        } else {
+            tracing::warn!("STF spend {stf_spend} of the last update was not sent");
+        }
+    }
+    let follower_from = |from: FollowFrom| {
+        ChainFollower::new(
+            Watchtower::new(
+                ZcashWatcher::new(&config.zcash_rpc),
+                EthWatcher::new(
+                    &config.eth_rpc,
+                    &config.eth_bridge_address,
+                    &config.wzec_token_address,
+                ),
+            )
+            .with_deposit_policy(config.deposits.clone()),
+            from.zcash_height,
+            from.eth_block,
+        )
+    };
 
-    let mut start_block_zcash = zcash_watcher.get_block_count().await?;
-    let mut start_block_eth = eth_watcher.get_block_number().await?;
+    let mut leadership = None;
+    let takeover = match &config.lease {
+        Some(lease) => {
+            let mut leader = Leadership::new(
+                Box::new(FileLease::new(&lease.path)),
+                format!("relayer-{}", std::process::id()),
+                lease.ttl,
+            );
+            // An STF deployed by a previous leader on this host supersedes the configured one.
+            let mut follower = follower_from(record.follow_from.unwrap_or(FollowFrom {
+                zcash_height: lease.follow_from_zcash_height,
+                eth_block: lease.follow_from_eth_block,
+            }));
+            let takeover = match relayer::standby(
+                &mut leader,
+                &mut follower,
+                &journal,
+                &eth_sender,
+                &shutdown,
+            )
+            .await
+            {
+                Err(err) if err.is::<Cancelled>() => return Ok(()),
+                result => result?,
+            };
+            leadership = Some(leader);
+            takeover
+        }
+        None => match record.follow_from {
+            Some(from) => relayer::resume(&mut follower_from(from), &journal, &eth_sender).await?,
+            None => None,
+        },
+    };
 
-    let mut prev_block_hash_zcash = zcash_watcher.get_block(start_block_zcash - 1).await?.hash();
-    let mut prev_block_hash_eth = eth_watcher.get_block(start_block_eth - 1).await?.hash();
+    let Start {
+        stf,
+        zcash: zcash_checkpoint,
+        eth: eth_checkpoint,
+        carried,
+        deposit_indices,
+    } = relayer::start(
+        takeover,
+        &mut zcash_sender,
+        &journal,
+        &zcash_watcher,
+        &eth_watcher,
+    )
+    .await?;
+    let mut stf = stf;
 
-    loop {
-        tokio::time::sleep(Duration::from_secs(5)).await;
-        let current_block_zcash = zcash_watcher.get_block_count().await?;
-        let current_block_eth = eth_watcher.get_block_number().await?;
+    let mut start_block_zcash = zcash_checkpoint.height as u32 + 1;
+    let mut start_block_eth = eth_checkpoint.height + 1;
 
-        if current_block_eth < start_block_eth || current_block_zcash < start_block_zcash {
-            // TODO: should we send an op in this scenario? I guess realistically not.
-            continue;
+    let mut prev_block_hash_zcash = zebra_chain::block::Hash(zcash_checkpoint.hash);
+    let mut prev_block_hash_eth = B256::new(eth_checkpoint.hash);
+
+    let mut zcash_headers = if let Some(params) = config.zcash_headers {
+        let first = start_block_zcash.saturating_sub(params.history_len() as u32);
+        let mut anchor = Vec::new();
+        for height in first..start_block_zcash {
+            let block = zcash_watcher.get_block(height).await?;
+            anchor.push(BlockHeader::from_zebra(&block.header)?);
         }
+        Some(HeaderChain::new(params, anchor, start_block_zcash - 1)?)
+    } else {
+        None
+    };
 
-        tracing::info!(
-            "Processing blocks ZEC {}-{}, ETH {}-{}",
-            start_block_zcash,
-            current_block_zcash,
-            start_block_eth,
-            current_block_eth
+    let relayer_state = SharedRelayerState::default();
+    *relayer_state.write().await = RelayerState {
+        stf_outpoint: Some(format!("{}:{}", stf.0.txid(), stf.0.n())),
+        deposited: stf.1.value.into_u64(),
+        state_root: zcash_sender.state().root(),
+        zcash: ChainCheckpoint {
+            height: (start_block_zcash - 1) as u64,
+            hash: prev_block_hash_zcash.0,
+        },
+        eth: ChainCheckpoint {
+            height: start_block_eth - 1,
+            hash: prev_block_hash_eth.0,
+        },
+        scanned_zcash_height: (start_block_zcash - 1) as u64,
+        scanned_eth_height: start_block_eth - 1,
+        updates_submitted: 0,
+        bridge_state: zcash_sender.state().clone(),
+        deposit_indices,
+        pending: PendingTransfers::default(),
+    };
+    let router = api::router(
+        relayer_state.clone(),
+        zcash_watcher.clone(),
+        eth_watcher.clone(),
+    );
+    let api_address = config.api_address.parse()?;
+    let api_server = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if let Err(err) = api::serve(api_address, router, shutdown).await {
+                tracing::error!("API server failed: {err:#}");
+            }
+        }
+    });
+
+    let mut zcash_heads = ZebraBlocks::new(zcash_watcher.client().clone(), config.poll_interval);
+    let mut eth_heads = EthBlocks::connect(&config.eth_ws_rpc, config.poll_interval).await?;
+
+    // Blocks and transfers fetched since the last update, kept until the batching policy
+    // submits them.
+    let mut next_block_zcash = start_block_zcash;
+    let mut next_block_eth = start_block_eth;
+    let mut zcash_blocks = Vec::new();
+    let mut zcash_block_headers = Vec::new();
+    let mut eth_blocks: Vec<alloy::rpc::types::Block> = Vec::new();
+    let mut eth_receipts = Vec::new();
+    // Transfers held by the previous leader stay held until an operator resolves them here, in
+    // the order the last update committed to.
+    let (held_deposits, held_withdrawals) =
+        quarantine::held(&quarantine_store.entries().await?, &[]);
+    for deposit in &carried.quarantined_deposits {
+        let outpoint = DepositOutpoint {
+            txid: *deposit.outpoint.txid().as_ref(),
+            index: deposit.outpoint.n(),
+        };
+        if !held_deposits.contains(&outpoint) {
+            let transfer = QuarantinedTransfer::Deposit(deposit.into());
+            quarantine_transfer(
+                &quarantine_store,
+                transfer,
+                "quarantined by another relayer".to_string(),
+            )
+            .await?;
+        }
+    }
+    for withdrawal in carried.quarantined_withdrawals {
+        if !held_withdrawals.contains(&withdrawal) {
+            let transfer = QuarantinedTransfer::Withdrawal(withdrawal);
+            quarantine_transfer(
+                &quarantine_store,
+                transfer,
+                "quarantined by another relayer".to_string(),
+            )
+            .await?;
+        }
+    }
+    // Deposits not claimed yet, including those the deposit policy queued in earlier updates.
+    // Quarantined deposits are added back once resolved.
+    let mut pending_deposits: Vec<_> = carried
+        .queued
+        .into_iter()
+        .filter(|deposit| {
+            !held_deposits.contains(&DepositOutpoint {
+                txid: *deposit.outpoint.txid().as_ref(),
+                index: deposit.outpoint.n(),
+            })
+        })
+        .collect();
+    let mut eth_to_zec_transfers = Vec::new();
+    let mut deposit_clock = DepositClock::default();
+    deposit_clock.observe(&pending_deposits, Instant::now());
+    let mut last_update = Instant::now();
+    // Quarantined transfers resolved by the operator, removed from the quarantine once submitted.
+    let mut resolved_ids = Vec::new();
+    // Resolved deposits with the refund address set by the operator, and released or returned
+    // withdrawals requested in blocks of earlier updates, by quarantine entry ID. They come after
+    // the other transfers of the update, see `quarantine::settle`.
+    let mut released_deposits = Vec::new();
+    let mut released_withdrawals = Vec::new();
+    let mut returned_withdrawals = Vec::new();
+
+    // When a time-based rule of the batching policy submits the blocks fetched so far. Without
+    // blocks on both chains nothing can be submitted, so only new blocks wake the loop up.
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+        let (current_block_zcash, current_block_eth) = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            heads = async {
+                tokio::try_join!(
+                    zcash_heads.wait_for_block(next_block_zcash as u64 - 1),
+                    eth_heads.wait_for_block(next_block_eth - 1),
+                )
+            } => {
+                let (zcash_tip, eth_tip) = heads?;
+                (u32::try_from(zcash_tip)?, eth_tip)
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()),
+                if deadline.is_some() =>
+            {
+                (
+                    zcash_watcher.get_block_count().await?,
+                    eth_watcher.get_block_number().await?,
+                )
+            }
+        };
+        deadline = None;
+        METRICS.set_processed(
+            "zcash",
+            (start_block_zcash - 1) as u64,
+            current_block_zcash as u64,
         );
+        METRICS.set_processed("eth", start_block_eth - 1, current_block_eth);
 
-        let mut zcash_blocks = Vec::new();
-        for height in start_block_zcash..=current_block_zcash {
+        for height in next_block_zcash..=current_block_zcash {
             let block = zcash_watcher.get_block(height).await?;
+            if let Some(headers) = &mut zcash_headers {
+                headers.push_block(&block)?;
+            }
+            let deposits = zcash_watcher
+                .extract_deposits(std::slice::from_ref(&block))
+                .await?;
+            deposit_clock.observe(&deposits, Instant::now());
+            pending_deposits.extend(deposits);
+            zcash_block_headers.push(BlockHeader::from_zebra(&block.header)?);
             zcash_blocks.push(block);
         }
+        next_block_zcash = next_block_zcash.max(current_block_zcash + 1);
 
-        let mut eth_blocks = Vec::new();
-        for number in start_block_eth..=current_block_eth {
+        let mut new_eth_blocks = Vec::new();
+        for number in next_block_eth..=current_block_eth {
             let block = eth_watcher.get_block(number).await?;
-            eth_blocks.push(block);
+            new_eth_blocks.push(block);
+        }
+        if !new_eth_blocks.is_empty() {
+            // The prover checks the withdrawals against the receipts of every block.
+            let mut receipts = Vec::new();
+            for block in &new_eth_blocks {
+                receipts.push(eth_watcher.get_verified_receipts(block).await?);
+            }
+            let transfers = if config.verify_eth_receipts {
+                let parent_hash = eth_blocks
+                    .last()
+                    .map_or(prev_block_hash_eth, |block| block.hash());
+                verifier::verify_chain(parent_hash, &new_eth_blocks)?;
+                let mut transfers = Vec::new();
+                for receipts in &receipts {
+                    transfers.extend(verifier::withdrawals_from_receipts(
+                        *eth_watcher.bridge_contract.address(),
+                        receipts,
+                    )?);
+                }
+                transfers
+            } else {
+                eth_watcher
+                    .extract_eth_to_zec_transfers(&new_eth_blocks)
+                    .await?
+            };
+            // The contract only checks the length of shielded receivers, and the STF spend
+            // cannot pay one that does not decode, so such withdrawals are quarantined.
+            for transfer in transfers {
+                if is_payable(&transfer.recipient) {
+                    eth_to_zec_transfers.push(transfer);
+                } else {
+                    quarantine_transfer(
+                        &quarantine_store,
+                        QuarantinedTransfer::Withdrawal(transfer),
+                        "recipient is not a valid Zcash address".to_string(),
+                    )
+                    .await?;
+                }
+            }
+            eth_blocks.extend(new_eth_blocks);
+            eth_receipts.extend(receipts);
+            next_block_eth = current_block_eth + 1;
         }
 
-        let eth_to_zec_transfers = eth_watcher
-            .extract_eth_to_zec_transfers(&eth_blocks)
-            .await?;
-        let (zec_to_eth_transfers, zcash_deposit_outpoints) = zcash_watcher
-            .extract_zec_to_eth_transfers(&zcash_blocks)
-            .await?;
+        {
+            let mut state = relayer_state.write().await;
+            state.scanned_zcash_height = (next_block_zcash - 1) as u64;
+            state.scanned_eth_height = next_block_eth - 1;
+            state.pending = PendingTransfers {
+                deposits: pending_deposits.iter().map(PendingDeposit::from).collect(),
+                withdrawals: eth_to_zec_transfers.clone(),
+            };
+        }
+
+        if eth_blocks.is_empty() || zcash_blocks.is_empty() {
+            // An update needs a new block on both chains.
+            continue;
+        }
+        for entry in quarantine_store.resolved().await? {
+            if resolved_ids.contains(&entry.id) {
+                continue;
+            }
+            tracing::info!(
+                "Applying {:?} to quarantined transfer #{}",
+                entry.resolution,
+                entry.id
+            );
+            match (entry.transfer, entry.resolution) {
+                (QuarantinedTransfer::Deposit(deposit), Some(Resolution::Release)) => {
+                    released_deposits.push((entry.id, deposit.observed()?, None))
+                }
+                (QuarantinedTransfer::Deposit(deposit), Some(Resolution::Refund { to })) => {
+                    released_deposits.push((entry.id, deposit.observed()?, Some(to)))
+                }
+                (QuarantinedTransfer::Withdrawal(withdrawal), Some(Resolution::Release)) => {
+                    released_withdrawals.push((entry.id, withdrawal))
+                }
+                (QuarantinedTransfer::Withdrawal(withdrawal), Some(Resolution::Return)) => {
+                    returned_withdrawals.push((entry.id, withdrawal))
+                }
+                (transfer, resolution) => {
+                    anyhow::bail!("cannot apply {resolution:?} to {transfer:?}")
+                }
+            }
+            resolved_ids.push(entry.id);
+        }
+        released_deposits.sort_by_key(|(id, _, _)| *id);
+        released_withdrawals.sort_by_key(|(id, _)| *id);
+        returned_withdrawals.sort_by_key(|(id, _)| *id);
+
+        let current_block_zcash = next_block_zcash - 1;
+        let current_block_eth = next_block_eth - 1;
+        let settlement = quarantine::settle(
+            &config.deposits,
+            pending_deposits.clone(),
+            released_deposits
+                .iter()
+                .map(|(_, deposit, to)| (deposit.clone(), to.clone()))
+                .collect(),
+            current_block_zcash as u64,
+        );
+        let (quarantined_deposits, quarantined_withdrawals) =
+            quarantine::held(&quarantine_store.entries().await?, &resolved_ids);
 
         let state_update = StateUpdate {
             old_eth_block: start_block_eth - 1,
@@ -114,47 +578,270 @@ async fn main() -> anyhow::Result<()> {
             new_zcash_block: current_block_zcash as u64,
             old_zcash_hash: prev_block_hash_zcash.0,
             new_zcash_hash: zcash_blocks.last().unwrap().hash().0,
-            eth_to_zec_transfers: eth_to_zec_transfers.clone(),
-            zec_to_eth_transfers: zec_to_eth_transfers.clone(),
+            eth_to_zec_transfers: eth_to_zec_transfers
+                .iter()
+                .chain(
+                    released_withdrawals
+                        .iter()
+                        .map(|(_, withdrawal)| withdrawal),
+                )
+                .cloned()
+                .collect(),
+            zec_to_eth_transfers: settlement.transfers.clone(),
+            refunds: settlement.refunds.clone(),
+            withdrawal_refunds: returned_withdrawals
+                .iter()
+                .map(|(_, withdrawal)| withdrawal.clone())
+                .collect(),
+            quarantined_deposits,
+            quarantined_withdrawals,
         };
 
-        if !state_update.eth_to_zec_transfers.is_empty() {
-            tracing::info!(
-                "Processing {} ETH -> ZEC transfers in blocks {}-{}",
-                state_update.eth_to_zec_transfers.len(),
-                start_block_eth,
-                current_block_eth
+        let pending = Pending::new(
+            &state_update,
+            last_update.elapsed(),
+            deposit_clock.oldest_age(Instant::now()),
+        )?;
+        let Some(reason) = config.batching.decide(&pending) else {
+            tracing::debug!(
+                "Batching blocks ZEC {start_block_zcash}-{current_block_zcash}, ETH {start_block_eth}-{current_block_eth}: {pending:?}"
             );
-            for t in &state_update.eth_to_zec_transfers {
-                tracing::info!("  {:?}", t);
+            deadline = Some(Instant::now() + config.batching.time_to_flush(&pending));
+            continue;
+        };
+
+        // A transfer the bridge rejects would make this and every later submission revert, so
+        // such transfers are quarantined and the update is rebuilt without them.
+        if let Err(error) = eth_sender.simulate_transfers(&state_update).await? {
+            tracing::warn!("Transfers of the state update revert: {error}");
+            let offending = quarantine::offending_transfers(&state_update, |subset| {
+                let eth_sender = &eth_sender;
+                async move { eth_sender.simulate_transfers(&subset).await }
+            })
+            .await?;
+            let mut deposit_outpoints = Vec::new();
+            let mut withdrawal_indices = Vec::new();
+            let mut return_indices = Vec::new();
+            for (index, error) in offending {
+                let deposit = match index {
+                    TransferIndex::Deposit(i) => settlement.credited.get(i).ok_or_else(|| {
+                        anyhow::anyhow!("minting the deposit fees reverts: {error}")
+                    })?,
+                    TransferIndex::Refund(i) => &settlement.refunded[i],
+                    TransferIndex::Withdrawal(i) => {
+                        withdrawal_indices.push(i);
+                        let transfer = QuarantinedTransfer::Withdrawal(
+                            state_update.eth_to_zec_transfers[i].clone(),
+                        );
+                        quarantine_transfer(&quarantine_store, transfer, error).await?;
+                        continue;
+                    }
+                    TransferIndex::WithdrawalRefund(i) => {
+                        return_indices.push(i);
+                        let transfer = QuarantinedTransfer::Withdrawal(
+                            state_update.withdrawal_refunds[i].clone(),
+                        );
+                        quarantine_transfer(&quarantine_store, transfer, error).await?;
+                        continue;
+                    }
+                };
+                deposit_outpoints.push(deposit.outpoint.clone());
+                quarantine_transfer(
+                    &quarantine_store,
+                    QuarantinedTransfer::Deposit(deposit.into()),
+                    error,
+                )
+                .await?;
             }
+            pending_deposits.retain(|deposit| !deposit_outpoints.contains(&deposit.outpoint));
+            deposit_clock.retain(&pending_deposits);
+            released_deposits
+                .retain(|(_, deposit, _)| !deposit_outpoints.contains(&deposit.outpoint));
+            // Released withdrawals come after those requested in the blocks of this update.
+            let requested = eth_to_zec_transfers.len();
+            for i in withdrawal_indices.into_iter().rev() {
+                if i < requested {
+                    eth_to_zec_transfers.remove(i);
+                } else {
+                    released_withdrawals.remove(i - requested);
+                }
+            }
+            for i in return_indices.into_iter().rev() {
+                returned_withdrawals.remove(i);
+            }
+            continue;
         }
 
-        if !state_update.zec_to_eth_transfers.is_empty() {
-            tracing::info!(
-                "Processing {} ZEC -> ETH transfers in blocks {}-{}",
-                state_update.zec_to_eth_transfers.len(),
-                start_block_zcash,
-                current_block_zcash
-            );
-            for t in &state_update.zec_to_eth_transfers {
-                tracing::info!("  {:?}", t);
+        tracing::info!(
+            "Processing blocks ZEC {}-{}, ETH {}-{} ({reason:?})",
+            start_block_zcash,
+            current_block_zcash,
+            start_block_eth,
+            current_block_eth
+        );
+
+        tracing::debug!(
+            "State update {}: {}",
+            hex::encode(state_update.commitment()),
+            serde_json::to_string(&state_update)?
+        );
+
+        relayer::log_update(&state_update, &settlement, &config.deposits);
+
+        let (deposits, zcash_history) = relayer::deposit_inclusions(
+            &zcash_watcher,
+            settlement.credited.iter().chain(&settlement.refunded),
+            start_block_zcash as u64,
+        )
+        .await?;
+        let witness = TransitionWitness {
+            previous: zcash_sender.state().clone(),
+            update: state_update.clone(),
+            zcash_headers: std::mem::take(&mut zcash_block_headers),
+            zcash_history,
+            deposits,
+            eth_headers: eth_blocks
+                .iter()
+                .map(|block| block.header.inner.clone())
+                .collect(),
+            eth_receipts: eth_receipts.clone(),
+            bridge: *eth_sender.bridge_contract.address(),
+            released_withdrawals: released_withdrawals
+                .iter()
+                .chain(&returned_withdrawals)
+                .map(|(_, withdrawal)| withdrawal.clone())
+                .collect(),
+        };
+        let Some(proof) = shutdown.run_until_cancelled(prover.prove(&witness)).await else {
+            break;
+        };
+        let proof = proof.inspect_err(|_| METRICS.state_updates_failed.inc())?;
+        prover.verify(&proof)?;
+
+        // Collected before spending the STF, so that an update the committee refuses to sign
+        // is not half-submitted.
+        let signatures = match &committee {
+            Some((committee, urls, chain_id, timeout)) => {
+                let Some(signatures) = shutdown
+                    .run_until_cancelled(collect_signatures(
+                        urls,
+                        &state_update,
+                        committee,
+                        *chain_id,
+                        *eth_sender.bridge_contract.address(),
+                        *timeout,
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                committee
+                    .aggregate(
+                        *chain_id,
+                        *eth_sender.bridge_contract.address(),
+                        &state_update,
+                        &signatures,
+                    )
+                    .inspect_err(|_| METRICS.state_updates_failed.inc())?
             }
+            None => Vec::new(),
+        };
+
+        // Nothing has been sent yet. From here on the update is completed on both chains even
+        // if a shutdown is requested, until the shutdown timeout interrupts the waits.
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        if let Some(leadership) = &mut leadership {
+            // Standbys take over once the lease expires, so it has to outlast the submission.
+            anyhow::ensure!(leadership.poll().await?, "lost the relayer lease");
         }
 
-        (stf_tze_outpoint, stf_tze_output) = zcash_sender
-            .update_zcash(
-                (stf_tze_outpoint, stf_tze_output),
-                zcash_deposit_outpoints,
-                state_update.clone(),
+        let signed = SignedUpdate {
+            update: state_update,
+            proof,
+            signatures,
+        };
+        stf = relayer::submit(
+            &journal,
+            &mut zcash_sender,
+            &eth_sender,
+            stf,
+            settlement.claimed(),
+            &signed,
+        )
+        .await?;
+        METRICS.record_update(&signed.update);
+        METRICS.stf_locked_value.set(stf.1.value.into_u64() as i64);
+        let wzec_supply = eth_watcher.wzec_contract.totalSupply().call().await?;
+        METRICS
+            .wzec_total_supply
+            .set(i64::try_from(wzec_supply).unwrap_or(i64::MAX));
+
+        if config.audit_solvency {
+            match audit_solvency(
+                zcash_watcher.client(),
+                &eth_watcher,
+                &stf.0,
+                current_block_zcash as u64,
             )
-            .await?;
-        eth_sender.update_bridge(state_update).await?;
-        zcash_sender.wait_for_tx(stf_tze_outpoint.txid()).await?;
+            .await?
+            {
+                Some(report) => {
+                    if check_report(&report) {
+                        tracing::debug!("Bridge is solvent: {report:?}");
+                    }
+                }
+                None => tracing::warn!(
+                    "Ethereum state does not cover ZEC block {current_block_zcash}, skipping audit"
+                ),
+            }
+        }
 
         start_block_eth = current_block_eth + 1;
         prev_block_hash_eth = eth_blocks.last().unwrap().hash();
         start_block_zcash = current_block_zcash + 1;
         prev_block_hash_zcash = zcash_blocks.last().unwrap().hash();
+        zcash_blocks.clear();
+        eth_blocks.clear();
+        eth_receipts.clear();
+        pending_deposits = settlement.queued;
+        eth_to_zec_transfers.clear();
+        released_deposits.clear();
+        released_withdrawals.clear();
+        returned_withdrawals.clear();
+        if !resolved_ids.is_empty() {
+            quarantine_store
+                .remove(std::mem::take(&mut resolved_ids))
+                .await?;
+        }
+        // Deposits kept queued go on ageing from when they were first observed.
+        deposit_clock.retain(&pending_deposits);
+        last_update = Instant::now();
+
+        relayer_state.write().await.record_update(
+            &stf,
+            zcash_sender.state(),
+            &settlement.credited,
+            &pending_deposits,
+        );
+    }
+
+    tracing::info!(
+        "Stopped after ZEC block {}, ETH block {}",
+        start_block_zcash - 1,
+        start_block_eth - 1
+    );
+    if let Some(leadership) = &mut leadership {
+        // Lets a standby take over without waiting for the lease to expire.
+        leadership.release().await?;
+    }
+    if tokio::time::timeout(config.shutdown_timeout, api_server)
+        .await
+        .is_err()
+    {
+        tracing::warn!("API server did not stop within the shutdown timeout");
     }
+    Ok(())
 }
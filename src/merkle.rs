@@ -0,0 +1,137 @@
+//! Keccak-256 binary Merkle tree, computable in Solidity with `keccak256(abi.encodePacked(..))`.
+//!
+//! Leaves and inner nodes are domain separated by a one-byte prefix, and a level with an odd
+//! number of nodes is padded with a zero hash. The root of an empty tree is the zero hash.
+
+use alloy::primitives::keccak256;
+use serde::{Serialize, Serializer};
+
+pub type Hash = [u8; 32];
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Hashes the data of a leaf.
+pub fn hash_leaf(data: &[u8]) -> Hash {
+    let mut preimage = Vec::with_capacity(1 + data.len());
+    preimage.push(LEAF_PREFIX);
+    preimage.extend_from_slice(data);
+    keccak256(preimage).0
+}
+
+/// Hashes two child nodes.
+pub fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    let mut preimage = [0; 65];
+    preimage[0] = NODE_PREFIX;
+    preimage[1..33].copy_from_slice(left);
+    preimage[33..].copy_from_slice(right);
+    keccak256(preimage).0
+}
+
+/// Computes the root of the tree over the given leaf hashes.
+pub fn root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Proof that a leaf is included in a tree at a given index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MerkleProof {
+    /// Index of the leaf; its bits select the side of the sibling on every level.
+    pub index: u64,
+    /// Siblings of the path from the leaf to the root, starting at the leaf level.
+    #[serde(serialize_with = "serialize_hashes")]
+    pub siblings: Vec<Hash>,
+}
+
+/// Number of levels above the leaves in a tree with `leaf_count` leaves.
+pub fn depth(leaf_count: u64) -> usize {
+    leaf_count.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+/// Builds the inclusion proof of the leaf at `index`, or `None` if it is out of bounds.
+pub fn proof(leaves: &[Hash], index: u64) -> Option<MerkleProof> {
+    if index >= leaves.len() as u64 {
+        return None;
+    }
+    let mut siblings = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index as usize;
+    while level.len() > 1 {
+        siblings.push(level.get(position ^ 1).copied().unwrap_or([0; 32]));
+        level = next_level(&level);
+        position /= 2;
+    }
+    Some(MerkleProof { index, siblings })
+}
+
+/// Computes the root implied by `leaf` and its inclusion proof.
+pub fn compute_root(leaf: &Hash, proof: &MerkleProof) -> Hash {
+    let mut node = *leaf;
+    let mut index = proof.index;
+    for sibling in &proof.siblings {
+        node = if index & 1 == 0 {
+            hash_node(&node, sibling)
+        } else {
+            hash_node(sibling, &node)
+        };
+        index >>= 1;
+    }
+    node
+}
+
+/// Checks that `leaf` is included in a tree with `leaf_count` leaves and the given root.
+pub fn verify(root: &Hash, leaf: &Hash, leaf_count: u64, proof: &MerkleProof) -> bool {
+    proof.index < leaf_count
+        && proof.siblings.len() == depth(leaf_count)
+        && compute_root(leaf, proof) == *root
+}
+
+fn serialize_hashes<S: Serializer>(hashes: &[Hash], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(hashes.iter().map(hex::encode))
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| hash_node(&pair[0], pair.get(1).unwrap_or(&[0; 32])))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_pads_odd_levels() {
+        let leaves: Vec<_> = (0u8..3).map(|i| hash_leaf(&[i])).collect();
+        assert_eq!(root(&[]), [0; 32]);
+        assert_eq!(root(&leaves[..1]), leaves[0]);
+        assert_eq!(
+            root(&leaves),
+            hash_node(
+                &hash_node(&leaves[0], &leaves[1]),
+                &hash_node(&leaves[2], &[0; 32]),
+            )
+        );
+    }
+
+    #[test]
+    fn proofs_roundtrip() {
+        for count in 1u8..=9 {
+            let leaves: Vec<_> = (0..count).map(|i| hash_leaf(&[i])).collect();
+            let root = root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let path = proof(&leaves, index as u64).unwrap();
+                assert!(verify(&root, leaf, count as u64, &path));
+            }
+            assert!(proof(&leaves, count as u64).is_none());
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Prometheus metrics of the relayer.
+
+use std::{sync::LazyLock, time::Instant};
+
+use prometheus::{
+    Counter, Encoder as _, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::types::StateUpdate;
+
+/// Metrics of the running process, exposed by the API at `/metrics`.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Last block included in a state update, by chain.
+    pub processed_height: IntGaugeVec,
+    /// Number of blocks between the chain tip and the last processed block, by chain.
+    pub chain_lag: IntGaugeVec,
+    pub state_updates_submitted: IntCounter,
+    pub state_updates_failed: IntCounter,
+    /// Number of processed transfers, by direction (`deposit`, `withdrawal` or `refund`).
+    pub transfers: IntCounterVec,
+    /// Zatoshis moved by processed transfers, by direction.
+    pub transfer_amount: IntCounterVec,
+    /// Transfers left out of state updates because they make submissions revert.
+    pub transfers_quarantined: IntCounter,
+    pub zcash_fees_paid: IntCounter,
+    pub eth_fees_paid: Counter,
+    pub stf_locked_value: IntGauge,
+    pub wzec_total_supply: IntGauge,
+    /// STF value minus the value it should hold according to the Ethereum side.
+    pub solvency_divergence: IntGauge,
+    /// Latency of node RPC calls, by chain and method.
+    pub rpc_latency: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("bridge".to_string()), None).unwrap();
+
+        let processed_height = IntGaugeVec::new(
+            Opts::new("processed_height", "Last block included in a state update"),
+            &["chain"],
+        )
+        .unwrap();
+        let chain_lag = IntGaugeVec::new(
+            Opts::new(
+                "chain_lag_blocks",
+                "Blocks between the chain tip and the last processed block",
+            ),
+            &["chain"],
+        )
+        .unwrap();
+        let state_updates_submitted = IntCounter::new(
+            "state_updates_submitted_total",
+            "State updates submitted to both chains",
+        )
+        .unwrap();
+        let state_updates_failed = IntCounter::new(
+            "state_updates_failed_total",
+            "State updates that failed to be submitted",
+        )
+        .unwrap();
+        let transfers = IntCounterVec::new(
+            Opts::new("transfers_total", "Processed transfers"),
+            &["direction"],
+        )
+        .unwrap();
+        let transfer_amount = IntCounterVec::new(
+            Opts::new(
+                "transfer_amount_zatoshis_total",
+                "Zatoshis moved by processed transfers",
+            ),
+            &["direction"],
+        )
+        .unwrap();
+        let transfers_quarantined = IntCounter::new(
+            "transfers_quarantined_total",
+            "Transfers quarantined because they make submissions revert",
+        )
+        .unwrap();
+        let zcash_fees_paid = IntCounter::new(
+            "zcash_fees_paid_zatoshis_total",
+            "Fees paid for Zcash state update transactions",
+        )
+        .unwrap();
+        let eth_fees_paid = Counter::new(
+            "eth_fees_paid_wei_total",
+            "Fees paid for Ethereum state update transactions",
+        )
+        .unwrap();
+        let stf_locked_value = IntGauge::new(
+            "stf_locked_zatoshis",
+            "Value locked in the STF UTXO on Zcash",
+        )
+        .unwrap();
+        let wzec_total_supply = IntGauge::new(
+            "wzec_total_supply_zatoshis",
+            "Total supply of WZEC on Ethereum",
+        )
+        .unwrap();
+        let solvency_divergence = IntGauge::new(
+            "solvency_divergence_zatoshis",
+            "STF value minus the minted and not yet burned WZEC plus lock-in dust",
+        )
+        .unwrap();
+        let rpc_latency = HistogramVec::new(
+            HistogramOpts::new("rpc_latency_seconds", "Latency of node RPC calls"),
+            &["chain", "method"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(processed_height.clone()))
+            .unwrap();
+        registry.register(Box::new(chain_lag.clone())).unwrap();
+        registry
+            .register(Box::new(state_updates_submitted.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(state_updates_failed.clone()))
+            .unwrap();
+        registry.register(Box::new(transfers.clone())).unwrap();
+        registry
+            .register(Box::new(transfer_amount.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(transfers_quarantined.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(zcash_fees_paid.clone()))
+            .unwrap();
+        registry.register(Box::new(eth_fees_paid.clone())).unwrap();
+        registry
+            .register(Box::new(stf_locked_value.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(wzec_total_supply.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(solvency_divergence.clone()))
+            .unwrap();
+        registry.register(Box::new(rpc_latency.clone())).unwrap();
+
+        Self {
+            registry,
+            processed_height,
+            chain_lag,
+            state_updates_submitted,
+            state_updates_failed,
+            transfers,
+            transfer_amount,
+            transfers_quarantined,
+            zcash_fees_paid,
+            eth_fees_paid,
+            stf_locked_value,
+            wzec_total_supply,
+            solvency_divergence,
+            rpc_latency,
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// Records the last processed block of `chain` and how far it is behind `tip`.
+    pub fn set_processed(&self, chain: &str, height: u64, tip: u64) {
+        self.processed_height
+            .with_label_values(&[chain])
+            .set(height as i64);
+        self.chain_lag
+            .with_label_values(&[chain])
+            .set(tip.saturating_sub(height) as i64);
+    }
+
+    /// Records a processed transfer in `direction` (`deposit`, `withdrawal`, `refund` or
+    /// `withdrawal_refund`).
+    pub fn record_transfer(&self, direction: &str, amount: u64) {
+        self.transfers.with_label_values(&[direction]).inc();
+        self.transfer_amount
+            .with_label_values(&[direction])
+            .inc_by(amount);
+    }
+
+    /// Records a submitted state update along with its transfers.
+    pub fn record_update(&self, update: &StateUpdate) {
+        self.state_updates_submitted.inc();
+        self.set_processed("zcash", update.new_zcash_block, update.new_zcash_block);
+        self.set_processed("eth", update.new_eth_block, update.new_eth_block);
+        for transfer in &update.zec_to_eth_transfers {
+            self.record_transfer("deposit", transfer.amount);
+        }
+        for transfer in &update.eth_to_zec_transfers {
+            self.record_transfer("withdrawal", transfer.amount);
+        }
+        for refund in &update.refunds {
+            self.record_transfer("refund", refund.amount);
+        }
+        for transfer in &update.withdrawal_refunds {
+            self.record_transfer("withdrawal_refund", transfer.amount);
+        }
+    }
+}
+
+/// Awaits an RPC call to `chain`, recording its latency under `method`.
+pub async fn observe_rpc<F: Future>(chain: &str, method: &str, call: F) -> F::Output {
+    let start = Instant::now();
+    let output = call.await;
+    METRICS
+        .rpc_latency
+        .with_label_values(&[chain, method])
+        .observe(start.elapsed().as_secs_f64());
+    output
+}
@@ -0,0 +1,41 @@
+//! Insecure prover for tests and local runs.
+
+use alloy::primitives::keccak256;
+use async_trait::async_trait;
+
+use super::{
+    PublicInputs, StateTransitionProof, StateTransitionProver, TransitionWitness, check_transition,
+};
+
+const MOCK_PROOF_DOMAIN: &[u8] = b"zcash-eth-bridge/mock-state-transition";
+
+/// Checks the transition natively and "proves" it with a hash of the public inputs.
+///
+/// Anyone can produce such a proof, so it must never be accepted outside of tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MockProver;
+
+impl MockProver {
+    fn proof_bytes(public_inputs: &PublicInputs) -> Vec<u8> {
+        keccak256([MOCK_PROOF_DOMAIN, &public_inputs.encode()].concat()).to_vec()
+    }
+}
+
+#[async_trait]
+impl StateTransitionProver for MockProver {
+    async fn prove(&self, witness: &TransitionWitness) -> anyhow::Result<StateTransitionProof> {
+        let public_inputs = check_transition(witness)?;
+        Ok(StateTransitionProof {
+            public_inputs,
+            proof: Self::proof_bytes(&public_inputs),
+        })
+    }
+
+    fn verify(&self, proof: &StateTransitionProof) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            proof.proof == Self::proof_bytes(&proof.public_inputs),
+            "invalid mock proof"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,604 @@
+//! Proofs that a state update is a valid transition of the bridge state.
+//!
+//! A [`StateTransitionProver`] proves that a [`StateUpdate`] follows the Zcash and Ethereum
+//! headers it covers, that its transfers are backed by transactions and receipts committed to by
+//! these headers, and that it applies to the previous [`BridgeState`]. The proof exposes
+//! [`PublicInputs`]: the old and new state roots and the update commitment, which both senders
+//! check against what they submit.
+//!
+//! The insecure [`MockProver`] is the default. With the `sp1` feature, `Sp1Prover` runs
+//! [`check_transition`] in an SP1 guest program and its proofs are verified on Ethereum by the
+//! `SP1StateTransitionVerifier` contract.
+
+use std::collections::HashSet;
+
+use alloy::{
+    consensus::{Header as EthHeader, ReceiptEnvelope, proofs::calculate_receipt_root},
+    primitives::{Address, B256, Log},
+};
+use async_trait::async_trait;
+use zebra_chain::{serialization::ZcashDeserialize as _, transaction::Transaction};
+
+use crate::{
+    eth::verifier::withdrawals_from_receipts,
+    merkle::{Hash, MerkleProof},
+    state::BridgeState,
+    types::{EthToZecTransfer, StateUpdate},
+    zcash::{
+        light_client::{BlockHeader, verify_transaction},
+        watcher::transaction_deposits,
+    },
+};
+
+pub mod mock;
+#[cfg(feature = "sp1")]
+pub mod sp1;
+mod witness;
+
+pub use mock::MockProver;
+#[cfg(feature = "sp1")]
+pub use sp1::Sp1Prover;
+
+/// Values a transition proof commits to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicInputs {
+    pub old_state_root: Hash,
+    pub new_state_root: Hash,
+    /// Commitment to the proven [`StateUpdate`].
+    pub update_commitment: Hash,
+}
+
+impl PublicInputs {
+    /// Encoding equal to `abi.encode(oldStateRoot, newStateRoot, updateCommitment)`.
+    pub fn encode(&self) -> Vec<u8> {
+        [
+            self.old_state_root,
+            self.new_state_root,
+            self.update_commitment,
+        ]
+        .concat()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() == 96,
+            "public inputs have {} bytes, expected 96",
+            bytes.len()
+        );
+        Ok(Self {
+            old_state_root: bytes[..32].try_into().unwrap(),
+            new_state_root: bytes[32..64].try_into().unwrap(),
+            update_commitment: bytes[64..].try_into().unwrap(),
+        })
+    }
+}
+
+/// Proof of a state transition along with its public inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateTransitionProof {
+    pub public_inputs: PublicInputs,
+    /// Backend-specific proof bytes.
+    pub proof: Vec<u8>,
+}
+
+impl StateTransitionProof {
+    /// Encoding carried by the STF spend on Zcash: the encoded public inputs, then the proof.
+    pub fn encode(&self) -> Vec<u8> {
+        [self.public_inputs.encode(), self.proof.clone()].concat()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(bytes.len() >= 96, "proof has {} bytes", bytes.len());
+        Ok(Self {
+            public_inputs: PublicInputs::decode(&bytes[..96])?,
+            proof: bytes[96..].to_vec(),
+        })
+    }
+
+    /// Checks that the proof is about `update`.
+    pub fn ensure_proves(&self, update: &StateUpdate) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.public_inputs.update_commitment == update.commitment(),
+            "proof is for update {}, not {}",
+            hex::encode(self.public_inputs.update_commitment),
+            hex::encode(update.commitment())
+        );
+        Ok(())
+    }
+
+    /// Checks that the proof is about `update` moving the state from `old_root` to `new_root`.
+    pub fn ensure_transition(
+        &self,
+        update: &StateUpdate,
+        old_root: &Hash,
+        new_root: &Hash,
+    ) -> anyhow::Result<()> {
+        self.ensure_proves(update)?;
+        anyhow::ensure!(
+            self.public_inputs.old_state_root == *old_root
+                && self.public_inputs.new_state_root == *new_root,
+            "proof does not cover the transition from state {} to {}",
+            hex::encode(old_root),
+            hex::encode(new_root)
+        );
+        Ok(())
+    }
+}
+
+/// Deposit output claimed by an update, with the inclusion of its transaction in a Zcash block.
+#[derive(Debug, Clone)]
+pub struct DepositInclusion {
+    /// Height of the block including the transaction.
+    pub height: u64,
+    /// Serialized transaction.
+    pub transaction: Vec<u8>,
+    /// Path from the txid to the `merkle_root` of the block header.
+    pub proof: MerkleProof,
+    /// Index of the deposit among the outputs of the transaction.
+    pub output: u32,
+}
+
+/// Everything the prover needs to prove a state update.
+#[derive(Debug, Clone)]
+pub struct TransitionWitness {
+    pub previous: BridgeState,
+    pub update: StateUpdate,
+    /// Zcash headers after `update.old_zcash_block` up to `update.new_zcash_block`.
+    pub zcash_headers: Vec<BlockHeader>,
+    /// Zcash headers up to `update.old_zcash_block`, back to the oldest block including a
+    /// claimed deposit, for deposits queued or quarantined by earlier updates.
+    pub zcash_history: Vec<BlockHeader>,
+    /// Deposits credited or refunded by the update.
+    pub deposits: Vec<DepositInclusion>,
+    /// Ethereum headers after `update.old_eth_block` up to `update.new_eth_block`.
+    pub eth_headers: Vec<EthHeader>,
+    /// Receipts of every block of `eth_headers`.
+    pub eth_receipts: Vec<Vec<ReceiptEnvelope<Log>>>,
+    /// Bridge contract whose `WithdrawalRequested` events are the withdrawals.
+    pub bridge: Address,
+    /// Withdrawals of earlier blocks left out of their update and released or returned by the
+    /// operator.
+    pub released_withdrawals: Vec<EthToZecTransfer>,
+}
+
+#[async_trait]
+pub trait StateTransitionProver: Send + Sync {
+    async fn prove(&self, witness: &TransitionWitness) -> anyhow::Result<StateTransitionProof>;
+
+    fn verify(&self, proof: &StateTransitionProof) -> anyhow::Result<()>;
+}
+
+/// Checks the statement proven by every backend and returns its public inputs.
+///
+/// The headers of both chains must link from the old to the new checkpoint of the update, the
+/// transfers must be backed by the chains, see `check_deposits` and `check_withdrawals`, and
+/// the update must apply to the previous state.
+pub fn check_transition(witness: &TransitionWitness) -> anyhow::Result<PublicInputs> {
+    let update = &witness.update;
+
+    let zcash_blocks = update
+        .new_zcash_block
+        .saturating_sub(update.old_zcash_block);
+    anyhow::ensure!(
+        witness.zcash_headers.len() as u64 == zcash_blocks,
+        "expected {zcash_blocks} Zcash headers, got {}",
+        witness.zcash_headers.len()
+    );
+    let mut zcash_hash = update.old_zcash_hash;
+    for header in &witness.zcash_headers {
+        anyhow::ensure!(header.prev_hash == zcash_hash, "Zcash headers do not link");
+        zcash_hash = header.hash();
+    }
+    anyhow::ensure!(
+        zcash_hash == update.new_zcash_hash,
+        "Zcash headers do not end at the new checkpoint"
+    );
+
+    let mut eth_hash = B256::new(update.old_eth_hash);
+    let mut eth_block = update.old_eth_block;
+    for header in &witness.eth_headers {
+        anyhow::ensure!(
+            header.parent_hash == eth_hash && header.number == eth_block + 1,
+            "Ethereum headers do not link at block {}",
+            header.number
+        );
+        eth_hash = header.hash_slow();
+        eth_block = header.number;
+    }
+    anyhow::ensure!(
+        eth_block == update.new_eth_block && eth_hash == B256::new(update.new_eth_hash),
+        "Ethereum headers do not end at the new checkpoint"
+    );
+
+    check_deposits(witness)?;
+    check_withdrawals(witness)?;
+
+    let mut new_state = witness.previous.clone();
+    new_state.apply(update)?;
+    Ok(PublicInputs {
+        old_state_root: witness.previous.root(),
+        new_state_root: new_state.root(),
+        update_commitment: update.commitment(),
+    })
+}
+
+/// Checks that the deposits minted and refunded by the update are paid by deposit outputs
+/// included in the Zcash blocks, each claimed once.
+///
+/// The fees and the split into credits and refunds follow the deposit policy of the relayer, so
+/// only the totals are bound here. Claims across updates are excluded by the STF spend on Zcash,
+/// which consumes the deposit outputs.
+fn check_deposits(witness: &TransitionWitness) -> anyhow::Result<()> {
+    let update = &witness.update;
+    let mut hash = witness
+        .zcash_history
+        .first()
+        .map_or(update.old_zcash_hash, |header| header.prev_hash);
+    for header in &witness.zcash_history {
+        anyhow::ensure!(header.prev_hash == hash, "Zcash history does not link");
+        hash = header.hash();
+    }
+    anyhow::ensure!(
+        hash == update.old_zcash_hash,
+        "Zcash history does not end at the old checkpoint"
+    );
+    let first_height = (update.old_zcash_block + 1)
+        .checked_sub(witness.zcash_history.len() as u64)
+        .ok_or_else(|| anyhow::anyhow!("Zcash history goes back before the genesis block"))?;
+    let headers: Vec<_> = witness
+        .zcash_history
+        .iter()
+        .chain(&witness.zcash_headers)
+        .collect();
+    let header_at = |height: u64| headers.get(height.checked_sub(first_height)? as usize);
+
+    let mut claimed = HashSet::new();
+    let mut deposited = 0u64;
+    let mut txids = HashSet::new();
+    for inclusion in &witness.deposits {
+        let header = header_at(inclusion.height).ok_or_else(|| {
+            anyhow::anyhow!("no Zcash header at deposit height {}", inclusion.height)
+        })?;
+        let tx = Transaction::zcash_deserialize(&inclusion.transaction[..])?;
+        let txid = tx.hash().0;
+        anyhow::ensure!(
+            verify_transaction(header, &txid, &inclusion.proof),
+            "deposit transaction is not in block {}",
+            inclusion.height
+        );
+        let (_, _, transfer) = transaction_deposits(&tx)
+            .find(|(outpoint, _, _)| outpoint.n() == inclusion.output)
+            .ok_or_else(|| anyhow::anyhow!("output {} is not a deposit", inclusion.output))?;
+        anyhow::ensure!(
+            claimed.insert((txid, inclusion.output)),
+            "deposit output {} is claimed twice",
+            inclusion.output
+        );
+        txids.insert(txid);
+        deposited = deposited
+            .checked_add(transfer.amount)
+            .ok_or_else(|| anyhow::anyhow!("deposits overflow"))?;
+    }
+
+    for refund in &update.refunds {
+        anyhow::ensure!(
+            txids.contains(&refund.deposit_txid),
+            "refund of {} does not refund a claimed deposit",
+            hex::encode(refund.deposit_txid)
+        );
+    }
+    let paid = update
+        .zec_to_eth_transfers
+        .iter()
+        .map(|transfer| transfer.amount)
+        .chain(update.refunds.iter().map(|refund| refund.amount))
+        .try_fold(0u64, u64::checked_add)
+        .ok_or_else(|| anyhow::anyhow!("transfers overflow"))?;
+    anyhow::ensure!(
+        paid == deposited,
+        "update pays {paid} zatoshis for deposits of {deposited}"
+    );
+    Ok(())
+}
+
+/// Checks that every withdrawal paid or returned by the update was requested in the receipts committed to by
+/// the Ethereum headers, or is a released withdrawal.
+///
+/// The relayer leaves quarantined withdrawals out, so the update may pay fewer of them.
+fn check_withdrawals(witness: &TransitionWitness) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        witness.eth_receipts.len() == witness.eth_headers.len(),
+        "expected receipts of {} Ethereum blocks, got {}",
+        witness.eth_headers.len(),
+        witness.eth_receipts.len()
+    );
+    let mut requested = witness.released_withdrawals.clone();
+    for (header, receipts) in witness.eth_headers.iter().zip(&witness.eth_receipts) {
+        let root = calculate_receipt_root(receipts);
+        anyhow::ensure!(
+            root == header.receipts_root,
+            "receipts of Ethereum block {} have root {root}, header commits to {}",
+            header.number,
+            header.receipts_root
+        );
+        requested.extend(withdrawals_from_receipts(witness.bridge, receipts)?);
+    }
+    // A withdrawal is either paid on Zcash or returned to its requester, at most once.
+    let withdrawals = &witness.update.eth_to_zec_transfers;
+    for withdrawal in withdrawals.iter().chain(&witness.update.withdrawal_refunds) {
+        let index = requested
+            .iter()
+            .position(|requested| requested == withdrawal)
+            .ok_or_else(|| anyhow::anyhow!("withdrawal {withdrawal:?} was not requested"))?;
+        requested.swap_remove(index);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::Receipt,
+        primitives::{Bytes, U256},
+        sol_types::SolEvent as _,
+    };
+
+    use super::*;
+    use crate::{
+        eth::contract::ZcashBridge::WithdrawalRequested,
+        state::TransferLog,
+        test_utils::{block_hash, update, withdrawal},
+        types::{DepositRefund, ZcashRecipient, ZecToEthTransfer},
+    };
+
+    const BRIDGE: Address = Address::repeat_byte(0x01);
+
+    fn zcash_header(prev_hash: [u8; 32], time: u32) -> BlockHeader {
+        BlockHeader {
+            version: 4,
+            prev_hash,
+            merkle_root: [1; 32],
+            block_commitments: [2; 32],
+            time,
+            bits: 0x200f0f0f,
+            nonce: [3; 32],
+            solution: vec![4; 36],
+        }
+    }
+
+    /// Receipt of a transaction requesting `withdrawal` from `bridge`.
+    fn withdrawal_receipt(bridge: Address, withdrawal: &EthToZecTransfer) -> ReceiptEnvelope<Log> {
+        let event = WithdrawalRequested {
+            requestId: U256::from(1),
+            requester: Address::repeat_byte(0x22),
+            amount: U256::from(withdrawal.amount),
+            receiverType: withdrawal.recipient.receiver_type(),
+            receiver: Bytes::copy_from_slice(withdrawal.recipient.receiver_bytes()),
+        };
+        ReceiptEnvelope::Legacy(
+            Receipt {
+                status: true.into(),
+                cumulative_gas_used: 21_000,
+                logs: vec![Log {
+                    address: bridge,
+                    data: event.encode_log_data(),
+                }],
+            }
+            .with_bloom(),
+        )
+    }
+
+    /// Witness of an update covering two blocks on each chain, paying one withdrawal requested in
+    /// the second Ethereum block and no deposits.
+    pub(super) fn witness() -> TransitionWitness {
+        let first = zcash_header(block_hash(100), 1);
+        let second = zcash_header(first.hash(), 2);
+
+        let receipts = vec![
+            Vec::new(),
+            vec![withdrawal_receipt(BRIDGE, &withdrawal(50_000))],
+        ];
+        let eth_first = EthHeader {
+            number: 21,
+            parent_hash: block_hash(20).into(),
+            receipts_root: calculate_receipt_root(&receipts[0]),
+            ..Default::default()
+        };
+        let eth_second = EthHeader {
+            number: 22,
+            parent_hash: eth_first.hash_slow(),
+            receipts_root: calculate_receipt_root(&receipts[1]),
+            ..Default::default()
+        };
+
+        let update = StateUpdate {
+            new_eth_hash: eth_second.hash_slow().0,
+            new_zcash_hash: second.hash(),
+            eth_to_zec_transfers: vec![withdrawal(50_000)],
+            ..update(20, 22).zcash_blocks(100, 102).build()
+        };
+
+        TransitionWitness {
+            previous: BridgeState::default(),
+            update,
+            zcash_headers: vec![first, second],
+            zcash_history: Vec::new(),
+            deposits: Vec::new(),
+            eth_headers: vec![eth_first, eth_second],
+            eth_receipts: receipts,
+            bridge: BRIDGE,
+            released_withdrawals: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn transition_must_follow_headers() -> anyhow::Result<()> {
+        let witness = witness();
+        let inputs = check_transition(&witness)?;
+        assert_eq!(inputs.old_state_root, BridgeState::default().root());
+        assert_eq!(inputs.update_commitment, witness.update.commitment());
+
+        let mut missing_block = witness.clone();
+        missing_block.zcash_headers.pop();
+        assert!(check_transition(&missing_block).is_err());
+
+        let mut other_tip = witness.clone();
+        other_tip.update.new_eth_hash = [0xff; 32];
+        assert!(check_transition(&other_tip).is_err());
+
+        let mut reordered = witness;
+        reordered.eth_headers.reverse();
+        assert!(check_transition(&reordered).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn withdrawals_must_be_requested_in_receipts() {
+        let witness = witness();
+
+        let mut unrequested = witness.clone();
+        unrequested
+            .update
+            .eth_to_zec_transfers
+            .push(withdrawal(60_000));
+        assert!(check_transition(&unrequested).is_err());
+
+        // Withdrawals released from the quarantine were requested in earlier blocks.
+        let mut released = unrequested.clone();
+        released.released_withdrawals.push(withdrawal(60_000));
+        assert!(check_transition(&released).is_ok());
+
+        // Each request is paid at most once.
+        let mut twice = witness.clone();
+        twice.update.eth_to_zec_transfers.push(withdrawal(50_000));
+        assert!(check_transition(&twice).is_err());
+
+        // Returned withdrawals were requested as well, and are not paid on Zcash too.
+        let mut returned = released.clone();
+        returned.update.eth_to_zec_transfers.pop();
+        returned.update.withdrawal_refunds.push(withdrawal(60_000));
+        assert!(check_transition(&returned).is_ok());
+        returned
+            .update
+            .eth_to_zec_transfers
+            .push(withdrawal(60_000));
+        assert!(check_transition(&returned).is_err());
+
+        // Quarantined withdrawals are left out.
+        let mut quarantined = witness.clone();
+        quarantined.update.eth_to_zec_transfers.clear();
+        assert!(check_transition(&quarantined).is_ok());
+
+        let mut other_receipts = witness.clone();
+        other_receipts.eth_receipts[1].clear();
+        assert!(check_transition(&other_receipts).is_err());
+
+        let mut other_contract = witness.clone();
+        other_contract.bridge = Address::repeat_byte(0x02);
+        assert!(check_transition(&other_contract).is_err());
+
+        let mut missing_receipts = witness;
+        missing_receipts.eth_receipts.pop();
+        assert!(check_transition(&missing_receipts).is_err());
+    }
+
+    #[test]
+    fn deposits_must_be_included_in_zcash_blocks() {
+        let witness = witness();
+
+        let mut unbacked = witness.clone();
+        unbacked.update.zec_to_eth_transfers.push(ZecToEthTransfer {
+            amount: 90_000,
+            eth_address: [0x33; 20],
+        });
+        assert!(check_transition(&unbacked).is_err());
+
+        let mut unbacked_refund = witness.clone();
+        unbacked_refund.update.refunds.push(DepositRefund {
+            amount: 5_000,
+            recipient: ZcashRecipient::Transparent([0x44; 20]),
+            deposit_txid: [0x55; 32],
+        });
+        assert!(check_transition(&unbacked_refund).is_err());
+
+        let inclusion = DepositInclusion {
+            height: 101,
+            transaction: vec![0; 10],
+            proof: MerkleProof {
+                index: 0,
+                siblings: Vec::new(),
+            },
+            output: 0,
+        };
+        let mut malformed = witness.clone();
+        malformed.deposits.push(inclusion.clone());
+        assert!(check_transition(&malformed).is_err());
+
+        // Deposits before the update need the headers linking their block to the old checkpoint.
+        let mut without_history = witness.clone();
+        without_history.deposits.push(DepositInclusion {
+            height: 99,
+            ..inclusion
+        });
+        assert!(check_transition(&without_history).is_err());
+
+        let mut unlinked_history = witness;
+        unlinked_history.zcash_history = vec![zcash_header([0x0f; 32], 0)];
+        assert!(check_transition(&unlinked_history).is_err());
+    }
+
+    #[test]
+    fn witness_encoding_roundtrips() -> anyhow::Result<()> {
+        let encoded = witness().encode();
+        assert_eq!(
+            check_transition(&TransitionWitness::decode(&encoded)?)?,
+            check_transition(&witness())?
+        );
+        assert!(TransitionWitness::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(TransitionWitness::decode(&[&encoded[..], &[0]].concat()).is_err());
+
+        let mut witness = witness();
+        witness.previous.deposits = TransferLog::from_leaves(70_000, vec![[0x11; 32], [0x22; 32]]);
+        witness.previous.updates = 3;
+        witness.zcash_history = vec![zcash_header([0x0f; 32], 0)];
+        witness.deposits.push(DepositInclusion {
+            height: 101,
+            transaction: vec![0xAA; 10],
+            proof: MerkleProof {
+                index: 1,
+                siblings: vec![[0x33; 32]],
+            },
+            output: 2,
+        });
+        witness.released_withdrawals.push(withdrawal(20_000));
+        let encoded = witness.encode();
+        let decoded = TransitionWitness::decode(&encoded)?;
+        assert_eq!(decoded.encode(), encoded);
+        assert_eq!(decoded.previous, witness.previous);
+        assert_eq!(decoded.eth_headers, witness.eth_headers);
+        assert_eq!(decoded.eth_receipts, witness.eth_receipts);
+        assert_eq!(decoded.released_withdrawals, witness.released_withdrawals);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mock_proofs_bind_public_inputs() -> anyhow::Result<()> {
+        let witness = witness();
+        let proof = MockProver.prove(&witness).await?;
+        MockProver.verify(&proof)?;
+
+        let mut new_state = witness.previous.clone();
+        new_state.apply(&witness.update)?;
+        proof.ensure_transition(&witness.update, &witness.previous.root(), &new_state.root())?;
+        assert!(
+            proof
+                .ensure_transition(&witness.update, &new_state.root(), &new_state.root())
+                .is_err()
+        );
+
+        let mut forged = proof.clone();
+        forged.public_inputs.new_state_root = [0; 32];
+        assert!(MockProver.verify(&forged).is_err());
+        Ok(())
+    }
+}
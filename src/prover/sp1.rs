@@ -0,0 +1,113 @@
+//! SP1 prover backend, producing Groth16 proofs verifiable on Ethereum by the
+//! `SP1StateTransitionVerifier` contract.
+//!
+//! The guest program in `program/` decodes the [`TransitionWitness`] written to its stdin, runs
+//! [`check_transition`] on it and commits the encoded [`PublicInputs`]. The build script of this
+//! crate compiles it when the `sp1` feature is enabled.
+
+use async_trait::async_trait;
+use sp1_sdk::{
+    EnvProver, HashableKey as _, ProverClient, SP1ProvingKey, SP1Stdin, SP1VerifyingKey,
+    include_elf,
+};
+use sp1_verifier::{GROTH16_VK_BYTES, Groth16Verifier};
+
+use super::{
+    PublicInputs, StateTransitionProof, StateTransitionProver, TransitionWitness, check_transition,
+};
+
+/// Guest program built from `program/`.
+pub const PROGRAM_ELF: &[u8] = include_elf!("zcash-eth-bridge-program");
+
+pub struct Sp1Prover {
+    client: EnvProver,
+    proving_key: SP1ProvingKey,
+    verifying_key: SP1VerifyingKey,
+}
+
+impl Sp1Prover {
+    /// Sets up the prover for the guest program; the backend is chosen by `SP1_PROVER`.
+    pub fn new() -> Self {
+        let client = ProverClient::from_env();
+        let (proving_key, verifying_key) = client.setup(PROGRAM_ELF);
+        Self {
+            client,
+            proving_key,
+            verifying_key,
+        }
+    }
+
+    /// Verification key of the guest program, as expected by the on-chain SP1 verifier.
+    pub fn program_vkey(&self) -> [u8; 32] {
+        self.verifying_key.bytes32_raw()
+    }
+
+    fn stdin(witness: &TransitionWitness) -> SP1Stdin {
+        let mut stdin = SP1Stdin::new();
+        stdin.write_vec(witness.encode());
+        stdin
+    }
+}
+
+impl Default for Sp1Prover {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl StateTransitionProver for Sp1Prover {
+    async fn prove(&self, witness: &TransitionWitness) -> anyhow::Result<StateTransitionProof> {
+        // Fail before spending time on a proof the guest would reject anyway.
+        let public_inputs = check_transition(witness)?;
+
+        let stdin = Self::stdin(witness);
+        let proof = tokio::task::block_in_place(|| {
+            self.client.prove(&self.proving_key, &stdin).groth16().run()
+        })?;
+        anyhow::ensure!(
+            PublicInputs::decode(proof.public_values.as_slice())? == public_inputs,
+            "guest committed unexpected public inputs"
+        );
+
+        Ok(StateTransitionProof {
+            public_inputs,
+            proof: proof.bytes(),
+        })
+    }
+
+    fn verify(&self, proof: &StateTransitionProof) -> anyhow::Result<()> {
+        Groth16Verifier::verify(
+            &proof.proof,
+            &proof.public_inputs.encode(),
+            &self.verifying_key.bytes32(),
+            &GROTH16_VK_BYTES,
+        )
+        .map_err(|e| anyhow::anyhow!("invalid SP1 proof: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the guest program in the SP1 executor, without proving.
+    fn execute(witness: &TransitionWitness) -> anyhow::Result<PublicInputs> {
+        let client = ProverClient::builder().cpu().build();
+        let (public_values, _) = client
+            .execute(PROGRAM_ELF, &Sp1Prover::stdin(witness))
+            .run()?;
+        PublicInputs::decode(public_values.as_slice())
+    }
+
+    #[test]
+    fn guest_program_checks_the_transition() -> anyhow::Result<()> {
+        let witness = super::super::tests::witness();
+        assert_eq!(execute(&witness)?, check_transition(&witness)?);
+
+        let mut unlinked = witness;
+        unlinked.eth_headers.remove(0);
+        assert!(execute(&unlinked).is_err());
+        Ok(())
+    }
+}
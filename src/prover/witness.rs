@@ -0,0 +1,210 @@
+//! Encoding of a [`TransitionWitness`], as passed to the SP1 guest program.
+
+use alloy::{
+    consensus::{Header as EthHeader, ReceiptEnvelope},
+    eips::{Decodable2718 as _, Encodable2718 as _},
+    primitives::Address,
+    rlp::{Decodable as _, Encodable as _},
+};
+
+use super::{DepositInclusion, TransitionWitness};
+use crate::{
+    merkle::MerkleProof,
+    state::{BridgeState, ChainCheckpoint, TransferLog},
+    types::{Reader, StateUpdate, encode_withdrawals},
+    zcash::light_client::BlockHeader,
+};
+
+impl TransitionWitness {
+    /// Encodes the witness; integers are big-endian and variable-length items are prefixed with
+    /// their length as u32:
+    ///
+    /// ```text
+    /// deposit log: total: u64 | leaf count: u32 | leaves: [u8; 32] each
+    /// withdrawal log: same as the deposit log
+    /// eth checkpoint: height: u64 | hash: [u8; 32]
+    /// zcash checkpoint: height: u64 | hash: [u8; 32]
+    /// last update: [u8; 32] | update count: u64
+    /// update: StateUpdate::encode
+    /// zcash header count: u32, then per header: serialized header
+    /// zcash history count: u32, then per header: serialized header
+    /// deposit count: u32, then per deposit: height: u64 | output: u32 | proof index: u64 |
+    ///     sibling count: u32 | siblings: [u8; 32] each | transaction
+    /// eth header count: u32, then per header: RLP-encoded header
+    /// receipt block count: u32, then per block: receipt count: u32, then per receipt:
+    ///     EIP-2718 encoded receipt
+    /// bridge: [u8; 20]
+    /// released withdrawals: as the withdrawals of StateUpdate::encode
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let previous = &self.previous;
+        let mut encoded = Vec::new();
+        encode_log(&mut encoded, &previous.deposits);
+        encode_log(&mut encoded, &previous.withdrawals);
+        encode_checkpoint(&mut encoded, &previous.eth);
+        encode_checkpoint(&mut encoded, &previous.zcash);
+        encoded.extend_from_slice(&previous.last_update);
+        encoded.extend_from_slice(&previous.updates.to_be_bytes());
+        encode_bytes(&mut encoded, &self.update.encode());
+
+        for headers in [&self.zcash_headers, &self.zcash_history] {
+            encoded.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+            for header in headers {
+                encode_bytes(&mut encoded, &header.serialize());
+            }
+        }
+
+        encoded.extend_from_slice(&(self.deposits.len() as u32).to_be_bytes());
+        for deposit in &self.deposits {
+            encoded.extend_from_slice(&deposit.height.to_be_bytes());
+            encoded.extend_from_slice(&deposit.output.to_be_bytes());
+            encoded.extend_from_slice(&deposit.proof.index.to_be_bytes());
+            encoded.extend_from_slice(&(deposit.proof.siblings.len() as u32).to_be_bytes());
+            for sibling in &deposit.proof.siblings {
+                encoded.extend_from_slice(sibling);
+            }
+            encode_bytes(&mut encoded, &deposit.transaction);
+        }
+
+        encoded.extend_from_slice(&(self.eth_headers.len() as u32).to_be_bytes());
+        for header in &self.eth_headers {
+            let mut rlp = Vec::new();
+            header.encode(&mut rlp);
+            encode_bytes(&mut encoded, &rlp);
+        }
+        encoded.extend_from_slice(&(self.eth_receipts.len() as u32).to_be_bytes());
+        for receipts in &self.eth_receipts {
+            encoded.extend_from_slice(&(receipts.len() as u32).to_be_bytes());
+            for receipt in receipts {
+                encode_bytes(&mut encoded, &receipt.encoded_2718());
+            }
+        }
+
+        encoded.extend_from_slice(self.bridge.as_slice());
+        encode_withdrawals(&mut encoded, &self.released_withdrawals);
+        encoded
+    }
+
+    /// Decodes a witness encoded by [`Self::encode`].
+    pub fn decode(encoded: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader(encoded);
+        let deposits = take_log(&mut reader)?;
+        let withdrawals = take_log(&mut reader)?;
+        let eth = take_checkpoint(&mut reader)?;
+        let zcash = take_checkpoint(&mut reader)?;
+        let previous = BridgeState {
+            eth,
+            zcash,
+            deposits,
+            withdrawals,
+            last_update: reader.take()?,
+            updates: u64::from_be_bytes(reader.take()?),
+        };
+        let update = StateUpdate::decode(take_bytes(&mut reader)?)?;
+        let zcash_headers = take_zcash_headers(&mut reader)?;
+        let zcash_history = take_zcash_headers(&mut reader)?;
+
+        let deposit_count = u32::from_be_bytes(reader.take()?);
+        let mut deposits = Vec::new();
+        for _ in 0..deposit_count {
+            let height = u64::from_be_bytes(reader.take()?);
+            let output = u32::from_be_bytes(reader.take()?);
+            let index = u64::from_be_bytes(reader.take()?);
+            let sibling_count = u32::from_be_bytes(reader.take()?);
+            let siblings = (0..sibling_count)
+                .map(|_| reader.take())
+                .collect::<anyhow::Result<_>>()?;
+            deposits.push(DepositInclusion {
+                height,
+                transaction: take_bytes(&mut reader)?.to_vec(),
+                proof: MerkleProof { index, siblings },
+                output,
+            });
+        }
+
+        let header_count = u32::from_be_bytes(reader.take()?);
+        let mut eth_headers = Vec::new();
+        for _ in 0..header_count {
+            let mut rlp = take_bytes(&mut reader)?;
+            eth_headers.push(EthHeader::decode(&mut rlp)?);
+            anyhow::ensure!(rlp.is_empty(), "trailing bytes after Ethereum header");
+        }
+        let block_count = u32::from_be_bytes(reader.take()?);
+        let mut eth_receipts = Vec::new();
+        for _ in 0..block_count {
+            let receipt_count = u32::from_be_bytes(reader.take()?);
+            let mut receipts = Vec::new();
+            for _ in 0..receipt_count {
+                let mut encoded = take_bytes(&mut reader)?;
+                receipts.push(ReceiptEnvelope::decode_2718(&mut encoded)?);
+                anyhow::ensure!(encoded.is_empty(), "trailing bytes after receipt");
+            }
+            eth_receipts.push(receipts);
+        }
+
+        let bridge = Address::new(reader.take()?);
+        let released_withdrawals = reader.take_withdrawals()?;
+        anyhow::ensure!(
+            reader.0.is_empty(),
+            "trailing bytes after transition witness"
+        );
+
+        Ok(Self {
+            previous,
+            update,
+            zcash_headers,
+            zcash_history,
+            deposits,
+            eth_headers,
+            eth_receipts,
+            bridge,
+            released_withdrawals,
+        })
+    }
+}
+
+fn encode_bytes(encoded: &mut Vec<u8>, bytes: &[u8]) {
+    encoded.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    encoded.extend_from_slice(bytes);
+}
+
+fn encode_log(encoded: &mut Vec<u8>, log: &TransferLog) {
+    encoded.extend_from_slice(&log.total.to_be_bytes());
+    encoded.extend_from_slice(&(log.leaves().len() as u32).to_be_bytes());
+    for leaf in log.leaves() {
+        encoded.extend_from_slice(leaf);
+    }
+}
+
+fn encode_checkpoint(encoded: &mut Vec<u8>, checkpoint: &ChainCheckpoint) {
+    encoded.extend_from_slice(&checkpoint.height.to_be_bytes());
+    encoded.extend_from_slice(&checkpoint.hash);
+}
+
+fn take_bytes<'a>(reader: &mut Reader<'a>) -> anyhow::Result<&'a [u8]> {
+    let len = u32::from_be_bytes(reader.take()?);
+    reader.take_slice(len as usize)
+}
+
+fn take_log(reader: &mut Reader<'_>) -> anyhow::Result<TransferLog> {
+    let total = u64::from_be_bytes(reader.take()?);
+    let count = u32::from_be_bytes(reader.take()?);
+    let leaves = (0..count)
+        .map(|_| reader.take())
+        .collect::<anyhow::Result<_>>()?;
+    Ok(TransferLog::from_leaves(total, leaves))
+}
+
+fn take_checkpoint(reader: &mut Reader<'_>) -> anyhow::Result<ChainCheckpoint> {
+    Ok(ChainCheckpoint {
+        height: u64::from_be_bytes(reader.take()?),
+        hash: reader.take()?,
+    })
+}
+
+fn take_zcash_headers(reader: &mut Reader<'_>) -> anyhow::Result<Vec<BlockHeader>> {
+    let count = u32::from_be_bytes(reader.take()?);
+    (0..count)
+        .map(|_| BlockHeader::parse(take_bytes(reader)?))
+        .collect()
+}
@@ -0,0 +1,706 @@
+//! Quarantine of transfers that make state update submissions revert.
+//!
+//! A transfer the bridge contract rejects would make every later update revert as well, since
+//! each retry covers the same blocks. Before proving an update, the relayer therefore simulates
+//! its transfers with `ZcashBridge.simulateTransfers`. If the simulation reverts, [`isolate`]
+//! bisects the transfers to find the offending ones, which are recorded in a
+//! [`QuarantineStore`] and left out of the update. An operator later releases them into a new
+//! update, refunds deposits on Zcash, or returns the tokens of withdrawals to their requesters on
+//! Ethereum.
+//!
+//! Every update commits to the transfers held in the quarantine after it, see
+//! [`StateUpdate::quarantined_deposits`], so that verifiers can tell them from censored ones.
+//! Released transfers come after the others, in the order they were quarantined, which lets
+//! verifiers re-derive the update without knowing the resolutions, see [`settle`].
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE;
+use zcash_primitives::{
+    extensions::transparent::Precondition,
+    transaction::components::{TzeOut, tze},
+};
+use zcash_protocol::{TxId, value::Zatoshis};
+
+use crate::{
+    deposits::{DepositPolicy, ObservedDeposit, Settlement},
+    store::JsonFile,
+    types::{DepositOutpoint, EthToZecTransfer, StateUpdate, ZcashRecipient, ZecToEthTransfer},
+    zcash::deposit::{MODE_DEPOSIT, is_payable},
+};
+
+/// Transfer left out of state updates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuarantinedTransfer {
+    /// Deposit credited or refunded by the update; its output stays unclaimed on Zcash.
+    Deposit(QuarantinedDeposit),
+    /// Withdrawal whose request stays pending on Ethereum.
+    Withdrawal(EthToZecTransfer),
+}
+
+/// Deposit output with what is needed to settle it later, see [`ObservedDeposit`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedDeposit {
+    #[serde(with = "hex::serde")]
+    pub txid: [u8; 32],
+    pub index: u32,
+    pub amount: u64,
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub eth_address: [u8; 20],
+    pub height: u64,
+    pub refund_to: Option<ZcashRecipient>,
+}
+
+impl From<&ObservedDeposit> for QuarantinedDeposit {
+    fn from(deposit: &ObservedDeposit) -> Self {
+        Self {
+            txid: *deposit.outpoint.txid().as_ref(),
+            index: deposit.outpoint.n(),
+            amount: deposit.transfer.amount,
+            payload: deposit.output.precondition.payload.clone(),
+            eth_address: deposit.transfer.eth_address,
+            height: deposit.height,
+            refund_to: deposit.refund_to.clone(),
+        }
+    }
+}
+
+impl QuarantinedDeposit {
+    pub fn observed(&self) -> anyhow::Result<ObservedDeposit> {
+        Ok(ObservedDeposit {
+            outpoint: tze::OutPoint::new(TxId::from_bytes(self.txid), self.index),
+            output: TzeOut {
+                value: Zatoshis::from_u64(self.amount)?,
+                precondition: Precondition {
+                    extension_id: EXTENSION_ETH_BRIDGE,
+                    mode: MODE_DEPOSIT,
+                    payload: self.payload.clone(),
+                },
+            },
+            transfer: ZecToEthTransfer {
+                amount: self.amount,
+                eth_address: self.eth_address,
+            },
+            height: self.height,
+            refund_to: self.refund_to.clone(),
+        })
+    }
+}
+
+/// What the relayer does with a quarantined transfer in its next update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Resolution {
+    /// Includes the transfer again, e.g. after the cause of the revert was fixed.
+    Release,
+    /// Pays a deposit back on Zcash instead of crediting it.
+    Refund { to: ZcashRecipient },
+    /// Returns the tokens locked by a withdrawal to its requester on Ethereum instead of paying
+    /// it, e.g. when its Zcash receiver does not decode.
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: u64,
+    pub transfer: QuarantinedTransfer,
+    /// Revert reason of the simulation ending with the transfer.
+    pub error: String,
+    /// Time of the quarantine as milliseconds since the Unix epoch.
+    pub quarantined_at_ms: u64,
+    /// Set by an operator, applied by the relayer in its next update.
+    pub resolution: Option<Resolution>,
+}
+
+/// Contents of a [`QuarantineStore`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Quarantine {
+    next_id: u64,
+    entries: Vec<QuarantineEntry>,
+}
+
+/// Quarantined transfers stored in a local file shared by the relayer and the operator.
+///
+/// Changes are written atomically, so an interrupted relayer or operator never loses entries.
+#[derive(Debug, Clone)]
+pub struct QuarantineStore {
+    file: JsonFile,
+}
+
+impl QuarantineStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            file: JsonFile::new(path.into()),
+        }
+    }
+
+    async fn update<T: Send + 'static>(
+        &self,
+        update: impl FnOnce(&mut Quarantine) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        self.file.update(update).await
+    }
+
+    pub async fn entries(&self) -> anyhow::Result<Vec<QuarantineEntry>> {
+        let quarantine: Quarantine = self.file.read().await?;
+        Ok(quarantine.entries)
+    }
+
+    /// Quarantines `transfer`, returning the ID of the entry.
+    pub async fn add(&self, transfer: QuarantinedTransfer, error: String) -> anyhow::Result<u64> {
+        self.update(move |quarantine| {
+            let id = quarantine.next_id;
+            quarantine.next_id += 1;
+            quarantine.entries.push(QuarantineEntry {
+                id,
+                transfer,
+                error,
+                quarantined_at_ms: now_ms(),
+                resolution: None,
+            });
+            Ok(id)
+        })
+        .await
+    }
+
+    /// Sets the resolution of entry `id`, unless the relayer already applied one.
+    pub async fn resolve(&self, id: u64, resolution: Resolution) -> anyhow::Result<()> {
+        self.update(move |quarantine| {
+            let entry = quarantine
+                .entries
+                .iter_mut()
+                .find(|entry| entry.id == id)
+                .ok_or_else(|| anyhow::anyhow!("no quarantined transfer #{id}"))?;
+            anyhow::ensure!(
+                entry.resolution.is_none(),
+                "transfer #{id} is already resolved"
+            );
+            match (&entry.transfer, &resolution) {
+                (QuarantinedTransfer::Withdrawal(_), Resolution::Refund { .. }) => {
+                    anyhow::bail!("withdrawals are returned to their requester on Ethereum")
+                }
+                (QuarantinedTransfer::Deposit(_), Resolution::Return) => {
+                    anyhow::bail!("deposits are refunded on Zcash")
+                }
+                _ => {}
+            }
+            if let (QuarantinedTransfer::Withdrawal(withdrawal), Resolution::Release) =
+                (&entry.transfer, &resolution)
+            {
+                anyhow::ensure!(
+                    is_payable(&withdrawal.recipient),
+                    "transfer #{id} pays an invalid Zcash address and cannot be released"
+                );
+            }
+            entry.resolution = Some(resolution);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Entries resolved by an operator, to be applied by the relayer.
+    pub async fn resolved(&self) -> anyhow::Result<Vec<QuarantineEntry>> {
+        let mut entries = self.entries().await?;
+        entries.retain(|entry| entry.resolution.is_some());
+        Ok(entries)
+    }
+
+    /// Removes the entries `ids`, once their resolution is part of a submitted update.
+    pub async fn remove(&self, ids: Vec<u64>) -> anyhow::Result<()> {
+        self.update(move |quarantine| {
+            quarantine.entries.retain(|entry| !ids.contains(&entry.id));
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before the Unix epoch")
+        .as_millis() as u64
+}
+
+/// Transfers held by the quarantine `entries` apart from the `applied` ones, in the order they
+/// were quarantined, as committed to by a state update.
+pub fn held(
+    entries: &[QuarantineEntry],
+    applied: &[u64],
+) -> (Vec<DepositOutpoint>, Vec<EthToZecTransfer>) {
+    let mut deposits = Vec::new();
+    let mut withdrawals = Vec::new();
+    for entry in entries.iter().filter(|entry| !applied.contains(&entry.id)) {
+        match &entry.transfer {
+            QuarantinedTransfer::Deposit(deposit) => deposits.push(DepositOutpoint {
+                txid: deposit.txid,
+                index: deposit.index,
+            }),
+            QuarantinedTransfer::Withdrawal(withdrawal) => withdrawals.push(withdrawal.clone()),
+        }
+    }
+    (deposits, withdrawals)
+}
+
+/// Settles the `pending` deposits and the deposits `released` from the quarantine, in the order
+/// they were quarantined.
+///
+/// Released deposits go through `policy` after the pending ones, unless they come with a refund
+/// address set by an operator. All refunds of released deposits, including those decided by the
+/// policy, come after the other refunds in the order of `released`. A verifier can therefore
+/// take the refund address of a released deposit from the update it checks.
+pub fn settle(
+    policy: &DepositPolicy,
+    mut pending: Vec<ObservedDeposit>,
+    released: Vec<(ObservedDeposit, Option<ZcashRecipient>)>,
+    zcash_height: u64,
+) -> Settlement {
+    let released_outpoints: Vec<_> = released
+        .iter()
+        .map(|(deposit, _)| deposit.outpoint.clone())
+        .collect();
+    let mut refunds = Vec::new();
+    for (position, (deposit, refund_to)) in released.into_iter().enumerate() {
+        match refund_to {
+            Some(to) => refunds.push((position, deposit, to)),
+            None => pending.push(deposit),
+        }
+    }
+
+    let mut settlement = policy.settle(pending, zcash_height);
+    let mut i = 0;
+    while i < settlement.refunded.len() {
+        match released_outpoints
+            .iter()
+            .position(|outpoint| *outpoint == settlement.refunded[i].outpoint)
+        {
+            Some(position) => {
+                let deposit = settlement.refunded.remove(i);
+                let refund = settlement.refunds.remove(i);
+                refunds.push((position, deposit, refund.recipient));
+            }
+            None => i += 1,
+        }
+    }
+    refunds.sort_by_key(|(position, _, _)| *position);
+    for (_, deposit, to) in refunds {
+        settlement.refund(deposit, to);
+    }
+    settlement
+}
+
+/// Position of a transfer in a state update, see [`offending_transfers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferIndex {
+    Deposit(usize),
+    Withdrawal(usize),
+    Refund(usize),
+    WithdrawalRefund(usize),
+}
+
+/// Number of transfers in `update`, indexed in the order the bridge contract processes them:
+/// deposits, withdrawals, refunds, then withdrawal refunds.
+fn transfer_count(update: &StateUpdate) -> usize {
+    update.zec_to_eth_transfers.len()
+        + update.eth_to_zec_transfers.len()
+        + update.refunds.len()
+        + update.withdrawal_refunds.len()
+}
+
+fn locate(update: &StateUpdate, index: usize) -> TransferIndex {
+    let deposits = update.zec_to_eth_transfers.len();
+    let withdrawals = update.eth_to_zec_transfers.len();
+    let refunds = update.refunds.len();
+    if index < deposits {
+        TransferIndex::Deposit(index)
+    } else if index < deposits + withdrawals {
+        TransferIndex::Withdrawal(index - deposits)
+    } else if index < deposits + withdrawals + refunds {
+        TransferIndex::Refund(index - deposits - withdrawals)
+    } else {
+        TransferIndex::WithdrawalRefund(index - deposits - withdrawals - refunds)
+    }
+}
+
+/// Returns `update` with only the transfers at `indices`, in ascending order.
+fn subset(update: &StateUpdate, indices: &[usize]) -> StateUpdate {
+    let mut subset = StateUpdate {
+        eth_to_zec_transfers: Vec::new(),
+        zec_to_eth_transfers: Vec::new(),
+        refunds: Vec::new(),
+        withdrawal_refunds: Vec::new(),
+        ..update.clone()
+    };
+    for &index in indices {
+        match locate(update, index) {
+            TransferIndex::Deposit(i) => subset
+                .zec_to_eth_transfers
+                .push(update.zec_to_eth_transfers[i].clone()),
+            TransferIndex::Withdrawal(i) => subset
+                .eth_to_zec_transfers
+                .push(update.eth_to_zec_transfers[i].clone()),
+            TransferIndex::Refund(i) => subset.refunds.push(update.refunds[i].clone()),
+            TransferIndex::WithdrawalRefund(i) => subset
+                .withdrawal_refunds
+                .push(update.withdrawal_refunds[i].clone()),
+        }
+    }
+    subset
+}
+
+/// Finds the transfers of `update` that make `simulate` revert, with the revert reasons.
+///
+/// `simulate` processes `update` restricted to some of its transfers, see [`isolate`].
+pub async fn offending_transfers<F, Fut>(
+    update: &StateUpdate,
+    mut simulate: F,
+) -> anyhow::Result<Vec<(TransferIndex, String)>>
+where
+    F: FnMut(StateUpdate) -> Fut,
+    Fut: Future<Output = anyhow::Result<Result<(), String>>>,
+{
+    let offending = isolate(transfer_count(update), |indices| {
+        simulate(subset(update, &indices))
+    })
+    .await?;
+    Ok(offending
+        .into_iter()
+        .map(|(index, error)| (locate(update, index), error))
+        .collect())
+}
+
+/// Finds the transfers among `count` that make `simulate` revert, with the revert reasons.
+///
+/// `simulate` processes the transfers at the given ascending indices and returns the revert
+/// reason if they revert. A reverting transfer is assumed to make every set containing it and
+/// the accepted transfers before it revert, as processing stops at the first failure, so each
+/// offending transfer is found with a binary search over the prefixes of the rest.
+pub async fn isolate<F, Fut>(count: usize, mut simulate: F) -> anyhow::Result<Vec<(usize, String)>>
+where
+    F: FnMut(Vec<usize>) -> Fut,
+    Fut: Future<Output = anyhow::Result<Result<(), String>>>,
+{
+    let mut accepted = Vec::new();
+    let mut offending = Vec::new();
+    let mut start = 0;
+    let with_prefix = |accepted: &Vec<usize>, start: usize, end: usize| {
+        let mut indices = accepted.clone();
+        indices.extend(start..end);
+        indices
+    };
+    loop {
+        let Err(mut error) = simulate(with_prefix(&accepted, start, count)).await? else {
+            return Ok(offending);
+        };
+        if offending.is_empty() {
+            // Otherwise the bridge itself rejects the simulation, and no transfer is at fault.
+            simulate(Vec::new()).await?.map_err(|error| {
+                anyhow::anyhow!("simulation reverts without transfers: {error}")
+            })?;
+        }
+        anyhow::ensure!(
+            start < count,
+            "simulation reverts without the offending transfers: {error}"
+        );
+        // The transfers before `passing` are accepted, and those before `failing` revert.
+        let (mut passing, mut failing) = (start, count);
+        while failing - passing > 1 {
+            let middle = passing + (failing - passing) / 2;
+            match simulate(with_prefix(&accepted, start, middle)).await? {
+                Ok(()) => passing = middle,
+                Err(reason) => {
+                    failing = middle;
+                    error = reason;
+                }
+            }
+        }
+        accepted.extend(start..passing);
+        offending.push((passing, error));
+        start = failing;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::test_utils::{deposit, ids, update};
+
+    fn store_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{name}-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Simulates transfers processed in order, reverting at the first one in `bad`.
+    fn simulate(bad: &[usize], indices: &[usize]) -> Result<(), String> {
+        match indices.iter().find(|index| bad.contains(index)) {
+            Some(index) => Err(format!("transfer {index} reverts")),
+            None => Ok(()),
+        }
+    }
+
+    #[tokio::test]
+    async fn isolate_finds_every_offending_transfer() -> anyhow::Result<()> {
+        let bad = [2, 5, 6];
+        let calls = Cell::new(0);
+        let offending = isolate(16, |indices| {
+            calls.set(calls.get() + 1);
+            let result = simulate(&bad, &indices);
+            async move { anyhow::Ok(result) }
+        })
+        .await?;
+        assert_eq!(
+            offending,
+            [
+                (2, "transfer 2 reverts".to_string()),
+                (5, "transfer 5 reverts".to_string()),
+                (6, "transfer 6 reverts".to_string()),
+            ]
+        );
+        // Bisecting takes logarithmically many simulations per offending transfer.
+        assert!(calls.get() <= 2 + bad.len() * 6, "{} calls", calls.get());
+
+        let offending = isolate(
+            16,
+            |indices| async move { anyhow::Ok(simulate(&[], &indices)) },
+        )
+        .await?;
+        assert!(offending.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn isolate_fails_when_no_transfer_is_at_fault() {
+        let result = isolate(4, |_| async {
+            anyhow::Ok(Err("InvalidPreviousState".to_string()))
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    fn state_update() -> StateUpdate {
+        update(1, 2)
+            .zcash_blocks(3, 4)
+            .withdrawal(50_000, ZcashRecipient::Transparent([0x11; 20]))
+            .deposit(90_000, [0x33; 20])
+            .deposit(80_000, [0x34; 20])
+            .refund(5_000, ZcashRecipient::Transparent([0x44; 20]), [0x55; 32])
+            .withdrawal_refund(7_000, ZcashRecipient::Transparent([0x66; 20]))
+            .build()
+    }
+
+    #[test]
+    fn transfers_are_indexed_in_processing_order() {
+        let update = state_update();
+        assert_eq!(transfer_count(&update), 5);
+        assert_eq!(locate(&update, 1), TransferIndex::Deposit(1));
+        assert_eq!(locate(&update, 2), TransferIndex::Withdrawal(0));
+        assert_eq!(locate(&update, 3), TransferIndex::Refund(0));
+        assert_eq!(locate(&update, 4), TransferIndex::WithdrawalRefund(0));
+
+        let subset = subset(&update, &[1, 3]);
+        assert_eq!(
+            subset.zec_to_eth_transfers,
+            update.zec_to_eth_transfers[1..]
+        );
+        assert!(subset.eth_to_zec_transfers.is_empty());
+        assert_eq!(subset.refunds, update.refunds);
+        assert!(subset.withdrawal_refunds.is_empty());
+        assert_eq!(subset.new_zcash_block, update.new_zcash_block);
+    }
+
+    #[tokio::test]
+    async fn offending_transfers_are_located_in_the_update() -> anyhow::Result<()> {
+        let update = state_update();
+        let offending = offending_transfers(&update, |subset| {
+            // The second deposit and the refund revert.
+            let result = if subset.zec_to_eth_transfers.len() == 2 || !subset.refunds.is_empty() {
+                Err("reverts".to_string())
+            } else {
+                Ok(())
+            };
+            async move { anyhow::Ok(result) }
+        })
+        .await?;
+        assert_eq!(
+            offending
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>(),
+            [TransferIndex::Deposit(1), TransferIndex::Refund(0)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_keeps_transfers_until_resolved_and_submitted() -> anyhow::Result<()> {
+        let path = store_path("quarantine");
+        let store = QuarantineStore::new(&path);
+        let deposit = QuarantinedTransfer::Deposit(QuarantinedDeposit {
+            txid: [7; 32],
+            index: 1,
+            amount: 100_000,
+            payload: vec![0xAB; 52],
+            eth_address: [0; 20],
+            height: 12,
+            refund_to: Some(ZcashRecipient::Sapling([9; 43])),
+        });
+        let withdrawal = QuarantinedTransfer::Withdrawal(EthToZecTransfer {
+            amount: 50_000,
+            recipient: ZcashRecipient::Orchard([8; 43]),
+        });
+        let deposit_id = store
+            .add(deposit.clone(), "InvalidRecipient".to_string())
+            .await?;
+        let withdrawal_id = store
+            .add(withdrawal.clone(), "ZeroAmount".to_string())
+            .await?;
+        assert_ne!(deposit_id, withdrawal_id);
+        assert!(store.resolved().await?.is_empty());
+
+        // Entries survive a restart of the relayer.
+        let store = QuarantineStore::new(&path);
+        let entries = store.entries().await?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].transfer, deposit);
+        assert_eq!(entries[1].error, "ZeroAmount");
+
+        let to = ZcashRecipient::Transparent([5; 20]);
+        assert!(
+            store
+                .resolve(withdrawal_id, Resolution::Refund { to: to.clone() })
+                .await
+                .is_err()
+        );
+        assert!(store.resolve(deposit_id, Resolution::Return).await.is_err());
+        store
+            .resolve(deposit_id, Resolution::Refund { to: to.clone() })
+            .await?;
+        assert!(
+            store
+                .resolve(deposit_id, Resolution::Release)
+                .await
+                .is_err()
+        );
+        assert!(store.resolve(42, Resolution::Release).await.is_err());
+        store.resolve(withdrawal_id, Resolution::Return).await?;
+
+        let resolved = store.resolved().await?;
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved[0].resolution, Some(Resolution::Refund { to }));
+        assert_eq!(resolved[1].resolution, Some(Resolution::Return));
+        let QuarantinedTransfer::Deposit(deposit) = &resolved[0].transfer else {
+            panic!("expected a deposit");
+        };
+        let observed = deposit.observed()?;
+        assert_eq!(observed.outpoint.n(), 1);
+        assert_eq!(observed.output.value.into_u64(), 100_000);
+        assert_eq!(observed.output.precondition.payload, deposit.payload);
+
+        store.remove(vec![deposit_id]).await?;
+        let entries = store.entries().await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, withdrawal_id);
+        Ok(())
+    }
+
+    #[test]
+    fn held_transfers_leave_out_applied_entries() {
+        let entry = |id, transfer| QuarantineEntry {
+            id,
+            transfer,
+            error: "reverts".to_string(),
+            quarantined_at_ms: 0,
+            resolution: None,
+        };
+        let withdrawal = EthToZecTransfer {
+            amount: 50_000,
+            recipient: ZcashRecipient::Transparent([8; 20]),
+        };
+        let entries = [
+            entry(
+                1,
+                QuarantinedTransfer::Deposit((&deposit(1, [0xA1; 20], 10, 5, true)).into()),
+            ),
+            entry(2, QuarantinedTransfer::Withdrawal(withdrawal.clone())),
+            entry(
+                3,
+                QuarantinedTransfer::Deposit((&deposit(3, [0xA1; 20], 10, 5, true)).into()),
+            ),
+        ];
+        let (deposits, withdrawals) = held(&entries, &[1]);
+        assert_eq!(
+            deposits,
+            [DepositOutpoint {
+                txid: [3; 32],
+                index: 0,
+            }]
+        );
+        assert_eq!(withdrawals, [withdrawal]);
+    }
+
+    #[test]
+    fn refunds_of_released_deposits_come_last_in_quarantine_order() {
+        let policy = DepositPolicy {
+            min_amount: 100_000,
+            refund_after: 10,
+            ..DepositPolicy::NONE
+        };
+        let pending = vec![
+            deposit(1, [0xA1; 20], 200_000, 5, true),
+            deposit(2, [0xB0; 20], 50_000, 0, true),
+        ];
+        let operator = ZcashRecipient::Transparent([9; 20]);
+        let released = vec![
+            // Refunded by the policy, being small and old.
+            (deposit(3, [0xC0; 20], 50_000, 0, true), None),
+            (
+                deposit(4, [0xA1; 20], 70_000, 5, true),
+                Some(operator.clone()),
+            ),
+            (deposit(5, [0xA1; 20], 30_000, 5, false), None),
+        ];
+        let settlement = settle(&policy, pending, released, 20);
+        assert_eq!(ids(&settlement.credited), [1, 5]);
+        assert_eq!(ids(&settlement.refunded), [2, 3, 4]);
+        assert_eq!(
+            settlement
+                .refunds
+                .iter()
+                .map(|refund| refund.recipient.clone())
+                .collect::<Vec<_>>(),
+            [
+                ZcashRecipient::Transparent([2; 20]),
+                ZcashRecipient::Transparent([3; 20]),
+                operator
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unpayable_withdrawals_are_returned_instead_of_released() -> anyhow::Result<()> {
+        let store = QuarantineStore::new(store_path("unpayable"));
+        // Not a canonical Pallas point encoding.
+        let withdrawal = QuarantinedTransfer::Withdrawal(EthToZecTransfer {
+            amount: 50_000,
+            recipient: ZcashRecipient::Orchard([0xFF; 43]),
+        });
+        let id = store
+            .add(withdrawal, "invalid recipient".to_string())
+            .await?;
+        assert!(store.resolve(id, Resolution::Release).await.is_err());
+        assert!(store.resolved().await?.is_empty());
+        store.resolve(id, Resolution::Return).await?;
+        assert_eq!(store.resolved().await?.len(), 1);
+        Ok(())
+    }
+}
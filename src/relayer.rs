@@ -0,0 +1,392 @@
+//! Startup and submission steps of the relayer, apart from its main loop.
+//!
+//! A relayer either stands by while another instance holds the lease, or takes over the STF
+//! both chains agree on, deploying one if none exists. It then submits each state update to
+//! Zcash and Ethereum in turn, with the update journaled in between, see [`crate::journal`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use alloy::primitives::Signature;
+use anyhow::Context as _;
+use zcash_primitives::transaction::components::{TzeOut, tze};
+use zcash_protocol::TxId;
+use zebra_chain::serialization::ZcashSerialize as _;
+
+use crate::{
+    deposits::{DepositPolicy, ObservedDeposit, Settlement},
+    eth::{sender::EthSender, watcher::EthWatcher},
+    journal::{FollowFrom, InFlightUpdate, Journal},
+    leader::{ChainFollower, Leadership, Takeover},
+    metrics::METRICS,
+    prover::{DepositInclusion, StateTransitionProof},
+    quarantine::{QuarantineStore, QuarantinedTransfer},
+    shutdown::{CancellationToken, Cancelled},
+    state::ChainCheckpoint,
+    types::StateUpdate,
+    watchtower::Carried,
+    zcash::{
+        light_client::{BlockHeader, transaction_proof},
+        sender::TzeSender,
+        watcher::ZcashWatcher,
+    },
+};
+
+/// What a relayer holding the lease does with the bridge as seen by its [`ChainFollower`].
+#[derive(Debug)]
+pub enum NextStep {
+    /// Continue from the STF both chains agree on.
+    TakeOver(Takeover),
+    /// Deploy an STF, as none exists yet.
+    Deploy,
+    /// Send the last update to Ethereum, as it only reached Zcash.
+    RollForward,
+    /// Wait for the last update to reach both chains.
+    Wait,
+}
+
+impl NextStep {
+    pub fn new(takeover: Option<Takeover>, has_stf: bool, awaits_ethereum: bool) -> Self {
+        match takeover {
+            Some(takeover) => Self::TakeOver(takeover),
+            None if !has_stf => Self::Deploy,
+            None if awaits_ethereum => Self::RollForward,
+            None => Self::Wait,
+        }
+    }
+
+    fn of(follower: &ChainFollower) -> Self {
+        Self::new(
+            follower.takeover(),
+            follower.has_stf(),
+            follower.awaits_ethereum(),
+        )
+    }
+}
+
+/// Follows the bridge until `leader` acquires the lease, then returns the STF to continue from,
+/// or `None` if a new one has to be deployed.
+///
+/// Fails with [`Cancelled`] if `shutdown` is cancelled while standing by.
+pub async fn standby(
+    leader: &mut Leadership,
+    follower: &mut ChainFollower,
+    journal: &Journal,
+    eth_sender: &EthSender,
+    shutdown: &CancellationToken,
+) -> anyhow::Result<Option<Takeover>> {
+    tracing::info!("Following the bridge as standby {}", leader.holder());
+    loop {
+        follower.poll().await?;
+        if leader.poll().await? {
+            // Catch up with the updates the previous leader sent before its lease expired.
+            follower.poll().await?;
+            match NextStep::of(follower) {
+                NextStep::TakeOver(takeover) => return Ok(Some(takeover)),
+                NextStep::Deploy => return Ok(None),
+                NextStep::RollForward => roll_forward(journal, eth_sender).await?,
+                NextStep::Wait => {
+                    tracing::info!("Waiting for the last update to reach both chains")
+                }
+            }
+        }
+        tokio::select! {
+            _ = shutdown.cancelled() => return Err(Cancelled.into()),
+            _ = tokio::time::sleep(leader.ttl() / 3) => {}
+        }
+    }
+}
+
+/// Returns the STF found by `follower` to continue from without a lease, or `None` if no STF
+/// exists. An update that only reached Zcash is rolled forward first.
+pub async fn resume(
+    follower: &mut ChainFollower,
+    journal: &Journal,
+    eth_sender: &EthSender,
+) -> anyhow::Result<Option<Takeover>> {
+    follower.poll().await?;
+    if follower.awaits_ethereum() {
+        roll_forward(journal, eth_sender).await?;
+        follower.poll().await?;
+    }
+    match NextStep::of(follower) {
+        NextStep::TakeOver(takeover) => Ok(Some(takeover)),
+        NextStep::Deploy => Ok(None),
+        NextStep::RollForward | NextStep::Wait => {
+            anyhow::bail!("the STF and the bridge contract have processed different updates")
+        }
+    }
+}
+
+/// Submits the journaled update, whose STF spend the follower found on Zcash, to Ethereum.
+pub async fn roll_forward(journal: &Journal, eth_sender: &EthSender) -> anyhow::Result<()> {
+    let in_flight = journal
+        .read()
+        .await?
+        .in_flight
+        .context("the STF processed an update missing from the journal")?;
+    tracing::info!(
+        "Rolling forward the update of ZEC blocks {}-{} on Ethereum",
+        in_flight.update.old_zcash_block,
+        in_flight.update.new_zcash_block
+    );
+    eth_sender
+        .update_bridge(
+            in_flight.update.clone(),
+            &in_flight.proof()?,
+            &in_flight.signatures()?,
+        )
+        .await?;
+    journal.complete().await
+}
+
+/// STF and checkpoints the relayer submits its first update from.
+#[derive(Debug)]
+pub struct Start {
+    pub stf: (tze::OutPoint, TzeOut),
+    pub zcash: ChainCheckpoint,
+    pub eth: ChainCheckpoint,
+    /// Deposits queued and transfers held in quarantine by the previous leader.
+    pub carried: Carried,
+    /// Indices of the credited deposits in the deposit log, by Zcash transaction.
+    pub deposit_indices: HashMap<TxId, Vec<u64>>,
+}
+
+/// Continues from `takeover`, or deploys a new STF at the current tips without one.
+pub async fn start(
+    takeover: Option<Takeover>,
+    zcash_sender: &mut TzeSender,
+    journal: &Journal,
+    zcash_watcher: &ZcashWatcher,
+    eth_watcher: &EthWatcher,
+) -> anyhow::Result<Start> {
+    if let Some(takeover) = takeover {
+        tracing::info!(
+            "Taking over STF {}:{} at ZEC block {}, ETH block {}",
+            takeover.stf.0.txid(),
+            takeover.stf.0.n(),
+            takeover.zcash.height,
+            takeover.eth.height
+        );
+        zcash_sender.resume(takeover.state, takeover.deposited);
+        // Both chains agree, so an update left in the journal was sent to neither.
+        journal.complete().await?;
+        return Ok(Start {
+            stf: takeover.stf,
+            zcash: takeover.zcash,
+            eth: takeover.eth,
+            carried: takeover.carried,
+            deposit_indices: takeover.deposit_indices,
+        });
+    }
+
+    let follow_from = FollowFrom {
+        zcash_height: zcash_watcher.get_block_count().await? - 1,
+        eth_block: eth_watcher.get_block_number().await? - 1,
+    };
+    let stf = zcash_sender.deploy().await?;
+    journal.deployed(follow_from).await?;
+    let zcash_height = zcash_watcher.get_block_count().await? - 1;
+    let eth_height = eth_watcher.get_block_number().await? - 1;
+    Ok(Start {
+        stf,
+        zcash: ChainCheckpoint {
+            height: zcash_height as u64,
+            hash: zcash_watcher.get_block(zcash_height).await?.hash().0,
+        },
+        eth: ChainCheckpoint {
+            height: eth_height,
+            hash: eth_watcher.get_block(eth_height).await?.hash().0,
+        },
+        carried: Carried::default(),
+        deposit_indices: HashMap::new(),
+    })
+}
+
+/// Adds `transfer` to the quarantine, left out of updates until an operator resolves it.
+pub async fn quarantine_transfer(
+    quarantine: &QuarantineStore,
+    transfer: QuarantinedTransfer,
+    error: String,
+) -> anyhow::Result<()> {
+    let id = quarantine.add(transfer.clone(), error.clone()).await?;
+    METRICS.transfers_quarantined.inc();
+    tracing::warn!("Quarantined transfer #{id} {transfer:?}: {error}");
+    Ok(())
+}
+
+/// Logs the transfers of `update`, and the deposits `settlement` refunds or keeps queued.
+pub fn log_update(update: &StateUpdate, settlement: &Settlement, policy: &DepositPolicy) {
+    let zcash_blocks = (update.old_zcash_block + 1, update.new_zcash_block);
+    let eth_blocks = (update.old_eth_block + 1, update.new_eth_block);
+    if !update.eth_to_zec_transfers.is_empty() {
+        tracing::info!(
+            "Processing {} ETH -> ZEC transfers in blocks {}-{}",
+            update.eth_to_zec_transfers.len(),
+            eth_blocks.0,
+            eth_blocks.1
+        );
+        for t in &update.eth_to_zec_transfers {
+            tracing::info!("  {:?}", t);
+        }
+    }
+    if !update.zec_to_eth_transfers.is_empty() {
+        tracing::info!(
+            "Processing {} ZEC -> ETH transfers in blocks {}-{}",
+            update.zec_to_eth_transfers.len(),
+            zcash_blocks.0,
+            zcash_blocks.1
+        );
+        for t in &update.zec_to_eth_transfers {
+            tracing::info!("  {:?}", t);
+        }
+    }
+
+    for (deposit, refund) in settlement.refunded.iter().zip(&update.refunds) {
+        match policy.check_recipient(&deposit.transfer.eth_address) {
+            Ok(()) => tracing::info!("Refunding deposit below the minimum: {refund:?}"),
+            Err(e) => tracing::warn!("Refunding deposit to {e}: {refund:?}"),
+        }
+    }
+    for deposit in &settlement.queued {
+        if let Err(e) = policy.check_recipient(&deposit.transfer.eth_address) {
+            tracing::warn!(
+                "Deposit {:?} to {e} has no refund address and stays locked",
+                deposit.outpoint
+            );
+        }
+    }
+    if !settlement.queued.is_empty() {
+        tracing::info!("Keeping {} deposits queued", settlement.queued.len());
+    }
+}
+
+/// Builds the inclusions of `deposits` in their blocks, along with the Zcash headers before
+/// `first_height` needed to check them, see [`crate::prover::TransitionWitness::zcash_history`].
+pub async fn deposit_inclusions(
+    zcash_watcher: &ZcashWatcher,
+    deposits: impl IntoIterator<Item = &ObservedDeposit>,
+    first_height: u64,
+) -> anyhow::Result<(Vec<DepositInclusion>, Vec<BlockHeader>)> {
+    let mut blocks = BTreeMap::new();
+    let mut inclusions = Vec::new();
+    for deposit in deposits {
+        if !blocks.contains_key(&deposit.height) {
+            let block = zcash_watcher.get_block(deposit.height as u32).await?;
+            blocks.insert(deposit.height, block);
+        }
+        let block = &blocks[&deposit.height];
+        let txids: Vec<_> = block.transactions.iter().map(|tx| tx.hash().0).collect();
+        let index = txids
+            .iter()
+            .position(|txid| txid == deposit.outpoint.txid().as_ref())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "deposit {} is not in block {}",
+                    deposit.outpoint.txid(),
+                    deposit.height
+                )
+            })?;
+        inclusions.push(DepositInclusion {
+            height: deposit.height,
+            transaction: block.transactions[index].zcash_serialize_to_vec()?,
+            proof: transaction_proof(&txids, index as u64).unwrap(),
+            output: deposit.outpoint.n(),
+        });
+    }
+
+    let oldest = blocks.keys().next().map_or(first_height, |&height| height);
+    let mut history = Vec::new();
+    for height in oldest..first_height {
+        let block = zcash_watcher.get_block(height as u32).await?;
+        history.push(BlockHeader::from_zebra(&block.header)?);
+    }
+    Ok((inclusions, history))
+}
+
+/// State update along with its proof and the committee signatures, if any.
+#[derive(Debug, Clone)]
+pub struct SignedUpdate {
+    pub update: StateUpdate,
+    pub proof: StateTransitionProof,
+    pub signatures: Vec<Signature>,
+}
+
+/// Spends `stf` with the update, claiming the `claimed` deposit outputs, then sends the update
+/// to Ethereum and waits for the spend to be mined. Returns the new STF output.
+///
+/// The update is journaled first, so that a relayer restarted before both chains have it
+/// rolls it forward.
+pub async fn submit(
+    journal: &Journal,
+    zcash_sender: &mut TzeSender,
+    eth_sender: &EthSender,
+    stf: (tze::OutPoint, TzeOut),
+    claimed: Vec<(tze::OutPoint, TzeOut)>,
+    signed: &SignedUpdate,
+) -> anyhow::Result<(tze::OutPoint, TzeOut)> {
+    let SignedUpdate {
+        update,
+        proof,
+        signatures,
+    } = signed;
+    journal
+        .begin(InFlightUpdate::new(update.clone(), proof, signatures))
+        .await?;
+    let stf = zcash_sender
+        .update_zcash(stf, claimed, update.clone(), proof, signatures)
+        .await
+        .inspect_err(|_| METRICS.state_updates_failed.inc())?;
+    eth_sender
+        .update_bridge(update.clone(), proof, signatures)
+        .await
+        .inspect_err(|_| METRICS.state_updates_failed.inc())
+        .with_context(|| {
+            format!(
+                "STF spend {} is not matched on Ethereum, it is rolled forward on restart",
+                stf.0.txid()
+            )
+        })?;
+    zcash_sender.wait_for_tx(stf.0.txid()).await?;
+    journal.complete().await?;
+    Ok(stf)
+}
+
+#[cfg(test)]
+mod tests {
+    use zcash_protocol::value::Zatoshis;
+
+    use super::*;
+    use crate::{state::BridgeState, test_utils::deposit};
+
+    fn takeover() -> Takeover {
+        let stf = deposit(1, [1; 20], 100_000, 10, false);
+        Takeover {
+            stf: (stf.outpoint, stf.output),
+            state: BridgeState::default(),
+            deposited: Zatoshis::const_from_u64(100_000),
+            carried: Carried::default(),
+            deposit_indices: HashMap::new(),
+            zcash: ChainCheckpoint::default(),
+            eth: ChainCheckpoint::default(),
+        }
+    }
+
+    #[test]
+    fn leader_continues_only_from_an_stf_both_chains_agree_on() {
+        assert!(matches!(
+            NextStep::new(Some(takeover()), true, false),
+            NextStep::TakeOver(_)
+        ));
+        assert!(matches!(
+            NextStep::new(None, false, false),
+            NextStep::Deploy
+        ));
+        // The previous leader sent the last update to Zcash only.
+        assert!(matches!(
+            NextStep::new(None, true, true),
+            NextStep::RollForward
+        ));
+        // The last update is on Ethereum, and its STF spend not mined yet.
+        assert!(matches!(NextStep::new(None, true, false), NextStep::Wait));
+    }
+}
@@ -0,0 +1,69 @@
+//! Cancellation of long-running tasks on SIGINT and SIGTERM.
+
+use std::time::Duration;
+
+pub use tokio_util::sync::CancellationToken;
+
+/// Error returned by waits interrupted through a [`CancellationToken`].
+#[derive(Debug, thiserror::Error)]
+#[error("cancelled by shutdown")]
+pub struct Cancelled;
+
+/// Returns a token cancelled on the first SIGINT or SIGTERM.
+pub fn on_signal() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        match wait_for_signal().await {
+            Ok(signal) => tracing::info!("Received {signal}, shutting down"),
+            Err(err) => tracing::error!("Failed to listen for signals, shutting down: {err:#}"),
+        }
+        cancel.cancel();
+    });
+    token
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> anyhow::Result<&'static str> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            result?;
+            Ok("SIGINT")
+        }
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> anyhow::Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("Ctrl-C")
+}
+
+/// Returns a token cancelled `timeout` after `shutdown`, bounding the time left to in-flight
+/// work.
+pub fn deadline(shutdown: &CancellationToken, timeout: Duration) -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    let shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        tokio::time::sleep(timeout).await;
+        cancel.cancel();
+    });
+    token
+}
+
+/// Runs `future` until it completes or `token` is cancelled.
+pub async fn cancellable<T>(
+    token: &CancellationToken,
+    future: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    token
+        .run_until_cancelled(future)
+        .await
+        .unwrap_or_else(|| Err(Cancelled.into()))
+}
@@ -0,0 +1,362 @@
+//! Bridge state model committed to by the STF UTXO on Zcash.
+//!
+//! The state root is the Merkle root over the following leaves:
+//!
+//! | Index | Leaf |
+//! |-------|------|
+//! | 0 | Ethereum checkpoint: `block: u64 \|\| hash: [u8; 32]` |
+//! | 1 | Zcash checkpoint: `block: u64 \|\| hash: [u8; 32]` |
+//! | 2 | Deposits: `count: u64 \|\| total: u64 \|\| root: [u8; 32]` |
+//! | 3 | Withdrawals: `count: u64 \|\| total: u64 \|\| root: [u8; 32]` |
+//! | 4 | Commitment to the last processed [`StateUpdate`] |
+//!
+//! The deposit and withdrawal roots are Merkle roots over every transfer processed so far, so a
+//! [`TransferProof`] against any later state root proves that a transfer was processed.
+
+use serde::Serialize;
+
+use crate::{
+    merkle::{self, Hash, MerkleProof},
+    types::{EthToZecTransfer, StateUpdate, ZecToEthTransfer},
+};
+
+/// Last block of a chain covered by a processed state update.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ChainCheckpoint {
+    pub height: u64,
+    #[serde(with = "hex::serde")]
+    pub hash: [u8; 32],
+}
+
+impl ChainCheckpoint {
+    fn leaf(&self) -> Hash {
+        let mut data = self.height.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.hash);
+        merkle::hash_leaf(&data)
+    }
+}
+
+/// Transfers of one direction processed over the lifetime of the bridge.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TransferLog {
+    /// Sum of the transferred amounts, in zatoshis.
+    pub total: u64,
+    /// Leaf hashes of the transfers, in processing order.
+    #[serde(skip)]
+    leaves: Vec<Hash>,
+}
+
+impl TransferLog {
+    /// Log of transfers with the given leaves and total, as carried in a transition witness.
+    pub(crate) fn from_leaves(total: u64, leaves: Vec<Hash>) -> Self {
+        Self { total, leaves }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    pub fn leaves(&self) -> &[Hash] {
+        &self.leaves
+    }
+
+    pub fn root(&self) -> Hash {
+        merkle::root(&self.leaves)
+    }
+
+    /// Fails if the total overflows, like the bridge contract.
+    fn push(&mut self, amount: u64, leaf: Hash) -> anyhow::Result<()> {
+        self.total = self
+            .total
+            .checked_add(amount)
+            .ok_or_else(|| anyhow::anyhow!("transfer log total overflows u64"))?;
+        self.leaves.push(leaf);
+        Ok(())
+    }
+
+    fn leaf(&self) -> Hash {
+        transfer_log_leaf(self.count(), self.total, &self.root())
+    }
+}
+
+fn transfer_log_leaf(count: u64, total: u64, root: &Hash) -> Hash {
+    let mut data = count.to_be_bytes().to_vec();
+    data.extend_from_slice(&total.to_be_bytes());
+    data.extend_from_slice(root);
+    merkle::hash_leaf(&data)
+}
+
+/// Index of the deposit log leaf in the state tree.
+const DEPOSITS_LEAF: u64 = 2;
+/// Index of the withdrawal log leaf in the state tree.
+const WITHDRAWALS_LEAF: u64 = 3;
+
+/// Proof that a transfer is included in the transfer log committed to by a state root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransferProof {
+    /// Proof of the transfer leaf in the transfer log.
+    pub transfer: MerkleProof,
+    /// Number of transfers in the log.
+    pub log_count: u64,
+    /// Sum of the transferred amounts in the log.
+    pub log_total: u64,
+    /// Proof of the transfer log leaf in the state tree.
+    pub log: MerkleProof,
+    /// State root the proof is valid against.
+    #[serde(with = "hex::serde")]
+    pub state_root: Hash,
+}
+
+impl TransferProof {
+    /// Checks that the transfer with leaf hash `leaf` is included in the state with the root
+    /// of the proof.
+    fn verify(&self, leaf: &Hash, log_index: u64) -> bool {
+        let log_root = merkle::compute_root(leaf, &self.transfer);
+        if !merkle::verify(&log_root, leaf, self.log_count, &self.transfer) {
+            return false;
+        }
+        let log_leaf = transfer_log_leaf(self.log_count, self.log_total, &log_root);
+        self.log.index == log_index && merkle::verify(&self.state_root, &log_leaf, 5, &self.log)
+    }
+}
+
+/// Checks that `transfer` is a processed deposit of the state with the root of `proof`.
+pub fn verify_deposit_proof(transfer: &ZecToEthTransfer, proof: &TransferProof) -> bool {
+    proof.verify(&deposit_leaf(transfer), DEPOSITS_LEAF)
+}
+
+/// Checks that `transfer` is a processed withdrawal of the state with the root of `proof`.
+pub fn verify_withdrawal_proof(transfer: &EthToZecTransfer, proof: &TransferProof) -> bool {
+    proof.verify(&withdrawal_leaf(transfer), WITHDRAWALS_LEAF)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BridgeState {
+    pub eth: ChainCheckpoint,
+    pub zcash: ChainCheckpoint,
+    pub deposits: TransferLog,
+    pub withdrawals: TransferLog,
+    /// Commitment to the last processed update, zero before the first one.
+    #[serde(with = "hex::serde")]
+    pub last_update: [u8; 32],
+    /// Number of processed updates.
+    pub updates: u64,
+}
+
+impl BridgeState {
+    /// Applies `update`, which has to start at the checkpoints of the state.
+    ///
+    /// The first update may start anywhere, like on the `ZcashBridge` contract.
+    pub fn apply(&mut self, update: &StateUpdate) -> anyhow::Result<()> {
+        let old_eth = ChainCheckpoint {
+            height: update.old_eth_block,
+            hash: update.old_eth_hash,
+        };
+        let old_zcash = ChainCheckpoint {
+            height: update.old_zcash_block,
+            hash: update.old_zcash_hash,
+        };
+        if self.updates > 0 {
+            anyhow::ensure!(
+                old_eth == self.eth,
+                "update starts at ETH block {}, state is at {}",
+                old_eth.height,
+                self.eth.height
+            );
+            anyhow::ensure!(
+                old_zcash == self.zcash,
+                "update starts at ZEC block {}, state is at {}",
+                old_zcash.height,
+                self.zcash.height
+            );
+        }
+
+        // Logs are extended on copies, so that a failing update leaves the state unchanged.
+        let mut deposits = self.deposits.clone();
+        for transfer in &update.zec_to_eth_transfers {
+            deposits.push(transfer.amount, deposit_leaf(transfer))?;
+        }
+        let mut withdrawals = self.withdrawals.clone();
+        for transfer in &update.eth_to_zec_transfers {
+            withdrawals.push(transfer.amount, withdrawal_leaf(transfer))?;
+        }
+        self.deposits = deposits;
+        self.withdrawals = withdrawals;
+        self.eth = ChainCheckpoint {
+            height: update.new_eth_block,
+            hash: update.new_eth_hash,
+        };
+        self.zcash = ChainCheckpoint {
+            height: update.new_zcash_block,
+            hash: update.new_zcash_hash,
+        };
+        self.last_update = update.commitment();
+        self.updates += 1;
+        Ok(())
+    }
+
+    /// Leaf hashes of the state tree, see the module documentation for the layout.
+    pub fn leaves(&self) -> [Hash; 5] {
+        [
+            self.eth.leaf(),
+            self.zcash.leaf(),
+            self.deposits.leaf(),
+            self.withdrawals.leaf(),
+            merkle::hash_leaf(&self.last_update),
+        ]
+    }
+
+    pub fn root(&self) -> Hash {
+        merkle::root(&self.leaves())
+    }
+
+    /// Proves the inclusion of the deposit with the given index, counting from the first
+    /// processed deposit.
+    pub fn deposit_proof(&self, index: u64) -> Option<TransferProof> {
+        self.transfer_proof(&self.deposits, DEPOSITS_LEAF, index)
+    }
+
+    /// Proves the inclusion of the withdrawal with the given index, counting from the first
+    /// processed withdrawal.
+    pub fn withdrawal_proof(&self, index: u64) -> Option<TransferProof> {
+        self.transfer_proof(&self.withdrawals, WITHDRAWALS_LEAF, index)
+    }
+
+    fn transfer_proof(
+        &self,
+        log: &TransferLog,
+        log_index: u64,
+        index: u64,
+    ) -> Option<TransferProof> {
+        Some(TransferProof {
+            transfer: merkle::proof(&log.leaves, index)?,
+            log_count: log.count(),
+            log_total: log.total,
+            log: merkle::proof(&self.leaves(), log_index)?,
+            state_root: self.root(),
+        })
+    }
+}
+
+/// Leaf hash of a processed deposit: `eth_address: [u8; 20] || amount: u64`.
+pub fn deposit_leaf(transfer: &ZecToEthTransfer) -> Hash {
+    let mut data = transfer.eth_address.to_vec();
+    data.extend_from_slice(&transfer.amount.to_be_bytes());
+    merkle::hash_leaf(&data)
+}
+
+/// Leaf hash of a processed withdrawal: `receiver_type: u8 || receiver || amount: u64`.
+pub fn withdrawal_leaf(transfer: &EthToZecTransfer) -> Hash {
+    let mut data = vec![transfer.recipient.receiver_type()];
+    data.extend_from_slice(transfer.recipient.receiver_bytes());
+    data.extend_from_slice(&transfer.amount.to_be_bytes());
+    merkle::hash_leaf(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::update, types::ZcashRecipient};
+
+    fn deposits(old_block: u64, new_block: u64, amounts: &[u64]) -> StateUpdate {
+        amounts
+            .iter()
+            .fold(update(old_block, new_block), |update, &amount| {
+                update.deposit(amount, [0x33; 20])
+            })
+            .build()
+    }
+
+    #[test]
+    fn empty_state_root_matches_contract_genesis() {
+        // `StateProofs.EMPTY_STATE_ROOT`, which the bridge contract is deployed with.
+        assert_eq!(
+            hex::encode(BridgeState::default().root()),
+            "5a91a052b93682c3b1c2000a5013d2a60f217bab58caa6abdb01a65cc3b8ecdf"
+        );
+    }
+
+    #[test]
+    fn bridge_state_tracks_updates() -> anyhow::Result<()> {
+        let mut state = BridgeState::default();
+        let genesis_root = state.root();
+
+        let first = deposits(10, 12, &[90_000, 10_000]);
+        state.apply(&first)?;
+        assert_eq!(state.deposits.total, 100_000);
+        assert_eq!(state.deposits.count(), 2);
+        assert_eq!(state.last_update, first.commitment());
+        let first_root = state.root();
+        assert_ne!(first_root, genesis_root);
+
+        // Updates must continue from the last checkpoints.
+        let mut diverged = state.clone();
+        assert!(diverged.apply(&deposits(11, 14, &[])).is_err());
+
+        // Even an update without transfers moves the root, since the checkpoints change.
+        state.apply(&deposits(12, 14, &[]))?;
+        assert_ne!(state.root(), first_root);
+        assert_eq!(
+            state.deposits.root(),
+            merkle::root(
+                &first
+                    .zec_to_eth_transfers
+                    .iter()
+                    .map(deposit_leaf)
+                    .collect::<Vec<_>>()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn overflowing_log_totals_are_rejected() -> anyhow::Result<()> {
+        let mut state = BridgeState::default();
+        state.apply(&deposits(10, 12, &[u64::MAX]))?;
+        let before = state.clone();
+        assert!(state.apply(&deposits(12, 14, &[0, 1])).is_err());
+        assert_eq!(state, before);
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_proofs_verify_against_state_root() -> anyhow::Result<()> {
+        let mut state = BridgeState::default();
+        let first = update(10, 12)
+            .deposit(90_000, [0x33; 20])
+            .deposit(10_000, [0x33; 20])
+            .deposit(5_000, [0x33; 20])
+            .withdrawal(50_000, ZcashRecipient::Transparent([0x11; 20]))
+            .build();
+        state.apply(&first)?;
+        let second = update(12, 14)
+            .deposit(20_000, [0x33; 20])
+            .withdrawal(50_000, ZcashRecipient::Transparent([0x11; 20]))
+            .build();
+        state.apply(&second)?;
+
+        let deposit = &first.zec_to_eth_transfers[1];
+        let proof = state.deposit_proof(1).unwrap();
+        assert_eq!(proof.state_root, state.root());
+        assert!(verify_deposit_proof(deposit, &proof));
+        assert!(state.deposit_proof(4).is_none());
+
+        // A different amount, another state root or the other transfer log are rejected.
+        let mut inflated = deposit.clone();
+        inflated.amount += 1;
+        assert!(!verify_deposit_proof(&inflated, &proof));
+        let mut other_root = proof.clone();
+        other_root.state_root = [0; 32];
+        assert!(!verify_deposit_proof(deposit, &other_root));
+
+        let withdrawal = &second.eth_to_zec_transfers[0];
+        let proof = state.withdrawal_proof(1).unwrap();
+        assert!(verify_withdrawal_proof(withdrawal, &proof));
+        let mut as_deposit = proof.clone();
+        as_deposit.log.index = 2;
+        assert!(!verify_withdrawal_proof(withdrawal, &as_deposit));
+
+        Ok(())
+    }
+}
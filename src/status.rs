@@ -0,0 +1,118 @@
+//! Lookup of the bridge progress for individual transfers.
+
+use alloy::{
+    primitives::{B256, U256},
+    providers::{DynProvider, Provider as _},
+    rpc::types::Filter,
+    sol_types::SolEvent as _,
+};
+use serde::Serialize;
+use zcash_protocol::TxId;
+use zebra_node_services::rpc_client::RpcRequestClient;
+use zebra_rpc::methods::GetRawTransaction;
+
+use crate::{
+    eth::contract::ZcashBridge::{self, ZcashBridgeInstance},
+    zebra_client::client::RpcClient as _,
+};
+
+/// Progress of a single transfer through the bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransferStatus {
+    /// The transfer is not known to the source chain.
+    NotFound,
+    /// The transfer is waiting in the mempool of the source chain.
+    Mempool,
+    /// The transfer is included at `height` of the source chain, but not processed yet.
+    Pending { height: u64 },
+    /// The transfer is included at `height` of the source chain and processed by the bridge.
+    Processed { height: u64 },
+}
+
+impl std::fmt::Display for TransferStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found"),
+            Self::Mempool => write!(f, "in mempool"),
+            Self::Pending { height } => write!(f, "pending (included at height {height})"),
+            Self::Processed { height } => write!(f, "processed (included at height {height})"),
+        }
+    }
+}
+
+/// Withdrawal request known to the `ZcashBridge` contract.
+#[derive(Debug, Clone, Serialize)]
+pub struct WithdrawalInfo {
+    pub request_id: U256,
+    pub requester: String,
+    pub amount: u64,
+    /// Hex-encoded raw receiver.
+    pub receiver: String,
+    pub receiver_type: u8,
+    #[serde(flatten)]
+    pub status: TransferStatus,
+}
+
+/// Returns the status of a Zcash -> Ethereum deposit made in transaction `txid`.
+pub async fn deposit_status(
+    client: &RpcRequestClient,
+    bridge: &ZcashBridgeInstance<DynProvider>,
+    txid: &TxId,
+) -> anyhow::Result<TransferStatus> {
+    let Ok(GetRawTransaction::Object(tx)) = client.get_raw_transaction(txid, true).await else {
+        return Ok(TransferStatus::NotFound);
+    };
+    // `None` = mempool, `Some(-1)` = side chain, `Some(height >= 0)` = main chain
+    let height = match tx.height() {
+        None => return Ok(TransferStatus::Mempool),
+        Some(height) if height < 0 => return Ok(TransferStatus::NotFound),
+        Some(height) => height as u64,
+    };
+
+    let state = bridge.latestState().call().await?;
+    if state.zecBlockNumber >= height {
+        Ok(TransferStatus::Processed { height })
+    } else {
+        Ok(TransferStatus::Pending { height })
+    }
+}
+
+/// Returns the status of an Ethereum -> Zcash withdrawal with the given request ID.
+pub async fn withdrawal_status(
+    bridge: &ZcashBridgeInstance<DynProvider>,
+    request_id: U256,
+) -> anyhow::Result<Option<WithdrawalInfo>> {
+    let request = bridge.getWithdrawalRequest(request_id).call().await?;
+    if request.amount.is_zero() {
+        return Ok(None);
+    }
+
+    let filter = Filter::new()
+        .address(*bridge.address())
+        .event_signature(ZcashBridge::WithdrawalRequested::SIGNATURE_HASH)
+        .topic1(B256::from(request_id))
+        .from_block(0);
+    let logs = bridge.provider().get_logs(&filter).await?;
+    let height = logs
+        .first()
+        .and_then(|log| log.block_number)
+        .ok_or_else(|| anyhow::anyhow!("no WithdrawalRequested event for {request_id}"))?;
+
+    let status = if request.processed {
+        TransferStatus::Processed { height }
+    } else {
+        TransferStatus::Pending { height }
+    };
+    let amount = u64::try_from(request.amount)
+        .map_err(|_| anyhow::anyhow!("amount of withdrawal request {request_id} exceeds u64"))?;
+    Ok(Some(WithdrawalInfo {
+        request_id,
+        requester: request.requester.to_string(),
+        amount,
+        // Receivers the relayer cannot pay are quarantined, so they are reported as requested.
+        receiver: hex::encode(&request.receiver),
+        receiver_type: request.receiverType,
+        status,
+    }))
+}
@@ -0,0 +1,138 @@
+//! Records kept in a local JSON file and shared by the processes on a host.
+//!
+//! Accesses are serialized by a lock on a separate `.lock` file. Updates are written to a
+//! temporary file, synced and renamed over the record, so a crash leaves either the old or the
+//! new record on disk, never a truncated one.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+#[derive(Debug, Clone)]
+pub(crate) struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Reads the record, or its default while none was written.
+    pub(crate) async fn read<R>(&self) -> anyhow::Result<R>
+    where
+        R: DeserializeOwned + Default + Send + 'static,
+    {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let lock = open_lock(&path)?;
+            lock.lock_shared()?;
+            read_record(&path)
+        })
+        .await?
+    }
+
+    /// Runs `update` on the record under the lock and stores the result, unless `update` fails.
+    pub(crate) async fn update<R, T>(
+        &self,
+        update: impl FnOnce(&mut R) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T>
+    where
+        R: Serialize + DeserializeOwned + Default + 'static,
+        T: Send + 'static,
+    {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let lock = open_lock(&path)?;
+            lock.lock()?;
+            let mut record = read_record(&path)?;
+            let result = update(&mut record)?;
+            write_record(&path, &record)?;
+            Ok(result)
+        })
+        .await?
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+
+fn open_lock(path: &Path) -> anyhow::Result<File> {
+    Ok(OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(with_suffix(path, ".lock"))?)
+}
+
+fn read_record<R: DeserializeOwned + Default>(path: &Path) -> anyhow::Result<R> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(R::default()),
+        Err(err) => return Err(err.into()),
+    };
+    if contents.trim().is_empty() {
+        return Ok(R::default());
+    }
+    Ok(serde_json::from_str(&contents)?)
+}
+
+fn write_record<R: Serialize>(path: &Path, record: &R) -> anyhow::Result<()> {
+    let temp = with_suffix(path, ".tmp");
+    let file = File::create(&temp)?;
+    serde_json::to_writer_pretty(&file, record)?;
+    // The record has to reach the disk before the transactions it protects are sent.
+    file.sync_all()?;
+    fs::rename(&temp, path)?;
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn interrupted_writes_keep_the_last_record() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("store-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let file = JsonFile::new(path.clone());
+        assert_eq!(file.read::<Vec<u64>>().await?, Vec::<u64>::new());
+
+        file.update(|record: &mut Vec<u64>| {
+            record.push(1);
+            Ok(())
+        })
+        .await?;
+        // A crash while writing the next record leaves a partial temporary file behind.
+        fs::write(with_suffix(&path, ".tmp"), "[1, 2")?;
+        assert_eq!(file.read::<Vec<u64>>().await?, [1]);
+
+        // A failed update stores nothing.
+        let failed: anyhow::Result<()> = file
+            .update(|record: &mut Vec<u64>| {
+                record.push(3);
+                anyhow::bail!("rejected")
+            })
+            .await;
+        assert!(failed.is_err());
+        file.update(|record: &mut Vec<u64>| {
+            record.push(2);
+            Ok(())
+        })
+        .await?;
+        assert_eq!(file.read::<Vec<u64>>().await?, [1, 2]);
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+//! Notifications of new blocks, replacing fixed-interval polling of the chain tips.
+//!
+//! See [`crate::eth::subscription`] and [`crate::zcash::subscription`] for the implementations.
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait BlockSubscription: Send {
+    /// Waits until the chain tip is above `height` and returns the new tip height.
+    async fn wait_for_block(&mut self, height: u64) -> anyhow::Result<u64>;
+}
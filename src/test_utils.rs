@@ -0,0 +1,153 @@
+//! Fixtures shared by the unit tests.
+
+use zcash_primitives::{
+    extensions::transparent::Precondition,
+    transaction::components::{TzeOut, tze},
+};
+use zcash_protocol::{TxId, value::Zatoshis};
+
+use crate::deposits::ObservedDeposit;
+use crate::types::{
+    DepositOutpoint, DepositRefund, EthToZecTransfer, StateUpdate, ZcashRecipient, ZecToEthTransfer,
+};
+
+/// Builds a [`StateUpdate`] whose checkpoint hashes are derived from the block numbers.
+pub(crate) struct UpdateBuilder {
+    update: StateUpdate,
+}
+
+/// Starts an update moving both chains from `old_block` to `new_block`, without transfers.
+pub(crate) fn update(old_block: u64, new_block: u64) -> UpdateBuilder {
+    UpdateBuilder {
+        update: StateUpdate {
+            old_eth_block: old_block,
+            new_eth_block: new_block,
+            old_eth_hash: block_hash(old_block),
+            new_eth_hash: block_hash(new_block),
+            old_zcash_block: old_block,
+            new_zcash_block: new_block,
+            old_zcash_hash: block_hash(old_block),
+            new_zcash_hash: block_hash(new_block),
+            eth_to_zec_transfers: Vec::new(),
+            zec_to_eth_transfers: Vec::new(),
+            refunds: Vec::new(),
+            withdrawal_refunds: Vec::new(),
+            quarantined_deposits: Vec::new(),
+            quarantined_withdrawals: Vec::new(),
+        },
+    }
+}
+
+/// Hash of the checkpoint at `block` in updates built by [`update`].
+pub(crate) fn block_hash(block: u64) -> [u8; 32] {
+    [block as u8; 32]
+}
+
+/// Withdrawal of `amount` to a fixed transparent recipient.
+pub(crate) fn withdrawal(amount: u64) -> EthToZecTransfer {
+    EthToZecTransfer {
+        amount,
+        recipient: ZcashRecipient::Transparent([0x11; 20]),
+    }
+}
+
+/// Deposit of `amount` to `to` in transaction `[id; 32]`, refundable to `[id; 20]` if
+/// `refundable`.
+pub(crate) fn deposit(
+    id: u8,
+    to: [u8; 20],
+    amount: u64,
+    height: u64,
+    refundable: bool,
+) -> ObservedDeposit {
+    ObservedDeposit {
+        outpoint: tze::OutPoint::new(TxId::from_bytes([id; 32]), 0),
+        output: TzeOut {
+            value: Zatoshis::from_u64(amount).unwrap(),
+            precondition: Precondition {
+                extension_id: 2,
+                mode: 2,
+                payload: Vec::new(),
+            },
+        },
+        transfer: ZecToEthTransfer {
+            amount,
+            eth_address: to,
+        },
+        height,
+        refund_to: refundable.then_some(ZcashRecipient::Transparent([id; 20])),
+    }
+}
+
+/// IDs of `deposits` as passed to [`deposit`].
+pub(crate) fn ids(deposits: &[ObservedDeposit]) -> Vec<u8> {
+    deposits
+        .iter()
+        .map(|deposit| deposit.outpoint.txid().as_ref()[0])
+        .collect()
+}
+
+impl UpdateBuilder {
+    /// Moves the Zcash checkpoints to `old_block` and `new_block`, apart from Ethereum.
+    pub(crate) fn zcash_blocks(mut self, old_block: u64, new_block: u64) -> Self {
+        self.update.old_zcash_block = old_block;
+        self.update.new_zcash_block = new_block;
+        self.update.old_zcash_hash = block_hash(old_block);
+        self.update.new_zcash_hash = block_hash(new_block);
+        self
+    }
+
+    pub(crate) fn deposit(mut self, amount: u64, eth_address: [u8; 20]) -> Self {
+        self.update.zec_to_eth_transfers.push(ZecToEthTransfer {
+            amount,
+            eth_address,
+        });
+        self
+    }
+
+    pub(crate) fn withdrawal(mut self, amount: u64, recipient: ZcashRecipient) -> Self {
+        self.update
+            .eth_to_zec_transfers
+            .push(EthToZecTransfer { amount, recipient });
+        self
+    }
+
+    pub(crate) fn refund(
+        mut self,
+        amount: u64,
+        recipient: ZcashRecipient,
+        deposit_txid: [u8; 32],
+    ) -> Self {
+        self.update.refunds.push(DepositRefund {
+            amount,
+            recipient,
+            deposit_txid,
+        });
+        self
+    }
+
+    pub(crate) fn withdrawal_refund(mut self, amount: u64, recipient: ZcashRecipient) -> Self {
+        self.update
+            .withdrawal_refunds
+            .push(EthToZecTransfer { amount, recipient });
+        self
+    }
+
+    pub(crate) fn quarantined_deposit(mut self, txid: [u8; 32], index: u32) -> Self {
+        self.update
+            .quarantined_deposits
+            .push(DepositOutpoint { txid, index });
+        self
+    }
+
+    pub(crate) fn quarantined_withdrawal(mut self, amount: u64, recipient: ZcashRecipient) -> Self {
+        self.update
+            .quarantined_withdrawals
+            .push(EthToZecTransfer { amount, recipient });
+        self
+    }
+
+    pub(crate) fn build(self) -> StateUpdate {
+        self.update
+    }
+}
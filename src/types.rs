@@ -1,25 +1,547 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+use zcash_address::{
+    ConversionError, TryFromAddress, ZcashAddress,
+    unified::{self, Container as _},
+};
+use zcash_protocol::consensus::NetworkType;
+
+/// Zcash receiver of a withdrawal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    content = "receiver",
+    rename_all = "snake_case",
+    try_from = "RawRecipient"
+)]
+pub enum ZcashRecipient {
+    /// Transparent P2PKH address, identified by its public key hash.
+    Transparent(#[serde(with = "hex::serde")] [u8; 20]),
+    /// Raw Sapling payment address (diversifier and `pk_d`).
+    Sapling(#[serde(serialize_with = "hex::serde::serialize")] [u8; 43]),
+    /// Raw Orchard address (diversifier and `pk_d`).
+    Orchard(#[serde(serialize_with = "hex::serde::serialize")] [u8; 43]),
+}
+
+/// Serialized form of [`ZcashRecipient`], whose shielded receivers have no array deserializer.
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "receiver", rename_all = "snake_case")]
+enum RawRecipient {
+    Transparent(#[serde(with = "hex::serde")] [u8; 20]),
+    Sapling(#[serde(with = "hex::serde")] Vec<u8>),
+    Orchard(#[serde(with = "hex::serde")] Vec<u8>),
+}
+
+impl TryFrom<RawRecipient> for ZcashRecipient {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawRecipient) -> anyhow::Result<Self> {
+        match raw {
+            RawRecipient::Transparent(hash) => Ok(Self::Transparent(hash)),
+            RawRecipient::Sapling(raw) => Self::from_receiver(1, &raw),
+            RawRecipient::Orchard(raw) => Self::from_receiver(2, &raw),
+        }
+    }
+}
+
+impl ZcashRecipient {
+    /// Receiver type as encoded in the `ZcashBridge.ReceiverType` enum.
+    pub fn receiver_type(&self) -> u8 {
+        match self {
+            Self::Transparent(_) => 0,
+            Self::Sapling(_) => 1,
+            Self::Orchard(_) => 2,
+        }
+    }
+
+    /// Raw receiver bytes as passed to the `ZcashBridge` contract.
+    pub fn receiver_bytes(&self) -> &[u8] {
+        match self {
+            Self::Transparent(hash) => hash,
+            Self::Sapling(raw) | Self::Orchard(raw) => raw,
+        }
+    }
+
+    /// Decodes the receiver from its contract representation.
+    pub fn from_receiver(receiver_type: u8, receiver: &[u8]) -> anyhow::Result<Self> {
+        let invalid_length = |_| anyhow::anyhow!("invalid receiver length {}", receiver.len());
+        match receiver_type {
+            0 => Ok(Self::Transparent(
+                receiver.try_into().map_err(invalid_length)?,
+            )),
+            1 => Ok(Self::Sapling(receiver.try_into().map_err(invalid_length)?)),
+            2 => Ok(Self::Orchard(receiver.try_into().map_err(invalid_length)?)),
+            _ => anyhow::bail!("unknown receiver type {receiver_type}"),
+        }
+    }
+
+    /// Parses an encoded Zcash address for the given network.
+    ///
+    /// For unified addresses the most private receiver is picked: Orchard, then Sapling, then
+    /// transparent P2PKH.
+    pub fn parse(address: &str, network: NetworkType) -> anyhow::Result<Self> {
+        ZcashAddress::try_from_encoded(address)?
+            .convert_if_network::<Self>(network)
+            .map_err(|e| anyhow::anyhow!("unsupported address {address}: {e}"))
+    }
+}
+
+impl TryFromAddress for ZcashRecipient {
+    type Error = &'static str;
+
+    fn try_from_sapling(
+        _net: NetworkType,
+        data: [u8; 43],
+    ) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(Self::Sapling(data))
+    }
+
+    fn try_from_unified(
+        _net: NetworkType,
+        data: unified::Address,
+    ) -> Result<Self, ConversionError<Self::Error>> {
+        let mut best = None;
+        for receiver in data.items() {
+            let candidate = match receiver {
+                unified::Receiver::Orchard(raw) => Self::Orchard(raw),
+                unified::Receiver::Sapling(raw) => Self::Sapling(raw),
+                unified::Receiver::P2pkh(hash) => Self::Transparent(hash),
+                _ => continue,
+            };
+            // Receiver types are numbered in the order of increasing privacy.
+            if best
+                .as_ref()
+                .is_none_or(|b: &Self| candidate.receiver_type() > b.receiver_type())
+            {
+                best = Some(candidate);
+            }
+        }
+        best.ok_or(ConversionError::User(
+            "unified address has no supported receiver",
+        ))
+    }
+
+    fn try_from_transparent_p2pkh(
+        _net: NetworkType,
+        data: [u8; 20],
+    ) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(Self::Transparent(data))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EthToZecTransfer {
     pub amount: u64, // TODO: use U256?
-    pub pubkey_hash: [u8; 20],
+    pub recipient: ZcashRecipient,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ZecToEthTransfer {
     pub amount: u64, // TODO: use U256?
+    #[serde(with = "hex::serde")]
     pub eth_address: [u8; 20],
 }
 
-#[derive(Debug, Clone)]
+/// Deposit paid back on Zcash instead of being credited on Ethereum.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositRefund {
+    pub amount: u64,
+    pub recipient: ZcashRecipient,
+    /// Transaction of the refunded deposit output.
+    #[serde(with = "hex::serde")]
+    pub deposit_txid: [u8; 32],
+}
+
+/// Deposit output on Zcash, identified by its transaction and output index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositOutpoint {
+    #[serde(with = "hex::serde")]
+    pub txid: [u8; 32],
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StateUpdate {
     pub old_eth_block: u64,
     pub new_eth_block: u64,
+    #[serde(with = "hex::serde")]
     pub old_eth_hash: [u8; 32],
+    #[serde(with = "hex::serde")]
     pub new_eth_hash: [u8; 32],
     pub old_zcash_block: u64,
     pub new_zcash_block: u64,
+    #[serde(with = "hex::serde")]
     pub old_zcash_hash: [u8; 32],
+    #[serde(with = "hex::serde")]
     pub new_zcash_hash: [u8; 32],
     pub eth_to_zec_transfers: Vec<EthToZecTransfer>,
     pub zec_to_eth_transfers: Vec<ZecToEthTransfer>,
+    pub refunds: Vec<DepositRefund>,
+    /// Quarantined withdrawals credited back to their requesters on Ethereum instead of being
+    /// paid on Zcash.
+    pub withdrawal_refunds: Vec<EthToZecTransfer>,
+    /// Deposits held in the quarantine after the update, in the order they were quarantined.
+    ///
+    /// Together with [`Self::quarantined_withdrawals`], it tells verifiers which transfers are
+    /// left out on purpose, see [`crate::quarantine`].
+    pub quarantined_deposits: Vec<DepositOutpoint>,
+    /// Withdrawals held in the quarantine after the update, in the order they were quarantined.
+    pub quarantined_withdrawals: Vec<EthToZecTransfer>,
+}
+
+impl StateUpdate {
+    /// Version of the canonical encoding, mirrored by `ZcashBridge.STATE_UPDATE_ENCODING_VERSION`.
+    pub const ENCODING_VERSION: u8 = 3;
+
+    /// Canonical binary encoding of the update.
+    ///
+    /// Integers are big-endian and amounts take 32 bytes, so the encoding is equal to the
+    /// `abi.encodePacked` encoding computed by `ZcashBridge.encodeStateUpdate`:
+    ///
+    /// ```text
+    /// version: u8
+    /// old_eth_block: u64 | new_eth_block: u64 | old_eth_hash: [u8; 32] | new_eth_hash: [u8; 32]
+    /// old_zcash_block: u64 | new_zcash_block: u64 | old_zcash_hash: [u8; 32] | new_zcash_hash: [u8; 32]
+    /// deposit count: u32, then per deposit: eth_address: [u8; 20] | amount: u256
+    /// withdrawal count: u32, then per withdrawal: receiver type: u8 | receiver | amount: u256
+    /// refund count: u32, then per refund: deposit_txid: [u8; 32] | receiver type: u8 | receiver | amount: u256
+    /// withdrawal refund count: u32, then per withdrawal: receiver type: u8 | receiver | amount: u256
+    /// quarantined deposit count: u32, then per deposit: txid: [u8; 32] | index: u32
+    /// quarantined withdrawal count: u32, then per withdrawal: receiver type: u8 | receiver | amount: u256
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = vec![Self::ENCODING_VERSION];
+        encoded.extend_from_slice(&self.old_eth_block.to_be_bytes());
+        encoded.extend_from_slice(&self.new_eth_block.to_be_bytes());
+        encoded.extend_from_slice(&self.old_eth_hash);
+        encoded.extend_from_slice(&self.new_eth_hash);
+        encoded.extend_from_slice(&self.old_zcash_block.to_be_bytes());
+        encoded.extend_from_slice(&self.new_zcash_block.to_be_bytes());
+        encoded.extend_from_slice(&self.old_zcash_hash);
+        encoded.extend_from_slice(&self.new_zcash_hash);
+
+        encoded.extend_from_slice(&(self.zec_to_eth_transfers.len() as u32).to_be_bytes());
+        for transfer in &self.zec_to_eth_transfers {
+            encoded.extend_from_slice(&transfer.eth_address);
+            encoded.extend_from_slice(&encode_amount(transfer.amount));
+        }
+        encode_withdrawals(&mut encoded, &self.eth_to_zec_transfers);
+        encoded.extend_from_slice(&(self.refunds.len() as u32).to_be_bytes());
+        for refund in &self.refunds {
+            encoded.extend_from_slice(&refund.deposit_txid);
+            encoded.push(refund.recipient.receiver_type());
+            encoded.extend_from_slice(refund.recipient.receiver_bytes());
+            encoded.extend_from_slice(&encode_amount(refund.amount));
+        }
+        encode_withdrawals(&mut encoded, &self.withdrawal_refunds);
+        encoded.extend_from_slice(&(self.quarantined_deposits.len() as u32).to_be_bytes());
+        for deposit in &self.quarantined_deposits {
+            encoded.extend_from_slice(&deposit.txid);
+            encoded.extend_from_slice(&deposit.index.to_be_bytes());
+        }
+        encode_withdrawals(&mut encoded, &self.quarantined_withdrawals);
+        encoded
+    }
+
+    /// Decodes an update from its canonical encoding.
+    pub fn decode(encoded: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader(encoded);
+        let version = reader.take::<1>()?[0];
+        anyhow::ensure!(
+            version == Self::ENCODING_VERSION,
+            "unsupported state update encoding version {version}"
+        );
+
+        let old_eth_block = u64::from_be_bytes(reader.take()?);
+        let new_eth_block = u64::from_be_bytes(reader.take()?);
+        let old_eth_hash = reader.take()?;
+        let new_eth_hash = reader.take()?;
+        let old_zcash_block = u64::from_be_bytes(reader.take()?);
+        let new_zcash_block = u64::from_be_bytes(reader.take()?);
+        let old_zcash_hash = reader.take()?;
+        let new_zcash_hash = reader.take()?;
+
+        let deposit_count = u32::from_be_bytes(reader.take()?);
+        let mut zec_to_eth_transfers = Vec::new();
+        for _ in 0..deposit_count {
+            let eth_address = reader.take()?;
+            let amount = decode_amount(reader.take()?)?;
+            zec_to_eth_transfers.push(ZecToEthTransfer {
+                amount,
+                eth_address,
+            });
+        }
+
+        let eth_to_zec_transfers = reader.take_withdrawals()?;
+
+        let refund_count = u32::from_be_bytes(reader.take()?);
+        let mut refunds = Vec::new();
+        for _ in 0..refund_count {
+            let deposit_txid = reader.take()?;
+            let recipient = reader.take_recipient()?;
+            let amount = decode_amount(reader.take()?)?;
+            refunds.push(DepositRefund {
+                amount,
+                recipient,
+                deposit_txid,
+            });
+        }
+        let withdrawal_refunds = reader.take_withdrawals()?;
+
+        let quarantined_count = u32::from_be_bytes(reader.take()?);
+        let mut quarantined_deposits = Vec::new();
+        for _ in 0..quarantined_count {
+            let txid = reader.take()?;
+            let index = u32::from_be_bytes(reader.take()?);
+            quarantined_deposits.push(DepositOutpoint { txid, index });
+        }
+        let quarantined_withdrawals = reader.take_withdrawals()?;
+        anyhow::ensure!(reader.0.is_empty(), "trailing bytes after state update");
+
+        Ok(Self {
+            old_eth_block,
+            new_eth_block,
+            old_eth_hash,
+            new_eth_hash,
+            old_zcash_block,
+            new_zcash_block,
+            old_zcash_hash,
+            new_zcash_hash,
+            eth_to_zec_transfers,
+            zec_to_eth_transfers,
+            refunds,
+            withdrawal_refunds,
+            quarantined_deposits,
+            quarantined_withdrawals,
+        })
+    }
+
+    /// Keccak-256 hash of the canonical encoding, which both chains bind their transactions to.
+    pub fn commitment(&self) -> [u8; 32] {
+        alloy::primitives::keccak256(self.encode()).0
+    }
+}
+
+pub(crate) fn encode_withdrawals(encoded: &mut Vec<u8>, withdrawals: &[EthToZecTransfer]) {
+    encoded.extend_from_slice(&(withdrawals.len() as u32).to_be_bytes());
+    for transfer in withdrawals {
+        encoded.push(transfer.recipient.receiver_type());
+        encoded.extend_from_slice(transfer.recipient.receiver_bytes());
+        encoded.extend_from_slice(&encode_amount(transfer.amount));
+    }
+}
+
+fn encode_amount(amount: u64) -> [u8; 32] {
+    let mut encoded = [0; 32];
+    encoded[24..].copy_from_slice(&amount.to_be_bytes());
+    encoded
+}
+
+fn decode_amount(encoded: [u8; 32]) -> anyhow::Result<u64> {
+    anyhow::ensure!(encoded[..24].iter().all(|b| *b == 0), "amount exceeds u64");
+    Ok(u64::from_be_bytes(encoded[24..].try_into().unwrap()))
+}
+
+/// Cursor over an encoded state update or transition witness.
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take_slice(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        anyhow::ensure!(self.0.len() >= len, "unexpected end of input");
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self.take_slice(N)?.try_into().unwrap())
+    }
+
+    fn take_recipient(&mut self) -> anyhow::Result<ZcashRecipient> {
+        let receiver_type = self.take::<1>()?[0];
+        let receiver_len = match receiver_type {
+            0 => 20,
+            _ => 43,
+        };
+        ZcashRecipient::from_receiver(receiver_type, self.take_slice(receiver_len)?)
+    }
+
+    pub(crate) fn take_withdrawals(&mut self) -> anyhow::Result<Vec<EthToZecTransfer>> {
+        let count = u32::from_be_bytes(self.take()?);
+        let mut withdrawals = Vec::new();
+        for _ in 0..count {
+            let recipient = self.take_recipient()?;
+            let amount = decode_amount(self.take()?)?;
+            withdrawals.push(EthToZecTransfer { amount, recipient });
+        }
+        Ok(withdrawals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::update;
+
+    fn state_update() -> StateUpdate {
+        update(2, 4)
+            .zcash_blocks(6, 8)
+            .withdrawal(50_000, ZcashRecipient::Transparent([0x11; 20]))
+            .withdrawal(60_000, ZcashRecipient::Orchard([0x22; 43]))
+            .deposit(90_000, [0x33; 20])
+            .refund(5_000, ZcashRecipient::Transparent([0x44; 20]), [0x55; 32])
+            .withdrawal_refund(8_000, ZcashRecipient::Transparent([0x88; 20]))
+            .quarantined_deposit([0x66; 32], 1)
+            .quarantined_withdrawal(7_000, ZcashRecipient::Sapling([0x77; 43]))
+            .build()
+    }
+
+    /// Hashes as `bytes32(uint256(n))` in Solidity.
+    fn hash(n: u8) -> [u8; 32] {
+        let mut hash = [0; 32];
+        hash[31] = n;
+        hash
+    }
+
+    /// Update of the shared test vector, mirrored by `test_EncodeStateUpdate_Layout` in
+    /// `contracts/test/ZcashBridge.t.sol`.
+    fn test_vector() -> StateUpdate {
+        StateUpdate {
+            old_eth_block: 2,
+            new_eth_block: 4,
+            old_eth_hash: hash(1),
+            new_eth_hash: hash(3),
+            old_zcash_block: 6,
+            new_zcash_block: 8,
+            old_zcash_hash: hash(5),
+            new_zcash_hash: hash(7),
+            eth_to_zec_transfers: vec![EthToZecTransfer {
+                amount: 50_000,
+                recipient: ZcashRecipient::Transparent([0x11; 20]),
+            }],
+            zec_to_eth_transfers: vec![ZecToEthTransfer {
+                amount: 90_000,
+                eth_address: [0x33; 20],
+            }],
+            refunds: vec![DepositRefund {
+                amount: 5_000,
+                recipient: ZcashRecipient::Transparent([0x44; 20]),
+                deposit_txid: hash(0x55),
+            }],
+            withdrawal_refunds: vec![EthToZecTransfer {
+                amount: 8_000,
+                recipient: ZcashRecipient::Transparent([0x88; 20]),
+            }],
+            quarantined_deposits: vec![DepositOutpoint {
+                txid: hash(0x66),
+                index: 1,
+            }],
+            quarantined_withdrawals: vec![EthToZecTransfer {
+                amount: 7_000,
+                recipient: ZcashRecipient::Transparent([0x77; 20]),
+            }],
+        }
+    }
+
+    const TEST_VECTOR_ENCODING: &[&str] = &[
+        "0300000000000000020000000000000004000000000000000000000000000000",
+        "0000000000000000000000000000000001000000000000000000000000000000",
+        "0000000000000000000000000000000003000000000000000600000000000000",
+        "0800000000000000000000000000000000000000000000000000000000000000",
+        "0500000000000000000000000000000000000000000000000000000000000000",
+        "0700000001333333333333333333333333333333333333333300000000000000",
+        "00000000000000000000000000000000000000000000015f9000000001001111",
+        "1111111111111111111111111111111111110000000000000000000000000000",
+        "00000000000000000000000000000000c3500000000100000000000000000000",
+        "0000000000000000000000000000000000000000005500444444444444444444",
+        "4444444444444444444444000000000000000000000000000000000000000000",
+        "0000000000000000001388000000010088888888888888888888888888888888",
+        "8888888800000000000000000000000000000000000000000000000000000000",
+        "00001f4000000001000000000000000000000000000000000000000000000000",
+        "0000000000000066000000010000000100777777777777777777777777777777",
+        "7777777777000000000000000000000000000000000000000000000000000000",
+        "0000001b58",
+    ];
+    const TEST_VECTOR_COMMITMENT: &str =
+        "e404d6112bf2a836b3c8bd9574136bc2ce84e9ea075b190e3968136c382e0f59";
+
+    #[test]
+    fn state_update_matches_contract_test_vector() -> anyhow::Result<()> {
+        let encoded = hex::decode(TEST_VECTOR_ENCODING.concat())?;
+        let update = test_vector();
+        assert_eq!(hex::encode(update.encode()), hex::encode(&encoded));
+        assert_eq!(hex::encode(update.commitment()), TEST_VECTOR_COMMITMENT);
+        assert_eq!(StateUpdate::decode(&encoded)?, update);
+        Ok(())
+    }
+
+    #[test]
+    fn state_update_encoding_roundtrip() -> anyhow::Result<()> {
+        let update = state_update();
+        let encoded = update.encode();
+
+        // Must match the layout computed by `ZcashBridge.encodeStateUpdate`.
+        assert_eq!(encoded[0], StateUpdate::ENCODING_VERSION);
+        let deposits = 20 + 32;
+        let withdrawals = (1 + 20 + 32) + (1 + 43 + 32);
+        let refunds = 32 + 1 + 20 + 32;
+        let withdrawal_refunds = 1 + 20 + 32;
+        let quarantined = (32 + 4) + (1 + 43 + 32);
+        assert_eq!(
+            encoded.len(),
+            1 + 4 * (8 + 32)
+                + 4
+                + deposits
+                + 4
+                + withdrawals
+                + 4
+                + refunds
+                + 4
+                + withdrawal_refunds
+                + 2 * 4
+                + quarantined
+        );
+        assert_eq!(StateUpdate::decode(&encoded)?, update);
+
+        // Unknown versions and truncated encodings are rejected.
+        let mut other_version = encoded.clone();
+        other_version[0] = StateUpdate::ENCODING_VERSION + 1;
+        assert!(StateUpdate::decode(&other_version).is_err());
+        assert!(StateUpdate::decode(&encoded[..encoded.len() - 1]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn state_update_json_roundtrip() -> anyhow::Result<()> {
+        let update = state_update();
+        let json = serde_json::to_string(&update)?;
+        assert_eq!(serde_json::from_str::<StateUpdate>(&json)?, update);
+        Ok(())
+    }
+
+    #[test]
+    fn state_update_commitment_binds_transfers() {
+        let update = state_update();
+        assert_eq!(update.commitment(), state_update().commitment());
+
+        let mut reordered = state_update();
+        reordered.eth_to_zec_transfers.reverse();
+        assert_ne!(update.commitment(), reordered.commitment());
+
+        let mut inflated = state_update();
+        inflated.zec_to_eth_transfers[0].amount += 1;
+        assert_ne!(update.commitment(), inflated.commitment());
+
+        let mut redirected = state_update();
+        redirected.refunds[0].recipient = ZcashRecipient::Transparent([0x66; 20]);
+        assert_ne!(update.commitment(), redirected.commitment());
+
+        let mut released = state_update();
+        released.quarantined_deposits.clear();
+        assert_ne!(update.commitment(), released.commitment());
+
+        let mut paid = state_update();
+        paid.withdrawal_refunds.clear();
+        assert_ne!(update.commitment(), paid.commitment());
+    }
 }
@@ -0,0 +1,1129 @@
+//! Independent verification of the state updates submitted by the bridge operator.
+//!
+//! Every `StateUpdated` event on Ethereum is checked against the update re-derived from both
+//! chains, and every spend of the STF UTXO on Zcash is checked against the update it processes,
+//! including the transition proof it carries.
+//!
+//! Transfers held in the quarantine of the relayer are left out of the re-derived update as the
+//! submitted update claims, as long as they are transfers still to be processed, and come back
+//! once it no longer holds them, see [`crate::quarantine`].
+
+use alloy::{
+    primitives::{Address, B256, Signature},
+    sol_types::SolEvent as _,
+};
+use serde::Serialize;
+use zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE;
+use zcash_primitives::transaction::{Transaction, components::tze};
+use zcash_protocol::{TxId, consensus::BranchId};
+use zcash_transparent::address::TransparentAddress;
+use zebra_chain::{block::Block, serialization::ZcashSerialize as _};
+
+use crate::{
+    committee::Committee,
+    deposits::{DepositPolicy, ObservedDeposit, Settlement},
+    eth::{
+        contract::{IStateTransitionVerifier, ZcashBridge},
+        watcher::EthWatcher,
+    },
+    prover::StateTransitionProof,
+    quarantine,
+    types::{
+        DepositOutpoint, DepositRefund, EthToZecTransfer, StateUpdate, ZcashRecipient,
+        ZecToEthTransfer,
+    },
+    zcash::{sender::PROOF_DATA_PREFIX, watcher::ZcashWatcher},
+    zebra_client::client::RpcClient as _,
+};
+
+/// Mode of the TZE witness progressing the STF, see the TZE modes table in the README.
+pub(crate) const MODE_STF: u32 = 1;
+
+/// State update as submitted to the `ZcashBridge` contract.
+#[derive(Debug, Clone)]
+pub struct SubmittedUpdate {
+    pub eth_tx: B256,
+    pub eth_block: u64,
+    pub update: StateUpdate,
+    /// Commitment the contract accepted the update with.
+    pub commitment: [u8; 32],
+}
+
+/// Update re-derived from the blocks of both chains covered by a submitted update.
+#[derive(Debug, Clone)]
+pub struct ExpectedUpdate {
+    pub update: StateUpdate,
+    /// Deposit outputs the STF spend processing the update has to claim.
+    pub deposit_outpoints: Vec<tze::OutPoint>,
+    /// Transfers left for the next update.
+    pub carried: Carried,
+}
+
+/// Transfers an update leaves for later updates.
+#[derive(Debug, Clone, Default)]
+pub struct Carried {
+    /// Deposits below the minimum, see [`crate::deposits::Settlement::queued`].
+    pub queued: Vec<ObservedDeposit>,
+    /// Deposits held in the quarantine, see [`StateUpdate::quarantined_deposits`].
+    pub quarantined_deposits: Vec<ObservedDeposit>,
+    /// Withdrawals held in the quarantine, see [`StateUpdate::quarantined_withdrawals`].
+    pub quarantined_withdrawals: Vec<EthToZecTransfer>,
+}
+
+/// Spend of the STF UTXO on Zcash.
+#[derive(Debug, Clone)]
+pub struct StfSpend {
+    pub txid: TxId,
+    pub height: u32,
+    /// Deposit outputs claimed by the spend.
+    pub deposit_inputs: Vec<tze::OutPoint>,
+    /// P2PKH outputs paying withdrawals and reimbursing shielded ones, following the change
+    /// output.
+    pub transparent_withdrawals: Vec<([u8; 20], u64)>,
+    /// Net value moved into the Sapling and Orchard pools.
+    pub shielded_outflow: i64,
+    /// Value of the spent STF output.
+    pub prev_value: u64,
+    /// Value of the created STF output.
+    pub new_value: u64,
+    /// Committee signatures on the processed update, carried in null-data outputs.
+    pub signatures: Vec<Signature>,
+    /// Transition proof carried in the null-data outputs after the signatures, if any decodes.
+    pub proof: Option<StateTransitionProof>,
+}
+
+/// Discrepancy between what the operator submitted and what the chains contain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// The update does not start where the previous one ended.
+    Discontinuity {
+        chain: &'static str,
+        expected_block: u64,
+        claimed_block: u64,
+    },
+    /// The claimed hash of a block differs from the hash on chain.
+    BlockHash {
+        chain: &'static str,
+        block: u64,
+        #[serde(with = "hex::serde")]
+        expected: [u8; 32],
+        #[serde(with = "hex::serde")]
+        claimed: [u8; 32],
+    },
+    /// The accepted commitment differs from the commitment to the re-derived update.
+    Commitment {
+        #[serde(with = "hex::serde")]
+        expected: [u8; 32],
+        #[serde(with = "hex::serde")]
+        claimed: [u8; 32],
+    },
+    /// The minted deposits differ from the deposits in the covered Zcash blocks.
+    Deposits {
+        expected: Vec<ZecToEthTransfer>,
+        claimed: Vec<ZecToEthTransfer>,
+    },
+    /// The processed withdrawals differ from the requests in the covered Ethereum blocks.
+    Withdrawals {
+        expected: Vec<EthToZecTransfer>,
+        claimed: Vec<EthToZecTransfer>,
+    },
+    /// The refunds differ from the deposits the deposit policy refunds.
+    Refunds {
+        expected: Vec<DepositRefund>,
+        claimed: Vec<DepositRefund>,
+    },
+    /// The returned withdrawals differ from the quarantined ones the update no longer holds.
+    WithdrawalRefunds {
+        expected: Vec<EthToZecTransfer>,
+        claimed: Vec<EthToZecTransfer>,
+    },
+    /// The quarantine holds deposits that are not waiting to be claimed.
+    QuarantinedDeposits {
+        expected: Vec<DepositOutpoint>,
+        claimed: Vec<DepositOutpoint>,
+    },
+    /// The quarantine holds withdrawals that are not waiting to be processed.
+    QuarantinedWithdrawals {
+        expected: Vec<EthToZecTransfer>,
+        claimed: Vec<EthToZecTransfer>,
+    },
+    /// The STF spend claims other deposit outputs than the covered ones.
+    StfDepositInputs {
+        txid: String,
+        expected: Vec<String>,
+        claimed: Vec<String>,
+    },
+    /// The STF spend pays other transparent withdrawals, refunds and reimbursements of shielded
+    /// payments than the processed ones.
+    StfTransparentWithdrawals {
+        txid: String,
+        expected: Vec<(String, u64)>,
+        paid: Vec<(String, u64)>,
+    },
+    /// The STF spend moves another value into shielded pools than the processed withdrawals
+    /// and refunds.
+    StfShieldedWithdrawals {
+        txid: String,
+        expected: u64,
+        paid: i64,
+    },
+    /// The STF output does not hold the previous value plus deposits minus withdrawals.
+    StfValue {
+        txid: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// The STF spend does not carry enough committee signatures on the processed update.
+    StfSignatures { txid: String, error: String },
+    /// The STF spend does not carry a valid transition proof of the processed update.
+    StfProof { txid: String, error: String },
+}
+
+pub struct Watchtower {
+    zcash_watcher: ZcashWatcher,
+    eth_watcher: EthWatcher,
+    deposit_policy: DepositPolicy,
+}
+
+impl Watchtower {
+    pub fn new(zcash_watcher: ZcashWatcher, eth_watcher: EthWatcher) -> Self {
+        Self {
+            zcash_watcher,
+            eth_watcher,
+            deposit_policy: DepositPolicy::NONE,
+        }
+    }
+
+    /// Re-derives deposits with the rules of the relayer instead of crediting them in full.
+    pub fn with_deposit_policy(mut self, policy: DepositPolicy) -> Self {
+        self.deposit_policy = policy;
+        self
+    }
+
+    pub fn zcash_watcher(&self) -> &ZcashWatcher {
+        &self.zcash_watcher
+    }
+
+    pub fn eth_watcher(&self) -> &EthWatcher {
+        &self.eth_watcher
+    }
+
+    /// Returns the state updates submitted in Ethereum blocks `from_block..=to_block`.
+    ///
+    /// The processed transfers are taken from the events emitted along with `StateUpdated`.
+    pub async fn submitted_updates(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> anyhow::Result<Vec<SubmittedUpdate>> {
+        let logs = self
+            .eth_watcher
+            .get_bridge_logs(from_block, to_block)
+            .await?;
+
+        let mut updates: Vec<SubmittedUpdate> = Vec::new();
+        for log in logs {
+            let topic = log.topic0().copied();
+            let tx_hash = log.transaction_hash.unwrap_or_default();
+            if topic == Some(ZcashBridge::StateUpdated::SIGNATURE_HASH) {
+                let event = ZcashBridge::StateUpdated::decode_log(&log.inner)?;
+                updates.push(SubmittedUpdate {
+                    eth_tx: tx_hash,
+                    eth_block: log.block_number.unwrap_or_default(),
+                    update: StateUpdate {
+                        old_eth_block: event.previousEthBlockNumber,
+                        new_eth_block: event.newEthBlockNumber,
+                        old_eth_hash: event.previousEthRoot.0,
+                        new_eth_hash: event.newEthRoot.0,
+                        old_zcash_block: event.previousZecBlockNumber,
+                        new_zcash_block: event.newZecBlockNumber,
+                        old_zcash_hash: event.previousZecRoot.0,
+                        new_zcash_hash: event.newZecRoot.0,
+                        eth_to_zec_transfers: Vec::new(),
+                        zec_to_eth_transfers: Vec::new(),
+                        refunds: Vec::new(),
+                        withdrawal_refunds: Vec::new(),
+                        quarantined_deposits: Vec::new(),
+                        quarantined_withdrawals: Vec::new(),
+                    },
+                    commitment: event.commitment.0,
+                });
+                continue;
+            }
+
+            // Transfers are processed after `StateUpdated` is emitted, in the same transaction.
+            let Some(current) = updates.last_mut().filter(|u| u.eth_tx == tx_hash) else {
+                continue;
+            };
+            if topic == Some(ZcashBridge::ZecTransferProcessed::SIGNATURE_HASH) {
+                let event = ZcashBridge::ZecTransferProcessed::decode_log(&log.inner)?;
+                current.update.zec_to_eth_transfers.push(ZecToEthTransfer {
+                    amount: u64::try_from(event.amount).expect("Amount exceeds u64"),
+                    eth_address: event.recipient.into_array(),
+                });
+            } else if topic == Some(ZcashBridge::WithdrawalProcessed::SIGNATURE_HASH) {
+                let event = ZcashBridge::WithdrawalProcessed::decode_log(&log.inner)?;
+                current.update.eth_to_zec_transfers.push(EthToZecTransfer {
+                    amount: u64::try_from(event.amount).expect("Amount exceeds u64"),
+                    recipient: ZcashRecipient::from_receiver(event.receiverType, &event.receiver)?,
+                });
+            } else if topic == Some(ZcashBridge::DepositRefunded::SIGNATURE_HASH) {
+                let event = ZcashBridge::DepositRefunded::decode_log(&log.inner)?;
+                current.update.refunds.push(DepositRefund {
+                    amount: u64::try_from(event.amount).expect("Amount exceeds u64"),
+                    recipient: ZcashRecipient::from_receiver(event.receiverType, &event.receiver)?,
+                    deposit_txid: event.depositTxid.0,
+                });
+            } else if topic == Some(ZcashBridge::WithdrawalRefunded::SIGNATURE_HASH) {
+                let event = ZcashBridge::WithdrawalRefunded::decode_log(&log.inner)?;
+                current.update.withdrawal_refunds.push(EthToZecTransfer {
+                    amount: u64::try_from(event.amount).expect("Amount exceeds u64"),
+                    recipient: ZcashRecipient::from_receiver(event.receiverType, &event.receiver)?,
+                });
+            } else if topic == Some(ZcashBridge::DepositQuarantined::SIGNATURE_HASH) {
+                let event = ZcashBridge::DepositQuarantined::decode_log(&log.inner)?;
+                current.update.quarantined_deposits.push(DepositOutpoint {
+                    txid: event.txid.0,
+                    index: event.index,
+                });
+            } else if topic == Some(ZcashBridge::WithdrawalQuarantined::SIGNATURE_HASH) {
+                let event = ZcashBridge::WithdrawalQuarantined::decode_log(&log.inner)?;
+                current
+                    .update
+                    .quarantined_withdrawals
+                    .push(EthToZecTransfer {
+                        amount: u64::try_from(event.amount).expect("Amount exceeds u64"),
+                        recipient: ZcashRecipient::from_receiver(
+                            event.receiverType,
+                            &event.receiver,
+                        )?,
+                    });
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Returns the spends of the STF UTXO in Zcash blocks `from_height..=to_height`.
+    pub async fn stf_spends(
+        &self,
+        from_height: u32,
+        to_height: u32,
+    ) -> anyhow::Result<Vec<StfSpend>> {
+        let mut spends = Vec::new();
+        for height in from_height..=to_height {
+            let block = self.zcash_watcher.get_block(height).await?;
+            for tx in &block.transactions {
+                let tx = Transaction::read(&tx.zcash_serialize_to_vec()?[..], BranchId::ZFuture)?;
+                if let Some(spend) = self.stf_spend(&tx, height).await? {
+                    spends.push(spend);
+                }
+            }
+        }
+        Ok(spends)
+    }
+
+    async fn stf_spend(&self, tx: &Transaction, height: u32) -> anyhow::Result<Option<StfSpend>> {
+        let Some(tze_bundle) = tx.tze_bundle() else {
+            return Ok(None);
+        };
+        let is_stf_input = |input: &&tze::TzeIn<_>| {
+            input.witness.extension_id == EXTENSION_ETH_BRIDGE && input.witness.mode == MODE_STF
+        };
+        let Some(stf_input) = tze_bundle.vin.iter().find(is_stf_input) else {
+            return Ok(None);
+        };
+
+        let deposit_inputs = tze_bundle
+            .vin
+            .iter()
+            .filter(|input| input.prevout != stf_input.prevout)
+            .map(|input| input.prevout.clone())
+            .collect();
+
+        // The first transparent output is the operator change.
+        let transparent_withdrawals = tx
+            .transparent_bundle()
+            .into_iter()
+            .flat_map(|bundle| bundle.vout.iter().skip(1))
+            .filter_map(|output| match output.recipient_address() {
+                Some(TransparentAddress::PublicKeyHash(hash)) => {
+                    Some((hash, output.value().into_u64()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let (signatures, proof) = signatures_and_proof(
+            tx.transparent_bundle()
+                .into_iter()
+                .flat_map(|bundle| bundle.vout.iter())
+                .filter_map(|output| {
+                    let script =
+                        zebra_chain::transparent::Script::from(output.script_pubkey().clone());
+                    null_data(script.as_raw_bytes()).map(<[u8]>::to_vec)
+                }),
+        );
+
+        let shielded_outflow = -tx
+            .sapling_bundle()
+            .map_or(0, |bundle| i64::from(*bundle.value_balance()))
+            - tx.orchard_bundle()
+                .map_or(0, |bundle| i64::from(*bundle.value_balance()));
+
+        // The STF output is the only TZE output of both the spent and the spending transaction.
+        let prev_tx = self
+            .zcash_watcher
+            .client()
+            .get_transaction(stf_input.prevout.txid(), BranchId::ZFuture)
+            .await?;
+        let stf_value = |tx: &Transaction| {
+            tx.tze_bundle()
+                .and_then(|bundle| bundle.vout.first())
+                .map(|output| output.value.into_u64())
+                .ok_or_else(|| anyhow::anyhow!("{} has no STF output", tx.txid()))
+        };
+
+        Ok(Some(StfSpend {
+            txid: tx.txid(),
+            height,
+            deposit_inputs,
+            transparent_withdrawals,
+            shielded_outflow,
+            prev_value: stf_value(&prev_tx)?,
+            new_value: stf_value(tx)?,
+            signatures,
+            proof,
+        }))
+    }
+
+    /// Checks the proof carried by `spend` with the verifier set on the bridge contract, if any.
+    ///
+    /// The proof has to be about the processed update, see [`check_stf_proof`].
+    pub async fn verify_stf_proof(&self, spend: &StfSpend) -> anyhow::Result<Vec<Mismatch>> {
+        let Some(proof) = &spend.proof else {
+            return Ok(Vec::new());
+        };
+        let bridge = &self.eth_watcher.bridge_contract;
+        let verifier = bridge.verifier().call().await?;
+        if verifier.is_zero() {
+            return Ok(Vec::new());
+        }
+        let verified = IStateTransitionVerifier::new(verifier, bridge.provider().clone())
+            .verifyProof(
+                proof.public_inputs.encode().into(),
+                proof.proof.clone().into(),
+            )
+            .call()
+            .await;
+        Ok(match verified {
+            Ok(_) => Vec::new(),
+            Err(error) => vec![Mismatch::StfProof {
+                txid: spend.txid.to_string(),
+                error: format!("rejected by the verifier contract: {error}"),
+            }],
+        })
+    }
+
+    /// Returns the committee set on the bridge contract, if any.
+    pub async fn committee(&self) -> anyhow::Result<Option<Committee>> {
+        let tip = self.eth_watcher.get_block_number().await?;
+        let logs = self.eth_watcher.get_bridge_logs(0, tip).await?;
+        let Some(log) = logs
+            .iter()
+            .rev()
+            .find(|log| log.topic0() == Some(&ZcashBridge::CommitteeUpdated::SIGNATURE_HASH))
+        else {
+            return Ok(None);
+        };
+        let event = ZcashBridge::CommitteeUpdated::decode_log(&log.inner)?;
+        Ok(Some(Committee {
+            members: event.members.clone(),
+            threshold: event.threshold.try_into()?,
+        }))
+    }
+
+    /// Re-derives the update covering the block ranges of `claimed` from both chains.
+    ///
+    /// `carried` are the transfers the previous update left for later, see
+    /// [`ExpectedUpdate::carried`].
+    pub async fn expected_update(
+        &self,
+        claimed: &StateUpdate,
+        carried: Carried,
+    ) -> anyhow::Result<ExpectedUpdate> {
+        let old_zcash_block = self
+            .zcash_watcher
+            .get_block(claimed.old_zcash_block as u32)
+            .await?;
+        let zcash_blocks = self.zcash_blocks(claimed).await?;
+        let deposits = self.zcash_watcher.extract_deposits(&zcash_blocks).await?;
+
+        let old_eth_block = self.eth_watcher.get_block(claimed.old_eth_block).await?;
+        let mut eth_blocks = Vec::new();
+        for number in claimed.old_eth_block + 1..=claimed.new_eth_block {
+            eth_blocks.push(self.eth_watcher.get_block(number).await?);
+        }
+        let withdrawals = if eth_blocks.is_empty() {
+            Vec::new()
+        } else {
+            self.eth_watcher
+                .extract_eth_to_zec_transfers(&eth_blocks)
+                .await?
+        };
+        let (settlement, eth_to_zec_transfers, withdrawal_refunds, carried) = honor_quarantine(
+            &self.deposit_policy,
+            claimed,
+            carried,
+            deposits,
+            withdrawals,
+        );
+
+        let new_zcash_hash = zcash_blocks
+            .last()
+            .map_or(old_zcash_block.hash(), |b| b.hash());
+        let new_eth_hash = eth_blocks.last().map_or(old_eth_block.hash(), |b| b.hash());
+        Ok(ExpectedUpdate {
+            update: StateUpdate {
+                old_eth_block: claimed.old_eth_block,
+                new_eth_block: claimed.new_eth_block,
+                old_eth_hash: old_eth_block.hash().0,
+                new_eth_hash: new_eth_hash.0,
+                old_zcash_block: claimed.old_zcash_block,
+                new_zcash_block: claimed.new_zcash_block,
+                old_zcash_hash: old_zcash_block.hash().0,
+                new_zcash_hash: new_zcash_hash.0,
+                eth_to_zec_transfers,
+                zec_to_eth_transfers: settlement.transfers.clone(),
+                refunds: settlement.refunds.clone(),
+                withdrawal_refunds,
+                quarantined_deposits: carried
+                    .quarantined_deposits
+                    .iter()
+                    .map(deposit_outpoint)
+                    .collect(),
+                quarantined_withdrawals: carried.quarantined_withdrawals.clone(),
+            },
+            deposit_outpoints: settlement
+                .claimed()
+                .into_iter()
+                .map(|(outpoint, _)| outpoint)
+                .collect(),
+            carried,
+        })
+    }
+
+    /// Zcash blocks covered by `update`.
+    async fn zcash_blocks(&self, update: &StateUpdate) -> anyhow::Result<Vec<Block>> {
+        let mut blocks = Vec::new();
+        for height in update.old_zcash_block + 1..=update.new_zcash_block {
+            blocks.push(self.zcash_watcher.get_block(height as u32).await?);
+        }
+        Ok(blocks)
+    }
+
+    /// Re-derives the transfers carried after the updates submitted in Ethereum blocks
+    /// `from_block..=to_block` that end at or before Zcash height `until_zcash_height`.
+    ///
+    /// They only depend on the chains, so they are replayed from the first update of the STF,
+    /// which has to be in the block range, instead of being kept across restarts.
+    pub async fn replay_carried(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        until_zcash_height: u64,
+    ) -> anyhow::Result<Carried> {
+        let mut carried = Carried::default();
+        if from_block > to_block {
+            return Ok(carried);
+        }
+        for submitted in self.submitted_updates(from_block, to_block).await? {
+            if submitted.update.new_zcash_block > until_zcash_height {
+                break;
+            }
+            carried = self.carried_after(carried, &submitted.update).await?;
+        }
+        Ok(carried)
+    }
+
+    /// Transfers carried after `update`, given the transfers `carried` before it.
+    ///
+    /// `update` is taken as verified, so the withdrawals it quarantines are not looked up on
+    /// Ethereum.
+    pub async fn carried_after(
+        &self,
+        carried: Carried,
+        update: &StateUpdate,
+    ) -> anyhow::Result<Carried> {
+        let blocks = self.zcash_blocks(update).await?;
+        let deposits = self.zcash_watcher.extract_deposits(&blocks).await?;
+        let (_, _, _, carried) = honor_quarantine(
+            &self.deposit_policy,
+            update,
+            carried,
+            deposits,
+            update.quarantined_withdrawals.clone(),
+        );
+        Ok(carried)
+    }
+}
+
+fn deposit_outpoint(deposit: &ObservedDeposit) -> DepositOutpoint {
+    DepositOutpoint {
+        txid: *deposit.outpoint.txid().as_ref(),
+        index: deposit.outpoint.n(),
+    }
+}
+
+/// Settles the `deposits` and orders the `withdrawals` of the blocks covered by `claimed` like
+/// the relayer, given the transfers `carried` by the previous update, and returns the transfers
+/// carried after `claimed`.
+///
+/// Transfers in the quarantine claimed by the update are left out if they are carried or in the
+/// covered blocks; the claimed quarantine only matches the returned one if all of them are.
+/// Transfers the quarantine no longer holds are released, see [`quarantine::settle`]. The refund
+/// address of a released deposit is taken from the refunds of `claimed`, since an operator
+/// chooses it. Likewise, a released withdrawal is returned to its requester instead of paid if
+/// `claimed` returns it.
+fn honor_quarantine(
+    policy: &DepositPolicy,
+    claimed: &StateUpdate,
+    carried: Carried,
+    deposits: Vec<ObservedDeposit>,
+    withdrawals: Vec<EthToZecTransfer>,
+) -> (
+    Settlement,
+    Vec<EthToZecTransfer>,
+    Vec<EthToZecTransfer>,
+    Carried,
+) {
+    let Carried {
+        queued: mut pending,
+        quarantined_deposits: mut released,
+        quarantined_withdrawals: mut released_withdrawals,
+    } = carried;
+    pending.extend(deposits);
+
+    let mut quarantined_deposits = Vec::new();
+    for outpoint in &claimed.quarantined_deposits {
+        let held = |deposit: &ObservedDeposit| deposit_outpoint(deposit) == *outpoint;
+        if let Some(i) = released.iter().position(held) {
+            quarantined_deposits.push(released.remove(i));
+        } else if let Some(i) = pending.iter().position(held) {
+            quarantined_deposits.push(pending.remove(i));
+        }
+    }
+    let released = released
+        .into_iter()
+        .map(|deposit| {
+            let refund_to = claimed
+                .refunds
+                .iter()
+                .find(|refund| {
+                    refund.deposit_txid == *deposit.outpoint.txid().as_ref()
+                        && refund.amount == deposit.transfer.amount
+                })
+                .map(|refund| refund.recipient.clone());
+            (deposit, refund_to)
+        })
+        .collect();
+    let settlement = quarantine::settle(policy, pending, released, claimed.new_zcash_block);
+
+    let mut withdrawals = withdrawals;
+    let mut quarantined_withdrawals = Vec::new();
+    for withdrawal in &claimed.quarantined_withdrawals {
+        if let Some(i) = released_withdrawals.iter().position(|w| w == withdrawal) {
+            quarantined_withdrawals.push(released_withdrawals.remove(i));
+        } else if let Some(i) = withdrawals.iter().position(|w| w == withdrawal) {
+            quarantined_withdrawals.push(withdrawals.remove(i));
+        }
+    }
+    let mut returns = claimed.withdrawal_refunds.clone();
+    let mut withdrawal_refunds = Vec::new();
+    for withdrawal in released_withdrawals {
+        match returns.iter().position(|w| *w == withdrawal) {
+            Some(i) => withdrawal_refunds.push(returns.remove(i)),
+            None => withdrawals.push(withdrawal),
+        }
+    }
+
+    let carried = Carried {
+        queued: settlement.queued.clone(),
+        quarantined_deposits,
+        quarantined_withdrawals,
+    };
+    (settlement, withdrawals, withdrawal_refunds, carried)
+}
+
+/// Compares a submitted update with the update re-derived for the same block ranges.
+///
+/// `previous` is the last verified update, if any, which `claimed` has to continue.
+pub fn check_update(
+    previous: Option<&StateUpdate>,
+    claimed: &StateUpdate,
+    expected: &StateUpdate,
+) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+
+    if let Some(previous) = previous {
+        let ranges = [
+            ("eth", previous.new_eth_block, claimed.old_eth_block),
+            ("zcash", previous.new_zcash_block, claimed.old_zcash_block),
+        ];
+        for (chain, expected_block, claimed_block) in ranges {
+            if expected_block != claimed_block {
+                mismatches.push(Mismatch::Discontinuity {
+                    chain,
+                    expected_block,
+                    claimed_block,
+                });
+            }
+        }
+    }
+
+    let hashes = [
+        (
+            "eth",
+            claimed.old_eth_block,
+            expected.old_eth_hash,
+            claimed.old_eth_hash,
+        ),
+        (
+            "eth",
+            claimed.new_eth_block,
+            expected.new_eth_hash,
+            claimed.new_eth_hash,
+        ),
+        (
+            "zcash",
+            claimed.old_zcash_block,
+            expected.old_zcash_hash,
+            claimed.old_zcash_hash,
+        ),
+        (
+            "zcash",
+            claimed.new_zcash_block,
+            expected.new_zcash_hash,
+            claimed.new_zcash_hash,
+        ),
+    ];
+    for (chain, block, expected, claimed) in hashes {
+        if expected != claimed {
+            mismatches.push(Mismatch::BlockHash {
+                chain,
+                block,
+                expected,
+                claimed,
+            });
+        }
+    }
+
+    if claimed.zec_to_eth_transfers != expected.zec_to_eth_transfers {
+        mismatches.push(Mismatch::Deposits {
+            expected: expected.zec_to_eth_transfers.clone(),
+            claimed: claimed.zec_to_eth_transfers.clone(),
+        });
+    }
+    if claimed.eth_to_zec_transfers != expected.eth_to_zec_transfers {
+        mismatches.push(Mismatch::Withdrawals {
+            expected: expected.eth_to_zec_transfers.clone(),
+            claimed: claimed.eth_to_zec_transfers.clone(),
+        });
+    }
+    if claimed.refunds != expected.refunds {
+        mismatches.push(Mismatch::Refunds {
+            expected: expected.refunds.clone(),
+            claimed: claimed.refunds.clone(),
+        });
+    }
+    if claimed.withdrawal_refunds != expected.withdrawal_refunds {
+        mismatches.push(Mismatch::WithdrawalRefunds {
+            expected: expected.withdrawal_refunds.clone(),
+            claimed: claimed.withdrawal_refunds.clone(),
+        });
+    }
+    if claimed.quarantined_deposits != expected.quarantined_deposits {
+        mismatches.push(Mismatch::QuarantinedDeposits {
+            expected: expected.quarantined_deposits.clone(),
+            claimed: claimed.quarantined_deposits.clone(),
+        });
+    }
+    if claimed.quarantined_withdrawals != expected.quarantined_withdrawals {
+        mismatches.push(Mismatch::QuarantinedWithdrawals {
+            expected: expected.quarantined_withdrawals.clone(),
+            claimed: claimed.quarantined_withdrawals.clone(),
+        });
+    }
+
+    mismatches
+}
+
+/// Checks that the contract accepted `submitted` with the commitment to the re-derived update.
+pub fn check_commitment(submitted: &SubmittedUpdate, expected: &StateUpdate) -> Option<Mismatch> {
+    let expected = expected.commitment();
+    (submitted.commitment != expected).then_some(Mismatch::Commitment {
+        expected,
+        claimed: submitted.commitment,
+    })
+}
+
+/// Recipient of the transparent outputs reimbursing the operator for shielded payments.
+const OPERATOR: &str = "operator";
+
+/// Compares a spend of the STF UTXO with the update it processes.
+pub fn check_stf_spend(spend: &StfSpend, expected: &ExpectedUpdate) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let txid = spend.txid.to_string();
+    let update = &expected.update;
+
+    let outpoint_strings = |outpoints: &[tze::OutPoint]| {
+        let mut strings: Vec<_> = outpoints
+            .iter()
+            .map(|outpoint| format!("{}:{}", outpoint.txid(), outpoint.n()))
+            .collect();
+        strings.sort();
+        strings
+    };
+    let expected_inputs = outpoint_strings(&expected.deposit_outpoints);
+    let claimed_inputs = outpoint_strings(&spend.deposit_inputs);
+    if expected_inputs != claimed_inputs {
+        mismatches.push(Mismatch::StfDepositInputs {
+            txid: txid.clone(),
+            expected: expected_inputs,
+            claimed: claimed_inputs,
+        });
+    }
+
+    // Refunds are paid like withdrawals, after them.
+    let payments: Vec<_> = update
+        .eth_to_zec_transfers
+        .iter()
+        .map(|transfer| (&transfer.recipient, transfer.amount))
+        .chain(
+            update
+                .refunds
+                .iter()
+                .map(|refund| (&refund.recipient, refund.amount)),
+        )
+        .collect();
+    let expected_transparent: Vec<_> = payments
+        .iter()
+        .filter_map(|(recipient, amount)| match recipient {
+            ZcashRecipient::Transparent(hash) => Some((hex::encode(hash), *amount)),
+            _ => None,
+        })
+        .collect();
+    // Shielded payments are reimbursed to the operator after the transparent ones, since the
+    // operator pays them from its own coin. Its address is not known here.
+    let reimbursements = payments
+        .iter()
+        .filter(|(recipient, _)| !matches!(recipient, ZcashRecipient::Transparent(_)))
+        .map(|(_, amount)| (OPERATOR.to_string(), *amount));
+    let transparent_count = expected_transparent.len();
+    let expected_transparent: Vec<_> = expected_transparent
+        .into_iter()
+        .chain(reimbursements)
+        .collect();
+    let paid_transparent: Vec<_> = spend
+        .transparent_withdrawals
+        .iter()
+        .enumerate()
+        .map(|(index, (hash, amount))| {
+            let to = if index < transparent_count {
+                hex::encode(hash)
+            } else {
+                OPERATOR.to_string()
+            };
+            (to, *amount)
+        })
+        .collect();
+    if expected_transparent != paid_transparent {
+        mismatches.push(Mismatch::StfTransparentWithdrawals {
+            txid: txid.clone(),
+            expected: expected_transparent,
+            paid: paid_transparent,
+        });
+    }
+
+    let expected_shielded: u64 = payments
+        .iter()
+        .filter(|(recipient, _)| !matches!(recipient, ZcashRecipient::Transparent(_)))
+        .map(|(_, amount)| amount)
+        .sum();
+    if spend.shielded_outflow != expected_shielded as i64 {
+        mismatches.push(Mismatch::StfShieldedWithdrawals {
+            txid: txid.clone(),
+            expected: expected_shielded,
+            paid: spend.shielded_outflow,
+        });
+    }
+
+    // Refunded deposits are claimed and paid out in the same spend, so they cancel out.
+    let deposited: u64 = update.zec_to_eth_transfers.iter().map(|t| t.amount).sum();
+    let withdrawn: u64 = update.eth_to_zec_transfers.iter().map(|t| t.amount).sum();
+    let expected_value = (spend.prev_value + deposited).saturating_sub(withdrawn);
+    if spend.new_value != expected_value {
+        mismatches.push(Mismatch::StfValue {
+            txid,
+            expected: expected_value,
+            actual: spend.new_value,
+        });
+    }
+
+    mismatches
+}
+
+/// Logs `mismatches` as structured alerts under the `watchtower` target.
+pub fn report(context: &str, mismatches: &[Mismatch]) {
+    for mismatch in mismatches {
+        tracing::error!(
+            target: "watchtower",
+            context,
+            mismatch = %serde_json::to_string(mismatch).unwrap(),
+            "State update mismatch"
+        );
+    }
+}
+
+/// Checks that the STF spend carries signatures of `committee.threshold` distinct members on the
+/// processed update, as the bridge contract requires on Ethereum.
+pub fn check_stf_signatures(
+    spend: &StfSpend,
+    expected: &ExpectedUpdate,
+    committee: &Committee,
+    chain_id: u64,
+    bridge: Address,
+) -> Vec<Mismatch> {
+    match committee.aggregate(chain_id, bridge, &expected.update, &spend.signatures) {
+        Ok(_) => Vec::new(),
+        Err(error) => vec![Mismatch::StfSignatures {
+            txid: spend.txid.to_string(),
+            error: error.to_string(),
+        }],
+    }
+}
+
+/// Checks that the STF spend carries a transition proof of the processed update.
+pub fn check_stf_proof(spend: &StfSpend, expected: &ExpectedUpdate) -> Vec<Mismatch> {
+    let checked = match &spend.proof {
+        Some(proof) => proof.ensure_proves(&expected.update),
+        None => Err(anyhow::anyhow!("no transition proof")),
+    };
+    match checked {
+        Ok(()) => Vec::new(),
+        Err(error) => vec![Mismatch::StfProof {
+            txid: spend.txid.to_string(),
+            error: error.to_string(),
+        }],
+    }
+}
+
+/// Splits the data of the null-data outputs of an STF spend into the committee signatures and the
+/// transition proof, see [`crate::zcash::sender::proof_null_data`].
+fn signatures_and_proof(
+    null_data: impl IntoIterator<Item = Vec<u8>>,
+) -> (Vec<Signature>, Option<StateTransitionProof>) {
+    let (proof_data, signature_data): (Vec<_>, Vec<_>) = null_data
+        .into_iter()
+        .partition(|data| data.starts_with(&PROOF_DATA_PREFIX));
+    let signatures = signature_data
+        .iter()
+        .filter_map(|data| Signature::try_from(data.as_slice()).ok())
+        .collect();
+    let proof = (!proof_data.is_empty())
+        .then(|| {
+            let encoded: Vec<u8> = proof_data
+                .iter()
+                .flat_map(|data| &data[PROOF_DATA_PREFIX.len()..])
+                .copied()
+                .collect();
+            StateTransitionProof::decode(&encoded).ok()
+        })
+        .flatten();
+    (signatures, proof)
+}
+
+/// Data pushed by a null-data (`OP_RETURN`) script.
+fn null_data(script: &[u8]) -> Option<&[u8]> {
+    const OP_RETURN: u8 = 0x6a;
+    const OP_PUSHDATA1: u8 = 0x4c;
+    match script {
+        [OP_RETURN, len @ 0..=0x4b, data @ ..] | [OP_RETURN, OP_PUSHDATA1, len, data @ ..]
+            if data.len() == usize::from(*len) =>
+        {
+            Some(data)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prover::PublicInputs,
+        test_utils::{block_hash, deposit, ids, update, withdrawal},
+        zcash::sender::proof_null_data,
+    };
+
+    fn state_update(old_eth_block: u64, old_zcash_block: u64) -> StateUpdate {
+        update(old_eth_block, old_eth_block + 5)
+            .zcash_blocks(old_zcash_block, old_zcash_block + 3)
+            .withdrawal(50_000, ZcashRecipient::Transparent([0x11; 20]))
+            .deposit(90_000, [0x22; 20])
+            .build()
+    }
+
+    #[test]
+    fn stf_spends_must_carry_a_proof_of_the_update() {
+        let expected = ExpectedUpdate {
+            update: state_update(15, 103),
+            deposit_outpoints: Vec::new(),
+            carried: Carried::default(),
+        };
+        let proof = StateTransitionProof {
+            public_inputs: PublicInputs {
+                old_state_root: [1; 32],
+                new_state_root: [2; 32],
+                update_commitment: expected.update.commitment(),
+            },
+            proof: vec![0x5a; 260],
+        };
+        let signature = Signature::from_raw(&[0x1b; 65]).unwrap();
+        let null_data = std::iter::once(signature.as_bytes().to_vec())
+            .chain(proof_null_data(&proof))
+            .collect::<Vec<_>>();
+        assert!(null_data.iter().all(|data| data.len() <= 80));
+        let (signatures, carried) = signatures_and_proof(null_data);
+        assert_eq!(signatures, [signature]);
+        assert_eq!(carried.as_ref(), Some(&proof));
+
+        let mut spend = StfSpend {
+            txid: TxId::from_bytes([0x77; 32]),
+            height: 104,
+            deposit_inputs: Vec::new(),
+            transparent_withdrawals: Vec::new(),
+            shielded_outflow: 0,
+            prev_value: 0,
+            new_value: 0,
+            signatures,
+            proof: carried,
+        };
+        assert!(check_stf_proof(&spend, &expected).is_empty());
+
+        let other = ExpectedUpdate {
+            update: state_update(10, 100),
+            ..expected.clone()
+        };
+        assert!(matches!(
+            check_stf_proof(&spend, &other)[..],
+            [Mismatch::StfProof { .. }]
+        ));
+        spend.proof = None;
+        assert!(matches!(
+            check_stf_proof(&spend, &expected)[..],
+            [Mismatch::StfProof { .. }]
+        ));
+    }
+
+    #[test]
+    fn check_update_flags_mismatches() {
+        let previous = state_update(10, 100);
+        let expected = state_update(15, 103);
+        assert!(check_update(Some(&previous), &expected, &expected).is_empty());
+
+        // The operator skips a Zcash block, claims a different tip and mints an extra deposit.
+        let mut claimed = expected.clone();
+        claimed.old_zcash_block = 104;
+        claimed.new_zcash_hash = [5; 32];
+        claimed.zec_to_eth_transfers.push(ZecToEthTransfer {
+            amount: 1_000_000,
+            eth_address: [0x33; 20],
+        });
+
+        let mismatches = check_update(Some(&previous), &claimed, &expected);
+        assert_eq!(
+            mismatches,
+            vec![
+                Mismatch::Discontinuity {
+                    chain: "zcash",
+                    expected_block: 103,
+                    claimed_block: 104,
+                },
+                Mismatch::BlockHash {
+                    chain: "zcash",
+                    block: 106,
+                    expected: block_hash(106),
+                    claimed: [5; 32],
+                },
+                Mismatch::Deposits {
+                    expected: expected.zec_to_eth_transfers.clone(),
+                    claimed: claimed.zec_to_eth_transfers.clone(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_update_flags_redirected_refunds() {
+        let mut expected = state_update(15, 103);
+        expected.refunds.push(DepositRefund {
+            amount: 5_000,
+            recipient: ZcashRecipient::Transparent([0x44; 20]),
+            deposit_txid: [0x55; 32],
+        });
+        let mut claimed = expected.clone();
+        claimed.refunds[0].recipient = ZcashRecipient::Transparent([0x66; 20]);
+
+        assert_eq!(
+            check_update(None, &claimed, &expected),
+            vec![Mismatch::Refunds {
+                expected: expected.refunds.clone(),
+                claimed: claimed.refunds.clone(),
+            }]
+        );
+    }
+
+    #[test]
+    fn quarantined_transfers_are_left_out_until_released() {
+        let operator = ZcashRecipient::Transparent([9; 20]);
+        let (held, kept, released) = (withdrawal(10_000), withdrawal(20_000), withdrawal(30_000));
+        let returned = withdrawal(40_000);
+        let carried = Carried {
+            queued: vec![deposit(1, [0xA1; 20], 50_000, 5, true)],
+            quarantined_deposits: vec![deposit(2, [0xA1; 20], 60_000, 5, true)],
+            quarantined_withdrawals: vec![released.clone(), returned.clone()],
+        };
+        // Deposit 2 is released with a refund, deposit 4 is quarantined along with a deposit
+        // that is not waiting to be claimed. A withdrawal that was never quarantined cannot be
+        // returned.
+        let claimed = update(15, 20)
+            .refund(60_000, operator.clone(), [2; 32])
+            .withdrawal_refund(returned.amount, returned.recipient.clone())
+            .withdrawal_refund(kept.amount, kept.recipient.clone())
+            .quarantined_deposit([4; 32], 0)
+            .quarantined_deposit([9; 32], 0)
+            .quarantined_withdrawal(10_000, held.recipient.clone())
+            .build();
+
+        let (settlement, withdrawals, withdrawal_refunds, carried) = honor_quarantine(
+            &DepositPolicy::NONE,
+            &claimed,
+            carried,
+            vec![
+                deposit(3, [0xB0; 20], 70_000, 6, true),
+                deposit(4, [0xB0; 20], 80_000, 6, true),
+            ],
+            vec![held.clone(), kept.clone()],
+        );
+        assert_eq!(ids(&settlement.credited), [1, 3]);
+        assert_eq!(ids(&settlement.refunded), [2]);
+        assert_eq!(settlement.refunds[0].recipient, operator);
+        assert_eq!(withdrawals, [kept, released]);
+        assert_eq!(withdrawal_refunds, [returned]);
+        assert!(carried.queued.is_empty());
+        assert_eq!(ids(&carried.quarantined_deposits), [4]);
+        assert_eq!(carried.quarantined_withdrawals, [held]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Deposit requests that any wallet can fund, not only the operator's.
+//!
+//! A deposit is a TZE output of the bridge extension in Deposit mode, naming the STF allowed to
+//! claim it and the Ethereum recipient of the minted WZEC. A [`DepositRequest`] describes it
+//! either as a [`PartialDeposit`], to which the wallet adds its inputs and change, or as a
+//! ZIP-321 payment request URI.
+//!
+//! The payload is `stf_identifier (32) | eth_recipient (20)`. Deposits the bridge cannot credit
+//! are refunded to the transparent address funding them.
+
+use serde::{Deserialize, Serialize};
+use zcash_extensions::{consensus::transparent::EXTENSION_ETH_BRIDGE, transparent::eth_bridge};
+use zcash_primitives::{
+    extensions::transparent::{FromPayload as _, Precondition},
+    transaction::components::TzeOut,
+};
+use zcash_protocol::{consensus::BranchId, value::Zatoshis};
+
+use crate::{types::ZcashRecipient, zcash::sender::STF_IDENTIFIER};
+
+/// Mode of the TZE precondition locking a deposit, see the TZE modes table in the README.
+pub const MODE_DEPOSIT: u32 = 2;
+
+/// Payment request parameter carrying the TZE output. ZIP-321 has no TZE recipients, and the
+/// `req-` prefix makes wallets without support reject the request instead of ignoring it.
+const URI_TZE_PARAM: &str = "req-tze";
+
+/// Length of the payload part naming the STF and the Ethereum recipient.
+const PAYLOAD_LEN: usize = 52;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepositRequest {
+    pub stf_identifier: [u8; 32],
+    pub eth_recipient: [u8; 20],
+    pub amount: Zatoshis,
+}
+
+/// Transaction with the deposit output and no inputs, to be funded and signed by a wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialDeposit {
+    /// Consensus branch the transaction has to be built for.
+    pub consensus_branch_id: u32,
+    pub tze_outputs: Vec<PartialTzeOutput>,
+    /// Value the wallet has to provide, including the fee.
+    pub required_value: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialTzeOutput {
+    pub value: u64,
+    pub extension_id: u32,
+    pub mode: u32,
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+}
+
+impl DepositRequest {
+    /// Request to deposit `amount` to `eth_recipient` through the operator's STF.
+    pub fn new(eth_recipient: [u8; 20], amount: Zatoshis) -> Self {
+        Self {
+            stf_identifier: STF_IDENTIFIER,
+            eth_recipient,
+            amount,
+        }
+    }
+
+    pub fn payload(&self) -> Vec<u8> {
+        [&self.stf_identifier[..], &self.eth_recipient[..]].concat()
+    }
+
+    /// Precondition of the deposit output, checked to parse back into the same deposit, so that
+    /// no deposit is funded that the STF could not claim.
+    pub fn precondition(&self) -> anyhow::Result<Precondition> {
+        let payload = self.payload();
+        let Ok(eth_bridge::Precondition::Deposit(deposit)) =
+            eth_bridge::Precondition::from_payload(MODE_DEPOSIT, &payload)
+        else {
+            anyhow::bail!("deposit payload is rejected by the bridge extension");
+        };
+        anyhow::ensure!(
+            deposit.to == self.eth_recipient,
+            "deposit payload names another recipient"
+        );
+        Ok(Precondition {
+            extension_id: EXTENSION_ETH_BRIDGE,
+            mode: MODE_DEPOSIT,
+            payload,
+        })
+    }
+
+    pub fn tze_output(&self) -> anyhow::Result<TzeOut> {
+        Ok(TzeOut {
+            value: self.amount,
+            precondition: self.precondition()?,
+        })
+    }
+
+    /// Returns the deposit as a transaction to be completed by a wallet paying `fee`.
+    pub fn partial(&self, fee: Zatoshis) -> anyhow::Result<PartialDeposit> {
+        let precondition = self.precondition()?;
+        Ok(PartialDeposit {
+            consensus_branch_id: u32::from(BranchId::ZFuture),
+            tze_outputs: vec![PartialTzeOutput {
+                value: self.amount.into_u64(),
+                extension_id: precondition.extension_id,
+                mode: precondition.mode,
+                payload: precondition.payload,
+            }],
+            required_value: (self.amount + fee)
+                .ok_or_else(|| anyhow::anyhow!("deposit value overflows"))?
+                .into_u64(),
+        })
+    }
+
+    /// Encodes the deposit as a payment request URI, e.g.
+    /// `zcash:?amount=1.5&req-tze=2:2:<payload hex>`.
+    pub fn to_uri(&self) -> anyhow::Result<String> {
+        let precondition = self.precondition()?;
+        Ok(format!(
+            "zcash:?amount={}&{URI_TZE_PARAM}={}:{}:{}",
+            format_zec_amount(self.amount.into_u64()),
+            precondition.extension_id,
+            precondition.mode,
+            hex::encode(&precondition.payload)
+        ))
+    }
+
+    /// Decodes a payment request produced by [`Self::to_uri`].
+    pub fn from_uri(uri: &str) -> anyhow::Result<Self> {
+        let query = uri
+            .strip_prefix("zcash:?")
+            .ok_or_else(|| anyhow::anyhow!("not a Zcash payment request without address"))?;
+        let mut amount = None;
+        let mut tze = None;
+        for param in query.split('&') {
+            match param.split_once('=') {
+                Some(("amount", value)) => amount = Some(parse_zec_amount(value)?),
+                Some((URI_TZE_PARAM, value)) => tze = Some(value),
+                Some((name, _)) if name.starts_with("req-") => {
+                    anyhow::bail!("unsupported required parameter {name}")
+                }
+                _ => {}
+            }
+        }
+        let amount = amount.ok_or_else(|| anyhow::anyhow!("payment request has no amount"))?;
+        let tze = tze.ok_or_else(|| anyhow::anyhow!("payment request has no TZE output"))?;
+
+        let mut parts = tze.splitn(3, ':');
+        let (Some(extension_id), Some(mode), Some(payload)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("malformed TZE output {tze}");
+        };
+        anyhow::ensure!(
+            extension_id.parse::<u32>()? == EXTENSION_ETH_BRIDGE
+                && mode.parse::<u32>()? == MODE_DEPOSIT,
+            "TZE output is not a bridge deposit"
+        );
+        let payload = hex::decode(payload)?;
+        anyhow::ensure!(
+            payload.len() == PAYLOAD_LEN,
+            "deposit payload has {} bytes",
+            payload.len()
+        );
+
+        let request = Self {
+            stf_identifier: payload[..32].try_into().unwrap(),
+            eth_recipient: payload[32..PAYLOAD_LEN].try_into().unwrap(),
+            amount: Zatoshis::from_u64(amount)?,
+        };
+        // Rejects payloads the extension would not accept.
+        request.precondition()?;
+        Ok(request)
+    }
+}
+
+/// Whether the STF transaction can pay `recipient`, i.e. shielded receivers decode.
+pub fn is_payable(recipient: &ZcashRecipient) -> bool {
+    match recipient {
+        ZcashRecipient::Transparent(_) => true,
+        ZcashRecipient::Sapling(raw) => ::sapling::PaymentAddress::from_bytes(raw).is_some(),
+        ZcashRecipient::Orchard(raw) => orchard::Address::from_raw_address_bytes(raw)
+            .is_some()
+            .into(),
+    }
+}
+
+/// Formats zatoshis as a decimal ZEC amount without trailing zeros, as ZIP-321 requires.
+fn format_zec_amount(zatoshis: u64) -> String {
+    let whole = zatoshis / 100_000_000;
+    let fraction = zatoshis % 100_000_000;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{fraction:08}");
+    format!("{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+fn parse_zec_amount(amount: &str) -> anyhow::Result<u64> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    anyhow::ensure!(
+        !whole.is_empty()
+            && fraction.len() <= 8
+            && whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit()),
+        "invalid amount {amount}"
+    );
+    let fraction: u64 = format!("{fraction:0<8}").parse()?;
+    whole
+        .parse::<u64>()?
+        .checked_mul(100_000_000)
+        .and_then(|zatoshis| zatoshis.checked_add(fraction))
+        .ok_or_else(|| anyhow::anyhow!("amount {amount} overflows"))
+}
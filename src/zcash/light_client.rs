@@ -0,0 +1,466 @@
+//! Zcash header-chain light client.
+//!
+//! Validates a chain of block headers starting at a trusted anchor: previous-hash links, median
+//! time past, Equihash solutions, proof of work and the difficulty adjustment. Transactions are
+//! checked against the `merkle_root` of an accepted header with [`verify_transaction`].
+//!
+//! Headers are handled in their consensus serialization, so the checks here double as the
+//! reference for the circuit and the on-chain verifier.
+
+use std::collections::VecDeque;
+
+use alloy::primitives::U256;
+use anyhow::Context as _;
+use sha2::{Digest as _, Sha256};
+use zebra_chain::{block, serialization::ZcashSerialize as _};
+
+use crate::merkle::{Hash, MerkleProof};
+
+/// Length of the header fields covered by the Equihash solution.
+const EQUIHASH_INPUT_LEN: usize = 4 + 32 + 32 + 32 + 4 + 4;
+
+/// Consensus rules of a Zcash network relevant to header validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsensusParams {
+    pub equihash_n: u32,
+    pub equihash_k: u32,
+    /// Easiest allowed target, in compact form.
+    pub pow_limit_bits: u32,
+    /// Whether the target stays at the value of the previous block.
+    pub no_retargeting: bool,
+    /// Target block spacing in seconds, as of Blossom.
+    pub target_spacing: u64,
+    pub averaging_window: usize,
+    pub median_time_span: usize,
+    pub max_adjust_up: u64,
+    pub max_adjust_down: u64,
+}
+
+impl ConsensusParams {
+    pub const MAINNET: Self = Self {
+        equihash_n: 200,
+        equihash_k: 9,
+        pow_limit_bits: 0x1f07ffff,
+        no_retargeting: false,
+        target_spacing: 75,
+        averaging_window: 17,
+        median_time_span: 11,
+        max_adjust_up: 16,
+        max_adjust_down: 32,
+    };
+
+    pub const REGTEST: Self = Self {
+        equihash_n: 48,
+        equihash_k: 5,
+        pow_limit_bits: 0x200f0f0f,
+        no_retargeting: true,
+        ..Self::MAINNET
+    };
+
+    /// Number of previous headers needed to validate the next one.
+    pub fn history_len(&self) -> usize {
+        self.averaging_window + self.median_time_span
+    }
+
+    pub fn pow_limit(&self) -> U256 {
+        expand_compact(self.pow_limit_bits).expect("valid PoW limit")
+    }
+}
+
+/// Zcash block header in its consensus serialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_hash: Hash,
+    pub merkle_root: Hash,
+    /// `hashBlockCommitments` as of NU5, see [`block_commitments_hash`].
+    pub block_commitments: Hash,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: Hash,
+    pub solution: Vec<u8>,
+}
+
+impl BlockHeader {
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(bytes.len() > EQUIHASH_INPUT_LEN + 32, "header is too short");
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let hash_at = |offset: usize| -> Hash { bytes[offset..offset + 32].try_into().unwrap() };
+
+        let (solution_len, prefix_len) =
+            read_compact_size(&bytes[EQUIHASH_INPUT_LEN + 32..]).context("solution length")?;
+        let solution = &bytes[EQUIHASH_INPUT_LEN + 32 + prefix_len..];
+        anyhow::ensure!(
+            solution.len() as u64 == solution_len,
+            "solution has {} bytes, expected {solution_len}",
+            solution.len()
+        );
+
+        Ok(Self {
+            version: u32_at(0),
+            prev_hash: hash_at(4),
+            merkle_root: hash_at(36),
+            block_commitments: hash_at(68),
+            time: u32_at(100),
+            bits: u32_at(104),
+            nonce: hash_at(108),
+            solution: solution.to_vec(),
+        })
+    }
+
+    pub fn from_zebra(header: &block::Header) -> anyhow::Result<Self> {
+        Self::parse(&header.zcash_serialize_to_vec()?)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = self.equihash_input();
+        bytes.extend_from_slice(&self.nonce);
+        write_compact_size(&mut bytes, self.solution.len() as u64);
+        bytes.extend_from_slice(&self.solution);
+        bytes
+    }
+
+    /// Block hash in internal byte order, like `zebra_chain::block::Hash`.
+    pub fn hash(&self) -> Hash {
+        sha256d(&self.serialize())
+    }
+
+    /// Header fields without the nonce and the solution.
+    fn equihash_input(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(EQUIHASH_INPUT_LEN);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.prev_hash);
+        bytes.extend_from_slice(&self.merkle_root);
+        bytes.extend_from_slice(&self.block_commitments);
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.bits.to_le_bytes());
+        bytes
+    }
+
+    /// Checks the Equihash solution and that the hash meets the target of the header.
+    pub fn verify_pow(&self, params: &ConsensusParams) -> anyhow::Result<()> {
+        equihash::is_valid_solution(
+            params.equihash_n,
+            params.equihash_k,
+            &self.equihash_input(),
+            &self.nonce,
+            &self.solution,
+        )
+        .map_err(|e| anyhow::anyhow!("invalid Equihash solution: {e}"))?;
+
+        let target = expand_compact(self.bits).context("invalid difficulty bits")?;
+        anyhow::ensure!(
+            target <= params.pow_limit(),
+            "target {target:#x} is easier than the PoW limit"
+        );
+        anyhow::ensure!(
+            U256::from_le_bytes(self.hash()) <= target,
+            "block hash does not meet target {target:#x}"
+        );
+        Ok(())
+    }
+}
+
+/// Header chain validated from a trusted anchor, keeping the headers needed for the next checks.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    params: ConsensusParams,
+    /// Recent headers, oldest first.
+    headers: VecDeque<BlockHeader>,
+    tip_height: u32,
+}
+
+impl HeaderChain {
+    /// Starts a chain at trusted headers, oldest first, the last of which is at `tip_height`.
+    ///
+    /// Unless the network does not retarget, at least [`ConsensusParams::history_len`] headers
+    /// are required to check the difficulty of the next one.
+    pub fn new(
+        params: ConsensusParams,
+        anchor: Vec<BlockHeader>,
+        tip_height: u32,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(!anchor.is_empty(), "anchor has no headers");
+        anyhow::ensure!(
+            params.no_retargeting || anchor.len() >= params.history_len(),
+            "anchor has {} headers, {} are needed",
+            anchor.len(),
+            params.history_len()
+        );
+        Ok(Self {
+            params,
+            headers: anchor.into(),
+            tip_height,
+        })
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.back().expect("chain is never empty")
+    }
+
+    pub fn tip_height(&self) -> u32 {
+        self.tip_height
+    }
+
+    /// Validates `header` as the child of the tip and makes it the new tip.
+    pub fn push(&mut self, header: BlockHeader) -> anyhow::Result<()> {
+        let height = self.tip_height + 1;
+        anyhow::ensure!(
+            header.prev_hash == self.tip().hash(),
+            "header {height} does not link to the tip"
+        );
+        let median_time = median_time_past(self.headers.iter().rev(), self.params.median_time_span);
+        anyhow::ensure!(
+            header.time > median_time,
+            "header {height} time {} is not after the median time past {median_time}",
+            header.time
+        );
+        let expected_bits = self.next_bits();
+        anyhow::ensure!(
+            header.bits == expected_bits,
+            "header {height} has bits {:#x}, expected {expected_bits:#x}",
+            header.bits
+        );
+        header
+            .verify_pow(&self.params)
+            .with_context(|| format!("header {height}"))?;
+
+        self.headers.push_back(header);
+        if self.headers.len() > self.params.history_len() {
+            self.headers.pop_front();
+        }
+        self.tip_height = height;
+        Ok(())
+    }
+
+    /// Validates the header of `block` as the child of the tip and checks its transactions
+    /// against the header Merkle root.
+    pub fn push_block(&mut self, block: &block::Block) -> anyhow::Result<()> {
+        let header = BlockHeader::from_zebra(&block.header)?;
+        let txids: Vec<_> = block.transactions.iter().map(|tx| tx.hash().0).collect();
+        anyhow::ensure!(
+            transaction_merkle_root(&txids) == header.merkle_root,
+            "transactions of header {} do not match its Merkle root",
+            self.tip_height + 1
+        );
+        self.push(header)
+    }
+
+    /// Difficulty bits required for the child of the tip.
+    pub fn next_bits(&self) -> u32 {
+        let params = &self.params;
+        if params.no_retargeting {
+            return self.tip().bits;
+        }
+
+        let window = params.averaging_window;
+        let total = self
+            .headers
+            .iter()
+            .rev()
+            .take(window)
+            .map(|header| expand_compact(header.bits).unwrap_or_default())
+            .fold(U256::ZERO, |total, target| total + target);
+        let mean_target = total / U256::from(window);
+
+        let recent = median_time_past(self.headers.iter().rev(), params.median_time_span);
+        let older = median_time_past(
+            self.headers.iter().rev().skip(window),
+            params.median_time_span,
+        );
+        let window_timespan = (window as u64 * params.target_spacing) as i64;
+        let actual_timespan = recent as i64 - older as i64;
+        let damped_timespan = window_timespan + (actual_timespan - window_timespan) / 4;
+        let bounded_timespan = damped_timespan.clamp(
+            window_timespan * (100 - params.max_adjust_up as i64) / 100,
+            window_timespan * (100 + params.max_adjust_down as i64) / 100,
+        );
+
+        let target = mean_target / U256::from(window_timespan) * U256::from(bounded_timespan);
+        to_compact(target.min(params.pow_limit()))
+    }
+}
+
+/// Median time of the `span` headers from the iterator, which yields the newest first.
+fn median_time_past<'a>(headers: impl Iterator<Item = &'a BlockHeader>, span: usize) -> u32 {
+    let mut times: Vec<_> = headers.take(span).map(|header| header.time).collect();
+    times.sort_unstable();
+    times.get(times.len() / 2).copied().unwrap_or_default()
+}
+
+/// Decodes a target in compact form, or `None` if it is negative or overflows.
+pub fn expand_compact(bits: u32) -> Option<U256> {
+    let size = (bits >> 24) as usize;
+    let word = bits & 0x007f_ffff;
+    if bits & 0x0080_0000 != 0 && word != 0 {
+        return None;
+    }
+    if word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32)) {
+        return None;
+    }
+    Some(if size <= 3 {
+        U256::from(word >> (8 * (3 - size)))
+    } else {
+        U256::from(word) << (8 * (size - 3))
+    })
+}
+
+/// Encodes a target in compact form, rounding it down.
+pub fn to_compact(target: U256) -> u32 {
+    let mut size = target.bit_len().div_ceil(8);
+    let mut word = if size <= 3 {
+        target.to::<u32>() << (8 * (3 - size))
+    } else {
+        (target >> (8 * (size - 3))).to::<u32>()
+    };
+    if word & 0x0080_0000 != 0 {
+        word >>= 8;
+        size += 1;
+    }
+    word | ((size as u32) << 24)
+}
+
+fn sha256d(data: &[u8]) -> Hash {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+/// Root of the transaction Merkle tree over txids in internal byte order.
+///
+/// Like Bitcoin, a level with an odd number of nodes repeats its last node.
+pub fn transaction_merkle_root(txids: &[Hash]) -> Hash {
+    if txids.is_empty() {
+        return [0; 32];
+    }
+    let mut level = txids.to_vec();
+    while level.len() > 1 {
+        level = next_transaction_level(&level);
+    }
+    level[0]
+}
+
+/// Builds the Merkle path of the transaction at `index`, or `None` if it is out of bounds.
+pub fn transaction_proof(txids: &[Hash], index: u64) -> Option<MerkleProof> {
+    if index >= txids.len() as u64 {
+        return None;
+    }
+    let mut siblings = Vec::new();
+    let mut level = txids.to_vec();
+    let mut position = index as usize;
+    while level.len() > 1 {
+        siblings.push(level.get(position ^ 1).copied().unwrap_or(level[position]));
+        level = next_transaction_level(&level);
+        position /= 2;
+    }
+    Some(MerkleProof { index, siblings })
+}
+
+/// Checks that the transaction with `txid` is included in the block with `header`.
+pub fn verify_transaction(header: &BlockHeader, txid: &Hash, proof: &MerkleProof) -> bool {
+    let mut node = *txid;
+    let mut index = proof.index;
+    for sibling in &proof.siblings {
+        node = if index & 1 == 0 {
+            hash_transaction_node(&node, sibling)
+        } else {
+            hash_transaction_node(sibling, &node)
+        };
+        index >>= 1;
+    }
+    index == 0 && node == header.merkle_root
+}
+
+fn hash_transaction_node(left: &Hash, right: &Hash) -> Hash {
+    let mut preimage = [0; 64];
+    preimage[..32].copy_from_slice(left);
+    preimage[32..].copy_from_slice(right);
+    sha256d(&preimage)
+}
+
+fn next_transaction_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| hash_transaction_node(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+/// Root of the ZIP 244 authorizing data tree over the auth digests of the block transactions.
+pub fn auth_data_root(auth_digests: &[Hash]) -> Hash {
+    let width = auth_digests.len().max(1).next_power_of_two();
+    let mut level: Vec<Hash> = auth_digests.to_vec();
+    level.resize(width, [0; 32]);
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let hash = blake2b_simd::Params::new()
+                    .hash_length(32)
+                    .personal(b"ZcashAuthDatHash")
+                    .to_state()
+                    .update(&pair[0])
+                    .update(&pair[1])
+                    .finalize();
+                hash.as_bytes().try_into().unwrap()
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// `hashBlockCommitments` of a block header as of NU5 (ZIP 244).
+pub fn block_commitments_hash(history_root: &Hash, auth_data_root: &Hash) -> Hash {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(b"ZcashBlockCommit")
+        .to_state()
+        .update(history_root)
+        .update(auth_data_root)
+        .update(&[0; 32])
+        .finalize();
+    hash.as_bytes().try_into().unwrap()
+}
+
+/// Checks the header commitment against the chain history root and the auth digests of the
+/// block transactions.
+///
+/// The history root is taken as given: re-deriving it needs the chain history tree, which this
+/// client does not maintain.
+pub fn verify_block_commitments(
+    header: &BlockHeader,
+    history_root: &Hash,
+    auth_digests: &[Hash],
+) -> bool {
+    header.block_commitments == block_commitments_hash(history_root, &auth_data_root(auth_digests))
+}
+
+fn read_compact_size(bytes: &[u8]) -> anyhow::Result<(u64, usize)> {
+    let (&first, rest) = bytes.split_first().context("missing compact size")?;
+    let len = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        _ => return Ok((first as u64, 1)),
+    };
+    anyhow::ensure!(rest.len() >= len, "truncated compact size");
+    let mut value = [0; 8];
+    value[..len].copy_from_slice(&rest[..len]);
+    Ok((u64::from_le_bytes(value), 1 + len))
+}
+
+fn write_compact_size(bytes: &mut Vec<u8>, value: u64) {
+    match value {
+        0..0xfd => bytes.push(value as u8),
+        0xfd..=0xffff => {
+            bytes.push(0xfd);
+            bytes.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            bytes.push(0xfe);
+            bytes.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        _ => {
+            bytes.push(0xff);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
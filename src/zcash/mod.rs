@@ -1,2 +1,12 @@
+#[cfg(feature = "node")]
+pub mod deposit;
+pub mod light_client;
+#[cfg(feature = "node")]
 pub mod sender;
+#[cfg(feature = "node")]
+pub mod shielded;
+#[cfg(feature = "node")]
+pub mod signer;
+#[cfg(feature = "node")]
+pub mod subscription;
 pub mod watcher;
@@ -1,24 +1,42 @@
 use crate::{
-    types::StateUpdate,
+    journal::Journal,
+    metrics::METRICS,
+    prover::StateTransitionProof,
+    shutdown::{CancellationToken, cancellable},
+    state::BridgeState,
+    subscription::BlockSubscription as _,
+    types::{EthToZecTransfer, StateUpdate, ZcashRecipient},
+    zcash::{
+        shielded::{ShieldedSpendingKey, SpendableNote, note_anchors, select_notes},
+        signer::{LocalSigner, ZcashSigner, build_and_sign, build_and_sign_with_shielded},
+        subscription::ZebraBlocks,
+    },
     zebra_client::{
         client::RpcClient as _,
-        helpers::spendable_coinbase_txid,
+        helpers::{spendable_coinbase_txid, tree_anchors},
         regtest::RegtestNetwork,
-        wallet::{Key, Wallet, regtest_default_wallet},
+        wallet::{Wallet, p2pkh_address, pubkey_hash, regtest_default_wallet, scan_chain},
     },
 };
-use rand_core::OsRng;
+use alloy::primitives::Signature;
+use anyhow::Context as _;
+use secp256k1::PublicKey;
+use std::{convert::Infallible, time::Duration};
 use zcash_extensions::transparent::eth_bridge::{self};
+use zcash_primitives::extensions::transparent::FromPayload as _;
 use zcash_primitives::transaction::{
     builder::{BuildResult, Builder},
     components::{TzeOut, tze},
     fees::fixed::FeeRule,
 };
-use zcash_proofs::prover::LocalTxProver;
-use zcash_protocol::{TxId, consensus::BranchId, value::Zatoshis};
+use zcash_protocol::{
+    TxId,
+    consensus::{BranchId, NetworkType},
+    memo::MemoBytes,
+    value::Zatoshis,
+};
 use zcash_transparent::{
     address::TransparentAddress,
-    builder::TransparentSigningSet,
     bundle::{OutPoint, TxOut},
 };
 use zebra_chain::transaction;
@@ -26,39 +44,122 @@ use zebra_node_services::rpc_client::RpcRequestClient;
 use zebra_rpc::methods::GetRawTransaction;
 
 /// The amount to lock in the TZE STF output for it to not be considered dust.
-const LOCK_IN_VALUE: Zatoshis = Zatoshis::const_from_u64(100_000);
+pub const LOCK_IN_VALUE: Zatoshis = Zatoshis::const_from_u64(100_000);
+
+/// Identifier of the STF deployed by the operator, which deposits have to name.
+pub const STF_IDENTIFIER: [u8; 32] = [0xAB; 32];
+
+/// Prefix of the null-data outputs carrying the transition proof, telling them apart from the
+/// committee signatures.
+pub const PROOF_DATA_PREFIX: [u8; 4] = *b"zbp1";
+
+/// Bytes of the proof carried by one null-data output, keeping the pushed data within the 80
+/// bytes relayed by nodes.
+const PROOF_CHUNK_LEN: usize = 76;
+
+/// Splits the encoded `proof` into the data of the null-data outputs carrying it.
+pub fn proof_null_data(proof: &StateTransitionProof) -> Vec<Vec<u8>> {
+    proof
+        .encode()
+        .chunks(PROOF_CHUNK_LEN)
+        .map(|chunk| [&PROOF_DATA_PREFIX[..], chunk].concat())
+        .collect()
+}
 
 pub struct TzeSender {
     pub client: RpcRequestClient,
     wallet: Wallet<RegtestNetwork>,
-    miner_key: Key,
+    signer: Box<dyn ZcashSigner>,
     stf_identifier: [u8; 32],
-    root_hash: [u8; 32],
-    // For now we expect that we can always pay for a tx with a single input.
-    fee_txid: TxId,
+    // Bridge state committed to by the current STF output.
+    state: BridgeState,
+    // Outputs funding the next transaction: the last change and the operator's reimbursements
+    // of shielded withdrawals.
+    fee_coins: Vec<(TxId, u32)>,
+    // Key controlling the outputs of `fee_coins`.
+    fee_key: PublicKey,
+    // Next unused index on the signer's change chain.
+    change_index: u32,
     // Tracks the amount of deposited funds
     deposited: Zatoshis,
+    // Interrupts waiting for transactions.
+    cancel: CancellationToken,
+    // Records the STF spends before they are sent.
+    journal: Option<Journal>,
 }
 
 impl TzeSender {
     pub async fn new(rpc_address: &str) -> anyhow::Result<Self> {
+        let signer = LocalSigner::from_wallet(&regtest_default_wallet(), 0);
+        Self::with_signer(rpc_address, Box::new(signer)).await
+    }
+
+    /// Creates a sender whose transparent inputs are authorized by `signer`.
+    ///
+    /// The signer's public key must control a spendable coinbase output. Change is sent to
+    /// the first change address of the signer that has not been used yet.
+    pub async fn with_signer(
+        rpc_address: &str,
+        signer: Box<dyn ZcashSigner>,
+    ) -> anyhow::Result<Self> {
         let client = RpcRequestClient::new(rpc_address.parse().unwrap());
         let wallet = regtest_default_wallet();
-        let miner_key = wallet.derive_key(0, 0);
 
         let target_height = client.get_block_count().await? + 1;
         let fee_txid = spendable_coinbase_txid(&client, target_height).await?;
+        let fee_key = signer.public_key();
+        let change_index = next_unused_change_index(&client, signer.as_ref()).await?;
         Ok(Self {
             client,
             wallet,
-            miner_key,
-            stf_identifier: [0xAB; 32],
-            root_hash: [0xCD; 32],
-            fee_txid,
+            signer,
+            stf_identifier: STF_IDENTIFIER,
+            state: BridgeState::default(),
+            fee_coins: vec![(fee_txid, 0)],
+            fee_key,
+            change_index,
             deposited: Zatoshis::ZERO,
+            cancel: CancellationToken::new(),
+            journal: None,
         })
     }
 
+    /// Bridge state committed to by the current STF output.
+    pub fn state(&self) -> &BridgeState {
+        &self.state
+    }
+
+    /// Makes waiting for transactions fail once `token` is cancelled.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    /// Records every STF spend in `journal` before sending it, so that a restart after an
+    /// interrupted send knows which transaction to look for.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    /// Returns whether `txid` reached the node, in its mempool or in a block.
+    pub async fn is_sent(&self, txid: &TxId) -> anyhow::Result<bool> {
+        if self
+            .client
+            .get_raw_mempool()
+            .await?
+            .contains(&txid.to_string())
+        {
+            return Ok(true);
+        }
+        Ok(self.client.get_raw_transaction(txid, true).await.is_ok())
+    }
+
+    /// Continues an STF committing to `state` and holding `deposited` for depositors, e.g. one
+    /// deployed by another relayer instance.
+    pub fn resume(&mut self, state: BridgeState, deposited: Zatoshis) {
+        self.state = state;
+        self.deposited = deposited;
+    }
+
     pub async fn send_tze_create(&mut self, fee: u64) -> anyhow::Result<(tze::OutPoint, TzeOut)> {
         let target_height = self.target_height().await?;
 
@@ -66,19 +167,19 @@ impl TzeSender {
             txn_builder: self.wallet.tx_builder(target_height),
             extension_id: zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE,
         };
-        let coin = self.add_fee_input(&mut builder.txn_builder).await?;
+        let funds = self.add_fee_input(&mut builder.txn_builder).await?;
 
-        let value = (coin.value() - Zatoshis::const_from_u64(fee)).unwrap();
+        let value = (funds - Zatoshis::const_from_u64(fee)).unwrap();
         let value = (value - LOCK_IN_VALUE).unwrap();
-        builder.add_create_output(LOCK_IN_VALUE, self.stf_identifier, self.root_hash)?;
+        builder.add_create_output(LOCK_IN_VALUE, self.stf_identifier, self.state.root())?;
         assert_eq!(
             self.deposited,
             Zatoshis::ZERO,
             "Create called on a dirty state"
         );
-        self.deposited = LOCK_IN_VALUE;
 
-        self.add_fee_output(&mut builder.txn_builder, value).await?;
+        let change_key = self.next_change_key().await?;
+        Self::add_fee_output(&mut builder.txn_builder, &change_key, value)?;
 
         let res = self.finish_tx(builder.txn_builder, fee).await?;
         let tx = res.transaction();
@@ -89,7 +190,8 @@ impl TzeSender {
 
         // TZE outpoints come after transparent outputs, so index 1.
         let outpoint = Self::outpoint(&hash, 1);
-        self.fee_txid = TxId::from_bytes(hash.0);
+        self.advance_fee_coin(&hash, change_key, [0]);
+        self.deposited = LOCK_IN_VALUE;
 
         Ok((outpoint, tze_output))
     }
@@ -106,13 +208,14 @@ impl TzeSender {
             txn_builder: self.wallet.tx_builder(target_height),
             extension_id: zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE,
         };
-        let coin = self.add_fee_input(&mut builder.txn_builder).await?;
+        let funds = self.add_fee_input(&mut builder.txn_builder).await?;
 
         builder.add_deposit_output(amount, self.stf_identifier, to_eth_addr)?;
 
-        let value = (coin.value() - Zatoshis::const_from_u64(fee)).unwrap();
+        let value = (funds - Zatoshis::const_from_u64(fee)).unwrap();
         let value = (value - amount).unwrap();
-        self.add_fee_output(&mut builder.txn_builder, value).await?;
+        let change_key = self.next_change_key().await?;
+        Self::add_fee_output(&mut builder.txn_builder, &change_key, value)?;
 
         let res = self.finish_tx(builder.txn_builder, fee).await?;
         let tx = res.transaction();
@@ -123,7 +226,64 @@ impl TzeSender {
 
         // TZE outpoints come after transparent outputs, so index 1.
         let outpoint = Self::outpoint(&hash, 1);
-        self.fee_txid = TxId::from_bytes(hash.0);
+        self.advance_fee_coin(&hash, change_key, [0]);
+
+        Ok((outpoint, tze_output))
+    }
+
+    /// Sends a deposit funded from a shielded pool, returning the change to the same pool.
+    ///
+    /// Notes are selected from `notes`, which must belong to `spending_key`. The transaction
+    /// has no transparent parts, so the operator coin is not touched.
+    pub async fn send_shielded_tze_deposit(
+        &self,
+        to_eth_addr: [u8; 20],
+        amount: Zatoshis,
+        fee: u64,
+        spending_key: &ShieldedSpendingKey,
+        notes: Vec<SpendableNote>,
+    ) -> anyhow::Result<(tze::OutPoint, TzeOut)> {
+        let target_height = self.target_height().await?;
+
+        let target = (amount + Zatoshis::const_from_u64(fee)).unwrap();
+        let (selected, change) = select_notes(notes, target)?;
+        let (sapling_anchor, orchard_anchor) = note_anchors(&selected)?;
+
+        let mut builder = eth_bridge::builder::EthBridgeTzeBuilder {
+            txn_builder: self.wallet.tx_builder_with_anchors(
+                target_height,
+                sapling_anchor,
+                orchard_anchor,
+            ),
+            extension_id: zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE,
+        };
+        for note in selected {
+            spending_key.add_spend(&mut builder.txn_builder, note)?;
+        }
+
+        builder.add_deposit_output(amount, self.stf_identifier, to_eth_addr)?;
+        if change > Zatoshis::ZERO {
+            spending_key.add_change_output(&mut builder.txn_builder, change)?;
+        }
+
+        let fee_rule = FeeRule::non_standard(Zatoshis::const_from_u64(fee));
+        let (sapling_extsks, orchard_saks) = spending_key.authorizing_keys();
+        let res = build_and_sign_with_shielded(
+            builder.txn_builder,
+            self.signer.as_ref(),
+            &sapling_extsks,
+            &orchard_saks,
+            &fee_rule,
+        )
+        .await?;
+        let tx = res.transaction();
+        tracing::debug!("[tze shielded deposit] Tx: {tx:?}");
+
+        let tze_output = tx.tze_bundle().unwrap().vout[0].clone();
+        let hash = self.client.send_raw_transaction(tx).await.unwrap().hash();
+
+        // No transparent outputs, so the TZE output is the first one.
+        let outpoint = Self::outpoint(&hash, 0);
 
         Ok((outpoint, tze_output))
     }
@@ -140,12 +300,13 @@ impl TzeSender {
             extension_id: zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE,
         };
 
-        let coin = self.add_fee_input(&mut builder.txn_builder).await?;
+        let funds = self.add_fee_input(&mut builder.txn_builder).await?;
         builder.add_create_input(prevout)?;
 
-        builder.add_stf_output(LOCK_IN_VALUE, self.stf_identifier, self.root_hash)?;
-        let value = (coin.value() - Zatoshis::const_from_u64(fee)).unwrap();
-        self.add_fee_output(&mut builder.txn_builder, value).await?;
+        builder.add_stf_output(LOCK_IN_VALUE, self.stf_identifier, self.state.root())?;
+        let value = (funds - Zatoshis::const_from_u64(fee)).unwrap();
+        let change_key = self.next_change_key().await?;
+        Self::add_fee_output(&mut builder.txn_builder, &change_key, value)?;
 
         let res = self.finish_tx(builder.txn_builder, fee).await?;
         let tx = res.transaction();
@@ -156,49 +317,100 @@ impl TzeSender {
 
         // TZE outpoints come after transparent outputs, so index 1.
         let outpoint = Self::outpoint(&hash, 1);
-        self.fee_txid = TxId::from_bytes(hash.0);
+        self.advance_fee_coin(&hash, change_key, [0]);
 
         Ok((outpoint, tze_output))
     }
 
+    /// Progresses the STF from the current state to `new_state`.
+    ///
+    /// The STF input carries the root of the current state and the new STF output the root of
+    /// `new_state`.
+    ///
+    /// The extension only knows transparent withdrawals, so each shielded withdrawal is listed
+    /// in the witness as a withdrawal of the same amount to the operator, which pays the
+    /// shielded output from its fee coin in the same transaction. All value leaving the STF is
+    /// thereby checked by the extension. The reimbursements go to the change key and fund the
+    /// next transaction along with the change, so the fee coin only pays the fee.
+    ///
+    /// The committee `signatures` on the update are carried in null-data outputs after the
+    /// withdrawals, one per signature, followed by the transition `proof`, see
+    /// [`proof_null_data`]. The extension cannot check either, watchtowers do.
+    #[allow(clippy::too_many_arguments)]
     pub async fn progress_tze_stf(
         &mut self,
         fee: u64,
         prevout: (tze::OutPoint, TzeOut),
         deposit_outpoints: Vec<(tze::OutPoint, TzeOut)>,
         processed_deposits: Vec<eth_bridge::modes::stf::ProcessedDeposit>,
-        processed_withdrawals: Vec<eth_bridge::modes::stf::ProcessedWithdrawal>,
+        mut processed_withdrawals: Vec<eth_bridge::modes::stf::ProcessedWithdrawal>,
+        shielded_withdrawals: Vec<EthToZecTransfer>,
+        new_state: BridgeState,
+        signatures: &[Signature],
+        proof: Option<&StateTransitionProof>,
     ) -> anyhow::Result<(tze::OutPoint, TzeOut)> {
+        let proof_data = proof.map(proof_null_data).unwrap_or_default();
         let target_height = self.target_height().await?;
+        let (sapling_anchor, orchard_anchor) =
+            tree_anchors(&self.client, target_height - 1).await?;
 
         let mut builder = eth_bridge::builder::EthBridgeTzeBuilder {
-            txn_builder: self.wallet.tx_builder(target_height),
+            txn_builder: self.wallet.tx_builder_with_anchors(
+                target_height,
+                Some(sapling_anchor),
+                Some(orchard_anchor),
+            ),
             extension_id: zcash_extensions::consensus::transparent::EXTENSION_ETH_BRIDGE,
         };
 
-        let coin = self.add_fee_input(&mut builder.txn_builder).await?;
-        // TZE outpoints come after transparent outputs, so index 1.
+        let funds = self.add_fee_input(&mut builder.txn_builder).await?;
+
+        let change_key = self.next_change_key().await?;
+        let operator_hash = pubkey_hash(&change_key);
+        // Reimbursements follow the change and the transparent withdrawals.
+        let first_reimbursement = 1 + processed_withdrawals.len() as u32;
+        let reimbursements =
+            first_reimbursement..first_reimbursement + shielded_withdrawals.len() as u32;
+        let mut shielded_total = Zatoshis::ZERO;
+        for withdrawal in &shielded_withdrawals {
+            let amount = Zatoshis::from_u64(withdrawal.amount)?;
+            shielded_total = (shielded_total + amount)
+                .ok_or_else(|| anyhow::anyhow!("shielded withdrawals overflow"))?;
+            processed_withdrawals.push(eth_bridge::modes::stf::ProcessedWithdrawal {
+                pubkey_hash: operator_hash,
+                amount,
+            });
+        }
+
         builder.add_stf_input(
             prevout,
             self.stf_identifier,
-            self.root_hash,
+            self.state.root(),
             processed_deposits,
             processed_withdrawals.clone(),
         )?;
 
+        // Value of the new STF output, only committed once the transaction is sent.
+        let mut deposited = self.deposited;
         for deposit_outpoint in deposit_outpoints {
-            self.deposited = (self.deposited + deposit_outpoint.1.value).unwrap();
+            deposited = (deposited + deposit_outpoint.1.value)
+                .ok_or_else(|| anyhow::anyhow!("deposited value overflows"))?;
             builder.add_deposit_input(deposit_outpoint)?;
         }
 
-        // TZE outpoints come after transparent outputs, so index 1 + number of withdrawal outputs.
-        let stf_output_number = 1 + processed_withdrawals.len() as u32;
+        // TZE outpoints come after transparent outputs, so index 1 + number of withdrawal,
+        // signature and proof outputs.
+        let stf_output_number =
+            1 + (processed_withdrawals.len() + signatures.len() + proof_data.len()) as u32;
 
-        // 1. Transparent inputs (they go first in vout)
-        let value = (coin.value() - Zatoshis::const_from_u64(fee)).unwrap();
-        self.add_fee_output(&mut builder.txn_builder, value).await?;
+        // 1. Transparent inputs (they go first in vout). The fee coin also pays the shielded
+        // withdrawals, which the STF pays back to the operator.
+        let value = (funds - Zatoshis::const_from_u64(fee))
+            .and_then(|value| value - shielded_total)
+            .ok_or_else(|| anyhow::anyhow!("fee coin cannot cover shielded withdrawals"))?;
+        Self::add_fee_output(&mut builder.txn_builder, &change_key, value)?;
 
-        // 2. Withdrawal outputs (still transparent).
+        // 2. Withdrawal outputs (still transparent), including the operator's reimbursements.
         for withdrawal in processed_withdrawals {
             builder
                 .txn_builder
@@ -207,21 +419,74 @@ impl TzeSender {
                     withdrawal.amount,
                 )
                 .map_err(wrap_anyhow)?;
-            self.deposited = (self.deposited - withdrawal.amount).unwrap();
+            deposited = (deposited - withdrawal.amount)
+                .ok_or_else(|| anyhow::anyhow!("withdrawals exceed the deposited value"))?;
+        }
+
+        // 3. Committee signatures and the transition proof.
+        for signature in signatures {
+            builder
+                .txn_builder
+                .add_transparent_null_data_output(&signature.as_bytes())
+                .map_err(wrap_anyhow)?;
+        }
+        for data in &proof_data {
+            builder
+                .txn_builder
+                .add_transparent_null_data_output(data)
+                .map_err(wrap_anyhow)?;
         }
 
-        // 3. TZE STF output
-        builder.add_stf_output(self.deposited, self.stf_identifier, self.root_hash)?;
+        // 4. Shielded withdrawal outputs, paid by the operator.
+        for withdrawal in shielded_withdrawals {
+            let amount = Zatoshis::from_u64(withdrawal.amount)?;
+            match withdrawal.recipient {
+                ZcashRecipient::Sapling(raw) => {
+                    let to = ::sapling::PaymentAddress::from_bytes(&raw)
+                        .ok_or_else(|| anyhow::anyhow!("invalid Sapling receiver"))?;
+                    builder
+                        .txn_builder
+                        .add_sapling_output::<Infallible>(None, to, amount, MemoBytes::empty())
+                        .map_err(wrap_anyhow)?;
+                }
+                ZcashRecipient::Orchard(raw) => {
+                    let to = orchard::Address::from_raw_address_bytes(&raw)
+                        .into_option()
+                        .ok_or_else(|| anyhow::anyhow!("invalid Orchard receiver"))?;
+                    builder
+                        .txn_builder
+                        .add_orchard_output::<Infallible>(
+                            None,
+                            to,
+                            amount.into_u64(),
+                            MemoBytes::empty(),
+                        )
+                        .map_err(wrap_anyhow)?;
+                }
+                ZcashRecipient::Transparent(_) => {
+                    anyhow::bail!("transparent withdrawals must be passed as processed withdrawals")
+                }
+            }
+        }
+
+        // 5. TZE STF output
+        builder.add_stf_output(deposited, self.stf_identifier, new_state.root())?;
 
         let res = self.finish_tx(builder.txn_builder, fee).await?;
         let tx = res.transaction();
         tracing::debug!("[tze progress stf] Tx: {tx:?}");
+        if let Some(journal) = &self.journal {
+            journal.sent(tx.txid()).await?;
+        }
 
         let tze_output = tx.tze_bundle().unwrap().vout[0].clone();
         let hash = self.client.send_raw_transaction(tx).await.unwrap().hash();
+        METRICS.zcash_fees_paid.inc_by(fee);
 
         let outpoint = Self::outpoint(&hash, stf_output_number);
-        self.fee_txid = TxId::from_bytes(hash.0);
+        self.advance_fee_coin(&hash, change_key, std::iter::once(0).chain(reimbursements));
+        self.state = new_state;
+        self.deposited = deposited;
 
         Ok((outpoint, tze_output))
     }
@@ -231,26 +496,58 @@ impl TzeSender {
         prevout: (tze::OutPoint, TzeOut),
         zcash_deposit_outpoints: Vec<(tze::OutPoint, TzeOut)>,
         state_update: StateUpdate,
+        proof: &StateTransitionProof,
+        signatures: &[Signature],
     ) -> anyhow::Result<(tze::OutPoint, TzeOut)> {
-        let zec_to_eth_transfers = state_update
-            .zec_to_eth_transfers
+        // The new state commits to the update, binding the STF output to the same commitment
+        // as the Ethereum transaction.
+        let mut new_state = self.state.clone();
+        new_state.apply(&state_update)?;
+        proof.ensure_transition(&state_update, &self.state.root(), &new_state.root())?;
+        // The witness lists every claimed deposit output in full. Fees and refunds only change
+        // how the claimed value is split between Ethereum and Zcash.
+        let zec_to_eth_transfers = zcash_deposit_outpoints
+            .iter()
+            .map(|(_, output)| {
+                let precondition = &output.precondition;
+                let Ok(eth_bridge::Precondition::Deposit(deposit)) =
+                    eth_bridge::Precondition::from_payload(
+                        precondition.mode,
+                        &precondition.payload,
+                    )
+                else {
+                    anyhow::bail!("claimed output is not a deposit");
+                };
+                Ok(eth_bridge::modes::stf::ProcessedDeposit {
+                    to: deposit.to,
+                    amount: output.value,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        // Refunds are paid like withdrawals, after them.
+        let refunds = state_update
+            .refunds
             .into_iter()
-            .map(
-                |t| zcash_extensions::transparent::eth_bridge::modes::stf::ProcessedDeposit {
-                    to: t.eth_address,
-                    amount: zcash_protocol::value::Zatoshis::from_u64(t.amount).unwrap(),
-                },
-            )
-            .collect();
-        let eth_to_zec_transers = state_update
+            .map(|refund| EthToZecTransfer {
+                amount: refund.amount,
+                recipient: refund.recipient,
+            });
+        let (transparent_withdrawals, shielded_withdrawals): (Vec<_>, Vec<_>) = state_update
             .eth_to_zec_transfers
             .into_iter()
-            .map(
-                |t| zcash_extensions::transparent::eth_bridge::modes::stf::ProcessedWithdrawal {
-                    pubkey_hash: t.pubkey_hash,
+            .chain(refunds)
+            .partition(|t| matches!(t.recipient, ZcashRecipient::Transparent(_)));
+        let eth_to_zec_transers = transparent_withdrawals
+            .into_iter()
+            .map(|t| {
+                let ZcashRecipient::Transparent(pubkey_hash) = t.recipient else {
+                    unreachable!("partitioned above");
+                };
+                zcash_extensions::transparent::eth_bridge::modes::stf::ProcessedWithdrawal {
+                    pubkey_hash,
                     amount: zcash_protocol::value::Zatoshis::from_u64(t.amount).unwrap(),
-                },
-            )
+                }
+            })
             .collect();
 
         self.progress_tze_stf(
@@ -259,6 +556,10 @@ impl TzeSender {
             zcash_deposit_outpoints,
             zec_to_eth_transfers,
             eth_to_zec_transers,
+            shielded_withdrawals,
+            new_state,
+            signatures,
+            Some(proof),
         )
         .await
     }
@@ -286,20 +587,25 @@ impl TzeSender {
     }
 
     pub async fn wait_for_tx(&self, txid: &TxId) -> anyhow::Result<u64> {
+        cancellable(&self.cancel, self.poll_tx(txid))
+            .await
+            .with_context(|| format!("waiting for transaction {txid}"))
+    }
+
+    async fn poll_tx(&self, txid: &TxId) -> anyhow::Result<u64> {
+        let mut blocks = ZebraBlocks::new(self.client.clone(), Duration::from_millis(200));
         loop {
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-            match self.client.get_raw_transaction(txid, true).await {
-                Ok(tx) => match tx {
-                    GetRawTransaction::Object(tx) => {
-                        // `None` = mempool, `Some(-1)` = side chain, `Some(height >= 0)` = main chain
-                        if tx.height() > Some(0) {
-                            return Ok(tx.height().unwrap() as u64);
-                        }
-                    }
-                    _ => panic!("Unexpected response"),
-                },
-                Err(_) => continue,
+            let tip = self.client.get_block_count().await?;
+            if let Ok(tx) = self.client.get_raw_transaction(txid, true).await {
+                let GetRawTransaction::Object(tx) = tx else {
+                    anyhow::bail!("expected a verbose transaction for {txid}, got raw bytes");
+                };
+                // `None` = mempool, `Some(-1)` = side chain, `Some(height >= 0)` = main chain
+                if tx.height() > Some(0) {
+                    return Ok(tx.height().unwrap() as u64);
+                }
             }
+            blocks.wait_for_block(tip as u64).await?;
         }
     }
 
@@ -308,33 +614,58 @@ impl TzeSender {
         Ok(block_count + 1)
     }
 
+    /// Spends all fee coins and returns their total value.
     async fn add_fee_input<'a>(
         &self,
         builder: &mut Builder<'a, RegtestNetwork, ()>,
-    ) -> anyhow::Result<TxOut> {
-        let (txid, coin) = self.spendable_tx().await?;
+    ) -> anyhow::Result<Zatoshis> {
+        let mut funds = Zatoshis::ZERO;
+        for ((txid, n), coin) in self.fee_coins.iter().zip(self.spendable_coins().await?) {
+            funds = (funds + coin.value()).ok_or_else(|| anyhow::anyhow!("fee coins overflow"))?;
+            builder
+                .add_transparent_input(self.fee_key, OutPoint::new((*txid).into(), *n), coin)
+                .map_err(wrap_anyhow)?;
+        }
+        Ok(funds)
+    }
 
-        builder
-            .add_transparent_input(
-                self.miner_key.public_key(),
-                OutPoint::new(txid.into(), 0),
-                coin.clone(),
-            )
-            .map_err(wrap_anyhow)?;
-
-        Ok(coin)
+    /// Key of the change output of the next transaction. Change has to stay spendable by the
+    /// signer, since it funds the transaction after.
+    async fn next_change_key(&self) -> anyhow::Result<PublicKey> {
+        self.signer.change_public_key(self.change_index).await
     }
 
-    async fn add_fee_output<'a>(
-        &self,
+    fn add_fee_output<'a>(
         builder: &mut Builder<'a, RegtestNetwork, ()>,
+        change_key: &PublicKey,
         value: Zatoshis,
     ) -> anyhow::Result<()> {
-        let to = self.wallet.derive_key(0, 0).transparent_address();
         builder
-            .add_transparent_output(&to, value)
-            .map_err(wrap_anyhow)?;
-        Ok(())
+            .add_transparent_output(&p2pkh_address(change_key), value)
+            .map_err(wrap_anyhow)
+    }
+
+    /// Records the `outputs` of a sent transaction paying `change_key`, the change at index 0
+    /// first, as the next fee coins.
+    fn advance_fee_coin(
+        &mut self,
+        hash: &transaction::Hash,
+        change_key: PublicKey,
+        outputs: impl IntoIterator<Item = u32>,
+    ) {
+        let txid = TxId::from_bytes(hash.0);
+        self.fee_coins = outputs.into_iter().map(|n| (txid, n)).collect();
+        self.fee_key = change_key;
+        self.change_index += 1;
+    }
+
+    /// Total value of the fee coins, which fund the fees and pre-fund shielded withdrawals.
+    pub async fn fee_funds(&self) -> anyhow::Result<Zatoshis> {
+        self.spendable_coins()
+            .await?
+            .iter()
+            .try_fold(Zatoshis::ZERO, |funds, coin| funds + coin.value())
+            .ok_or_else(|| anyhow::anyhow!("fee coins overflow"))
     }
 
     async fn finish_tx<'a>(
@@ -342,35 +673,21 @@ impl TzeSender {
         builder: Builder<'a, RegtestNetwork, ()>,
         fee: u64,
     ) -> anyhow::Result<BuildResult> {
-        let mut transparent_signing_set = TransparentSigningSet::new();
-        transparent_signing_set.add_key(self.miner_key.secret_key());
-
         let fee_rule = FeeRule::non_standard(Zatoshis::const_from_u64(fee));
-        let prover = LocalTxProver::bundled();
-
-        let res = builder
-            .build_zfuture(
-                &transparent_signing_set,
-                &[],
-                &[],
-                OsRng,
-                &prover,
-                &prover,
-                &fee_rule,
-            )
-            .map_err(|e| format!("build failure: {:?}", e))
-            .unwrap();
-
-        Ok(res)
+        build_and_sign(builder, self.signer.as_ref(), &fee_rule).await
     }
 
-    async fn spendable_tx(&self) -> anyhow::Result<(TxId, TxOut)> {
-        let tx = self
-            .client
-            .get_transaction(&self.fee_txid, BranchId::ZFuture)
-            .await?;
-        let coin = tx.transparent_bundle().unwrap().vout[0].clone();
-        Ok((self.fee_txid, coin))
+    async fn spendable_coins(&self) -> anyhow::Result<Vec<TxOut>> {
+        let mut coins = Vec::with_capacity(self.fee_coins.len());
+        for (txid, n) in &self.fee_coins {
+            let tx = self.client.get_transaction(txid, BranchId::ZFuture).await?;
+            let coin = tx
+                .transparent_bundle()
+                .and_then(|bundle| bundle.vout.get(*n as usize))
+                .ok_or_else(|| anyhow::anyhow!("fee coin {txid}:{n} not found"))?;
+            coins.push(coin.clone());
+        }
+        Ok(coins)
     }
 
     fn outpoint(hash: &transaction::Hash, vout: u32) -> tze::OutPoint {
@@ -378,6 +695,24 @@ impl TzeSender {
     }
 }
 
+/// Finds the first change address of the signer after the last used one, so restarts do not
+/// reuse them.
+async fn next_unused_change_index(
+    client: &RpcRequestClient,
+    signer: &dyn ZcashSigner,
+) -> anyhow::Result<u32> {
+    if signer.change_public_key(0).await? == signer.public_key() {
+        // The signer reuses the funding address for change, nothing to discover.
+        return Ok(0);
+    }
+    let (next_unused, _) = scan_chain(client, async |index| {
+        let change_key = signer.change_public_key(index).await?;
+        Ok(p2pkh_address(&change_key).to_zcash_address(NetworkType::Regtest))
+    })
+    .await?;
+    Ok(next_unused)
+}
+
 fn wrap_anyhow<T: std::fmt::Display>(err: T) -> anyhow::Error {
     anyhow::anyhow!(err.to_string())
 }
@@ -0,0 +1,309 @@
+use std::convert::Infallible;
+
+use ::sapling::zip32::ExtendedSpendingKey;
+use orchard::{
+    keys::{FullViewingKey, Scope, SpendAuthorizingKey, SpendingKey},
+    note::ExtractedNoteCommitment,
+};
+use zcash_primitives::transaction::builder::Builder;
+use zcash_protocol::{consensus::Parameters, memo::MemoBytes, value::Zatoshis};
+
+/// Spending key of the shielded pool funding a deposit.
+pub enum ShieldedSpendingKey {
+    Sapling(ExtendedSpendingKey),
+    Orchard(SpendingKey),
+}
+
+/// Note owned by a [`ShieldedSpendingKey`], along with its witness in the note commitment tree.
+#[derive(Debug, Clone)]
+pub enum SpendableNote {
+    Sapling {
+        note: ::sapling::Note,
+        merkle_path: ::sapling::MerklePath,
+    },
+    Orchard {
+        note: orchard::Note,
+        merkle_path: orchard::tree::MerklePath,
+    },
+}
+
+/// Root of the note commitment tree a note is witnessed against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoteAnchor {
+    Sapling(::sapling::Anchor),
+    Orchard(orchard::Anchor),
+}
+
+impl SpendableNote {
+    pub fn value(&self) -> Zatoshis {
+        let value = match self {
+            Self::Sapling { note, .. } => note.value().inner(),
+            Self::Orchard { note, .. } => note.value().inner(),
+        };
+        Zatoshis::from_u64(value).unwrap()
+    }
+
+    fn anchor(&self) -> NoteAnchor {
+        match self {
+            Self::Sapling { note, merkle_path } => {
+                let node = ::sapling::Node::from_cmu(&note.cmu());
+                NoteAnchor::Sapling(::sapling::Anchor::from(merkle_path.root(node)))
+            }
+            Self::Orchard { note, merkle_path } => NoteAnchor::Orchard(
+                merkle_path.root(ExtractedNoteCommitment::from(note.commitment())),
+            ),
+        }
+    }
+}
+
+impl ShieldedSpendingKey {
+    /// Adds a spend of `note` to the builder.
+    pub fn add_spend<P: Parameters>(
+        &self,
+        builder: &mut Builder<'_, P, ()>,
+        note: SpendableNote,
+    ) -> anyhow::Result<()> {
+        match (self, note) {
+            (Self::Sapling(extsk), SpendableNote::Sapling { note, merkle_path }) => {
+                let fvk = extsk.to_diversifiable_full_viewing_key().fvk().clone();
+                builder
+                    .add_sapling_spend::<Infallible>(fvk, note, merkle_path)
+                    .map_err(|e| anyhow::anyhow!("failed to add Sapling spend: {e}"))
+            }
+            (Self::Orchard(sk), SpendableNote::Orchard { note, merkle_path }) => {
+                let fvk = FullViewingKey::from(sk);
+                builder
+                    .add_orchard_spend::<Infallible>(fvk, note, merkle_path)
+                    .map_err(|e| anyhow::anyhow!("failed to add Orchard spend: {e}"))
+            }
+            _ => anyhow::bail!("note does not belong to the pool of the spending key"),
+        }
+    }
+
+    /// Adds an output sending `value` to the internal (change) address of the key.
+    pub fn add_change_output<P: Parameters>(
+        &self,
+        builder: &mut Builder<'_, P, ()>,
+        value: Zatoshis,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Sapling(extsk) => {
+                let dfvk = extsk.to_diversifiable_full_viewing_key();
+                let (_, to) = dfvk.change_address();
+                let ovk = dfvk.to_ovk(zip32::Scope::Internal);
+                builder
+                    .add_sapling_output::<Infallible>(Some(ovk), to, value, MemoBytes::empty())
+                    .map_err(|e| anyhow::anyhow!("failed to add Sapling change: {e}"))
+            }
+            Self::Orchard(sk) => {
+                let fvk = FullViewingKey::from(sk);
+                let to = fvk.address_at(0u32, Scope::Internal);
+                let ovk = fvk.to_ovk(Scope::Internal);
+                builder
+                    .add_orchard_output::<Infallible>(
+                        Some(ovk),
+                        to,
+                        value.into_u64(),
+                        MemoBytes::empty(),
+                    )
+                    .map_err(|e| anyhow::anyhow!("failed to add Orchard change: {e}"))
+            }
+        }
+    }
+
+    /// Returns the keys authorizing spends, in the form expected by the transaction builder.
+    pub fn authorizing_keys(&self) -> (Vec<ExtendedSpendingKey>, Vec<SpendAuthorizingKey>) {
+        match self {
+            Self::Sapling(extsk) => (vec![extsk.clone()], Vec::new()),
+            Self::Orchard(sk) => (Vec::new(), vec![SpendAuthorizingKey::from(sk)]),
+        }
+    }
+}
+
+/// Picks notes witnessed at a single anchor to cover `target`, largest first to keep the number
+/// of spends low.
+///
+/// Notes spent together have to be witnessed against the same tree state, so notes are grouped by
+/// anchor, and the group covering `target` with the fewest spends is used. Returns the selected
+/// notes and the change left after covering `target`.
+pub fn select_notes(
+    notes: Vec<SpendableNote>,
+    target: Zatoshis,
+) -> anyhow::Result<(Vec<SpendableNote>, Zatoshis)> {
+    let mut by_anchor: Vec<(NoteAnchor, Vec<SpendableNote>)> = Vec::new();
+    for note in notes {
+        let anchor = note.anchor();
+        match by_anchor.iter_mut().find(|(other, _)| *other == anchor) {
+            Some((_, group)) => group.push(note),
+            None => by_anchor.push((anchor, vec![note])),
+        }
+    }
+
+    let mut best: Option<(Vec<SpendableNote>, Zatoshis)> = None;
+    let mut available = Zatoshis::ZERO;
+    for (_, mut group) in by_anchor {
+        group.sort_by_key(|note| std::cmp::Reverse(note.value()));
+
+        let mut selected = Vec::new();
+        let mut total = Zatoshis::ZERO;
+        for note in group {
+            if total >= target {
+                break;
+            }
+            total = (total + note.value()).unwrap();
+            selected.push(note);
+        }
+
+        available = available.max(total);
+        let Some(change) = total - target else {
+            continue;
+        };
+        if best
+            .as_ref()
+            .is_none_or(|(notes, _)| selected.len() < notes.len())
+        {
+            best = Some((selected, change));
+        }
+    }
+
+    best.ok_or_else(|| {
+        anyhow::anyhow!(
+            "insufficient shielded funds: have {} at a single anchor, need {}",
+            available.into_u64(),
+            target.into_u64()
+        )
+    })
+}
+
+/// Computes the anchors the notes are witnessed at.
+///
+/// All notes of a pool have to be witnessed against the same tree state.
+pub fn note_anchors(
+    notes: &[SpendableNote],
+) -> anyhow::Result<(Option<::sapling::Anchor>, Option<orchard::Anchor>)> {
+    let mut sapling_anchor = None;
+    let mut orchard_anchor = None;
+    for note in notes {
+        match note.anchor() {
+            NoteAnchor::Sapling(anchor) => anyhow::ensure!(
+                *sapling_anchor.get_or_insert(anchor) == anchor,
+                "Sapling notes are witnessed at different anchors"
+            ),
+            NoteAnchor::Orchard(anchor) => anyhow::ensure!(
+                *orchard_anchor.get_or_insert(anchor) == anchor,
+                "Orchard notes are witnessed at different anchors"
+            ),
+        }
+    }
+    Ok((sapling_anchor, orchard_anchor))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::sapling::{Node, Rseed, value::NoteValue};
+
+    use super::*;
+
+    /// Sapling note of `value` to a fixed address, distinct for each `seed`.
+    fn sapling_note(value: u64, seed: u8) -> ::sapling::Note {
+        let (_, recipient) = ExtendedSpendingKey::master(&[0; 32]).default_address();
+        ::sapling::Note::from_parts(
+            recipient,
+            NoteValue::from_raw(value),
+            Rseed::AfterZip212([seed; 32]),
+        )
+    }
+
+    /// Node whose first byte is `byte`.
+    fn node(byte: u8) -> Node {
+        let mut bytes = [0; 32];
+        bytes[0] = byte;
+        Node::from_bytes(bytes).unwrap()
+    }
+
+    /// Witness at `position` of a tree whose leaf-level sibling is `sibling` and whose other
+    /// siblings are derived from `tree`, so that trees differ in their anchors.
+    fn merkle_path(position: u64, sibling: Node, tree: u8) -> ::sapling::MerklePath {
+        let siblings = [vec![sibling], vec![node(tree); 31]].concat();
+        ::sapling::MerklePath::from_parts(siblings, position.into()).unwrap()
+    }
+
+    /// Notes of `values` as the first two leaves of `tree`, so witnessed at the same anchor.
+    fn notes_in_tree(values: [u64; 2], tree: u8) -> Vec<SpendableNote> {
+        let notes = [
+            sapling_note(values[0], 2 * tree),
+            sapling_note(values[1], 2 * tree + 1),
+        ];
+        let [first, second] = notes.clone().map(|note| Node::from_cmu(&note.cmu()));
+        let [first_note, second_note] = notes;
+        vec![
+            SpendableNote::Sapling {
+                note: first_note,
+                merkle_path: merkle_path(0, second, tree),
+            },
+            SpendableNote::Sapling {
+                note: second_note,
+                merkle_path: merkle_path(1, first, tree),
+            },
+        ]
+    }
+
+    /// Single note of `value` as the first leaf of `tree`.
+    fn note_in_tree(value: u64, tree: u8) -> SpendableNote {
+        SpendableNote::Sapling {
+            note: sapling_note(value, tree),
+            merkle_path: merkle_path(0, node(0), tree),
+        }
+    }
+
+    fn values(notes: &[SpendableNote]) -> Vec<u64> {
+        notes.iter().map(|note| note.value().into_u64()).collect()
+    }
+
+    #[test]
+    fn selects_the_largest_notes_and_returns_the_change() -> anyhow::Result<()> {
+        let notes = notes_in_tree([30_000, 50_000], 1);
+
+        let (selected, change) = select_notes(notes.clone(), Zatoshis::const_from_u64(40_000))?;
+        assert_eq!(values(&selected), [50_000]);
+        assert_eq!(change.into_u64(), 10_000);
+
+        let (selected, change) = select_notes(notes, Zatoshis::const_from_u64(80_000))?;
+        assert_eq!(values(&selected), [50_000, 30_000]);
+        assert_eq!(change, Zatoshis::ZERO);
+        let (sapling_anchor, orchard_anchor) = note_anchors(&selected)?;
+        assert!(sapling_anchor.is_some() && orchard_anchor.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn selected_notes_share_an_anchor() -> anyhow::Result<()> {
+        let mut notes = notes_in_tree([40_000, 40_000], 1);
+        notes.push(note_in_tree(70_000, 2));
+        notes.push(note_in_tree(30_000, 3));
+        assert!(note_anchors(&notes).is_err());
+
+        // By value alone, the 70_000 note would be spent along with a note of another tree.
+        let (selected, change) = select_notes(notes.clone(), Zatoshis::const_from_u64(75_000))?;
+        assert_eq!(values(&selected), [40_000, 40_000]);
+        assert_eq!(change.into_u64(), 5_000);
+        note_anchors(&selected)?;
+
+        // A single note covering the target takes fewer spends than a pair.
+        let (selected, change) = select_notes(notes, Zatoshis::const_from_u64(60_000))?;
+        assert_eq!(values(&selected), [70_000]);
+        assert_eq!(change.into_u64(), 10_000);
+        Ok(())
+    }
+
+    #[test]
+    fn funds_spread_over_anchors_are_insufficient() {
+        let notes = vec![note_in_tree(40_000, 1), note_in_tree(40_000, 2)];
+        let err = select_notes(notes, Zatoshis::const_from_u64(60_000)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "insufficient shielded funds: have 40000 at a single anchor, need 60000"
+        );
+        assert!(select_notes(Vec::new(), Zatoshis::const_from_u64(1)).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ::sapling::zip32::ExtendedSpendingKey;
+use async_trait::async_trait;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+use orchard::keys::SpendAuthorizingKey;
+use rand_core::OsRng;
+use secp256k1::{Message, PublicKey, Secp256k1, ecdsa::Signature};
+use serde::{Deserialize, Serialize};
+use zcash_primitives::transaction::{
+    builder::{BuildResult, Builder},
+    fees::fixed::FeeRule,
+};
+use zcash_proofs::prover::LocalTxProver;
+use zcash_protocol::consensus::{NetworkType, Parameters};
+use zcash_transparent::keys::AccountPrivKey;
+
+use crate::zebra_client::wallet::{Key, KeyScope, Wallet, derive_account_key};
+
+/// Sighash of a single transparent input, to be signed by the key behind `pubkey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SighashRequest {
+    pub pubkey: PublicKey,
+    pub sighash: [u8; 32],
+}
+
+/// Backend that authorizes transparent inputs of the transactions built by the bridge.
+///
+/// Transactions are built unsigned, the sighashes of their transparent inputs are handed
+/// to the signer, and the returned signatures are applied to produce the final transaction.
+/// This way the operator keys never have to be present in the relayer process.
+#[async_trait]
+pub trait ZcashSigner: Send + Sync {
+    /// Returns the public key that funds the bridge transactions.
+    fn public_key(&self) -> PublicKey;
+
+    /// Returns the public key at `index` of the internal (change) chain.
+    ///
+    /// Signers that only hold a single key return the funding key, i.e. reuse one address.
+    async fn change_public_key(&self, index: u32) -> anyhow::Result<PublicKey> {
+        let _ = index;
+        Ok(self.public_key())
+    }
+
+    /// Signs the provided sighashes. Signatures are returned in the same order as requests.
+    async fn sign(&self, requests: &[SighashRequest]) -> anyhow::Result<Vec<Signature>>;
+}
+
+/// Signer that keeps the secret keys in memory.
+pub struct LocalSigner {
+    funding_key: PublicKey,
+    keys: Mutex<HashMap<PublicKey, Key>>,
+    // Account used to derive change keys, if any.
+    account: Option<(AccountPrivKey, NetworkType)>,
+}
+
+impl LocalSigner {
+    pub fn new(funding_key: Key) -> Self {
+        let public_key = funding_key.public_key();
+        let mut keys = HashMap::new();
+        keys.insert(public_key, funding_key);
+        Self {
+            funding_key: public_key,
+            keys: Mutex::new(keys),
+            account: None,
+        }
+    }
+
+    /// Creates a signer funded by the first external key of the account, which sends change
+    /// to the internal chain of the same account.
+    pub fn from_wallet<P: Parameters>(wallet: &Wallet<P>, account_id: u32) -> Self {
+        let mut signer = Self::new(wallet.derive_key(account_id, 0));
+        signer.account = Some((wallet.account_key(account_id), wallet.network_type()));
+        signer
+    }
+
+    /// Makes an additional key available for signing.
+    pub fn add_key(&self, key: Key) {
+        self.keys.lock().unwrap().insert(key.public_key(), key);
+    }
+}
+
+#[async_trait]
+impl ZcashSigner for LocalSigner {
+    fn public_key(&self) -> PublicKey {
+        self.funding_key
+    }
+
+    async fn change_public_key(&self, index: u32) -> anyhow::Result<PublicKey> {
+        let Some((account_key, network_type)) = &self.account else {
+            return Ok(self.funding_key);
+        };
+        let key = derive_account_key(account_key, KeyScope::Internal, index, *network_type);
+        let public_key = key.public_key();
+        self.add_key(key);
+        Ok(public_key)
+    }
+
+    async fn sign(&self, requests: &[SighashRequest]) -> anyhow::Result<Vec<Signature>> {
+        let secp = Secp256k1::signing_only();
+        let keys = self.keys.lock().unwrap();
+        requests
+            .iter()
+            .map(|request| {
+                let key = keys.get(&request.pubkey).ok_or_else(|| {
+                    anyhow::anyhow!("no secret key for public key {}", request.pubkey)
+                })?;
+                let message = Message::from_digest(request.sighash);
+                Ok(secp.sign_ecdsa(&message, &key.secret_key()))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PublicKeyResponse {
+    pubkey: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignRequestItem {
+    pubkey: String,
+    sighash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignRequest {
+    requests: Vec<SignRequestItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignResponse {
+    signatures: Vec<String>,
+}
+
+/// Signer that delegates signing to an external signer service over HTTP.
+///
+/// The service is expected to expose `GET /pubkey`, `GET /change_pubkey/{index}` and
+/// `POST /sign`, see [`signer_service`] for the reference implementation.
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    funding_key: PublicKey,
+}
+
+impl RemoteSigner {
+    /// Connects to the signer service and fetches the funding public key.
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let client = reqwest::Client::new();
+        let url = url.trim_end_matches('/').to_string();
+        let response: PublicKeyResponse = client
+            .get(format!("{url}/pubkey"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let funding_key = parse_public_key(&response.pubkey)?;
+        Ok(Self {
+            client,
+            url,
+            funding_key,
+        })
+    }
+}
+
+#[async_trait]
+impl ZcashSigner for RemoteSigner {
+    fn public_key(&self) -> PublicKey {
+        self.funding_key
+    }
+
+    async fn change_public_key(&self, index: u32) -> anyhow::Result<PublicKey> {
+        let response: PublicKeyResponse = self
+            .client
+            .get(format!("{}/change_pubkey/{index}", self.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        parse_public_key(&response.pubkey)
+    }
+
+    async fn sign(&self, requests: &[SighashRequest]) -> anyhow::Result<Vec<Signature>> {
+        let request = SignRequest {
+            requests: requests
+                .iter()
+                .map(|r| SignRequestItem {
+                    pubkey: hex::encode(r.pubkey.serialize()),
+                    sighash: hex::encode(r.sighash),
+                })
+                .collect(),
+        };
+        let response: SignResponse = self
+            .client
+            .post(format!("{}/sign", self.url))
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        anyhow::ensure!(
+            response.signatures.len() == requests.len(),
+            "signer returned {} signatures for {} requests",
+            response.signatures.len(),
+            requests.len()
+        );
+
+        let secp = Secp256k1::verification_only();
+        let mut signatures = Vec::with_capacity(requests.len());
+        for (request, signature) in requests.iter().zip(response.signatures) {
+            let signature = Signature::from_der(&hex::decode(signature)?)?;
+            // Do not trust the external service blindly, a bad signature would only surface
+            // as a rejected transaction otherwise.
+            secp.verify_ecdsa(
+                &Message::from_digest(request.sighash),
+                &signature,
+                &request.pubkey,
+            )?;
+            signatures.push(signature);
+        }
+        Ok(signatures)
+    }
+}
+
+/// Returns an HTTP service exposing `signer` with the protocol expected by [`RemoteSigner`].
+///
+/// Used to run a signer as a separate process, as well as to emulate one in tests.
+pub fn signer_service(signer: Arc<dyn ZcashSigner>) -> Router {
+    Router::new()
+        .route("/pubkey", get(pubkey_handler))
+        .route("/change_pubkey/:index", get(change_pubkey_handler))
+        .route("/sign", post(sign_handler))
+        .with_state(signer)
+}
+
+async fn pubkey_handler(State(signer): State<Arc<dyn ZcashSigner>>) -> Json<PublicKeyResponse> {
+    Json(PublicKeyResponse {
+        pubkey: hex::encode(signer.public_key().serialize()),
+    })
+}
+
+async fn change_pubkey_handler(
+    State(signer): State<Arc<dyn ZcashSigner>>,
+    Path(index): Path<u32>,
+) -> Result<Json<PublicKeyResponse>, (axum::http::StatusCode, String)> {
+    let pubkey = signer
+        .change_public_key(index)
+        .await
+        .map_err(|e| (axum::http::StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(Json(PublicKeyResponse {
+        pubkey: hex::encode(pubkey.serialize()),
+    }))
+}
+
+async fn sign_handler(
+    State(signer): State<Arc<dyn ZcashSigner>>,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignResponse>, (axum::http::StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (axum::http::StatusCode::BAD_REQUEST, e.to_string());
+
+    let requests = request
+        .requests
+        .into_iter()
+        .map(|r| {
+            let pubkey = parse_public_key(&r.pubkey)?;
+            let sighash = hex::decode(r.sighash)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("sighash must be 32 bytes"))?;
+            Ok(SighashRequest { pubkey, sighash })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(bad_request)?;
+
+    let signatures = signer.sign(&requests).await.map_err(bad_request)?;
+    Ok(Json(SignResponse {
+        signatures: signatures
+            .iter()
+            .map(|s| hex::encode(s.serialize_der()))
+            .collect(),
+    }))
+}
+
+fn parse_public_key(hex_string: &str) -> anyhow::Result<PublicKey> {
+    Ok(PublicKey::from_slice(&hex::decode(hex_string)?)?)
+}
+
+/// Builds the transaction without signing it, collects signatures for its transparent
+/// inputs from `signer` and returns the authorized transaction.
+pub async fn build_and_sign<P: Parameters>(
+    builder: Builder<'_, P, ()>,
+    signer: &dyn ZcashSigner,
+    fee_rule: &FeeRule,
+) -> anyhow::Result<BuildResult> {
+    build_and_sign_with_shielded(builder, signer, &[], &[], fee_rule).await
+}
+
+/// Same as [`build_and_sign`], additionally authorizing shielded spends with the given keys.
+pub async fn build_and_sign_with_shielded<P: Parameters>(
+    builder: Builder<'_, P, ()>,
+    signer: &dyn ZcashSigner,
+    sapling_extsks: &[ExtendedSpendingKey],
+    orchard_saks: &[SpendAuthorizingKey],
+    fee_rule: &FeeRule,
+) -> anyhow::Result<BuildResult> {
+    let prover = LocalTxProver::bundled();
+
+    let unsigned = builder
+        .build_zfuture_unsigned(
+            sapling_extsks,
+            orchard_saks,
+            OsRng,
+            &prover,
+            &prover,
+            fee_rule,
+        )
+        .map_err(|e| anyhow::anyhow!("build failure: {:?}", e))?;
+
+    let requests: Vec<_> = unsigned
+        .transparent_sighashes()
+        .into_iter()
+        .map(|(pubkey, sighash)| SighashRequest { pubkey, sighash })
+        .collect();
+    let signatures = signer.sign(&requests).await?;
+
+    let res = unsigned
+        .apply_transparent_signatures(&signatures)
+        .map_err(|e| anyhow::anyhow!("failed to apply signatures: {:?}", e))?;
+    Ok(res)
+}
@@ -0,0 +1,59 @@
+//! New Zcash blocks from Zebra, through `getblocktemplate` long polling.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use zebra_node_services::rpc_client::RpcRequestClient;
+
+use crate::{subscription::BlockSubscription, zebra_client::client::RpcClient as _};
+
+/// Waits for Zebra blocks by long polling `getblocktemplate`, which returns once the tip or the
+/// mempool changes.
+///
+/// Nodes without mining support reject `getblocktemplate`; the tip is then polled every
+/// `poll_interval` instead.
+pub struct ZebraBlocks {
+    client: RpcRequestClient,
+    poll_interval: Duration,
+    long_poll: bool,
+    /// Identifier of the last template, which long polls wait to change.
+    longpoll_id: Option<String>,
+}
+
+impl ZebraBlocks {
+    pub fn new(client: RpcRequestClient, poll_interval: Duration) -> Self {
+        Self {
+            client,
+            poll_interval,
+            long_poll: true,
+            longpoll_id: None,
+        }
+    }
+}
+
+#[async_trait]
+impl BlockSubscription for ZebraBlocks {
+    async fn wait_for_block(&mut self, height: u64) -> anyhow::Result<u64> {
+        loop {
+            let tip = self.client.get_block_count().await? as u64;
+            if tip > height {
+                return Ok(tip);
+            }
+            if !self.long_poll {
+                tokio::time::sleep(self.poll_interval).await;
+                continue;
+            }
+            match self
+                .client
+                .get_block_template_long_poll(self.longpoll_id.as_deref())
+                .await
+            {
+                Ok(longpoll_id) => self.longpoll_id = Some(longpoll_id),
+                Err(err) => {
+                    tracing::warn!("Long polling is not available, polling the tip: {err:#}");
+                    self.long_poll = false;
+                }
+            }
+        }
+    }
+}
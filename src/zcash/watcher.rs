@@ -1,25 +1,44 @@
-use crate::{types::ZecToEthTransfer, zebra_client::client::RpcClient as _};
 use zcash_extensions::{consensus::transparent::EXTENSION_ETH_BRIDGE, transparent::eth_bridge};
+use zcash_primitives::extensions::transparent::FromPayload;
 use zcash_primitives::transaction::components::{TzeOut, tze};
-use zcash_primitives::{block::BlockHash, extensions::transparent::FromPayload};
 use zcash_protocol::TxId;
 use zcash_protocol::value::Zatoshis;
-use zebra_chain::{
-    block::Block, serialization::ZcashDeserialize as _, transparent::ExtendedScript,
+use zebra_chain::{transaction::Transaction, transparent::ExtendedScript};
+
+use crate::types::ZecToEthTransfer;
+#[cfg(feature = "node")]
+use crate::{
+    deposits::ObservedDeposit, types::ZcashRecipient, zebra_client::client::RpcClient as _,
 };
+#[cfg(feature = "node")]
+use zcash_primitives::block::BlockHash;
+#[cfg(feature = "node")]
+use zcash_protocol::consensus::BranchId;
+#[cfg(feature = "node")]
+use zcash_transparent::address::TransparentAddress;
+#[cfg(feature = "node")]
+use zebra_chain::{block::Block, serialization::ZcashDeserialize as _, transparent};
+#[cfg(feature = "node")]
 use zebra_node_services::rpc_client::RpcRequestClient;
+#[cfg(feature = "node")]
 use zebra_rpc::methods::GetBlockResponse;
 
+#[cfg(feature = "node")]
 pub struct ZcashWatcher {
     client: RpcRequestClient,
 }
 
+#[cfg(feature = "node")]
 impl ZcashWatcher {
     pub fn new(rpc_url: &str) -> Self {
         let client = RpcRequestClient::new(rpc_url.parse().unwrap());
         Self { client }
     }
 
+    pub fn client(&self) -> &RpcRequestClient {
+        &self.client
+    }
+
     pub async fn get_block_count(&self) -> anyhow::Result<u32> {
         let count = self.client.get_block_count().await?;
         Ok(count)
@@ -29,50 +48,49 @@ impl ZcashWatcher {
         &self,
         blocks: &[Block],
     ) -> anyhow::Result<(Vec<ZecToEthTransfer>, Vec<(tze::OutPoint, TzeOut)>)> {
-        let mut transfers = Vec::new();
-        let mut outpoints = Vec::new();
-
-        for block in blocks {
-            for tx in &block.transactions {
-                for (n, output) in tx.outputs().iter().enumerate() {
-                    let ExtendedScript::Extension(tze) = &output.lock_script else {
-                        // Not a TZE
-                        continue;
-                    };
-
-                    if tze.extension_id != EXTENSION_ETH_BRIDGE {
-                        // Not an EthBridge deposit
-                        continue;
-                    }
-
-                    let Ok(eth_bridge::Precondition::Deposit(deposit_data)) =
-                        eth_bridge::Precondition::from_payload(tze.mode, &tze.payload)
-                    else {
-                        // Not a (valid, at least) deposit
-                        continue;
-                    };
-
-                    let transfer = ZecToEthTransfer {
-                        eth_address: deposit_data.to,
-                        amount: output.value.zatoshis() as u64,
-                    };
-                    transfers.push(transfer);
+        Ok(deposit_outputs(blocks)
+            .map(|(_, _, outpoint, output, transfer)| (transfer, (outpoint, output)))
+            .unzip())
+    }
 
-                    let outpoint = tze::OutPoint::new(TxId::from_bytes(tx.hash().0), n as u32);
-                    let tze_out = TzeOut {
-                        value: Zatoshis::from_nonnegative_i64(output.value.zatoshis()).unwrap(),
-                        precondition: zcash_primitives::extensions::transparent::Precondition {
-                            extension_id: tze.extension_id,
-                            mode: tze.mode,
-                            payload: tze.payload.clone(),
-                        },
-                    };
-                    outpoints.push((outpoint, tze_out));
-                }
-            }
+    /// Returns the deposits in `blocks` along with their heights and refund addresses.
+    pub async fn extract_deposits(&self, blocks: &[Block]) -> anyhow::Result<Vec<ObservedDeposit>> {
+        let mut deposits = Vec::new();
+        for (block, tx, outpoint, output, transfer) in deposit_outputs(blocks) {
+            let height = block
+                .coinbase_height()
+                .ok_or_else(|| anyhow::anyhow!("block {} has no height", block.hash()))?;
+            let refund_to = self.funding_address(tx).await?;
+            deposits.push(ObservedDeposit {
+                outpoint,
+                output,
+                transfer,
+                height: height.0 as u64,
+                refund_to,
+            });
         }
+        Ok(deposits)
+    }
 
-        Ok((transfers, outpoints))
+    /// Returns the P2PKH address spent by the first transparent input of `tx`, i.e. the wallet
+    /// funding a deposit, if it is funded from one.
+    async fn funding_address(&self, tx: &Transaction) -> anyhow::Result<Option<ZcashRecipient>> {
+        let Some(transparent::Input::PrevOut { outpoint, .. }) = tx.inputs().first() else {
+            return Ok(None);
+        };
+        let prev_tx = self
+            .client
+            .get_transaction(&TxId::from_bytes(outpoint.hash.0), BranchId::ZFuture)
+            .await?;
+        let output = prev_tx
+            .transparent_bundle()
+            .and_then(|bundle| bundle.vout.get(outpoint.index as usize));
+        Ok(match output.and_then(|output| output.recipient_address()) {
+            Some(TransparentAddress::PublicKeyHash(hash)) => {
+                Some(ZcashRecipient::Transparent(hash))
+            }
+            _ => None,
+        })
     }
 
     pub async fn get_block(&self, height: u32) -> anyhow::Result<Block> {
@@ -83,8 +101,74 @@ impl ZcashWatcher {
             .await?;
         let block = match block {
             GetBlockResponse::Raw(raw) => Block::zcash_deserialize(raw.as_ref())?,
-            GetBlockResponse::Object(_obj) => todo!("Only raw blocks are supported for now"),
+            GetBlockResponse::Object(_) => {
+                anyhow::bail!("expected a raw block at height {height}, got a block object")
+            }
         };
         Ok(block)
     }
 }
+
+/// Iterates over the valid deposit outputs in `blocks`.
+#[cfg(feature = "node")]
+fn deposit_outputs(
+    blocks: &[Block],
+) -> impl Iterator<
+    Item = (
+        &Block,
+        &Transaction,
+        tze::OutPoint,
+        TzeOut,
+        ZecToEthTransfer,
+    ),
+> {
+    blocks.iter().flat_map(|block| {
+        block.transactions.iter().flat_map(move |tx| {
+            transaction_deposits(tx).map(move |(outpoint, output, transfer)| {
+                (block, tx.as_ref(), outpoint, output, transfer)
+            })
+        })
+    })
+}
+
+/// Iterates over the valid deposit outputs of `tx`.
+pub fn transaction_deposits(
+    tx: &Transaction,
+) -> impl Iterator<Item = (tze::OutPoint, TzeOut, ZecToEthTransfer)> {
+    tx.outputs()
+        .iter()
+        .enumerate()
+        .filter_map(move |(n, output)| {
+            let ExtendedScript::Extension(tze) = &output.lock_script else {
+                // Not a TZE
+                return None;
+            };
+
+            if tze.extension_id != EXTENSION_ETH_BRIDGE {
+                // Not an EthBridge deposit
+                return None;
+            }
+
+            let Ok(eth_bridge::Precondition::Deposit(deposit_data)) =
+                eth_bridge::Precondition::from_payload(tze.mode, &tze.payload)
+            else {
+                // Not a (valid, at least) deposit
+                return None;
+            };
+
+            let transfer = ZecToEthTransfer {
+                eth_address: deposit_data.to,
+                amount: output.value.zatoshis() as u64,
+            };
+            let outpoint = tze::OutPoint::new(TxId::from_bytes(tx.hash().0), n as u32);
+            let tze_out = TzeOut {
+                value: Zatoshis::from_nonnegative_i64(output.value.zatoshis()).unwrap(),
+                precondition: zcash_primitives::extensions::transparent::Precondition {
+                    extension_id: tze.extension_id,
+                    mode: tze.mode,
+                    payload: tze.payload.clone(),
+                },
+            };
+            Some((outpoint, tze_out, transfer))
+        })
+}
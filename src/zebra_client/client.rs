@@ -1,3 +1,4 @@
+use crate::metrics::observe_rpc;
 use async_trait::async_trait;
 use std::collections::HashSet;
 use tracing::{debug, info, warn};
@@ -12,6 +13,20 @@ use zebra_rpc::methods::{
     GetRawTransactionResponse, SendRawTransactionResponse, Utxo,
 };
 
+/// Calls `method` on the node, recording the call latency.
+async fn call<T: serde::de::DeserializeOwned>(
+    client: &RpcRequestClient,
+    method: &str,
+    params: String,
+) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+    observe_rpc(
+        "zcash",
+        method,
+        client.json_result_from_call(method, params),
+    )
+    .await
+}
+
 #[async_trait]
 pub trait RpcClient {
     async fn send_raw_transaction(
@@ -44,6 +59,26 @@ pub trait RpcClient {
     async fn get_block(&self, hash: &BlockHash) -> Result<GetBlockResponse, anyhow::Error>;
     async fn get_address_utxos(&self, address: String) -> Result<Vec<Utxo>, anyhow::Error>;
 
+    /// Returns the ids of the transactions in the mempool.
+    async fn get_raw_mempool(&self) -> Result<Vec<String>, anyhow::Error>;
+
+    /// Returns the ids of the mined transactions spending from or paying to `address`.
+    async fn get_address_tx_ids(&self, address: String) -> Result<Vec<String>, anyhow::Error>;
+
+    /// Returns the final Sapling and Orchard note commitment tree roots of the block at
+    /// `height`, in internal byte order. A missing root means the tree is empty.
+    async fn get_block_tree_roots(
+        &self,
+        height: u32,
+    ) -> Result<(Option<[u8; 32]>, Option<[u8; 32]>), anyhow::Error>;
+
+    /// Requests a block template, waiting until the template identified by `longpoll_id`
+    /// changes. Returns the `longpollid` of the new template.
+    async fn get_block_template_long_poll(
+        &self,
+        longpoll_id: Option<&str>,
+    ) -> Result<String, anyhow::Error>;
+
     /// Get up-to-date UTXOs for an address, including mempool transactions.
     ///
     /// This method combines data from getaddressutxos and getrawmempool to provide
@@ -69,7 +104,7 @@ impl RpcClient for RpcRequestClient {
         let mut tx_data = Vec::new();
         transaction.write(&mut tx_data)?;
         let tx_data_hex = hex::encode(tx_data);
-        self.json_result_from_call("sendrawtransaction", format!(r#"["{tx_data_hex}"]"#))
+        call(self, "sendrawtransaction", format!(r#"["{tx_data_hex}"]"#))
             .await
             .map_err(|e| anyhow::anyhow!("failed to send transaction: {:?}", e))
     }
@@ -81,37 +116,78 @@ impl RpcClient for RpcRequestClient {
     ) -> Result<zebra_rpc::methods::GetRawTransactionResponse, anyhow::Error> {
         let verbose = if verbose { 1 } else { 0 };
         let txid_hex = txid.to_string();
-        self.json_result_from_call("getrawtransaction", format!(r#"["{txid_hex}", {verbose}]"#))
-            .await
-            .map_err(|e| anyhow::anyhow!("failed to get raw transaction: {:?}", e))
+        call(
+            self,
+            "getrawtransaction",
+            format!(r#"["{txid_hex}", {verbose}]"#),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to get raw transaction: {:?}", e))
     }
 
     async fn get_block_count(&self) -> Result<u32, anyhow::Error> {
-        self.json_result_from_call("getblockcount", "[]".to_string())
+        call(self, "getblockcount", "[]".to_string())
             .await
             .map_err(|e| anyhow::anyhow!("failed to get block count: {}", e))
     }
 
     async fn get_block_hash(&self, height: u32) -> Result<GetBlockHashResponse, anyhow::Error> {
-        self.json_result_from_call("getblockhash", format!(r#"[{height}]"#))
+        call(self, "getblockhash", format!(r#"[{height}]"#))
             .await
             .map_err(|e| anyhow::anyhow!("failed to get block hash: {}", e))
     }
 
     async fn get_block(&self, hash: &BlockHash) -> Result<GetBlockResponse, anyhow::Error> {
         let block_hash_hex = hash.to_string();
-        self.json_result_from_call("getblock", format!(r#"["{block_hash_hex}", 0]"#))
+        call(self, "getblock", format!(r#"["{block_hash_hex}", 0]"#))
             .await
             .map_err(|e| anyhow::anyhow!("failed to get block: {}", e))
     }
 
+    async fn get_block_tree_roots(
+        &self,
+        height: u32,
+    ) -> Result<(Option<[u8; 32]>, Option<[u8; 32]>), anyhow::Error> {
+        let block: serde_json::Value = call(self, "getblock", format!(r#"["{height}", 1]"#))
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to get block: {}", e))?;
+        // Roots are reported in display order, like hashes.
+        let root = |field: &str| -> Result<Option<[u8; 32]>, anyhow::Error> {
+            let Some(hex_root) = block[field].as_str() else {
+                return Ok(None);
+            };
+            let mut root: [u8; 32] = hex::decode(hex_root)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("{field} is not 32 bytes"))?;
+            root.reverse();
+            Ok(Some(root))
+        };
+        Ok((root("finalsaplingroot")?, root("finalorchardroot")?))
+    }
+
+    async fn get_block_template_long_poll(
+        &self,
+        longpoll_id: Option<&str>,
+    ) -> Result<String, anyhow::Error> {
+        let params = match longpoll_id {
+            Some(id) => format!(r#"[{{"mode": "template", "longpollid": "{id}"}}]"#),
+            None => "[]".to_string(),
+        };
+        let template: serde_json::Value = call(self, "getblocktemplate", params)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to get block template: {}", e))?;
+        template["longpollid"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("block template has no longpollid"))
+    }
+
     async fn get_address_utxos(&self, address: String) -> Result<Vec<Utxo>, anyhow::Error> {
         let request = GetAddressUtxosRequest::new(vec![address], false);
         let request_json = serde_json::to_string(&request)
             .map_err(|e| anyhow::anyhow!("failed to serialize request: {}", e))?;
         let params = format!("[{}]", request_json);
-        let response: GetAddressUtxosResponse = self
-            .json_result_from_call("getaddressutxos", params)
+        let response: GetAddressUtxosResponse = call(self, "getaddressutxos", params)
             .await
             .map_err(|e| anyhow::anyhow!("failed to get address utxos: {}", e))?;
 
@@ -123,6 +199,20 @@ impl RpcClient for RpcRequestClient {
         Ok(utxos)
     }
 
+    async fn get_raw_mempool(&self) -> Result<Vec<String>, anyhow::Error> {
+        call(self, "getrawmempool", "[false]".to_string())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to get raw mempool: {}", e))
+    }
+
+    async fn get_address_tx_ids(&self, address: String) -> Result<Vec<String>, anyhow::Error> {
+        let tip = self.get_block_count().await?;
+        let request = serde_json::json!({ "addresses": [address], "start": 1, "end": tip });
+        call(self, "getaddresstxids", format!("[{request}]"))
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to get address txids: {}", e))
+    }
+
     async fn get_address_utxos_with_mempool(
         &self,
         address: String,
@@ -137,10 +227,7 @@ impl RpcClient for RpcRequestClient {
         let mut confirmed_utxos = self.get_address_utxos(address.clone()).await?;
 
         // Step 2: Get all transaction IDs in the mempool
-        let mempool_tx_ids: Vec<String> = self
-            .json_result_from_call("getrawmempool", "[false]".to_string())
-            .await
-            .map_err(|e| anyhow::anyhow!("failed to get raw mempool: {}", e))?;
+        let mempool_tx_ids = self.get_raw_mempool().await?;
 
         info!(
             "Found {} confirmed UTXOs, {} mempool transactions",
@@ -161,9 +248,12 @@ impl RpcClient for RpcRequestClient {
         for tx_id_hex in mempool_tx_ids {
             // Fetch the transaction details directly using the hex string from getrawmempool
             // Call getrawtransaction with verbose=1 for mempool compatibility
-            let response: Result<zebra_rpc::methods::GetRawTransactionResponse, _> = self
-                .json_result_from_call("getrawtransaction", format!(r#"["{}", 1]"#, tx_id_hex))
-                .await;
+            let response: Result<zebra_rpc::methods::GetRawTransactionResponse, _> = call(
+                self,
+                "getrawtransaction",
+                format!(r#"["{}", 1]"#, tx_id_hex),
+            )
+            .await;
 
             let tx = match response {
                 Ok(GetRawTransactionResponse::Object(tx_obj)) => {
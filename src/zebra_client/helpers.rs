@@ -12,11 +12,11 @@ use super::client::RpcClient;
 
 /// Converts a transaction hash in RPC format (reversed) into byte format.
 pub fn txid_from_rpc_string(hex_string: &str) -> Result<TxId, anyhow::Error> {
-    let bytes_rev = hex::decode(hex_string)
-        .unwrap()
+    let bytes_rev = hex::decode(hex_string)?
         .into_iter()
         .rev()
         .collect::<Vec<_>>();
+    anyhow::ensure!(bytes_rev.len() == 32, "txid has {} bytes", bytes_rev.len());
     TxId::read(&bytes_rev[..]).map_err(|e| anyhow::anyhow!("failed to parse txid: {}", e))
 }
 
@@ -47,6 +47,28 @@ pub async fn spendable_coinbase_txid(
     }
 }
 
+/// Returns the Sapling and Orchard anchors of the note commitment trees after block `height`.
+///
+/// Shielded outputs of a transaction mined after that block need these anchors, even if the
+/// transaction spends no notes.
+pub async fn tree_anchors(
+    client: &RpcRequestClient,
+    height: u32,
+) -> Result<(::sapling::Anchor, orchard::Anchor), anyhow::Error> {
+    let (sapling_root, orchard_root) = client.get_block_tree_roots(height).await?;
+    let sapling_anchor = match sapling_root {
+        Some(root) => Option::from(::sapling::Anchor::from_bytes(root))
+            .ok_or_else(|| anyhow::anyhow!("invalid Sapling root at height {height}"))?,
+        None => ::sapling::Anchor::empty_tree(),
+    };
+    let orchard_anchor = match orchard_root {
+        Some(root) => Option::from(orchard::Anchor::from_bytes(root))
+            .ok_or_else(|| anyhow::anyhow!("invalid Orchard root at height {height}"))?,
+        None => orchard::Anchor::empty_tree(),
+    };
+    Ok((sapling_anchor, orchard_anchor))
+}
+
 pub fn tx_convert_librustzcash_to_zebra(
     tx: &zcash_primitives::transaction::Transaction,
 ) -> zebra_chain::transaction::Transaction {
@@ -4,11 +4,33 @@ use secp256k1::{PublicKey, Secp256k1, SecretKey};
 use sha2::Sha256;
 use zcash_address::ZcashAddress;
 use zcash_primitives::transaction::builder::{BuildConfig, Builder};
-use zcash_protocol::consensus::{BlockHeight, NetworkType, Parameters};
-use zcash_transparent::{address::TransparentAddress, keys::NonHardenedChildIndex};
+use zcash_protocol::{
+    consensus::{BlockHeight, NetworkType, Parameters},
+    value::Zatoshis,
+};
+use zcash_transparent::{
+    address::TransparentAddress,
+    keys::{AccountPrivKey, NonHardenedChildIndex},
+};
+use zebra_rpc::methods::Utxo;
 use zip32::AccountId;
 
-use super::regtest::{REGTEST_DEFAULT_SEED, REGTEST_NETWORK, RegtestNetwork};
+use super::{
+    client::RpcClient,
+    regtest::{REGTEST_DEFAULT_SEED, REGTEST_NETWORK, RegtestNetwork},
+};
+
+/// Number of consecutive unused addresses after which a chain is considered exhausted (BIP44).
+pub const GAP_LIMIT: u32 = 20;
+
+/// BIP44 chain a transparent key belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyScope {
+    /// Receiving addresses, handed out to other parties.
+    External,
+    /// Change addresses, only used by the wallet itself.
+    Internal,
+}
 
 /// A wallet for a given network.
 pub struct Wallet<P: Parameters> {
@@ -37,26 +59,110 @@ impl<P: Parameters> Wallet<P> {
         Self::new(seed, network_params)
     }
 
+    /// Derives a key on the external (receiving) chain of the account.
     pub fn derive_key(&self, account_id: u32, address_index: u32) -> Key {
-        let account = AccountId::try_from(account_id).unwrap();
-        let sk = zcash_transparent::keys::AccountPrivKey::from_seed(
-            &self.network_params,
-            &self.seed,
-            account,
+        self.derive_scoped_key(account_id, KeyScope::External, address_index)
+    }
+
+    /// Derives a key on the internal (change) chain of the account.
+    pub fn derive_change_key(&self, account_id: u32, address_index: u32) -> Key {
+        self.derive_scoped_key(account_id, KeyScope::Internal, address_index)
+    }
+
+    /// Derives the key at `address_index` on the `scope` chain of the account, i.e. at
+    /// `m/44'/coin_type'/account_id'/scope/address_index`.
+    pub fn derive_scoped_key(&self, account_id: u32, scope: KeyScope, address_index: u32) -> Key {
+        derive_account_key(
+            &self.account_key(account_id),
+            scope,
+            address_index,
+            self.network_params.network_type(),
         )
-        .unwrap()
-        .derive_external_secret_key(NonHardenedChildIndex::from_index(address_index).unwrap())
-        .unwrap();
-        Key::new(sk, self.network_params.network_type())
+    }
+
+    /// Returns the BIP44 account-level private key.
+    pub fn account_key(&self, account_id: u32) -> AccountPrivKey {
+        let account = AccountId::try_from(account_id).unwrap();
+        AccountPrivKey::from_seed(&self.network_params, &self.seed, account).unwrap()
+    }
+
+    pub fn network_type(&self) -> NetworkType {
+        self.network_params.network_type()
+    }
+
+    /// Discovers used accounts and addresses of the wallet and collects their UTXOs.
+    ///
+    /// Accounts are scanned in order until the first unused one; each chain of an account is
+    /// scanned until [`GAP_LIMIT`] consecutive unused addresses are found. An address is used
+    /// once it appears in a mined transaction, even if its outputs were all spent since.
+    pub async fn sync(&self, client: &(impl RpcClient + Sync)) -> anyhow::Result<WalletState> {
+        let mut accounts = Vec::new();
+        for account_id in 0.. {
+            let account = self.sync_account(client, account_id).await?;
+            if account.next_external_index == 0 && account.next_internal_index == 0 {
+                break;
+            }
+            accounts.push(account);
+        }
+        Ok(WalletState { accounts })
+    }
+
+    /// Scans both chains of a single account.
+    pub async fn sync_account(
+        &self,
+        client: &(impl RpcClient + Sync),
+        account_id: u32,
+    ) -> anyhow::Result<AccountState> {
+        let account_key = self.account_key(account_id);
+        let network_type = self.network_type();
+
+        let mut utxos = Vec::new();
+        let mut next_indices = [0; 2];
+        for (next_index, scope) in next_indices
+            .iter_mut()
+            .zip([KeyScope::External, KeyScope::Internal])
+        {
+            let (next_unused, chain_utxos) = scan_chain(client, async |address_index| {
+                Ok(derive_account_key(&account_key, scope, address_index, network_type).address())
+            })
+            .await?;
+            *next_index = next_unused;
+            utxos.extend(
+                chain_utxos
+                    .into_iter()
+                    .map(|(address_index, utxo)| WalletUtxo {
+                        scope,
+                        address_index,
+                        utxo,
+                    }),
+            );
+        }
+
+        Ok(AccountState {
+            account_id,
+            next_external_index: next_indices[0],
+            next_internal_index: next_indices[1],
+            utxos,
+        })
     }
 
     pub fn tx_builder<'b>(&'b self, target_height: u32) -> Builder<'b, P, ()> {
+        self.tx_builder_with_anchors(target_height, None, None)
+    }
+
+    /// Returns a transaction builder able to spend shielded notes witnessed at the given anchors.
+    pub fn tx_builder_with_anchors<'b>(
+        &'b self,
+        target_height: u32,
+        sapling_anchor: Option<::sapling::Anchor>,
+        orchard_anchor: Option<orchard::Anchor>,
+    ) -> Builder<'b, P, ()> {
         Builder::new(
             self.network_params.clone(),
             BlockHeight::from_u32(target_height),
             BuildConfig::Standard {
-                sapling_anchor: None,
-                orchard_anchor: None,
+                sapling_anchor,
+                orchard_anchor,
             },
         )
     }
@@ -68,6 +174,103 @@ impl Default for Wallet<RegtestNetwork> {
     }
 }
 
+/// UTXO owned by one of the wallet addresses.
+#[derive(Debug, Clone)]
+pub struct WalletUtxo {
+    pub scope: KeyScope,
+    pub address_index: u32,
+    pub utxo: Utxo,
+}
+
+/// Result of scanning a single account.
+#[derive(Debug, Clone)]
+pub struct AccountState {
+    pub account_id: u32,
+    /// First address index on the external chain that has not been used yet.
+    pub next_external_index: u32,
+    /// First address index on the internal chain that has not been used yet.
+    pub next_internal_index: u32,
+    pub utxos: Vec<WalletUtxo>,
+}
+
+impl AccountState {
+    pub fn balance(&self) -> Zatoshis {
+        sum_utxos(self.utxos.iter().map(|u| &u.utxo))
+    }
+}
+
+/// Snapshot of the wallet state produced by [`Wallet::sync`].
+#[derive(Debug, Clone)]
+pub struct WalletState {
+    pub accounts: Vec<AccountState>,
+}
+
+impl WalletState {
+    pub fn account(&self, account_id: u32) -> Option<&AccountState> {
+        self.accounts.iter().find(|a| a.account_id == account_id)
+    }
+
+    /// Total balance across all discovered accounts.
+    pub fn balance(&self) -> Zatoshis {
+        sum_utxos(
+            self.accounts
+                .iter()
+                .flat_map(|a| a.utxos.iter().map(|u| &u.utxo)),
+        )
+    }
+}
+
+fn sum_utxos<'a>(utxos: impl Iterator<Item = &'a Utxo>) -> Zatoshis {
+    utxos
+        .map(|utxo| Zatoshis::from_u64(utxo.satoshis().to_owned()).unwrap())
+        .fold(Zatoshis::ZERO, |acc, value| (acc + value).unwrap())
+}
+
+/// Walks a chain of addresses until [`GAP_LIMIT`] consecutive unused addresses are found.
+///
+/// Addresses without UTXOs are looked up in the transaction history, so that spent change
+/// addresses still count as used. Returns the first index after the last used address and the
+/// UTXOs found, tagged with their address index.
+pub async fn scan_chain(
+    client: &(impl RpcClient + Sync),
+    address: impl AsyncFn(u32) -> anyhow::Result<ZcashAddress>,
+) -> anyhow::Result<(u32, Vec<(u32, Utxo)>)> {
+    let mut utxos = Vec::new();
+    let mut next_unused = 0;
+    let mut address_index = 0;
+    while address_index < next_unused + GAP_LIMIT {
+        let address = address(address_index).await?;
+        let address_utxos = client.get_address_utxos(address.encode()).await?;
+        let used = !address_utxos.is_empty()
+            || !client
+                .get_address_tx_ids(address.encode())
+                .await?
+                .is_empty();
+        if used {
+            next_unused = address_index + 1;
+            utxos.extend(address_utxos.into_iter().map(|u| (address_index, u)));
+        }
+        address_index += 1;
+    }
+    Ok((next_unused, utxos))
+}
+
+/// Derives a transparent key from the account-level private key.
+pub fn derive_account_key(
+    account_key: &AccountPrivKey,
+    scope: KeyScope,
+    address_index: u32,
+    network_type: NetworkType,
+) -> Key {
+    let index = NonHardenedChildIndex::from_index(address_index).unwrap();
+    let sk = match scope {
+        KeyScope::External => account_key.derive_external_secret_key(index),
+        KeyScope::Internal => account_key.derive_internal_secret_key(index),
+    }
+    .unwrap();
+    Key::new(sk, network_type)
+}
+
 /// Returns a default wallet for the Regtest network.
 pub fn regtest_default_wallet() -> Wallet<RegtestNetwork> {
     Wallet::<RegtestNetwork>::default()
@@ -90,14 +293,12 @@ impl Key {
 
     /// Returns the public key hash for the derived key.
     pub fn pubkey_hash(&self) -> [u8; 20] {
-        let pubkey = self.public_key();
-        Ripemd160::digest(Sha256::digest(pubkey.serialize())).into()
+        pubkey_hash(&self.public_key())
     }
 
     /// Returns the transparent address for the derived key.
     pub fn transparent_address(&self) -> TransparentAddress {
-        let hash = self.pubkey_hash();
-        TransparentAddress::PublicKeyHash(hash)
+        p2pkh_address(&self.public_key())
     }
 
     /// Returns the Zcash address for the derived key.
@@ -107,13 +308,210 @@ impl Key {
     }
 }
 
+/// Returns the hash160 of a serialized public key.
+pub fn pubkey_hash(pubkey: &PublicKey) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(pubkey.serialize())).into()
+}
+
+/// Returns the P2PKH transparent address of a public key.
+pub fn p2pkh_address(pubkey: &PublicKey) -> TransparentAddress {
+    TransparentAddress::PublicKeyHash(pubkey_hash(pubkey))
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use async_trait::async_trait;
+    use zcash_primitives::{
+        block::BlockHash,
+        transaction::{Transaction, TxId},
+    };
+    use zebra_chain::{
+        block::Height,
+        transparent::{OutputIndex, Script},
+    };
+    use zebra_rpc::methods::{
+        GetBlockHashResponse, GetBlockResponse, GetRawTransactionResponse,
+        SendRawTransactionResponse,
+    };
+
     use super::*;
 
+    /// Node knowing only the UTXOs and the transaction history of some addresses.
+    #[derive(Default)]
+    struct UtxoNode {
+        utxos: HashMap<String, Vec<Utxo>>,
+        history: HashSet<String>,
+    }
+
+    impl UtxoNode {
+        /// Records an address whose outputs were all spent.
+        fn spend(&mut self, key: Key) {
+            self.history.insert(key.address().encode());
+        }
+
+        fn fund(&mut self, key: Key, value: u64) {
+            let address = key.address().encode();
+            let utxo = Utxo::new(
+                address.parse().unwrap(),
+                zebra_chain::transaction::Hash([0; 32]),
+                OutputIndex::from_usize(0),
+                Script::new(&[]),
+                Zatoshis::from_u64(value).unwrap().into(),
+                Height(1),
+            );
+            self.history.insert(address.clone());
+            self.utxos.entry(address).or_default().push(utxo);
+        }
+    }
+
+    #[async_trait]
+    impl RpcClient for UtxoNode {
+        async fn send_raw_transaction(
+            &self,
+            _transaction: &Transaction,
+        ) -> anyhow::Result<SendRawTransactionResponse> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_raw_transaction(
+            &self,
+            _txid: &TxId,
+            _verbose: bool,
+        ) -> anyhow::Result<GetRawTransactionResponse> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_block_count(&self) -> anyhow::Result<u32> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_block_hash(&self, _height: u32) -> anyhow::Result<GetBlockHashResponse> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_block(&self, _hash: &BlockHash) -> anyhow::Result<GetBlockResponse> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_address_utxos(&self, address: String) -> anyhow::Result<Vec<Utxo>> {
+            Ok(self.utxos.get(&address).cloned().unwrap_or_default())
+        }
+
+        async fn get_raw_mempool(&self) -> anyhow::Result<Vec<String>> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_address_tx_ids(&self, address: String) -> anyhow::Result<Vec<String>> {
+            Ok(if self.history.contains(&address) {
+                vec![hex::encode([0; 32])]
+            } else {
+                Vec::new()
+            })
+        }
+
+        async fn get_block_tree_roots(
+            &self,
+            _height: u32,
+        ) -> anyhow::Result<(Option<[u8; 32]>, Option<[u8; 32]>)> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_block_template_long_poll(
+            &self,
+            _longpoll_id: Option<&str>,
+        ) -> anyhow::Result<String> {
+            anyhow::bail!("not used by wallet sync")
+        }
+
+        async fn get_address_utxos_with_mempool(
+            &self,
+            address: String,
+        ) -> anyhow::Result<Vec<Utxo>> {
+            self.get_address_utxos(address).await
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_discovers_addresses_within_gap_limit() -> anyhow::Result<()> {
+        let wallet = regtest_default_wallet();
+        let mut node = UtxoNode::default();
+        node.fund(wallet.derive_key(0, 0), 100_000);
+        node.fund(wallet.derive_key(0, GAP_LIMIT - 5), 200_000);
+        // More than `GAP_LIMIT` unused addresses after the last used one.
+        node.fund(wallet.derive_key(0, 2 * GAP_LIMIT), 1);
+        node.fund(wallet.derive_change_key(0, 3), 300_000);
+        node.fund(wallet.derive_key(1, 0), 400_000);
+        // Accounts after the first unused one are not scanned.
+        node.fund(wallet.derive_key(3, 0), 1);
+
+        let state = wallet.sync(&node).await?;
+        assert_eq!(state.accounts.len(), 2);
+        let account = state.account(0).unwrap();
+        assert_eq!(account.next_external_index, GAP_LIMIT - 4);
+        assert_eq!(account.next_internal_index, 4);
+        assert_eq!(account.balance(), Zatoshis::const_from_u64(600_000));
+        let mut used: Vec<_> = account
+            .utxos
+            .iter()
+            .map(|utxo| (utxo.scope, utxo.address_index))
+            .collect();
+        used.sort_by_key(|&(scope, index)| (scope == KeyScope::Internal, index));
+        assert_eq!(
+            used,
+            [
+                (KeyScope::External, 0),
+                (KeyScope::External, GAP_LIMIT - 5),
+                (KeyScope::Internal, 3),
+            ]
+        );
+        assert_eq!(state.account(1).unwrap().next_external_index, 1);
+        assert!(state.account(3).is_none());
+        assert_eq!(state.balance(), Zatoshis::const_from_u64(1_000_000));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sync_counts_spent_addresses_as_used() -> anyhow::Result<()> {
+        let wallet = regtest_default_wallet();
+        let mut node = UtxoNode::default();
+        // Every update spends the change of the previous one, more than `GAP_LIMIT` times.
+        for index in 0..GAP_LIMIT + 5 {
+            node.spend(wallet.derive_change_key(0, index));
+        }
+        node.fund(wallet.derive_change_key(0, GAP_LIMIT + 5), 100_000);
+        // An account whose funds were all spent is still scanned, and so are those after it.
+        node.spend(wallet.derive_key(1, 0));
+        node.fund(wallet.derive_key(2, 0), 200_000);
+
+        let state = wallet.sync(&node).await?;
+        let account = state.account(0).unwrap();
+        assert_eq!(account.next_internal_index, GAP_LIMIT + 6);
+        assert_eq!(account.next_external_index, 0);
+        assert_eq!(account.balance(), Zatoshis::const_from_u64(100_000));
+        let spent = state.account(1).unwrap();
+        assert_eq!(spent.next_external_index, 1);
+        assert!(spent.utxos.is_empty());
+        assert_eq!(state.account(2).unwrap().next_external_index, 1);
+        assert_eq!(state.accounts.len(), 3);
+        assert_eq!(state.balance(), Zatoshis::const_from_u64(300_000));
+        Ok(())
+    }
+
     #[test]
     fn test_miner_address() {
         let address = regtest_default_wallet().derive_key(0, 0).address();
         assert_eq!(address.encode(), "tmLTZegcJN5zaufWQBARHkvqC62mTumm3jR");
     }
+
+    #[test]
+    fn test_change_keys_are_distinct() {
+        let wallet = regtest_default_wallet();
+        let external = wallet.derive_key(0, 0).address();
+        let change = wallet.derive_change_key(0, 0).address();
+        assert_ne!(external, change);
+        assert_ne!(change, wallet.derive_change_key(0, 1).address());
+        assert_eq!(change, wallet.derive_change_key(0, 0).address());
+    }
 }
@@ -0,0 +1,141 @@
+//! Checks the rules of the batching policy.
+
+use std::time::Duration;
+
+use zcash_eth_bridge::{
+    batching::{BatchingPolicy, FlushReason, Pending},
+    types::{StateUpdate, ZecToEthTransfer},
+};
+
+const POLICY: BatchingPolicy = BatchingPolicy {
+    min_transfers: 3,
+    min_value: 1_000_000,
+    max_delay: Duration::from_secs(60),
+    max_deposit_age: Duration::from_secs(30),
+    heartbeat: Duration::from_secs(600),
+};
+
+fn pending(transfers: usize, value: u64) -> Pending {
+    Pending {
+        transfers,
+        value,
+        since_last_update: Duration::from_secs(10),
+        oldest_deposit_age: None,
+    }
+}
+
+#[test]
+fn empty_ranges_wait_for_heartbeat() {
+    assert_eq!(POLICY.decide(&pending(0, 0)), None);
+    let stale = Pending {
+        since_last_update: Duration::from_secs(600),
+        ..pending(0, 0)
+    };
+    assert_eq!(POLICY.decide(&stale), Some(FlushReason::Heartbeat));
+}
+
+#[test]
+fn thresholds_trigger_submission() {
+    assert_eq!(POLICY.decide(&pending(2, 500)), None);
+    assert_eq!(
+        POLICY.decide(&pending(3, 500)),
+        Some(FlushReason::TransferCount)
+    );
+    assert_eq!(
+        POLICY.decide(&pending(1, 1_000_000)),
+        Some(FlushReason::Value)
+    );
+
+    let old_deposit = Pending {
+        oldest_deposit_age: Some(Duration::from_secs(30)),
+        ..pending(1, 500)
+    };
+    assert_eq!(POLICY.decide(&old_deposit), Some(FlushReason::DepositAge));
+
+    let delayed = Pending {
+        since_last_update: Duration::from_secs(60),
+        ..pending(1, 500)
+    };
+    assert_eq!(POLICY.decide(&delayed), Some(FlushReason::MaxDelay));
+}
+
+#[test]
+fn every_block_policy_always_submits() {
+    assert_eq!(
+        BatchingPolicy::EVERY_BLOCK.decide(&Pending::default()),
+        Some(FlushReason::Heartbeat)
+    );
+}
+
+#[test]
+fn pending_sums_both_directions() {
+    let update = StateUpdate {
+        old_eth_block: 1,
+        new_eth_block: 2,
+        old_eth_hash: [0; 32],
+        new_eth_hash: [1; 32],
+        old_zcash_block: 1,
+        new_zcash_block: 2,
+        old_zcash_hash: [0; 32],
+        new_zcash_hash: [1; 32],
+        eth_to_zec_transfers: Vec::new(),
+        zec_to_eth_transfers: vec![
+            ZecToEthTransfer {
+                amount: 100,
+                eth_address: [1; 20],
+            },
+            ZecToEthTransfer {
+                amount: 250,
+                eth_address: [2; 20],
+            },
+        ],
+        refunds: Vec::new(),
+        withdrawal_refunds: Vec::new(),
+        quarantined_deposits: Vec::new(),
+        quarantined_withdrawals: Vec::new(),
+    };
+    let pending = Pending::new(&update, Duration::ZERO, None).unwrap();
+    assert_eq!(pending.transfers, 2);
+    assert_eq!(pending.value, 350);
+}
+
+#[test]
+fn time_to_flush_is_the_earliest_time_rule() {
+    assert_eq!(
+        POLICY.time_to_flush(&pending(0, 0)),
+        Duration::from_secs(590)
+    );
+    assert_eq!(
+        POLICY.time_to_flush(&pending(1, 500)),
+        Duration::from_secs(50)
+    );
+    let young_deposit = Pending {
+        oldest_deposit_age: Some(Duration::from_secs(5)),
+        ..pending(1, 500)
+    };
+    assert_eq!(
+        POLICY.time_to_flush(&young_deposit),
+        Duration::from_secs(25)
+    );
+    // Deposits that are not submitted do not count without transfers.
+    let queued_deposit = Pending {
+        oldest_deposit_age: Some(Duration::from_secs(5)),
+        ..pending(0, 0)
+    };
+    assert_eq!(
+        POLICY.time_to_flush(&queued_deposit),
+        Duration::from_secs(590)
+    );
+
+    // Once a time rule is due, the policy submits.
+    for pending in [pending(0, 0), pending(1, 500), young_deposit] {
+        let due = Pending {
+            since_last_update: pending.since_last_update + POLICY.time_to_flush(&pending),
+            oldest_deposit_age: pending
+                .oldest_deposit_age
+                .map(|age| age + POLICY.time_to_flush(&pending)),
+            ..pending
+        };
+        assert!(POLICY.decide(&due).is_some(), "{pending:?}");
+    }
+}
@@ -0,0 +1,90 @@
+//! Checks waiting for Zcash blocks against an emulated Zebra RPC endpoint.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+use serde_json::{Value, json};
+use tokio::sync::watch;
+use zcash_eth_bridge::{subscription::BlockSubscription as _, zcash::subscription::ZebraBlocks};
+use zebra_node_services::rpc_client::RpcRequestClient;
+
+#[derive(Clone)]
+struct Node {
+    tip: watch::Sender<u64>,
+    /// Whether the node answers `getblocktemplate`, as Zebra does with mining enabled.
+    mining: bool,
+    template_calls: Arc<AtomicU64>,
+}
+
+async fn rpc(State(node): State<Node>, body: String) -> Result<Json<Value>, StatusCode> {
+    let request: Value = serde_json::from_str(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let result = match request["method"].as_str() {
+        Some("getblockcount") => json!(*node.tip.borrow()),
+        Some("getblocktemplate") if node.mining => {
+            node.template_calls.fetch_add(1, Ordering::Relaxed);
+            let mut tip = node.tip.subscribe();
+            let longpoll_id = request["params"][0]["longpollid"].as_str();
+            if longpoll_id == Some(tip.borrow().to_string().as_str()) {
+                tip.changed().await.unwrap();
+            }
+            json!({ "longpollid": tip.borrow().to_string() })
+        }
+        _ => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    Ok(Json(
+        json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+    ))
+}
+
+async fn start_node(mining: bool) -> anyhow::Result<(RpcRequestClient, Node)> {
+    let node = Node {
+        tip: watch::Sender::new(5),
+        mining,
+        template_calls: Arc::default(),
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let router = Router::new().route("/", post(rpc)).with_state(node.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok((RpcRequestClient::new(addr), node))
+}
+
+fn mine_after(node: &Node, delay: Duration) {
+    let tip = node.tip.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        tip.send_modify(|tip| *tip += 1);
+    });
+}
+
+#[tokio::test]
+async fn long_poll_returns_on_new_block() -> anyhow::Result<()> {
+    let (client, node) = start_node(true).await?;
+    // Polling would not notice the block within the test.
+    let mut blocks = ZebraBlocks::new(client, Duration::from_secs(3600));
+
+    assert_eq!(blocks.wait_for_block(4).await?, 5);
+
+    mine_after(&node, Duration::from_millis(200));
+    let tip = tokio::time::timeout(Duration::from_secs(5), blocks.wait_for_block(5)).await??;
+    assert_eq!(tip, 6);
+    assert!(node.template_calls.load(Ordering::Relaxed) >= 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn falls_back_to_polling_without_mining() -> anyhow::Result<()> {
+    let (client, node) = start_node(false).await?;
+    let mut blocks = ZebraBlocks::new(client, Duration::from_millis(50));
+
+    mine_after(&node, Duration::from_millis(200));
+    let tip = tokio::time::timeout(Duration::from_secs(5), blocks.wait_for_block(5)).await??;
+    assert_eq!(tip, 6);
+    Ok(())
+}
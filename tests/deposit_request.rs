@@ -0,0 +1,77 @@
+//! Checks encoding deposit requests for third-party wallets.
+
+use zcash_eth_bridge::zcash::{
+    deposit::{DepositRequest, MODE_DEPOSIT},
+    sender::STF_IDENTIFIER,
+};
+use zcash_protocol::value::Zatoshis;
+
+const RECIPIENT: [u8; 20] = [0x11; 20];
+
+fn request(amount: u64) -> DepositRequest {
+    DepositRequest::new(RECIPIENT, Zatoshis::from_u64(amount).unwrap())
+}
+
+#[test]
+fn uri_roundtrip() -> anyhow::Result<()> {
+    let request = request(150_000_000);
+    let uri = request.to_uri()?;
+    assert!(uri.starts_with("zcash:?amount=1.5&req-tze="), "{uri}");
+    assert_eq!(DepositRequest::from_uri(&uri)?, request);
+    Ok(())
+}
+
+#[test]
+fn amounts_have_no_trailing_zeros() -> anyhow::Result<()> {
+    assert!(
+        request(200_000_000)
+            .to_uri()?
+            .starts_with("zcash:?amount=2&")
+    );
+    assert!(
+        request(1)
+            .to_uri()?
+            .starts_with("zcash:?amount=0.00000001&")
+    );
+    assert!(
+        request(10_000)
+            .to_uri()?
+            .starts_with("zcash:?amount=0.0001&")
+    );
+    Ok(())
+}
+
+#[test]
+fn partial_transaction_carries_deposit_output() -> anyhow::Result<()> {
+    let partial = request(100_000).partial(Zatoshis::from_u64(10_000)?)?;
+    assert_eq!(partial.required_value, 110_000);
+    let [output] = partial.tze_outputs.as_slice() else {
+        panic!("expected a single TZE output");
+    };
+    assert_eq!(output.value, 100_000);
+    assert_eq!(output.mode, MODE_DEPOSIT);
+    assert_eq!(
+        output.payload,
+        [&STF_IDENTIFIER[..], &RECIPIENT[..]].concat()
+    );
+    Ok(())
+}
+
+#[test]
+fn malformed_uris_are_rejected() {
+    let uri = request(100_000).to_uri().unwrap();
+    let (prefix, tze) = uri.split_once("&req-tze=").unwrap();
+    for malformed in [
+        "zcash:t1abc?amount=1".to_owned(),
+        format!("{prefix}&req-memo=00&req-tze={tze}"),
+        format!("zcash:?amount=1.123456789&req-tze={tze}"),
+        format!("zcash:?amount=-1&req-tze={tze}"),
+        prefix.to_owned(),
+        format!("{prefix}&req-tze={}", tze.replacen(":2:", ":1:", 1)),
+        format!("{prefix}&req-tze={}", &tze[..tze.len() - 2]),
+        // Trailing bytes, such as a refund address, are not part of a deposit payload.
+        format!("{prefix}&req-tze={tze}00{}", "22".repeat(20)),
+    ] {
+        assert!(DepositRequest::from_uri(&malformed).is_err(), "{malformed}");
+    }
+}
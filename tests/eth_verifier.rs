@@ -0,0 +1,79 @@
+//! Checks the verification of Ethereum headers and withdrawal receipts without a node.
+
+use alloy::{
+    consensus::{Header, Receipt, ReceiptEnvelope},
+    primitives::{Address, Bytes, Log, U256},
+    rpc::types::{Block, BlockTransactions},
+    sol_types::SolEvent as _,
+};
+use zcash_eth_bridge::{
+    eth::{contract::ZcashBridge::WithdrawalRequested, verifier},
+    types::{EthToZecTransfer, ZcashRecipient},
+};
+
+fn block(number: u64, parent_hash: alloy::primitives::B256) -> Block {
+    Block {
+        header: alloy::rpc::types::Header::new(Header {
+            number,
+            parent_hash,
+            ..Default::default()
+        }),
+        uncles: Vec::new(),
+        transactions: BlockTransactions::Hashes(Vec::new()),
+        withdrawals: None,
+    }
+}
+
+fn withdrawal_log(address: Address, amount: u64) -> Log {
+    let event = WithdrawalRequested {
+        requestId: U256::from(1),
+        requester: Address::repeat_byte(0x22),
+        amount: U256::from(amount),
+        receiverType: 0,
+        receiver: Bytes::from(vec![0x11; 20]),
+    };
+    Log {
+        address,
+        data: event.encode_log_data(),
+    }
+}
+
+#[test]
+fn chain_must_link_and_hash() {
+    let first = block(1, [0xaa; 32].into());
+    let second = block(2, first.header.hash);
+    assert!(verifier::verify_chain([0xaa; 32].into(), &[first.clone(), second.clone()]).is_ok());
+
+    // A block that does not follow its parent, or whose hash does not match its header.
+    assert!(verifier::verify_chain([0xbb; 32].into(), &[first.clone(), second.clone()]).is_err());
+    assert!(verifier::verify_chain([0xaa; 32].into(), &[second.clone()]).is_err());
+    let mut forged = second;
+    forged.header.inner.gas_used += 1;
+    assert!(verifier::verify_chain([0xaa; 32].into(), &[first, forged]).is_err());
+}
+
+#[test]
+fn withdrawals_are_read_from_bridge_logs() -> anyhow::Result<()> {
+    let bridge = Address::repeat_byte(0x01);
+    let receipt = ReceiptEnvelope::Legacy(
+        Receipt {
+            status: true.into(),
+            cumulative_gas_used: 21_000,
+            logs: vec![
+                withdrawal_log(bridge, 50_000),
+                // The same event emitted by another contract is ignored.
+                withdrawal_log(Address::repeat_byte(0x02), 1_000_000),
+            ],
+        }
+        .with_bloom(),
+    );
+
+    assert_eq!(
+        verifier::withdrawals_from_receipts(bridge, &[receipt])?,
+        vec![EthToZecTransfer {
+            amount: 50_000,
+            recipient: ZcashRecipient::Transparent([0x11; 20]),
+        }]
+    );
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! Checks the file lease electing the submitting relayer.
+
+use std::{path::PathBuf, time::Duration};
+
+use zcash_eth_bridge::leader::{FileLease, Leadership, LeaseBackend as _};
+
+fn lease_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{name}-{}.lease", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn only_one_instance_holds_the_lease() -> anyhow::Result<()> {
+    let lease = FileLease::new(lease_path("single-holder"));
+    let ttl = Duration::from_secs(60);
+
+    assert!(lease.try_acquire("a", ttl).await?);
+    assert!(!lease.try_acquire("b", ttl).await?);
+    // The holder renews its lease.
+    assert!(lease.try_acquire("a", ttl).await?);
+
+    // Releasing by another instance has no effect.
+    lease.release("b").await?;
+    assert!(!lease.try_acquire("b", ttl).await?);
+
+    lease.release("a").await?;
+    assert!(lease.try_acquire("b", ttl).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn standby_takes_over_expired_lease() -> anyhow::Result<()> {
+    let path = lease_path("failover");
+    let mut leader = Leadership::new(
+        Box::new(FileLease::new(&path)),
+        "leader",
+        Duration::from_millis(100),
+    );
+    let mut standby = Leadership::new(
+        Box::new(FileLease::new(&path)),
+        "standby",
+        Duration::from_secs(60),
+    );
+
+    assert!(leader.poll().await?);
+    assert!(!standby.poll().await?);
+
+    // The leader stops renewing.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(standby.poll().await?);
+    assert!(!leader.poll().await?);
+    assert!(!leader.is_leader());
+    Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_instances_elect_a_single_leader() -> anyhow::Result<()> {
+    let path = lease_path("concurrent");
+    let attempts = (0..8).map(|i| {
+        let lease = FileLease::new(&path);
+        tokio::spawn(async move {
+            lease
+                .try_acquire(&format!("relayer-{i}"), Duration::from_secs(60))
+                .await
+        })
+    });
+    let mut leaders = 0;
+    for attempt in attempts {
+        leaders += attempt.await?? as usize;
+    }
+    assert_eq!(leaders, 1);
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Checks decoding Zcash withdrawal recipients from addresses and contract receivers.
+
+use zcash_address::{
+    ToAddress as _, ZcashAddress,
+    unified::{self, Encoding as _},
+};
+use zcash_eth_bridge::types::ZcashRecipient;
+use zcash_protocol::consensus::NetworkType;
+use zcash_transparent::address::TransparentAddress;
+
+const P2PKH: [u8; 20] = [0x11; 20];
+const SAPLING: [u8; 43] = [0x22; 43];
+const ORCHARD: [u8; 43] = [0x33; 43];
+
+fn unified_address(receivers: Vec<unified::Receiver>) -> String {
+    unified::Address::try_from_items(receivers)
+        .unwrap()
+        .encode(&NetworkType::Regtest)
+}
+
+#[test]
+fn parses_single_receiver_addresses() -> anyhow::Result<()> {
+    let transparent = TransparentAddress::PublicKeyHash(P2PKH)
+        .to_zcash_address(NetworkType::Regtest)
+        .encode();
+    assert_eq!(
+        ZcashRecipient::parse(&transparent, NetworkType::Regtest)?,
+        ZcashRecipient::Transparent(P2PKH)
+    );
+
+    let sapling = ZcashAddress::from_sapling(NetworkType::Regtest, SAPLING).encode();
+    assert_eq!(
+        ZcashRecipient::parse(&sapling, NetworkType::Regtest)?,
+        ZcashRecipient::Sapling(SAPLING)
+    );
+    Ok(())
+}
+
+#[test]
+fn rejects_addresses_of_other_networks() {
+    let sapling = ZcashAddress::from_sapling(NetworkType::Main, SAPLING).encode();
+    assert!(ZcashRecipient::parse(&sapling, NetworkType::Regtest).is_err());
+    assert!(ZcashRecipient::parse("not an address", NetworkType::Regtest).is_err());
+}
+
+#[test]
+fn unified_addresses_pick_the_most_private_receiver() -> anyhow::Result<()> {
+    let all = unified_address(vec![
+        unified::Receiver::P2pkh(P2PKH),
+        unified::Receiver::Sapling(SAPLING),
+        unified::Receiver::Orchard(ORCHARD),
+    ]);
+    assert_eq!(
+        ZcashRecipient::parse(&all, NetworkType::Regtest)?,
+        ZcashRecipient::Orchard(ORCHARD)
+    );
+
+    let without_orchard = unified_address(vec![
+        unified::Receiver::P2pkh(P2PKH),
+        unified::Receiver::Sapling(SAPLING),
+    ]);
+    assert_eq!(
+        ZcashRecipient::parse(&without_orchard, NetworkType::Regtest)?,
+        ZcashRecipient::Sapling(SAPLING)
+    );
+    Ok(())
+}
+
+#[test]
+fn unified_addresses_need_a_supported_receiver() {
+    let unknown = unified_address(vec![unified::Receiver::Unknown {
+        typecode: 0x42,
+        data: vec![0x44; 32],
+    }]);
+    assert!(ZcashRecipient::parse(&unknown, NetworkType::Regtest).is_err());
+}
+
+#[test]
+fn contract_receivers_round_trip() -> anyhow::Result<()> {
+    for recipient in [
+        ZcashRecipient::Transparent(P2PKH),
+        ZcashRecipient::Sapling(SAPLING),
+        ZcashRecipient::Orchard(ORCHARD),
+    ] {
+        let decoded =
+            ZcashRecipient::from_receiver(recipient.receiver_type(), recipient.receiver_bytes())?;
+        assert_eq!(decoded, recipient);
+    }
+    Ok(())
+}
+
+#[test]
+fn contract_receivers_are_validated() {
+    assert!(ZcashRecipient::from_receiver(0, &SAPLING).is_err());
+    assert!(ZcashRecipient::from_receiver(1, &P2PKH).is_err());
+    assert!(ZcashRecipient::from_receiver(2, &[0x33; 42]).is_err());
+    assert!(ZcashRecipient::from_receiver(3, &P2PKH).is_err());
+}
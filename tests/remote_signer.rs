@@ -0,0 +1,61 @@
+//! Checks that an external signer service can authorize sighashes for the bridge operator.
+
+use std::sync::Arc;
+
+use secp256k1::{Message, Secp256k1};
+use zcash_eth_bridge::{
+    zcash::signer::{LocalSigner, RemoteSigner, SighashRequest, ZcashSigner, signer_service},
+    zebra_client::wallet::regtest_default_wallet,
+};
+
+#[tokio::test]
+async fn remote_signer_roundtrip() -> anyhow::Result<()> {
+    let wallet = regtest_default_wallet();
+    let pubkey = wallet.derive_key(0, 0).public_key();
+
+    // Emulate the external signer process with in-memory keys behind the HTTP service.
+    let service = signer_service(Arc::new(LocalSigner::from_wallet(&wallet, 0)));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, service).await });
+
+    let signer = RemoteSigner::connect(&format!("http://{addr}")).await?;
+    assert_eq!(signer.public_key(), pubkey);
+
+    let change_pubkey = signer.change_public_key(0).await?;
+    assert_eq!(change_pubkey, wallet.derive_change_key(0, 0).public_key());
+
+    let requests = vec![
+        SighashRequest {
+            pubkey,
+            sighash: [0x11; 32],
+        },
+        SighashRequest {
+            pubkey: change_pubkey,
+            sighash: [0x22; 32],
+        },
+    ];
+    let signatures = signer.sign(&requests).await?;
+    assert_eq!(signatures.len(), requests.len());
+
+    let secp = Secp256k1::verification_only();
+    for (request, signature) in requests.iter().zip(&signatures) {
+        secp.verify_ecdsa(
+            &Message::from_digest(request.sighash),
+            signature,
+            &request.pubkey,
+        )?;
+    }
+
+    // Keys unknown to the signer must be rejected.
+    let unknown = wallet.derive_key(0, 1).public_key();
+    let result = signer
+        .sign(&[SighashRequest {
+            pubkey: unknown,
+            sighash: [0x33; 32],
+        }])
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
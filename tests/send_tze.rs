@@ -64,6 +64,10 @@ async fn send_tze() -> anyhow::Result<()> {
             vec![(deposit_outpoint, deposit_tze_output)],
             vec![processed_deposit],
             Vec::new(),
+            Vec::new(),
+            sender.state().clone(),
+            &[],
+            None,
         )
         .await?;
     tracing::info!(
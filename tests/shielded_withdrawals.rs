@@ -0,0 +1,75 @@
+//! Runs consecutive shielded withdrawals against a local regtest zebrad node.
+
+use zcash_eth_bridge::{
+    types::{EthToZecTransfer, ZcashRecipient},
+    zcash::sender::TzeSender,
+};
+use zcash_extensions::transparent::eth_bridge;
+use zcash_protocol::value::Zatoshis;
+
+const FEE: u64 = 50_000;
+
+#[tokio::test]
+async fn shielded_withdrawals_do_not_drain_the_fee_coin() -> anyhow::Result<()> {
+    let mut sender = TzeSender::new("127.0.0.1:18232").await?;
+    let (create_outpoint, create_tze_output) = sender.send_tze_create(FEE).await?;
+    sender.wait_for_tx(create_outpoint.txid()).await?;
+
+    let deposit_eth_addr = [0xAB; 20];
+    let deposit_amount = Zatoshis::const_from_u64(1_000_000);
+    let (deposit_outpoint, deposit_tze_output) = sender
+        .send_tze_deposit(deposit_eth_addr, deposit_amount, FEE)
+        .await?;
+    sender.wait_for_tx(deposit_outpoint.txid()).await?;
+
+    let mut stf = sender
+        .initialize_tze_stf(FEE, (create_outpoint, create_tze_output))
+        .await?;
+    sender.wait_for_tx(stf.0.txid()).await?;
+    stf = sender
+        .progress_tze_stf(
+            FEE,
+            stf,
+            vec![(deposit_outpoint, deposit_tze_output)],
+            vec![eth_bridge::modes::stf::ProcessedDeposit {
+                to: deposit_eth_addr,
+                amount: deposit_amount,
+            }],
+            Vec::new(),
+            Vec::new(),
+            sender.state().clone(),
+            &[],
+            None,
+        )
+        .await?;
+    sender.wait_for_tx(stf.0.txid()).await?;
+
+    let (_, address) = ::sapling::zip32::ExtendedSpendingKey::master(&[7; 32]).default_address();
+    let withdrawal = EthToZecTransfer {
+        amount: 200_000,
+        recipient: ZcashRecipient::Sapling(address.to_bytes()),
+    };
+    let mut funds = sender.fee_funds().await?;
+    for _ in 0..4 {
+        stf = sender
+            .progress_tze_stf(
+                FEE,
+                stf,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                vec![withdrawal.clone()],
+                sender.state().clone(),
+                &[],
+                None,
+            )
+            .await?;
+        sender.wait_for_tx(stf.0.txid()).await?;
+
+        // The STF reimburses the shielded output, so the operator only pays the fee.
+        let remaining = sender.fee_funds().await?;
+        assert_eq!(Some(remaining), funds - Zatoshis::const_from_u64(FEE));
+        funds = remaining;
+    }
+    Ok(())
+}
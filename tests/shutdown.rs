@@ -0,0 +1,35 @@
+//! Checks the cancellation of long-running waits.
+
+use std::time::Duration;
+
+use zcash_eth_bridge::shutdown::{CancellationToken, Cancelled, cancellable, deadline};
+
+#[tokio::test]
+async fn cancellation_interrupts_waits() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let result = cancellable(&token, std::future::pending::<anyhow::Result<()>>()).await;
+    assert!(result.unwrap_err().is::<Cancelled>());
+
+    let token = CancellationToken::new();
+    assert_eq!(
+        cancellable(&token, async { anyhow::Ok(1) }).await.unwrap(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn deadline_follows_shutdown() {
+    let shutdown = CancellationToken::new();
+    let deadline = deadline(&shutdown, Duration::from_millis(200));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!deadline.is_cancelled());
+
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!deadline.is_cancelled());
+    tokio::time::timeout(Duration::from_secs(5), deadline.cancelled())
+        .await
+        .expect("deadline not reached");
+}
@@ -0,0 +1,48 @@
+//! Checks the solvency invariants on hand-crafted bridge balances.
+
+use zcash_eth_bridge::{
+    audit::{SolvencyReport, SolvencyViolation},
+    zcash::sender::LOCK_IN_VALUE,
+};
+
+fn report(stf_value: u64, total_supply: u64) -> SolvencyReport {
+    SolvencyReport {
+        zcash_height: 120,
+        eth_block: 40,
+        stf_value,
+        wzec_total_supply: total_supply,
+        total_minted: 500_000,
+        total_burned: 200_000,
+        total_locked: 50_000,
+    }
+}
+
+#[test]
+fn solvency_invariants() {
+    let lock_in = LOCK_IN_VALUE.into_u64();
+
+    let solvent = report(300_000 + lock_in, 300_000);
+    assert!(solvent.violations().is_empty());
+    assert_eq!(solvent.divergence(), 0);
+
+    // Funds leaked from the STF UTXO.
+    let leaked = report(290_000 + lock_in, 300_000);
+    assert_eq!(
+        leaked.violations(),
+        vec![SolvencyViolation::StfValueMismatch {
+            stf_value: 290_000 + lock_in,
+            expected: 300_000 + lock_in,
+        }]
+    );
+    assert_eq!(leaked.divergence(), -10_000);
+
+    // WZEC minted outside of the bridge.
+    let inflated = report(300_000 + lock_in, 310_000);
+    assert_eq!(
+        inflated.violations(),
+        vec![SolvencyViolation::SupplyMismatch {
+            total_supply: 310_000,
+            expected: 300_000,
+        }]
+    );
+}
@@ -0,0 +1,123 @@
+//! Checks the Zcash header-chain light client without a node.
+
+use zcash_eth_bridge::zcash::light_client::{
+    BlockHeader, ConsensusParams, HeaderChain, expand_compact, to_compact, transaction_merkle_root,
+    transaction_proof, verify_transaction,
+};
+
+fn header(prev_hash: [u8; 32], time: u32, bits: u32) -> BlockHeader {
+    BlockHeader {
+        version: 4,
+        prev_hash,
+        merkle_root: [1; 32],
+        block_commitments: [2; 32],
+        time,
+        bits,
+        nonce: [3; 32],
+        solution: vec![4; 36],
+    }
+}
+
+/// Headers linked to each other, `spacing` seconds apart.
+fn anchor(count: usize, spacing: u32, bits: u32) -> Vec<BlockHeader> {
+    let mut headers: Vec<BlockHeader> = Vec::new();
+    for i in 0..count as u32 {
+        let prev_hash = headers.last().map_or([0; 32], BlockHeader::hash);
+        headers.push(header(prev_hash, 1_700_000_000 + i * spacing, bits));
+    }
+    headers
+}
+
+#[test]
+fn header_serialization_roundtrip() -> anyhow::Result<()> {
+    let header = header([9; 32], 1_700_000_000, 0x200f0f0f);
+    let bytes = header.serialize();
+    assert_eq!(bytes.len(), 140 + 1 + 36);
+    assert_eq!(BlockHeader::parse(&bytes)?, header);
+    assert!(BlockHeader::parse(&bytes[..bytes.len() - 1]).is_err());
+    Ok(())
+}
+
+#[test]
+fn compact_targets_roundtrip() {
+    for bits in [0x1f07ffff, 0x200f0f0f, 0x1d00ffff, 0x1c0ffff0] {
+        assert_eq!(to_compact(expand_compact(bits).unwrap()), bits);
+    }
+    // Negative and overflowing targets are rejected.
+    assert!(expand_compact(0x1d80ffff).is_none());
+    assert!(expand_compact(0x2300ffff).is_none());
+}
+
+#[test]
+fn difficulty_follows_block_times() -> anyhow::Result<()> {
+    let params = ConsensusParams::MAINNET;
+    let bits = 0x1d00ffff;
+    let target = expand_compact(bits).unwrap();
+
+    let fast = HeaderChain::new(params, anchor(params.history_len(), 30, bits), 100)?;
+    assert!(expand_compact(fast.next_bits()).unwrap() < target);
+    let slow = HeaderChain::new(params, anchor(params.history_len(), 300, bits), 100)?;
+    assert!(expand_compact(slow.next_bits()).unwrap() > target);
+
+    // Difficulty cannot be checked without a full averaging window.
+    assert!(HeaderChain::new(params, anchor(10, 75, bits), 100).is_err());
+    Ok(())
+}
+
+#[test]
+fn header_chain_rejects_invalid_headers() -> anyhow::Result<()> {
+    let bits = ConsensusParams::REGTEST.pow_limit_bits;
+    let mut chain = HeaderChain::new(ConsensusParams::REGTEST, anchor(1, 75, bits), 10)?;
+    let tip = chain.tip().clone();
+
+    let unlinked = header([0xff; 32], tip.time + 75, bits);
+    assert!(chain.push(unlinked).is_err());
+    let early = header(tip.hash(), tip.time, bits);
+    assert!(chain.push(early).is_err());
+    let wrong_bits = header(tip.hash(), tip.time + 75, 0x1f07ffff);
+    assert!(chain.push(wrong_bits).is_err());
+    // The solution of a made-up header is not a valid Equihash solution.
+    let unsolved = header(tip.hash(), tip.time + 75, bits);
+    assert!(chain.push(unsolved).is_err());
+    assert_eq!(chain.tip_height(), 10);
+    Ok(())
+}
+
+#[test]
+fn header_chain_accepts_solved_headers() -> anyhow::Result<()> {
+    let params = ConsensusParams::REGTEST;
+    let bits = params.pow_limit_bits;
+    let mut chain = HeaderChain::new(params, anchor(1, 75, bits), 10)?;
+
+    // Equihash (48, 5) solution of the header, found offline for the zero nonce.
+    let mut solved = header(chain.tip().hash(), 1_700_000_075, bits);
+    solved.nonce = [0; 32];
+    solved.solution =
+        hex::decode("100e0fbfa11e2a3ff52db0a69d036df1db9e1bcc1c0e7b5f7763bf1d8fbcbe85bee98326")?;
+    solved.verify_pow(&params)?;
+
+    chain.push(solved.clone())?;
+    assert_eq!(chain.tip_height(), 11);
+    assert_eq!(chain.tip(), &solved);
+
+    // Any change to the header invalidates the solution.
+    let mut tampered = header(chain.tip().hash(), 1_700_000_150, bits);
+    tampered.nonce = [0; 32];
+    tampered.solution = solved.solution.clone();
+    assert!(chain.push(tampered).is_err());
+    Ok(())
+}
+
+#[test]
+fn transaction_proofs_verify_against_merkle_root() {
+    let txids: Vec<[u8; 32]> = (0u8..5).map(|i| [i; 32]).collect();
+    let mut header = header([0; 32], 0, 0x200f0f0f);
+    header.merkle_root = transaction_merkle_root(&txids);
+
+    for (index, txid) in txids.iter().enumerate() {
+        let proof = transaction_proof(&txids, index as u64).unwrap();
+        assert!(verify_transaction(&header, txid, &proof));
+        assert!(!verify_transaction(&header, &[0xaa; 32], &proof));
+    }
+    assert!(transaction_proof(&txids, 5).is_none());
+}